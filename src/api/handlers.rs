@@ -171,6 +171,6 @@ pub async fn session_new(
         .await
     {
         Ok(session_id) => HttpResponse::Ok().json(session_id),
-        Err(e) => HttpResponse::BadRequest().body(e),
+        Err(e) => HttpResponse::BadRequest().body(e.to_string()),
     }
 }
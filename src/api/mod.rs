@@ -41,11 +41,11 @@ fn config(cfg: &mut web::ServiceConfig) {
 }
 
 pub(crate) async fn start(opts: Options) -> Result<(), Error> {
-    let address = opts.api.unwrap();
+    let address = opts.api.clone().unwrap();
 
     log::info!("starting api on http://{} ...", &address);
 
-    let state = Arc::new(RwLock::new(Sessions::new(opts.concurrency)));
+    let state = Arc::new(RwLock::new(Sessions::new(opts.concurrency())));
 
     HttpServer::new(move || {
         let cors = Cors::permissive();
@@ -61,5 +61,5 @@ pub(crate) async fn start(opts: Options) -> Result<(), Error> {
     .map_err(|e| e.to_string())?
     .run()
     .await
-    .map_err(|e| e.to_string())
+    .map_err(Error::from)
 }
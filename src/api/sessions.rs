@@ -18,7 +18,7 @@ static STATS_PARSER: Lazy<Regex> = lazy_regex!(
 );
 static LOOT_PARSER: Lazy<Regex> = lazy_regex!(r"(?m)^.+\[(.+)\]\s\(([^)]+)\)(\s<(.+)>)?\s(.+)");
 
-use crate::{session::Error, utils::parse_multiple_targets, Options};
+use crate::{session::Error, utils::parse_targets, Options};
 
 pub(crate) type SharedState = Arc<RwLock<Sessions>>;
 
@@ -227,14 +227,14 @@ impl Session {
                                 .unwrap()
                                 .last()
                                 .unwrap_or(&String::new())
-                                .to_string(),
+                                .to_string()
+                                .into(),
                         ));
                     }
                 }
                 Err(error) => {
                     log::error!("[{id}] child process {process_id} completed with error {error}");
-                    *child_completed.lock().unwrap() =
-                        Some(Completion::with_error(error.to_string()));
+                    *child_completed.lock().unwrap() = Some(Completion::with_error(error.into()));
                 }
             }
 
@@ -265,7 +265,7 @@ impl Session {
             nix::unistd::Pid::from_raw(self.process_id as nix::libc::pid_t),
             nix::sys::signal::Signal::SIGTERM,
         )
-        .map_err(|e| e.to_string())
+        .map_err(|e| e.to_string().into())
     }
 }
 
@@ -294,25 +294,25 @@ impl Sessions {
 
         // validate argv
         let opts = Options::try_parse_from(&argv).map_err(|e| e.to_string())?;
-        let targets = if let Some(target) = opts.target.as_ref() {
-            parse_multiple_targets(target)?
-        } else {
-            return Err("no --target/-T argument provided".to_owned());
-        };
+        let targets = parse_targets(
+            opts.target.as_ref(),
+            opts.targets.as_ref(),
+            opts.ports.as_ref(),
+            &opts.exclude,
+        )?;
 
         let avail_workers = self
             .available_workers
             .load(std::sync::atomic::Ordering::Relaxed) as usize;
-        if opts.concurrency > avail_workers {
-            return Err(format!(
-                "can't start new session, {avail_workers} available workers"
-            ));
+        let concurrency = opts.concurrency();
+        if concurrency > avail_workers {
+            return Err(
+                format!("can't start new session, {avail_workers} available workers").into(),
+            );
         }
 
-        self.available_workers.fetch_sub(
-            opts.concurrency as u64,
-            std::sync::atomic::Ordering::Relaxed,
-        );
+        self.available_workers
+            .fetch_sub(concurrency as u64, std::sync::atomic::Ordering::Relaxed);
 
         let session_id = uuid::Uuid::new_v4();
 
@@ -324,7 +324,7 @@ impl Sessions {
                 session_id,
                 argv,
                 targets,
-                opts.concurrency,
+                concurrency,
                 self.available_workers.clone(),
             )
             .await?,
@@ -336,7 +336,7 @@ impl Sessions {
     pub fn stop_session(&self, id: &uuid::Uuid) -> Result<(), Error> {
         let session = match self.sessions.get(id) {
             Some(s) => s,
-            None => return Err(format!("session {id} not found")),
+            None => return Err(format!("session {id} not found").into()),
         };
         session.stop()
     }
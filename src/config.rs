@@ -0,0 +1,189 @@
+use clap::parser::ValueSource;
+use clap::CommandFactory;
+
+use crate::session::Error;
+use crate::Options;
+
+// turn a single TOML value into command line values, arrays are repeated
+fn values(key: &str, value: &toml::Value) -> Result<Vec<String>, Error> {
+    match value {
+        toml::Value::String(s) => Ok(vec![s.to_owned()]),
+        toml::Value::Integer(i) => Ok(vec![i.to_string()]),
+        toml::Value::Float(f) => Ok(vec![f.to_string()]),
+        toml::Value::Boolean(b) => Ok(vec![b.to_string()]),
+        toml::Value::Datetime(d) => Ok(vec![d.to_string()]),
+        toml::Value::Array(items) => {
+            let mut all = vec![];
+            for item in items {
+                if matches!(item, toml::Value::Array(_) | toml::Value::Table(_)) {
+                    return Err(format!("{}: nested values are not supported", key).into());
+                }
+                all.extend(values(key, item)?);
+            }
+            Ok(all)
+        }
+        toml::Value::Table(_) => Err(format!("{}: unexpected table", key).into()),
+    }
+}
+
+// keys are the same as the command line flags, with either dashes or underscores, and can be
+// grouped in tables like [http] which are only there for readability
+fn collect(table: &toml::value::Table, into: &mut Vec<(String, toml::Value)>) {
+    for (key, value) in table {
+        match value {
+            toml::Value::Table(table) => collect(table, into),
+            _ => into.push((key.replace('-', "_"), value.clone())),
+        }
+    }
+}
+
+// parse the TOML configuration as the arguments that would set the same options, so that it
+// goes through the same validation of the command line
+pub(crate) fn to_argv(data: &str) -> Result<Vec<String>, Error> {
+    let table: toml::value::Table = toml::from_str(data).map_err(|e| e.to_string())?;
+    let mut pairs = vec![];
+    collect(&table, &mut pairs);
+
+    let command = Options::command();
+    let mut argv = vec![
+        "".to_owned(), // simulates argv[0]
+    ];
+
+    for (key, value) in pairs {
+        let Some(arg) = command
+            .get_arguments()
+            .find(|arg| arg.get_id().as_str() == key && key != "config")
+        else {
+            log::warn!("ignoring unknown configuration key '{}'", key);
+            continue;
+        };
+
+        let values = values(&key, &value)?;
+        match arg.get_long() {
+            // the plugin name
+            None => argv.extend(values),
+            Some(long) if !arg.get_action().takes_values() => match value {
+                toml::Value::Boolean(true) => argv.push(format!("--{}", long)),
+                toml::Value::Boolean(false) => {}
+                _ => return Err(format!("{} must be true or false", key).into()),
+            },
+            Some(long) => {
+                for value in values {
+                    argv.push(format!("--{}={}", long, value));
+                }
+            }
+        }
+    }
+
+    Ok(argv)
+}
+
+// append the command line to the arguments from the configuration so that it's parsed once,
+// with defaults only applied to what neither of them sets; the last occurrence of a flag wins
+// and the plugin given on the command line replaces the configured one
+pub(crate) fn with_command_line(
+    mut argv: Vec<String>,
+    args: impl IntoIterator<Item = String>,
+) -> Result<Vec<String>, Error> {
+    let args: Vec<String> = args.into_iter().collect();
+    let matches = Options::command()
+        .try_get_matches_from(&args)
+        .map_err(|e| e.to_string())?;
+    if matches.value_source("plugin") == Some(ValueSource::CommandLine) {
+        // skip argv[0], everything else from the configuration is in the --long=value form
+        argv = argv
+            .into_iter()
+            .enumerate()
+            .filter(|(i, arg)| *i == 0 || arg.starts_with("--"))
+            .map(|(_, arg)| arg)
+            .collect();
+    }
+
+    argv.extend(args.into_iter().skip(1));
+    Ok(argv)
+}
+
+pub(crate) fn from_path(path: &str) -> Result<Vec<String>, Error> {
+    let data =
+        std::fs::read_to_string(path).map_err(|e| format!("could not read {}: {}", path, e))?;
+    to_argv(&data).map_err(|e| format!("{}: {}", path, e).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::{to_argv, with_command_line};
+    use crate::Options;
+
+    const CONFIG: &str = r#"
+plugin = "http"
+target = "https://example.com/login"
+concurrency = 4
+single-match = true
+quiet = false
+
+[http]
+http_success_codes = "200,302"
+http_headers = ["X-Foo: bar", "X-Bar: -baz"]
+
+[typos]
+cncurrency = 8
+"#;
+
+    #[test]
+    fn can_convert_config_to_argv() {
+        let argv = to_argv(CONFIG).unwrap();
+
+        assert_eq!(argv[0], "");
+        assert!(argv.contains(&"http".to_owned()));
+        assert!(argv.contains(&"--concurrency=4".to_owned()));
+        assert!(argv.contains(&"--single-match".to_owned()));
+        assert!(argv.contains(&"--http-headers=X-Bar: -baz".to_owned()));
+        assert!(!argv.iter().any(|arg| arg.contains("quiet")));
+        assert!(!argv.iter().any(|arg| arg.contains("cncurrency")));
+    }
+
+    #[test]
+    fn command_line_overrides_config() {
+        let options = Options::try_parse_from(to_argv(CONFIG).unwrap()).unwrap();
+        assert_eq!(options.plugin.as_deref(), Some("http"));
+        assert_eq!(options.concurrency, Some(4));
+        assert_eq!(options.http.http_headers.len(), 2);
+
+        let args = [
+            "legba",
+            "--config",
+            "legba.toml",
+            "--concurrency",
+            "16",
+            "--http-headers",
+            "X-Baz: qux",
+        ]
+        .map(String::from);
+        let argv = with_command_line(to_argv(CONFIG).unwrap(), args).unwrap();
+        let options = Options::try_parse_from(argv).unwrap();
+        assert_eq!(options.plugin.as_deref(), Some("http"));
+        assert_eq!(options.concurrency, Some(16));
+        assert_eq!(options.http.http_success_codes, "200,302");
+        // values of repeated flags add up
+        assert_eq!(options.http.http_headers.len(), 3);
+        assert!(options.single_match);
+    }
+
+    #[test]
+    fn command_line_plugin_replaces_config() {
+        let args = ["legba", "ssh", "--config", "legba.toml"].map(String::from);
+        let argv = with_command_line(to_argv(CONFIG).unwrap(), args).unwrap();
+        let options = Options::try_parse_from(argv).unwrap();
+        assert_eq!(options.plugin.as_deref(), Some("ssh"));
+        assert_eq!(options.http.http_success_codes, "200,302");
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        assert!(to_argv("single_match = \"yes\"").is_err());
+        assert!(to_argv("concurrency = [[1]]").is_err());
+        assert!(to_argv("not toml").is_err());
+    }
+}
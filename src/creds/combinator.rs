@@ -1,11 +1,13 @@
 use std::time;
 
+use ahash::HashSet;
 use clap::ValueEnum;
 use itertools::Itertools;
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    creds::{self, expression, iterator, Credentials},
+    creds::{self, expression, iterator, validation, Credentials, Decoder, Provenance},
     options::Options,
     session::Error,
 };
@@ -23,39 +25,131 @@ enum Mode {
     Multi,
     Combo,
     Single,
+    Validate,
 }
 
+// target, outer payload and inner payload, each payload with its index in its iterator
+type Element = (String, (usize, String), (usize, String));
+type Product = Box<dyn Iterator<Item = Element>>;
+
+// index of the payloads coming from --priority, they have no line in the base lists
+const PRIORITY_INDEX: usize = usize::MAX;
+
+// credentials are generated in stages, always in this order:
+//
+//   1. base lists: the --username and --password expressions (wordlists, globs, permutations,
+//      ranges, constants), or the --combinations file, or the --validate rows
+//   2. empty values: --add-empty-username / --add-empty-password prepend an empty element
+//   3. product: targets x outer payloads x inner payloads, the outer one picked by --iterate-by
+//   4. priority: a first product where the --priority entries take the place of the passwords
+//      (or of the single payload, or of the combinations), the product of stage 3 follows
+//      without them
+//   5. sample: --sample keeps each element with the given probability
+//
+// the search space size is the product of the sizes after stage 2, plus the size of the
+// priority product as an upper bound since the duplicates are only skipped while iterating,
+// and unknown once sampling
 pub(crate) struct Combinator {
     options: Options,
 
     mode: Mode,
     user_expr: creds::Expression,
     pass_expr: creds::Expression,
-    product: Box<dyn Iterator<Item = (String, String, String)>>,
+    // lines of the username and password wordlists that can't be decoded, still counted for
+    // the provenance
+    user_skipped: Vec<usize>,
+    pass_skipped: Vec<usize>,
+    product: Product,
+    // fraction of the product to attempt and the generator deciding which elements
+    sampler: Option<(f64, StdRng)>,
 
     wait: Option<time::Duration>,
+    // requests sent for each credentials, counted by --rate-limit
+    requests_per_attempt: usize,
     dispatched: usize,
     search_space_size: usize,
+    prioritized: bool,
+    username_format: Option<creds::UsernameFormat>,
+}
+
+// get the expression for the username or password from the environment, a literal value or
+// an expression
+fn payload_expression(
+    value: Option<&String>,
+    env_var: Option<&String>,
+    literal: bool,
+) -> Result<Expression, Error> {
+    if let Some(env_var) = env_var {
+        let value = std::env::var(env_var)
+            .map_err(|e| format!("could not read environment variable {}: {}", env_var, e))?;
+        Ok(Expression::Constant { value })
+    } else if let (true, Some(value)) = (literal, value) {
+        Ok(Expression::Constant {
+            value: value.to_owned(),
+        })
+    } else {
+        Ok(expression::parse_expression(value))
+    }
+}
+
+// get the line number of the payload at the given index if the expression reads from a wordlist
+fn line_number(expr: &Expression, index: usize, skipped: &[usize]) -> Option<usize> {
+    if index == PRIORITY_INDEX {
+        return None;
+    }
+
+    // the skipped lines before the payload move it further in the file
+    let physical = |index: usize| {
+        skipped.iter().fold(
+            index,
+            |line, &skip| if skip <= line { line + 1 } else { line },
+        ) + 1
+    };
+
+    match expr {
+        Expression::Wordlist { .. } => Some(physical(index)),
+        // the empty payload added by --add-empty-username/password comes before the wordlist
+        Expression::Multiple { expressions } => match expressions.as_slice() {
+            [Expression::Constant { value }, Expression::Wordlist { .. }] if value.is_empty() => {
+                (index > 0).then(|| physical(index - 1))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
 }
 
 impl Combinator {
     fn reset_from(&mut self, from: usize) {
         if from > 0 {
             let start = time::Instant::now();
-            while self.dispatched < from {
-                let _ = self.product.next();
+            while self.dispatched < from && self.next_product().is_some() {
                 self.dispatched += 1;
             }
             log::info!("restored from credential {} in {:?}", from, start.elapsed());
         }
     }
 
+    // get the next element of the product, skipping the ones left out by --sample
+    fn next_product(&mut self) -> Option<Element> {
+        loop {
+            let next = self.product.next()?;
+            let sampled = match &mut self.sampler {
+                Some((fraction, rng)) => rng.gen_bool(*fraction),
+                None => true,
+            };
+            if sampled {
+                return Some(next);
+            }
+        }
+    }
+
     fn combine_iterators(
         options: &Options,
         targets: Vec<String>,
         user_it: Box<dyn creds::Iterator>,
         pass_it: Option<Box<dyn creds::Iterator>>,
-    ) -> Box<dyn Iterator<Item = (String, String, String)>> {
+    ) -> Product {
         if let Some(pass_it) = pass_it {
             let (outer, inner) = match options.iterate_by {
                 IterationStrategy::User => (user_it, pass_it),
@@ -65,16 +159,16 @@ impl Combinator {
             Box::new(
                 targets
                     .into_iter()
-                    .cartesian_product(outer)
-                    .cartesian_product(inner)
+                    .cartesian_product(outer.enumerate())
+                    .cartesian_product(inner.enumerate())
                     .map(|((t, out), inn)| (t.to_owned(), out, inn)),
             )
         } else {
             Box::new(
                 targets
                     .into_iter()
-                    .cartesian_product(user_it)
-                    .map(|(t, payload)| (t.to_owned(), payload, "".to_owned())),
+                    .cartesian_product(user_it.enumerate())
+                    .map(|(t, payload)| (t.to_owned(), payload, (0, "".to_owned()))),
             )
         }
     }
@@ -85,6 +179,7 @@ impl Combinator {
         override_expr: Option<Expression>,
     ) -> Result<Self, Error> {
         let mode = Mode::Single;
+        let decoder = Decoder::new(options.encoding.as_ref(), options.encoding_errors)?;
         let dispatched = 0;
         let wait = if options.wait > 0 {
             Some(time::Duration::from_millis(options.wait as u64))
@@ -95,13 +190,32 @@ impl Combinator {
         // get either override, username or password
         let payload_expr = if let Some(override_expr) = override_expr {
             override_expr
-        } else if options.username.is_some() {
-            expression::parse_expression(options.username.as_ref())
+        } else if options.username.is_some() || options.username_env.is_some() {
+            let expr = payload_expression(
+                options.username.as_ref(),
+                options.username_env.as_ref(),
+                options.literal,
+            )?;
+            if options.add_empty_username {
+                expr.with_empty()
+            } else {
+                expr
+            }
         } else {
-            expression::parse_expression(options.password.as_ref())
+            let expr = payload_expression(
+                options.password.as_ref(),
+                options.password_env.as_ref(),
+                options.literal,
+            )?;
+            if options.add_empty_password {
+                expr.with_empty()
+            } else {
+                expr
+            }
         };
-        let payload_it = iterator::new(payload_expr.clone())?;
-        let search_space_size: usize = targets.len() * payload_it.search_space_size();
+        let payload_it = iterator::with_decoder(payload_expr.clone(), decoder)?;
+        let user_skipped = payload_it.skipped_lines().to_vec();
+        let search_space_size = targets.len().saturating_mul(payload_it.search_space_size());
         let product = Self::combine_iterators(&options, targets.to_owned(), payload_it, None);
 
         Ok(Self {
@@ -110,13 +224,24 @@ impl Combinator {
             mode,
             user_expr: payload_expr,
             pass_expr: creds::Expression::default(),
+            user_skipped,
+            pass_skipped: vec![],
             product,
+            sampler: None,
             search_space_size,
+            requests_per_attempt: 1,
             dispatched,
+            prioritized: false,
+            username_format: None,
         })
     }
 
-    fn for_double_payload(targets: &Vec<String>, options: Options) -> Result<Self, Error> {
+    fn for_double_payload(
+        targets: &Vec<String>,
+        options: Options,
+        override_expr: Option<Expression>,
+    ) -> Result<Self, Error> {
+        let decoder = Decoder::new(options.encoding.as_ref(), options.encoding_errors)?;
         let dispatched = 0;
         let wait = if options.wait > 0 {
             Some(time::Duration::from_millis(options.wait as u64))
@@ -130,10 +255,12 @@ impl Combinator {
             let combo_expr = expression::Expression::Wordlist {
                 filename: combo_filename.to_owned(),
             };
-            let combo_it = iterator::new(combo_expr.clone())?;
+            let combo_it = iterator::with_decoder(combo_expr.clone(), decoder)?;
             let pass_expr = combo_expr.clone();
+            let user_skipped = combo_it.skipped_lines().to_vec();
+            let pass_skipped = user_skipped.clone();
 
-            let search_space_size = targets.len() * combo_it.search_space_size();
+            let search_space_size = targets.len().saturating_mul(combo_it.search_space_size());
             let product = Self::combine_iterators(&options, targets.to_owned(), combo_it, None);
 
             Ok(Self {
@@ -142,19 +269,48 @@ impl Combinator {
                 wait,
                 user_expr: combo_expr,
                 pass_expr,
+                user_skipped,
+                pass_skipped,
                 product,
+                sampler: None,
                 search_space_size,
+                requests_per_attempt: 1,
                 dispatched,
+                prioritized: false,
+                username_format: None,
             })
         } else {
             // perform the cartesian product of all usernames and passwords from distinct sources
             let mode = Mode::Multi;
-            let user_expr = expression::parse_expression(options.username.as_ref());
-            let user_it = iterator::new(user_expr.clone())?;
-            let pass_expr = expression::parse_expression(options.password.as_ref());
-            let pass_it = iterator::new(pass_expr.clone())?;
-            let search_space_size =
-                targets.len() * user_it.search_space_size() * pass_it.search_space_size();
+            let mut user_expr = payload_expression(
+                options.username.as_ref(),
+                options.username_env.as_ref(),
+                options.literal,
+            )?;
+            if options.add_empty_username {
+                user_expr = user_expr.with_empty();
+            }
+            let user_it = iterator::with_decoder(user_expr.clone(), decoder)?;
+            // plugins can provide their own passwords (like the ssh keys)
+            let mut pass_expr = if let Some(override_expr) = override_expr {
+                override_expr
+            } else {
+                payload_expression(
+                    options.password.as_ref(),
+                    options.password_env.as_ref(),
+                    options.literal,
+                )?
+            };
+            if options.add_empty_password {
+                pass_expr = pass_expr.with_empty();
+            }
+            let pass_it = iterator::with_decoder(pass_expr.clone(), decoder)?;
+            let user_skipped = user_it.skipped_lines().to_vec();
+            let pass_skipped = pass_it.skipped_lines().to_vec();
+            let search_space_size = targets
+                .len()
+                .saturating_mul(user_it.search_space_size())
+                .saturating_mul(pass_it.search_space_size());
             let product =
                 Self::combine_iterators(&options, targets.to_owned(), user_it, Some(pass_it));
 
@@ -164,13 +320,116 @@ impl Combinator {
                 wait,
                 user_expr,
                 pass_expr,
+                user_skipped,
+                pass_skipped,
                 product,
+                sampler: None,
                 search_space_size,
+                requests_per_attempt: 1,
                 dispatched,
+                prioritized: false,
+                username_format: None,
             })
         }
     }
 
+    fn for_validation(options: Options) -> Result<Self, Error> {
+        let mode = Mode::Validate;
+        let dispatched = 0;
+        let wait = if options.wait > 0 {
+            Some(time::Duration::from_millis(options.wait as u64))
+        } else {
+            None
+        };
+
+        // every row already has its own target, no product to perform
+        let filename = options.validate.clone().unwrap_or_default();
+        let creds: Vec<_> = validation::load(&filename)?
+            .into_iter()
+            .map(|creds| (creds.target, (0, creds.username), (0, creds.password)))
+            .collect();
+        let expr = expression::Expression::Wordlist { filename };
+
+        Ok(Self {
+            options,
+            mode,
+            wait,
+            user_expr: expr.clone(),
+            pass_expr: expr,
+            user_skipped: vec![],
+            pass_skipped: vec![],
+            search_space_size: creds.len(),
+            product: Box::new(creds.into_iter()),
+            sampler: None,
+            requests_per_attempt: 1,
+            dispatched,
+            prioritized: false,
+            username_format: None,
+        })
+    }
+
+    // attempt the --priority entries first on every target, then the base product without them
+    fn prioritize(&mut self, targets: &[String], filename: &str) -> Result<(), Error> {
+        let decoder = Decoder::new(self.options.encoding.as_ref(), self.options.encoding_errors)?;
+        let priority_it = iterator::with_decoder(
+            Expression::Wordlist {
+                filename: filename.to_owned(),
+            },
+            decoder,
+        )?;
+        let entries: HashSet<String> = priority_it.clone().collect();
+
+        let (priority, size) = match self.mode {
+            Mode::Multi => {
+                let user_it = iterator::with_decoder(self.user_expr.clone(), decoder)?;
+                let size = targets
+                    .len()
+                    .saturating_mul(user_it.search_space_size())
+                    .saturating_mul(priority_it.search_space_size());
+                let product = Self::combine_iterators(
+                    &self.options,
+                    targets.to_owned(),
+                    user_it,
+                    Some(priority_it),
+                );
+                (product, size)
+            }
+            _ => {
+                let size = targets
+                    .len()
+                    .saturating_mul(priority_it.search_space_size());
+                let product =
+                    Self::combine_iterators(&self.options, targets.to_owned(), priority_it, None);
+                (product, size)
+            }
+        };
+
+        // the passwords are the inner payloads when iterating by user
+        let inner = matches!(self.mode, Mode::Multi)
+            && matches!(self.options.iterate_by, IterationStrategy::User);
+
+        let priority = priority.map(
+            move |(target, (outer_idx, outer), (inner_idx, inner_payload))| {
+                if inner {
+                    (target, (outer_idx, outer), (PRIORITY_INDEX, inner_payload))
+                } else {
+                    (target, (PRIORITY_INDEX, outer), (inner_idx, inner_payload))
+                }
+            },
+        );
+        let base = std::mem::replace(&mut self.product, Box::new(std::iter::empty())).filter(
+            move |(_, (_, outer), (_, inner_payload))| {
+                !entries.contains(if inner { inner_payload } else { outer })
+            },
+        );
+
+        self.product = Box::new(priority.chain(base));
+        self.search_space_size = self.search_space_size.saturating_add(size);
+        self.prioritized = true;
+
+        Ok(())
+    }
+
     pub fn create(
         targets: &Vec<String>,
         options: Options,
@@ -178,22 +437,90 @@ impl Combinator {
         single: bool,
         override_expression: Option<Expression>,
     ) -> Result<Self, Error> {
-        let mut combinator = if single {
+        let overridden = override_expression.is_some();
+        let mut combinator = if options.validate.is_some() {
+            Self::for_validation(options)?
+        } else if single {
             Self::for_single_payload(targets, options, override_expression)?
         } else {
-            Self::for_double_payload(targets, options)?
+            Self::for_double_payload(targets, options, override_expression)?
         };
 
+        if let Some(filename) = combinator.options.priority.clone() {
+            if combinator.options.validate.is_some() {
+                log::warn!("--priority is ignored when validating credentials");
+            } else if overridden {
+                log::warn!("the plugin provides its own payloads, --priority is ignored");
+            } else {
+                combinator.prioritize(targets, &filename)?;
+            }
+        }
+
+        if let Some(format) = creds::UsernameFormat::from_options(&combinator.options)? {
+            if combinator.options.validate.is_some() {
+                log::warn!("--username-format is ignored when validating credentials");
+            } else if single {
+                log::warn!("--username-format is ignored by single payload plugins");
+            } else {
+                format.check_targets(targets);
+                combinator.username_format = Some(format);
+            }
+        }
+
+        // the same seed always selects the same combinations
+        if let Some(percent) = combinator.options.sample {
+            let fraction = percent / 100.0;
+            let seed = combinator.options.seed.unwrap_or_else(rand::random);
+            if combinator.search_space_size != usize::MAX {
+                log::info!(
+                    "sampling about {} of {} combinations with seed {}",
+                    (combinator.search_space_size as f64 * fraction).ceil() as usize,
+                    combinator.search_space_size,
+                    seed
+                );
+            } else {
+                log::info!(
+                    "sampling {}% of the combinations with seed {}",
+                    percent,
+                    seed
+                );
+            }
+
+            combinator.sampler = Some((fraction, StdRng::seed_from_u64(seed)));
+            // the actual number is only known once all combinations are generated, an estimate
+            // lower than that would end the session early
+            combinator.search_space_size = usize::MAX;
+        }
+
         // restore from last state if needed
         combinator.reset_from(from);
 
         Ok(combinator)
     }
 
+    // the total number of combinations, usize::MAX if too big to be known
     pub fn search_space_size(&self) -> usize {
         self.search_space_size
     }
 
+    // number of credentials generated so far
+    pub fn dispatched(&self) -> usize {
+        self.dispatched
+    }
+
+    pub fn set_requests_per_attempt(&mut self, requests: usize) {
+        self.requests_per_attempt = requests.max(1);
+    }
+
+    pub fn is_sampling(&self) -> bool {
+        self.sampler.is_some()
+    }
+
+    // whether the search space size is just an estimate of the credentials to be generated
+    pub fn is_estimate(&self) -> bool {
+        self.is_sampling() || self.prioritized
+    }
+
     pub fn username_expression(&self) -> &creds::Expression {
         &self.user_expr
     }
@@ -203,51 +530,115 @@ impl Combinator {
     }
 }
 
-impl Iterator for Combinator {
-    type Item = Credentials;
-
-    fn next(&mut self) -> Option<Self::Item> {
+impl Combinator {
+    // get the next credentials along with the wordlist lines their payloads come from
+    pub fn next_with_provenance(&mut self) -> Option<(Credentials, Provenance)> {
         // we're done
-        if let Some((target, outer, inner)) = self.product.next() {
-            // check if we have to rate limit
-            if self.options.rate_limit > 0 && self.dispatched % self.options.rate_limit == 0 {
-                std::thread::sleep(time::Duration::from_secs(1));
+        let (target, (outer_idx, outer), (inner_idx, inner)) = self.next_product()?;
+
+        // check if we have to rate limit, once for every multiple of the limit reached by the
+        // requests of these credentials
+        if self.options.rate_limit > 0 {
+            let limit = self.options.rate_limit;
+            let sent = self.dispatched.saturating_mul(self.requests_per_attempt);
+            let seconds = sent
+                .saturating_add(self.requests_per_attempt)
+                .div_ceil(limit)
+                - sent.div_ceil(limit);
+            if seconds > 0 {
+                std::thread::sleep(time::Duration::from_secs(seconds as u64));
             }
+        }
+
+        // check if we have a wait time
+        if let Some(wait) = self.wait {
+            std::thread::sleep(wait);
+        }
 
-            // check if we have a wait time
-            if let Some(wait) = self.wait {
-                std::thread::sleep(wait);
+        let ((username, user_idx), (password, pass_idx)) = match self.mode {
+            Mode::Multi | Mode::Single => match self.options.iterate_by {
+                IterationStrategy::User => ((outer, outer_idx), (inner, inner_idx)),
+                IterationStrategy::Password => ((inner, inner_idx), (outer, outer_idx)),
+            },
+            Mode::Validate => ((outer, outer_idx), (inner, inner_idx)),
+            Mode::Combo => {
+                if let Some((user, pass)) = outer.split_once(&self.options.separator) {
+                    ((user.to_owned(), outer_idx), (pass.to_owned(), outer_idx))
+                } else {
+                    panic!(
+                        "line '{}' of {} can't be splitted with '{}'",
+                        outer,
+                        self.options.combinations.as_ref().unwrap(),
+                        &self.options.separator,
+                    );
+                }
             }
+        };
 
-            let (username, password) = match self.mode {
-                Mode::Multi | Mode::Single => match self.options.iterate_by {
-                    IterationStrategy::User => (outer, inner),
-                    IterationStrategy::Password => (inner, outer),
-                },
-                Mode::Combo => {
-                    if let Some((user, pass)) = outer.split_once(&self.options.separator) {
-                        (user.to_owned(), pass.to_owned())
-                    } else {
-                        panic!(
-                            "line '{}' of {} can't be splitted with '{}'",
-                            outer,
-                            self.options.combinations.as_ref().unwrap(),
-                            &self.options.separator,
-                        );
-                    }
+        let provenance = match self.mode {
+            Mode::Multi => Provenance {
+                username_line: line_number(&self.user_expr, user_idx, &self.user_skipped),
+                password_line: line_number(&self.pass_expr, pass_idx, &self.pass_skipped),
+                ..Default::default()
+            },
+            // the payload ends up either in the username or in the password
+            Mode::Single => {
+                let line = line_number(&self.user_expr, outer_idx, &self.user_skipped);
+                match self.options.iterate_by {
+                    IterationStrategy::User => Provenance {
+                        username_line: line,
+                        ..Default::default()
+                    },
+                    IterationStrategy::Password => Provenance {
+                        password_line: line,
+                        ..Default::default()
+                    },
                 }
-            };
+            }
+            // both come from the same line, --priority entries have none
+            Mode::Combo => Provenance {
+                username_line: line_number(&self.user_expr, user_idx, &self.user_skipped),
+                password_line: line_number(&self.pass_expr, pass_idx, &self.pass_skipped),
+                ..Default::default()
+            },
+            // commented lines are skipped when loading, the row index is not the line number
+            Mode::Validate => Provenance::default(),
+        };
+
+        // the wordlists hold the bare usernames
+        let (username, provenance) = match self
+            .username_format
+            .as_ref()
+            .and_then(|format| format.apply(&username, &target))
+        {
+            Some(formatted) => (
+                formatted,
+                Provenance {
+                    raw_username: Some(username),
+                    ..provenance
+                },
+            ),
+            None => (username, provenance),
+        };
 
-            self.dispatched += 1;
+        self.dispatched += 1;
 
-            Some(Credentials {
+        Some((
+            Credentials {
                 target,
                 username,
                 password,
-            })
-        } else {
-            None
-        }
+            },
+            provenance,
+        ))
+    }
+}
+
+impl Iterator for Combinator {
+    type Item = Credentials;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_with_provenance().map(|(creds, _)| creds)
     }
 }
 
@@ -256,7 +647,7 @@ mod tests {
     use std::fs::File;
     use std::io::Write;
 
-    use crate::creds::{Credentials, Expression, IterationStrategy};
+    use crate::creds::{Credentials, Expression, IterationStrategy, Provenance};
 
     use super::Combinator;
 
@@ -290,6 +681,54 @@ mod tests {
         assert_eq!(expected, got);
     }
 
+    #[test]
+    fn attempts_priority_entries_first() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let tmppath = tmpdir.path().join("priority.txt");
+        let mut tmpfile = File::create(&tmppath).unwrap();
+        writeln!(tmpfile, "pp\nsecret").unwrap();
+        tmpfile.flush().unwrap();
+        drop(tmpfile);
+
+        let targets = vec!["foo".to_owned(), "bar".to_owned()];
+        let mut opts = crate::Options::default();
+        opts.iterate_by = IterationStrategy::Password;
+        opts.username = Some("#1-2:u".to_owned());
+        opts.password = Some("#1-2:p".to_owned());
+        opts.priority = Some(tmppath.to_str().unwrap().to_owned());
+
+        let mut comb = Combinator::create(&targets, opts, 0, false, None).unwrap();
+        assert!(comb.is_estimate());
+
+        let mut got = vec![];
+        while let Some((creds, provenance)) = comb.next_with_provenance() {
+            assert_eq!(provenance.password_line, None);
+            got.push(format!(
+                "{} {} {}",
+                creds.target, creds.username, creds.password
+            ));
+        }
+
+        assert_eq!(
+            got,
+            vec![
+                "foo u pp",
+                "foo uu pp",
+                "foo u secret",
+                "foo uu secret",
+                "bar u pp",
+                "bar uu pp",
+                "bar u secret",
+                "bar uu secret",
+                // the base list without the priority entries
+                "foo u p",
+                "foo uu p",
+                "bar u p",
+                "bar uu p",
+            ]
+        );
+    }
+
     #[test]
     fn can_handle_user_iteration_strategy() {
         let targets = vec!["foo".to_owned()];
@@ -401,6 +840,61 @@ mod tests {
         assert_eq!(by_user, by_pass);
     }
 
+    #[test]
+    fn can_add_empty_password() {
+        let targets = vec!["foo".to_owned()];
+        let mut opts = crate::Options::default();
+
+        opts.username = Some("admin".to_owned());
+        opts.password = Some("#1-1:p".to_owned());
+        opts.add_empty_password = true;
+
+        let comb = Combinator::create(&targets, opts, 0, false, None).unwrap();
+        assert_eq!(comb.search_space_size(), 2);
+
+        let expected = vec![
+            Credentials {
+                target: "foo".to_owned(),
+                username: "admin".to_owned(),
+                password: "".to_owned(),
+            },
+            Credentials {
+                target: "foo".to_owned(),
+                username: "admin".to_owned(),
+                password: "p".to_owned(),
+            },
+        ];
+        let got: Vec<Credentials> = comb.collect();
+
+        assert_eq!(expected, got);
+    }
+
+    #[test]
+    fn can_use_literal_and_env_values() {
+        let targets = vec!["foo".to_owned()];
+        let mut opts = crate::Options::default();
+
+        std::env::set_var("LEGBA_TEST_PASSWORD", "s3cr3t");
+
+        // would be a permutations expression otherwise
+        opts.username = Some("#1-2".to_owned());
+        opts.literal = true;
+        opts.password_env = Some("LEGBA_TEST_PASSWORD".to_owned());
+
+        let comb = Combinator::create(&targets, opts, 0, false, None).unwrap();
+        assert_eq!(comb.search_space_size(), 1);
+
+        let got: Vec<Credentials> = comb.collect();
+        assert_eq!(
+            got,
+            vec![Credentials {
+                target: "foo".to_owned(),
+                username: "#1-2".to_owned(),
+                password: "s3cr3t".to_owned(),
+            }]
+        );
+    }
+
     #[test]
     fn can_handle_multiple_targets_and_double_credentials() {
         let targets = vec!["foo".to_owned(), "bar".to_owned()];
@@ -640,4 +1134,149 @@ mod tests {
         assert_eq!(got.len(), tot);
         assert_eq!(expected, got);
     }
+
+    #[test]
+    fn can_handle_validate_mode() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let tmppath = tmpdir.path().join("creds.csv");
+        std::fs::write(&tmppath, "10.0.0.1,root,toor\n10.0.0.2:2222,admin,admin\n").unwrap();
+
+        let mut opts = crate::Options::default();
+        opts.validate = Some(tmppath.to_str().unwrap().to_owned());
+
+        let targets = vec!["10.0.0.1".to_owned(), "10.0.0.2:2222".to_owned()];
+        let comb = Combinator::create(&targets, opts, 0, false, None).unwrap();
+        assert_eq!(comb.search_space_size(), 2);
+
+        let got: Vec<Credentials> = comb.collect();
+        assert_eq!(
+            got,
+            vec![
+                Credentials {
+                    target: "10.0.0.1".to_owned(),
+                    username: "root".to_owned(),
+                    password: "toor".to_owned(),
+                },
+                Credentials {
+                    target: "10.0.0.2:2222".to_owned(),
+                    username: "admin".to_owned(),
+                    password: "admin".to_owned(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn provides_wordlist_lines() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let users = tmpdir.path().join("users.txt");
+        // the undecodable line is skipped but still counted
+        std::fs::write(&users, b"root\n\xff\nadmin\n").unwrap();
+
+        let mut opts = crate::Options::default();
+        opts.username = Some(users.to_str().unwrap().to_owned());
+        opts.password = Some("#1-2:p".to_owned());
+        opts.add_empty_username = true;
+
+        let targets = vec!["foo".to_owned()];
+        let mut comb = Combinator::create(&targets, opts, 0, false, None).unwrap();
+
+        let mut got = vec![];
+        while let Some((creds, provenance)) = comb.next_with_provenance() {
+            got.push((creds.username, provenance));
+        }
+
+        let lines = |username_line| Provenance {
+            username_line,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            got,
+            vec![
+                ("".to_owned(), lines(None)),
+                ("".to_owned(), lines(None)),
+                ("root".to_owned(), lines(Some(1))),
+                ("root".to_owned(), lines(Some(1))),
+                ("admin".to_owned(), lines(Some(3))),
+                ("admin".to_owned(), lines(Some(3))),
+            ]
+        );
+    }
+
+    #[test]
+    fn formats_usernames_by_target() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let domains = tmpdir.path().join("domains.txt");
+        std::fs::write(&domains, "dc01=LAB\n").unwrap();
+
+        let mut opts = crate::Options::default();
+        opts.username = Some("admin".to_owned());
+        opts.password = Some("s3cret".to_owned());
+        opts.username_format = Some("{domain}\\{user}".to_owned());
+        opts.username_domain = Some("CORP".to_owned());
+        opts.username_domains = Some(domains.to_str().unwrap().to_owned());
+
+        let targets = vec!["dc01:445".to_owned(), "dc02:445".to_owned()];
+        let mut comb = Combinator::create(&targets, opts, 0, false, None).unwrap();
+
+        let mut got = vec![];
+        while let Some((creds, provenance)) = comb.next_with_provenance() {
+            got.push((creds.username, provenance.raw_username));
+        }
+
+        assert_eq!(
+            got,
+            vec![
+                ("LAB\\admin".to_owned(), Some("admin".to_owned())),
+                ("CORP\\admin".to_owned(), Some("admin".to_owned())),
+            ]
+        );
+    }
+
+    #[test]
+    fn sampling_is_reproducible() {
+        let targets = vec!["foo".to_owned()];
+        let mut opts = crate::Options::default();
+        opts.username = Some("[1-100]".to_owned());
+        opts.password = Some("[1-100]".to_owned());
+        opts.sample = Some(10.0);
+        opts.seed = Some(42);
+
+        let comb = Combinator::create(&targets, opts.clone(), 0, false, None).unwrap();
+        assert_eq!(comb.search_space_size(), usize::MAX);
+
+        let first: Vec<Credentials> = comb.collect();
+        assert!(first.len() > 800 && first.len() < 1200);
+
+        let second: Vec<Credentials> = Combinator::create(&targets, opts.clone(), 0, false, None)
+            .unwrap()
+            .collect();
+        assert_eq!(first, second);
+
+        // restoring a session skips what was already sampled
+        let restored: Vec<Credentials> = Combinator::create(&targets, opts, 10, false, None)
+            .unwrap()
+            .collect();
+        assert_eq!(&first[10..], &restored[..]);
+    }
+
+    #[test]
+    fn empty_values_are_sampled_like_the_others() {
+        let targets = vec!["foo".to_owned()];
+        let mut opts = crate::Options::default();
+
+        opts.username = Some("admin".to_owned());
+        opts.password = Some("[1-3]".to_owned());
+        opts.add_empty_password = true;
+        opts.sample = Some(100.0);
+        opts.seed = Some(1);
+
+        let got: Vec<String> = Combinator::create(&targets, opts, 0, false, None)
+            .unwrap()
+            .map(|creds| creds.password)
+            .collect();
+
+        assert_eq!(got, vec!["", "1", "2", "3"]);
+    }
 }
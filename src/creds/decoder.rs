@@ -0,0 +1,105 @@
+use clap::ValueEnum;
+use encoding_rs::{Encoding, UTF_8};
+use serde::{Deserialize, Serialize};
+
+use crate::session::Error;
+
+// what to do with wordlist lines that are not valid in the selected encoding
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) enum InvalidSequences {
+    // ignore the line
+    #[default]
+    Skip,
+    // use the unicode replacement character for the invalid bytes
+    Replace,
+    // refuse to load the wordlist
+    Error,
+}
+
+// decodes the raw lines of a wordlist to strings
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Decoder {
+    encoding: &'static Encoding,
+    invalid: InvalidSequences,
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self {
+            encoding: UTF_8,
+            invalid: InvalidSequences::default(),
+        }
+    }
+}
+
+impl Decoder {
+    pub fn new(label: Option<&String>, invalid: InvalidSequences) -> Result<Self, Error> {
+        let encoding = match label {
+            None => UTF_8,
+            Some(label) => Encoding::for_label(label.trim().as_bytes())
+                .ok_or_else(|| format!("unknown encoding '{}'", label))?,
+        };
+
+        // lines are split on the \n byte, which doesn't work for encodings like UTF-16
+        if !encoding.is_ascii_compatible() {
+            return Err(format!("encoding {} is not supported", encoding.name()).into());
+        }
+
+        Ok(Self { encoding, invalid })
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.encoding.name()
+    }
+
+    pub fn invalid_sequences(&self) -> InvalidSequences {
+        self.invalid
+    }
+
+    // None if the line contains invalid sequences and these are not replaced
+    pub fn decode(&self, raw: &[u8]) -> Option<String> {
+        // same as BufRead::lines
+        let raw = raw.strip_suffix(b"\n").unwrap_or(raw);
+        let raw = raw.strip_suffix(b"\r").unwrap_or(raw);
+
+        if self.invalid == InvalidSequences::Replace {
+            Some(
+                self.encoding
+                    .decode_without_bom_handling(raw)
+                    .0
+                    .into_owned(),
+            )
+        } else {
+            self.encoding
+                .decode_without_bom_handling_and_without_replacement(raw)
+                .map(|line| line.into_owned())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Decoder, InvalidSequences};
+
+    #[test]
+    fn can_decode_latin1() {
+        let decoder = Decoder::new(Some(&"latin1".to_owned()), InvalidSequences::Error).unwrap();
+        assert_eq!(decoder.decode(b"caf\xe9\r\n"), Some("café".to_owned()));
+    }
+
+    #[test]
+    fn handles_invalid_sequences() {
+        let skip = Decoder::default();
+        assert_eq!(skip.decode(b"caf\xe9"), None);
+        assert_eq!(skip.decode("café".as_bytes()), Some("café".to_owned()));
+
+        let replace = Decoder::new(None, InvalidSequences::Replace).unwrap();
+        assert_eq!(replace.decode(b"caf\xe9"), Some("caf\u{FFFD}".to_owned()));
+    }
+
+    #[test]
+    fn rejects_unknown_or_unsupported_encodings() {
+        assert!(Decoder::new(Some(&"klingon".to_owned()), InvalidSequences::Skip).is_err());
+        assert!(Decoder::new(Some(&"utf-16le".to_owned()), InvalidSequences::Skip).is_err());
+    }
+}
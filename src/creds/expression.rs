@@ -72,6 +72,26 @@ impl Expression {
     pub fn is_default(&self) -> bool {
         self == &Expression::default()
     }
+
+    // prepend an empty string to this expression, unless already present
+    pub fn with_empty(self) -> Expression {
+        let empty = Expression::Constant {
+            value: String::new(),
+        };
+
+        match self {
+            Expression::Constant { ref value } if value.is_empty() => self,
+            Expression::Multiple { mut expressions } => {
+                if !expressions.contains(&empty) {
+                    expressions.insert(0, empty);
+                }
+                Expression::Multiple { expressions }
+            }
+            expr => Expression::Multiple {
+                expressions: vec![empty, expr],
+            },
+        }
+    }
 }
 
 impl Default for Expression {
@@ -383,6 +403,31 @@ mod tests {
         )
     }
 
+    #[test]
+    fn can_add_empty() {
+        let empty = Expression::Constant {
+            value: "".to_owned(),
+        };
+        let admin = Expression::Constant {
+            value: "admin".to_owned(),
+        };
+
+        assert_eq!(
+            admin.clone().with_empty(),
+            Expression::Multiple {
+                expressions: vec![empty.clone(), admin.clone()]
+            }
+        );
+        // no duplicates
+        assert_eq!(
+            admin.clone().with_empty().with_empty(),
+            Expression::Multiple {
+                expressions: vec![empty.clone(), admin]
+            }
+        );
+        assert_eq!(empty.clone().with_empty(), empty);
+    }
+
     #[test]
     fn can_parse_multiple_with_spaces() {
         let expr = "1, [3-4], 9 ";
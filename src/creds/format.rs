@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+
+use lazy_regex::{lazy_regex, Lazy};
+use regex::{Captures, Regex};
+
+use crate::session::Error;
+use crate::utils::parse_target;
+use crate::Options;
+
+static PLACEHOLDER_PARSER: Lazy<Regex> = lazy_regex!(r"\{(user|domain)\}");
+
+// rewrites the usernames for the target they're attempted against, like {domain}\{user}
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct UsernameFormat {
+    template: String,
+    default_domain: Option<String>,
+    // by target, either with or without the port
+    domains: HashMap<String, String>,
+}
+
+// target=domain lines, empty lines and comments are skipped
+fn parse_domains(data: &str) -> Result<HashMap<String, String>, Error> {
+    let mut domains = HashMap::new();
+    for (idx, line) in data.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let Some((target, domain)) = line.split_once('=') else {
+            return Err(format!(
+                "line {} of --username-domains is not in the target=domain format",
+                idx + 1
+            )
+            .into());
+        };
+        domains.insert(target.trim().to_owned(), domain.trim().to_owned());
+    }
+    Ok(domains)
+}
+
+impl UsernameFormat {
+    pub fn from_options(options: &Options) -> Result<Option<Self>, Error> {
+        let Some(template) = options.username_format.as_ref() else {
+            if options.username_domain.is_some() || options.username_domains.is_some() {
+                log::warn!("--username-domain and --username-domains have no effect without --username-format");
+            }
+            return Ok(None);
+        };
+
+        if !template.contains("{user}") {
+            return Err("--username-format must contain the {user} placeholder".into());
+        }
+
+        let domains = if let Some(path) = options.username_domains.as_ref() {
+            parse_domains(
+                &std::fs::read_to_string(path)
+                    .map_err(|e| format!("could not read {}: {}", path, e))?,
+            )?
+        } else {
+            HashMap::new()
+        };
+
+        Ok(Some(Self {
+            template: template.to_owned(),
+            default_domain: options.username_domain.clone(),
+            domains,
+        }))
+    }
+
+    fn domain(&self, target: &str) -> Option<&String> {
+        self.domains
+            .get(target)
+            .or_else(|| {
+                parse_target(target, 0)
+                    .ok()
+                    .and_then(|(host, _)| self.domains.get(&host))
+            })
+            .or(self.default_domain.as_ref())
+    }
+
+    // report the targets the usernames can't be formatted for, as they're left as they are
+    pub fn check_targets(&self, targets: &[String]) {
+        if !self.template.contains("{domain}") {
+            return;
+        }
+
+        let missing: Vec<&str> = targets
+            .iter()
+            .filter(|target| self.domain(target).is_none())
+            .map(|target| target.as_str())
+            .collect();
+        if !missing.is_empty() {
+            log::warn!(
+                "no --username-domain for {}, the usernames are not formatted for them",
+                missing.join(", ")
+            );
+        }
+    }
+
+    // None if the username is left as it is
+    pub fn apply(&self, username: &str, target: &str) -> Option<String> {
+        // the empty username of --add-empty-username must stay empty
+        if username.is_empty() {
+            return None;
+        }
+
+        let domain = if self.template.contains("{domain}") {
+            self.domain(target)?.as_str()
+        } else {
+            ""
+        };
+
+        // single pass, so that a username or domain containing a placeholder is taken as it is
+        let formatted = PLACEHOLDER_PARSER
+            .replace_all(&self.template, |caps: &Captures| match &caps[1] {
+                "user" => username.to_owned(),
+                _ => domain.to_owned(),
+            })
+            .into_owned();
+
+        (formatted != username).then_some(formatted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UsernameFormat;
+
+    fn format(template: &str, domain: Option<&str>, domains: &str) -> UsernameFormat {
+        UsernameFormat {
+            template: template.to_owned(),
+            default_domain: domain.map(|d| d.to_owned()),
+            domains: super::parse_domains(domains).unwrap(),
+        }
+    }
+
+    #[test]
+    fn can_format_usernames() {
+        let format = format(
+            "{domain}\\{user}",
+            Some("CORP"),
+            "# comment\n10.0.0.1=LAB\n10.0.0.2:445 = DEV\n",
+        );
+
+        assert_eq!(
+            format.apply("admin", "10.0.0.1:445"),
+            Some("LAB\\admin".to_owned())
+        );
+        assert_eq!(
+            format.apply("admin", "10.0.0.2:445"),
+            Some("DEV\\admin".to_owned())
+        );
+        assert_eq!(
+            format.apply("admin", "10.0.0.3"),
+            Some("CORP\\admin".to_owned())
+        );
+        assert_eq!(format.apply("", "10.0.0.1"), None);
+    }
+
+    #[test]
+    fn unmapped_targets_are_left_alone() {
+        let format = format("{user}@{domain}", None, "dc01=corp.local");
+        assert_eq!(
+            format.apply("admin", "dc01:389"),
+            Some("admin@corp.local".to_owned())
+        );
+        assert_eq!(format.apply("admin", "dc02:389"), None);
+    }
+
+    #[test]
+    fn placeholders_in_values_are_not_expanded() {
+        let format = format("{domain}\\{user}", Some("{user}"), "");
+        assert_eq!(
+            format.apply("{domain}", "10.0.0.1"),
+            Some("{user}\\{domain}".to_owned())
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_domains() {
+        assert!(super::parse_domains("10.0.0.1 LAB").is_err());
+    }
+}
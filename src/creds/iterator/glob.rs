@@ -10,7 +10,7 @@ impl Glob {
     pub fn new(pattern: String) -> Result<Self, Error> {
         // validate the pattern and count the elements first
         let paths = match glob::glob(&pattern) {
-            Err(e) => return Err(e.to_string()),
+            Err(e) => return Err(e.to_string().into()),
             Ok(paths) => paths,
         };
         let elements = paths.count();
@@ -1,3 +1,4 @@
+use crate::creds::decoder::Decoder;
 use crate::creds::expression::Expression;
 use crate::session::Error;
 
@@ -12,6 +13,11 @@ mod wordlist;
 // https://stackoverflow.com/questions/30353462/how-to-clone-a-struct-storing-a-boxed-trait-object
 pub(crate) trait Iterator: IteratorClone + std::iter::Iterator<Item = String> {
     fn search_space_size(&self) -> usize;
+
+    // 0-based lines of the wordlist that were skipped because they can't be decoded
+    fn skipped_lines(&self) -> &[usize] {
+        &[]
+    }
 }
 
 pub(crate) trait IteratorClone {
@@ -24,14 +30,20 @@ impl Clone for Box<dyn Iterator> {
     }
 }
 
+#[cfg(test)]
 pub(crate) fn new(expr: Expression) -> Result<Box<dyn Iterator>, Error> {
+    with_decoder(expr, Decoder::default())
+}
+
+// same as new, decoding the lines of wordlists with the given decoder
+pub(crate) fn with_decoder(expr: Expression, decoder: Decoder) -> Result<Box<dyn Iterator>, Error> {
     match expr {
         Expression::Constant { value } => {
             let it = constant::Constant::new(value)?;
             Ok(Box::new(it))
         }
         Expression::Wordlist { filename } => {
-            let it = wordlist::Wordlist::new(filename)?;
+            let it = wordlist::Wordlist::new(filename, decoder)?;
             Ok(Box::new(it))
         }
         Expression::Permutations { min, max, charset } => {
@@ -49,7 +61,7 @@ pub(crate) fn new(expr: Expression) -> Result<Box<dyn Iterator>, Error> {
         Expression::Multiple { expressions } => {
             let mut iters = vec![];
             for expr in expressions.iter() {
-                iters.push(new(expr.clone())?)
+                iters.push(with_decoder(expr.clone(), decoder)?)
             }
 
             let it = multi::Multi::new(iters)?;
@@ -14,9 +14,9 @@ impl Multi {
         // count the number of items for each iterator
         let curr_it = 0;
         let num_its = iters.len();
-        let mut elements = 0;
+        let mut elements = 0usize;
         for it in iters.iter() {
-            elements += it.search_space_size();
+            elements = elements.saturating_add(it.search_space_size());
         }
 
         Ok(Self {
@@ -32,6 +32,14 @@ impl creds::Iterator for Multi {
     fn search_space_size(&self) -> usize {
         self.elements
     }
+
+    fn skipped_lines(&self) -> &[usize] {
+        self.iters
+            .iter()
+            .map(|it| it.skipped_lines())
+            .find(|skipped| !skipped.is_empty())
+            .unwrap_or_default()
+    }
 }
 
 impl creds::IteratorClone for Multi {
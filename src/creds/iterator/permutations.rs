@@ -13,9 +13,9 @@ pub(crate) struct Permutations {
 impl Permutations {
     pub fn new(charset: String, min_length: usize, max_length: usize) -> Result<Self, Error> {
         if min_length == 0 {
-            return Err("min length can't be zero".to_owned());
+            return Err("min length can't be zero".into());
         } else if min_length > max_length {
-            return Err("min length can't be greater than max length".to_owned());
+            return Err("min length can't be greater than max length".into());
         }
 
         let permutator = Permutator::new(charset.chars().collect(), min_length, max_length);
@@ -1,11 +1,11 @@
 use ahash::AHashMap;
 
 fn search_space_size(charset: &Vec<char>, min_size: usize, max_size: usize) -> usize {
-    // compute the number of permutations to generate
+    // compute the number of permutations to generate, saturating if too big to be represented
     let charset_len = charset.len();
-    let mut count = 0;
+    let mut count: usize = 0;
     for i in min_size..=max_size {
-        count += charset_len.pow(i as u32)
+        count = count.saturating_add(charset_len.saturating_pow(i as u32));
     }
     count
 }
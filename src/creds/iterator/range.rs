@@ -13,11 +13,12 @@ impl Range {
         if set.is_empty() {
             if min > max {
                 return Err(
-                    "left side of range expression can't be greater than the right side".to_owned(),
+                    "left side of range expression can't be greater than the right side".into(),
                 );
             }
 
-            let elements = max - min + 1;
+            // [0-18446744073709551615] has one element more than a usize can count
+            let elements = (max - min).saturating_add(1);
             Ok(Self {
                 min,
                 max,
@@ -107,4 +108,16 @@ mod tests {
         assert_eq!(tot, expected.len());
         assert_eq!(vec, expected);
     }
+
+    #[test]
+    fn saturates_full_range() {
+        let gen = iterator::new(Expression::Range {
+            min: 0,
+            max: usize::MAX,
+            set: vec![],
+        })
+        .unwrap();
+
+        assert_eq!(gen.search_space_size(), usize::MAX);
+    }
 }
@@ -1,25 +1,57 @@
 use std::{
     fs::File,
-    io::{prelude::*, BufReader, Lines},
+    io::{prelude::*, BufReader, Split},
 };
 
-use crate::{creds, session::Error};
+use crate::{
+    creds::{self, Decoder, InvalidSequences},
+    session::Error,
+};
 
 pub(crate) struct Wordlist {
     path: String,
-    lines: Lines<BufReader<File>>,
+    decoder: Decoder,
+    lines: Split<BufReader<File>>,
     current: usize,
     elements: usize,
+    // 0-based lines that can't be decoded and are not returned
+    skipped: Vec<usize>,
 }
 
 impl Wordlist {
-    pub fn new(path: String) -> Result<Self, Error> {
+    pub fn new(path: String, decoder: Decoder) -> Result<Self, Error> {
         log::debug!("loading wordlist from {} ...", &path);
 
-        // count the number of lines first
+        // count the number of lines first, this also finds out which lines can't be decoded
         let file = File::open(&path).map_err(|e| e.to_string())?;
         let reader = BufReader::new(file);
-        let elements = reader.lines().count();
+        let mut elements = 0;
+        let mut skipped = vec![];
+        for (index, raw) in reader.split(b'\n').enumerate() {
+            let raw = raw.map_err(|e| e.to_string())?;
+            if decoder.decode(&raw).is_some() {
+                elements += 1;
+            } else if decoder.invalid_sequences() == InvalidSequences::Error {
+                return Err(format!(
+                    "{}:{}: invalid {} sequence",
+                    &path,
+                    index + 1,
+                    decoder.name()
+                )
+                .into());
+            } else {
+                skipped.push(index);
+            }
+        }
+
+        if !skipped.is_empty() {
+            log::warn!(
+                "skipping {} lines of {} that are not valid {}",
+                skipped.len(),
+                &path,
+                decoder.name()
+            );
+        }
 
         // create actual reader
         let file = File::open(&path).map_err(|e| e.to_string())?;
@@ -27,9 +59,11 @@ impl Wordlist {
 
         Ok(Self {
             path,
+            decoder,
             elements,
+            skipped,
             current: 0,
-            lines: reader.lines(),
+            lines: reader.split(b'\n'),
         })
     }
 }
@@ -38,11 +72,15 @@ impl creds::Iterator for Wordlist {
     fn search_space_size(&self) -> usize {
         self.elements
     }
+
+    fn skipped_lines(&self) -> &[usize] {
+        &self.skipped
+    }
 }
 
 impl creds::IteratorClone for Wordlist {
     fn create_boxed_copy(&self) -> Box<dyn creds::Iterator> {
-        Box::new(Self::new(self.path.clone()).unwrap())
+        Box::new(Self::new(self.path.clone(), self.decoder).unwrap())
     }
 }
 
@@ -50,13 +88,18 @@ impl std::iter::Iterator for Wordlist {
     type Item = String;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.current < self.elements {
-            self.current += 1;
-            if let Some(res) = self.lines.next() {
-                if let Ok(line) = res {
-                    return Some(line);
-                } else {
-                    log::error!("could not read line: {:?}", res.err());
+        while self.current < self.elements {
+            match self.lines.next()? {
+                Ok(raw) => {
+                    // lines that can't be decoded were not counted
+                    if let Some(line) = self.decoder.decode(&raw) {
+                        self.current += 1;
+                        return Some(line);
+                    }
+                }
+                Err(e) => {
+                    log::error!("could not read line: {:?}", e);
+                    return None;
                 }
             }
         }
@@ -69,7 +112,8 @@ mod tests {
     use std::fs::File;
     use std::io::Write;
 
-    use crate::creds::{iterator, Expression};
+    use super::Wordlist;
+    use crate::creds::{self, iterator, Decoder, Expression, InvalidSequences};
 
     #[test]
     fn can_handle_wordlist() {
@@ -96,4 +140,40 @@ mod tests {
         assert_eq!(tot, num_items);
         assert_eq!(vec, expected);
     }
+
+    #[test]
+    fn can_decode_latin1_wordlist() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let tmppath = tmpdir.path().join("latin1.txt");
+        std::fs::write(&tmppath, b"caf\xe9\r\nm\xfcller\nna\xefve\n").unwrap();
+
+        let decoder = Decoder::new(Some(&"latin1".to_owned()), InvalidSequences::Error).unwrap();
+        let wordlist = Wordlist::new(tmppath.to_str().unwrap().to_owned(), decoder).unwrap();
+        let vec: Vec<String> = wordlist.collect();
+
+        assert_eq!(vec, vec!["café", "müller", "naïve"]);
+    }
+
+    #[test]
+    fn can_handle_invalid_lines() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let tmppath = tmpdir.path().join("mixed.txt");
+        std::fs::write(&tmppath, b"first\ncaf\xe9\nlast\n").unwrap();
+        let path = tmppath.to_str().unwrap().to_owned();
+
+        let skip = Wordlist::new(path.clone(), Decoder::default()).unwrap();
+        assert_eq!(creds::Iterator::search_space_size(&skip), 2);
+        assert_eq!(creds::Iterator::skipped_lines(&skip), &[1]);
+        assert_eq!(skip.collect::<Vec<String>>(), vec!["first", "last"]);
+
+        let replace = Decoder::new(None, InvalidSequences::Replace).unwrap();
+        let replace = Wordlist::new(path.clone(), replace).unwrap();
+        assert_eq!(
+            replace.collect::<Vec<String>>(),
+            vec!["first", "caf\u{FFFD}", "last"]
+        );
+
+        let error = Decoder::new(None, InvalidSequences::Error).unwrap();
+        assert!(Wordlist::new(path, error).is_err());
+    }
 }
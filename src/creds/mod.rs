@@ -1,9 +1,14 @@
 mod combinator;
+mod decoder;
 mod expression;
+mod format;
 mod iterator;
+pub(crate) mod validation;
 
 pub(crate) use combinator::{Combinator, IterationStrategy};
+pub(crate) use decoder::{Decoder, InvalidSequences};
 pub(crate) use expression::{parse_expression, Expression};
+pub(crate) use format::UsernameFormat;
 pub(crate) use iterator::{Iterator, IteratorClone};
 
 use serde::{Deserialize, Serialize};
@@ -15,6 +20,15 @@ pub(crate) struct Credentials {
     pub password: String,
 }
 
+// line numbers of the username and password in their wordlists, if they come from one
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq, Debug)]
+pub(crate) struct Provenance {
+    pub username_line: Option<usize>,
+    pub password_line: Option<usize>,
+    // the username before --username-format, if it changed it
+    pub raw_username: Option<String>,
+}
+
 impl Credentials {
     #[inline(always)]
     pub fn single(&self) -> &str {
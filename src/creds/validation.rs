@@ -0,0 +1,82 @@
+use crate::{creds::Credentials, session::Error};
+
+// load the target,username,password rows of a --validate CSV file, empty lines and lines
+// starting with # are ignored
+pub(crate) fn load(path: &str) -> Result<Vec<Credentials>, Error> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .comment(Some(b'#'))
+        .from_path(path)
+        .map_err(|e| format!("could not open {}: {}", path, e))?;
+
+    let mut all = vec![];
+    for (line, record) in reader.records().enumerate() {
+        let record = record.map_err(|e| format!("can't parse {}: {}", path, e))?;
+        if record.len() < 3 {
+            return Err(format!(
+                "row {} of {} is not in the target,username,password format",
+                line + 1,
+                path
+            )
+            .into());
+        }
+
+        all.push(Credentials {
+            target: record[0].trim().to_owned(),
+            username: record[1].to_owned(),
+            // unquoted passwords containing the separator
+            password: record.iter().skip(2).collect::<Vec<&str>>().join(","),
+        });
+    }
+
+    if all.is_empty() {
+        return Err(format!("no credentials found in {}", path).into());
+    }
+
+    Ok(all)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::load;
+    use crate::creds::Credentials;
+
+    #[test]
+    fn can_load_rows() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let path = tmpdir.path().join("creds.csv");
+        fs::write(
+            &path,
+            "# harvested\n10.0.0.1:22,root,toor\n\n10.0.0.2,admin,\"pa,ss\"\n10.0.0.3,bob,a,b\n",
+        )
+        .unwrap();
+
+        let creds = load(path.to_str().unwrap()).unwrap();
+        let make = |target: &str, username: &str, password: &str| Credentials {
+            target: target.to_owned(),
+            username: username.to_owned(),
+            password: password.to_owned(),
+        };
+
+        assert_eq!(
+            creds,
+            vec![
+                make("10.0.0.1:22", "root", "toor"),
+                make("10.0.0.2", "admin", "pa,ss"),
+                make("10.0.0.3", "bob", "a,b"),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_incomplete_rows() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let path = tmpdir.path().join("creds.csv");
+        fs::write(&path, "10.0.0.1,root\n").unwrap();
+
+        assert!(load(path.to_str().unwrap()).is_err());
+    }
+}
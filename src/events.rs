@@ -0,0 +1,168 @@
+use std::sync::Arc;
+
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use crate::session::Error;
+
+// events a consumer can fall behind before it starts missing some
+const BACKLOG: usize = 4096;
+
+#[derive(Serialize)]
+struct Event<'a, T: Serialize> {
+    event: &'a str,
+    time: chrono::DateTime<chrono::Local>,
+    data: T,
+}
+
+fn line<T: Serialize>(event: &str, data: T) -> Result<String, Error> {
+    let mut line = serde_json::to_string(&Event {
+        event,
+        time: chrono::Local::now(),
+        data,
+    })
+    .map_err(|e| e.to_string())?;
+    line.push('\n');
+    Ok(line)
+}
+
+// the events of a session, streamed to the consumers of --event-socket
+#[derive(Debug)]
+pub(crate) struct Events {
+    sender: broadcast::Sender<Arc<String>>,
+}
+
+impl Default for Events {
+    fn default() -> Self {
+        Self {
+            sender: broadcast::channel(BACKLOG).0,
+        }
+    }
+}
+
+impl Events {
+    // whether there's any consumer connected, to skip building events nobody would read
+    pub fn has_consumers(&self) -> bool {
+        self.sender.receiver_count() > 0
+    }
+
+    #[cfg(test)]
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<String>> {
+        self.sender.subscribe()
+    }
+
+    // send the event to every connected consumer, does nothing if nobody is listening
+    pub fn emit<T: Serialize>(&self, event: &str, data: T) {
+        if self.has_consumers() {
+            match line(event, data) {
+                // can only fail if every consumer disconnected in the meantime
+                Ok(line) => {
+                    let _ = self.sender.send(Arc::new(line));
+                }
+                Err(e) => log::error!("could not serialize {} event: {}", event, e),
+            }
+        }
+    }
+
+    // accept consumers on this unix socket and stream newline delimited JSON events to them
+    #[cfg(unix)]
+    pub fn listen(&self, path: &str) -> Result<(), Error> {
+        use std::os::unix::fs::FileTypeExt;
+
+        use tokio::io::AsyncWriteExt;
+        use tokio::net::UnixListener;
+
+        // a socket left behind by a previous run
+        if let Ok(meta) = std::fs::metadata(path) {
+            if meta.file_type().is_socket() {
+                std::fs::remove_file(path).map_err(|e| e.to_string())?;
+            } else {
+                return Err(format!("{} already exists and is not a socket", path).into());
+            }
+        }
+
+        let listener =
+            UnixListener::bind(path).map_err(|e| format!("could not bind {}: {}", path, e))?;
+        let sender = self.sender.clone();
+
+        log::info!("streaming events to {}", path);
+
+        tokio::spawn(async move {
+            loop {
+                let mut stream = match listener.accept().await {
+                    Ok((stream, _)) => stream,
+                    Err(e) => {
+                        log::error!("could not accept event consumer: {}", e);
+                        continue;
+                    }
+                };
+
+                let mut receiver = sender.subscribe();
+                tokio::spawn(async move {
+                    log::debug!("event consumer connected");
+                    loop {
+                        match receiver.recv().await {
+                            Ok(line) => {
+                                // the consumer went away, the run goes on without it
+                                if stream.write_all(line.as_bytes()).await.is_err() {
+                                    break;
+                                }
+                            }
+                            Err(broadcast::error::RecvError::Lagged(missed)) => {
+                                log::warn!("event consumer too slow, {} events dropped", missed);
+                            }
+                            Err(broadcast::error::RecvError::Closed) => break,
+                        }
+                    }
+                    log::debug!("event consumer disconnected");
+                });
+            }
+        });
+
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    pub fn listen(&self, _path: &str) -> Result<(), Error> {
+        Err("--event-socket is only supported on unix systems".into())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use tokio::io::{AsyncBufReadExt, BufReader};
+    use tokio::net::UnixStream;
+
+    use super::Events;
+
+    #[tokio::test]
+    async fn streams_events_to_consumers() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let path = tmpdir.path().join("events.sock");
+        let path = path.to_str().unwrap();
+
+        let events = Events::default();
+        events.listen(path).unwrap();
+
+        // nobody is listening yet
+        events.emit("stats", serde_json::json!({"done": 0}));
+
+        let stream = UnixStream::connect(path).await.unwrap();
+        let mut lines = BufReader::new(stream).lines();
+
+        // the listener subscribes the consumer once it accepts the connection
+        while !events.has_consumers() {
+            tokio::task::yield_now().await;
+        }
+        events.emit("attempt", serde_json::json!({"username": "admin"}));
+
+        let line = lines.next_line().await.unwrap().unwrap();
+        let event: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(event["event"], "attempt");
+        assert_eq!(event["data"]["username"], "admin");
+
+        // a consumer going away doesn't affect the others or the emitter
+        drop(lines);
+        events.emit("attempt", serde_json::json!({"username": "root"}));
+    }
+}
@@ -10,7 +10,9 @@ use env_logger::Target;
 use rlimit::{setrlimit, Resource};
 
 mod api;
+mod config;
 mod creds;
+mod events;
 mod options;
 mod plugins;
 mod recipe;
@@ -41,6 +43,16 @@ fn setup() -> Result<Options, session::Error> {
 
     let mut options: Options = Options::parse();
 
+    // load the configuration file, then apply the command line on top of it
+    if let Some(path) = options.config.as_ref() {
+        let argv = config::from_path(path)?;
+        // an empty configuration would just print the help
+        if argv.len() > 1 {
+            let argv = config::with_command_line(argv, env::args())?;
+            options = Options::try_parse_from(argv).map_err(|e| e.to_string())?;
+        }
+    }
+
     // generate shell completions and exit
     if let Some(shell) = options.generate_completions {
         clap_complete::generate(shell, &mut Options::command(), "legba", &mut io::stdout());
@@ -51,6 +63,9 @@ fn setup() -> Result<Options, session::Error> {
     if options.list_plugins {
         plugins::manager::list();
         std::process::exit(0);
+    } else if options.list_plugins_json {
+        plugins::manager::list_json()?;
+        std::process::exit(0);
     }
 
     print!(
@@ -86,29 +101,73 @@ fn setup() -> Result<Options, session::Error> {
     Ok(options)
 }
 
-async fn start_session(opts: Options) -> Result<(), session::Error> {
+async fn start_session(mut opts: Options) -> Result<(), session::Error> {
+    if opts.srv {
+        plugins::manager::discover_srv(&mut opts).await?;
+    }
+
     // create the session object with runtime information
     // NOTE: from this moment on we use session.options
     let session = Session::new(opts.clone())?;
 
-    // get selected plugin and configure it
-    let plugin = plugins::manager::setup(&session.options).map_err(|e| {
+    // get selected plugins and configure them
+    let plugins = plugins::manager::setup(&session.options).inspect_err(|_| {
         // set stop signal if the plugin failed to load
         session.set_stop();
-        e
     })?;
 
+    // fail before any attempt if a target can't be handled by the plugins
+    plugins::manager::validate_targets(&plugins, &session).inspect_err(|_| {
+        session.set_stop();
+    })?;
+
+    plugins::manager::prepare(&plugins, &session)
+        .await
+        .inspect_err(|_| session.set_stop())?;
+
+    if let Some(path) = session.options.event_socket.as_ref() {
+        session.events().listen(path)?;
+    }
+
     let start = time::Instant::now();
 
-    // start plugin
-    plugins::manager::run(plugin, session.clone()).await?;
+    // stop the run once the time is up, in-flight attempts still complete
+    if let Some(max_time) = session.options.max_time {
+        let session = session.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(max_time).await;
+            if !session.is_finished() {
+                log::warn!("maximum run time of {:?} reached, stopping ...", max_time);
+                session.set_stop();
+            }
+        });
+    }
+
+    if session.options.only_open {
+        plugins::manager::sweep(&plugins, session.clone()).await;
+    }
+
+    if session.options.check {
+        plugins::manager::check(&plugins, session.clone()).await;
+    }
 
-    let one_sec = time::Duration::from_secs(1);
-    while !session.is_finished() {
-        tokio::time::sleep(one_sec).await;
+    // start plugins
+    let run = plugins::manager::run(plugins, session.clone()).await?;
+
+    // once stopped, give in-flight attempts the chance to complete instead of dropping their
+    // connections
+    let grace = time::Duration::from_millis(session.options.timeout);
+    tokio::select! {
+        res = run.wait() => res?,
+        _ = async {
+            session.wait_stop().await;
+            tokio::time::sleep(grace).await;
+        } => {
+            log::debug!("{} attempts still in flight", session.get_in_flight());
+        }
     }
 
-    log::info!("runtime {:?}", start.elapsed());
+    report::summary(&session, start.elapsed()).await;
 
     // sometimes the program hangs waiting for some remaining tokio tasks
     // to complete - we just exit(0) to avoid this.
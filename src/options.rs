@@ -9,20 +9,38 @@ use crate::{creds, session};
 // TODO: refactor with subcommands?
 
 #[derive(Parser, Debug, Serialize, Deserialize, Clone, Default)]
-#[clap(version, arg_required_else_help(true))]
+#[clap(version, arg_required_else_help(true), args_override_self(true))]
 pub(crate) struct Options {
     #[clap(short = 'L', long, default_value_t = false)]
     /// List all available protocol plugins.
     pub list_plugins: bool,
-    /// Protocol plugin to use, run with --list-plugins for a list of all available plugins.
+    #[clap(long, default_value_t = false)]
+    /// List all available protocol plugins as JSON.
+    pub list_plugins_json: bool,
+    /// Protocol plugin to use or comma separated list of plugins, run with --list-plugins for a list of all available plugins.
     pub plugin: Option<String>,
     #[clap(short = 'R', long)]
     /// Load a recipe from this YAML file.
     pub recipe: Option<String>,
+    #[clap(long)]
+    /// Load the options from this TOML file, with the same names as the command line flags. Command line flags take precedence.
+    pub config: Option<String>,
 
     /// Single target host, url or IP address, IP range, CIDR, @filename or comma separated combination of them.
     #[clap(short = 'T', long)]
     pub target: Option<String>,
+    /// Load targets from this file, one per line. Empty lines and lines starting with # are ignored.
+    #[clap(long)]
+    pub targets: Option<String>,
+    /// Exclude this target, IP range, CIDR or @filename from the targets, can be repeated. Use target:port to only exclude a specific port.
+    #[clap(long)]
+    pub exclude: Vec<String>,
+    /// Comma separated ports or port ranges like 6379,6380 or 8000-8010, targets without a port are attempted on each of them.
+    #[clap(long)]
+    pub ports: Option<String>,
+    /// Treat the targets as domains and attack the servers found in their SRV records for the selected plugin, like _ldap._tcp.<domain>.
+    #[clap(long, default_value_t = false)]
+    pub srv: bool,
 
     /// Enable the REST API and bind it to the specified address:port.
     #[clap(long)]
@@ -34,9 +52,51 @@ pub(crate) struct Options {
     /// Constant, filename, glob expression as @/some/path/*.txt or permutations as #min-max:charset / #min-max or range as [min-max] / [n, n, n]
     #[clap(short = 'P', long, visible_alias = "key")]
     pub password: Option<String>,
+    /// Read a single username from this environment variable.
+    #[clap(long)]
+    pub username_env: Option<String>,
+    /// Read a single password from this environment variable, so that it doesn't show in the process arguments.
+    #[clap(long)]
+    pub password_env: Option<String>,
+    /// Use --username and --password as literal values instead of parsing them as expressions.
+    #[clap(long, default_value_t = false)]
+    pub literal: bool,
+    /// Also try an empty username. For single payload plugins it applies to --payloads.
+    #[clap(long, default_value_t = false)]
+    pub add_empty_username: bool,
+    /// Also try an empty password. For single payload plugins it applies to --password.
+    #[clap(long, default_value_t = false)]
+    pub add_empty_password: bool,
+    /// Template applied to every username for the target it's attempted against, like '{domain}\{user}' or '{user}@{domain}'. The original username is added to the loot as raw_username.
+    #[clap(long)]
+    pub username_format: Option<String>,
+    /// Value of {domain} in --username-format for the targets not listed in --username-domains.
+    #[clap(long)]
+    pub username_domain: Option<String>,
+    /// File of target=domain lines setting {domain} in --username-format per target, with or without the port.
+    #[clap(long)]
+    pub username_domains: Option<String>,
     /// Load username:password combinations from this file.
     #[clap(short = 'C', long)]
     pub combinations: Option<String>,
+    /// Wordlist of likely passwords to attempt on every target before the --password ones, which then skip them. Holds the payloads of single payload plugins and username:password lines with --combinations.
+    #[clap(long)]
+    pub priority: Option<String>,
+    /// Validate the target,username,password rows of this CSV file instead of combining targets and credentials.
+    #[clap(long)]
+    pub validate: Option<String>,
+    /// Only attempt this percentage of the combinations, picked at random. The total number of attempts is unknown until all combinations are generated.
+    #[clap(long)]
+    pub sample: Option<f64>,
+    /// Seed for --sample, runs with the same seed attempt the same combinations.
+    #[clap(long)]
+    pub seed: Option<u64>,
+    /// Character encoding of the wordlists, like latin1 or windows-1251. Defaults to UTF-8.
+    #[clap(long)]
+    pub encoding: Option<String>,
+    /// What to do with wordlist lines that are not valid in the selected encoding.
+    #[clap(long, value_enum, default_value_t = creds::InvalidSequences::Skip)]
+    pub encoding_errors: creds::InvalidSequences,
     /// Separator if using the --combinations/-C argument.
     #[clap(long, default_value = ":")]
     pub separator: String,
@@ -45,6 +105,10 @@ pub(crate) struct Options {
     #[clap(short = 'I', long, value_enum, default_value_t = creds::IterationStrategy::User)]
     pub iterate_by: creds::IterationStrategy,
 
+    /// What to do when a plugin reports a locked out account: skip the rest of its passwords, abort the run or continue.
+    #[clap(long, value_enum, default_value_t = session::LockoutPolicy::Skip)]
+    pub on_lockout: session::LockoutPolicy,
+
     /// Save and restore session information to this file.
     #[clap(short = 'S', long)]
     pub session: Option<String>,
@@ -54,16 +118,88 @@ pub(crate) struct Options {
     /// Output file format.
     #[clap(long, value_enum, default_value_t = session::loot::OutputFormat::Text)]
     pub output_format: session::loot::OutputFormat,
-    /// Connection timeout in milliseconds.
+    /// Flush and sync the output file to disk after every write.
+    #[clap(long, default_value_t = false)]
+    pub output_fsync: bool,
+    /// Redact inline passwords, proxy and URL credentials, cookies, headers and payloads from the run manifest written with --output-format jsonl.
+    #[clap(long, default_value_t = false)]
+    pub redact: bool,
+    /// Stream attempt, error, loot and stats events as newline delimited JSON to the consumers connected to this unix socket.
+    #[clap(long)]
+    pub event_socket: Option<String>,
+    /// Include the passwords in the attempt events of --event-socket, they are left out otherwise.
+    #[clap(long, default_value_t = false)]
+    pub event_passwords: bool,
+    /// Instead of printing the loot as it's found, print it grouped by target at the end of the run. Ignored with --quiet or when the output is not a terminal.
+    #[clap(long, default_value_t = false)]
+    pub group_by_target: bool,
+    /// Print the loot to the console with this template, like '{plugin}:{target}:{username}:{password}'.
+    #[clap(long)]
+    pub loot_template: Option<String>,
+    /// Timeout in milliseconds, used for both connections and reads unless --connect-timeout or --read-timeout are set.
     #[clap(long, default_value_t = 10000)]
     pub timeout: u64,
+    /// Connection timeout in milliseconds, defaults to --timeout.
+    #[clap(long)]
+    pub connect_timeout: Option<u64>,
+    /// Timeout in milliseconds for each attempt once connected, defaults to --timeout.
+    #[clap(long)]
+    pub read_timeout: Option<u64>,
+    /// Disable Nagle's algorithm on TCP connections, sending small packets right away.
+    #[clap(long, default_value_t = false)]
+    pub tcp_nodelay: bool,
+    /// Send TCP keepalive probes after this many seconds of inactivity on a connection.
+    #[clap(long)]
+    pub tcp_keepalive: Option<u64>,
+    /// Minimum TLS version to negotiate, for services that must be forced to newer versions.
+    #[clap(long, value_enum)]
+    pub tls_min_version: Option<crate::utils::net::TlsVersion>,
+    /// Maximum TLS version to negotiate, for legacy services failing the handshake with modern defaults.
+    #[clap(long, value_enum)]
+    pub tls_max_version: Option<crate::utils::net::TlsVersion>,
+    /// Add the subject, issuer, alternative names and expiry of the server TLS certificate to the loot.
+    #[clap(long, default_value_t = false)]
+    pub capture_cert: bool,
+    /// Don't use STARTTLS for IMAP, POP3 and SMTP, even if the server only accepts authentication over TLS.
+    #[clap(long, default_value_t = false)]
+    pub no_starttls: bool,
+    /// Force the IMAP (LOGIN or PLAIN) or SMTP (PLAIN, LOGIN or XOAUTH2) authentication mechanism instead of negotiating it from the ones advertised by the server.
+    #[clap(long, visible_alias = "smtp-mechanism")]
+    pub auth_mech: Option<String>,
+    /// Stop after this amount of time even if there are combinations left, like 30m or 2h.
+    #[clap(long, value_parser = crate::utils::parse_duration)]
+    pub max_time: Option<std::time::Duration>,
+    /// Only perform attempts inside these daily time windows, like 22:00-06:00 or 22:00-06:00,12:00-13:00. Attempts in progress are completed when a window closes.
+    #[clap(long)]
+    pub schedule: Option<String>,
+    /// Timezone of the --schedule windows: local, UTC or an offset like +02:00.
+    #[clap(long)]
+    pub schedule_timezone: Option<String>,
+    /// Check that every target is reachable before starting, for the plugins supporting it. Unreachable ones are skipped.
+    #[clap(long, default_value_t = false)]
+    pub check: bool,
+    /// Before starting, connect to the port of every target and skip the closed ones. Faster than --check as no protocol is spoken.
+    #[clap(long, default_value_t = false)]
+    pub only_open: bool,
+    /// Number of concurrent connections of the --only-open sweep.
+    #[clap(long, default_value_t = 256)]
+    pub sweep_concurrency: usize,
     /// Number of attempts if a request fails.
     #[clap(long, default_value_t = 5)]
     pub retries: usize,
     /// Delay in milliseconds to wait before a retry.
     #[clap(long, default_value_t = 1000)]
     pub retry_time: u64,
+    /// Only retry attempts failing with an error matching this regular expression.
+    #[clap(long)]
+    pub retry_on: Option<String>,
+    /// Never retry attempts failing with an error matching this regular expression.
+    #[clap(long)]
+    pub no_retry_on: Option<String>,
+    /// Confirm every success with a second attempt before recording it, discarding the ones that can't be verified.
     #[clap(long, default_value_t = false)]
+    pub retries_on_success_verify: bool,
+    #[clap(long, default_value_t = false, visible_alias = "first-match-exit")]
     /// Exit after the first positive match is found.
     pub single_match: bool,
 
@@ -72,9 +208,12 @@ pub(crate) struct Options {
     #[clap(long, default_value_t = 10000)]
     pub ulimit: u64,
 
-    /// Number of concurrent workers.
-    #[clap(long, default_value_t = num_cpus::get())]
-    pub concurrency: usize,
+    /// Number of concurrent workers, defaults to the number of CPUs or to the plugin limit if lower.
+    #[clap(long)]
+    pub concurrency: Option<usize>,
+    /// Maximum number of credentials waiting to be consumed by the workers, defaults to --concurrency.
+    #[clap(long)]
+    pub queue_size: Option<usize>,
     /// Limit the number of requests per second.
     #[clap(long, default_value_t = 0)]
     pub rate_limit: usize,
@@ -87,9 +226,21 @@ pub(crate) struct Options {
     /// Maximum number of milliseconds for random request jittering.
     #[clap(long, default_value_t = 0)]
     pub jitter_max: u64,
+    /// Automatically increase the jitter for targets returning errors.
+    #[clap(long, default_value_t = false)]
+    pub jitter_adaptive: bool,
+    /// Minimum number of milliseconds between two consecutive attempts to the same target.
+    #[clap(long, default_value_t = 0)]
+    pub target_delay: u64,
+    /// Number of seconds between two statistics reports, at least one.
+    #[clap(long, default_value_t = 1)]
+    pub stats_interval: u64,
     /// Do not report statistics.
     #[clap(short = 'Q', long, default_value_t = false)]
     pub quiet: bool,
+    /// Measure the latency of every attempt and report its p50, p90 and p99 per plugin in the summary.
+    #[clap(long, default_value_t = false)]
+    pub timing_stats: bool,
 
     /// Generate shell completions
     #[clap(long)]
@@ -101,6 +252,12 @@ pub(crate) struct Options {
     #[cfg(feature = "amqp")]
     #[clap(flatten, next_help_heading = "AMQP")]
     pub amqp: crate::plugins::amqp::options::Options,
+    #[cfg(feature = "bench")]
+    #[clap(flatten, next_help_heading = "BENCH")]
+    pub bench: crate::plugins::bench::options::Options,
+    #[cfg(feature = "ftp")]
+    #[clap(flatten, next_help_heading = "FTP")]
+    pub ftp: crate::plugins::ftp::options::Options,
     #[cfg(feature = "http")]
     #[clap(flatten, next_help_heading = "HTTP")]
     pub http: crate::plugins::http::options::Options,
@@ -116,12 +273,15 @@ pub(crate) struct Options {
     #[cfg(feature = "ssh")]
     #[clap(flatten, next_help_heading = "SSH")]
     pub ssh: crate::plugins::ssh::options::Options,
-    #[cfg(feature = "smtp")]
-    #[clap(flatten, next_help_heading = "SMTP")]
-    pub smtp: crate::plugins::smtp::options::Options,
     #[cfg(feature = "socks5")]
     #[clap(flatten, next_help_heading = "SOCKS5")]
     pub socks5: crate::plugins::socks5::options::Options,
+    #[cfg(feature = "imap")]
+    #[clap(flatten, next_help_heading = "IMAP")]
+    pub imap: crate::plugins::imap::options::Options,
+    #[cfg(feature = "nntp")]
+    #[clap(flatten, next_help_heading = "NNTP")]
+    pub nntp: crate::plugins::nntp::options::Options,
     #[cfg(feature = "pop3")]
     #[clap(flatten, next_help_heading = "POP3")]
     pub pop3: crate::plugins::pop3::options::Options,
@@ -143,7 +303,29 @@ pub(crate) struct Options {
     #[cfg(feature = "redis")]
     #[clap(flatten, next_help_heading = "REDIS")]
     pub redis: crate::plugins::redis::options::Options,
+    #[cfg(feature = "sql")]
+    #[clap(flatten, next_help_heading = "MYSQL / POSTGRESQL")]
+    pub sql: crate::plugins::sql::options::Options,
+    #[cfg(feature = "couchdb")]
+    #[clap(flatten, next_help_heading = "COUCHDB")]
+    pub couchdb: crate::plugins::couchdb::options::Options,
+    #[cfg(feature = "etcd")]
+    #[clap(flatten, next_help_heading = "ETCD")]
+    pub etcd: crate::plugins::etcd::options::Options,
+    #[cfg(feature = "wordpress")]
+    #[clap(flatten, next_help_heading = "WORDPRESS")]
+    pub wordpress: crate::plugins::wordpress::options::Options,
+    #[cfg(feature = "xmpp")]
+    #[clap(flatten, next_help_heading = "XMPP")]
+    pub xmpp: crate::plugins::xmpp::options::Options,
     #[cfg(feature = "port_scanner")]
     #[clap(flatten, next_help_heading = "PORT SCANNER")]
     pub port_scanner: crate::plugins::port_scanner::options::Options,
 }
+
+impl Options {
+    // number of workers requested by the user, or the default one
+    pub fn concurrency(&self) -> usize {
+        self.concurrency.unwrap_or_else(num_cpus::get)
+    }
+}
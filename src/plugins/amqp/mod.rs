@@ -18,6 +18,8 @@ super::manager::register_plugin! {
     "amqp" => AMQP::new()
 }
 
+const DEFAULT_PORT: u16 = 5672;
+
 #[derive(Clone)]
 pub(crate) struct AMQP {
     ssl: bool,
@@ -35,6 +37,10 @@ impl Plugin for AMQP {
         "AMQP password authentication (ActiveMQ, RabbitMQ, Qpid, JORAM and Solace)."
     }
 
+    fn default_port(&self) -> Option<u16> {
+        Some(DEFAULT_PORT)
+    }
+
     fn setup(&mut self, opts: &Options) -> Result<(), Error> {
         self.ssl = opts.amqp.amqp_ssl;
         Ok(())
@@ -45,7 +51,7 @@ impl Plugin for AMQP {
         creds: &Credentials,
         timeout: Duration,
     ) -> Result<Option<Vec<Loot>>, Error> {
-        let address = utils::parse_target_address(&creds.target, 5672)?;
+        let address = utils::parse_target_address(&creds.target, DEFAULT_PORT)?;
         let mut stream = crate::utils::net::async_tcp_stream(&address, timeout, self.ssl).await?;
 
         // send proto header
@@ -60,6 +66,10 @@ impl Plugin for AMQP {
             .read_exact(&mut conn_start_header)
             .await
             .map_err(|e| e.to_string())?;
+        // either a method frame or the protocol versions supported by the server
+        if conn_start_header[0] != 0x01 && !conn_start_header.starts_with(b"AMQP") {
+            return Err(Error::protocol_mismatch("amqp", &conn_start_header));
+        }
         let size_raw: [u8; 4] = conn_start_header[3..].try_into().unwrap();
         let payload_size = u32::from_be_bytes(size_raw) + 1;
         // read connection.start body
@@ -0,0 +1,133 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use crate::session::{Error, Loot};
+use crate::Options;
+use crate::Plugin;
+
+use crate::creds::Credentials;
+
+pub(crate) mod options;
+
+super::manager::register_plugin! {
+    "bench" => Bench::new()
+}
+
+// no I/O at all, measures the engine itself: scheduling, rate limiting, statistics and loot
+#[derive(Clone)]
+pub(crate) struct Bench {
+    delay: Option<Duration>,
+    success_rate: f64,
+}
+
+impl Bench {
+    pub fn new() -> Self {
+        Bench {
+            delay: None,
+            success_rate: 0.0,
+        }
+    }
+}
+
+#[async_trait]
+impl Plugin for Bench {
+    fn description(&self) -> &'static str {
+        "Simulated attempts for benchmarking and testing legba itself."
+    }
+
+    fn uses_tcp(&self) -> bool {
+        false
+    }
+
+    fn setup(&mut self, opts: &Options) -> Result<(), Error> {
+        if !(0.0..=1.0).contains(&opts.bench.bench_success_rate) {
+            return Err(format!(
+                "--bench-success-rate must be between 0.0 and 1.0, got {}",
+                opts.bench.bench_success_rate
+            )
+            .into());
+        }
+
+        self.delay = opts.bench.bench_delay;
+        self.success_rate = opts.bench.bench_success_rate;
+        Ok(())
+    }
+
+    async fn attempt(
+        &self,
+        creds: &Credentials,
+        timeout: Duration,
+    ) -> Result<Option<Vec<Loot>>, Error> {
+        if let Some(delay) = self.delay {
+            tokio::time::timeout(timeout, tokio::time::sleep(delay)).await?;
+        }
+
+        // random() is in [0, 1) so a rate of 1.0 always succeeds and 0.0 never does
+        if rand::random::<f64>() < self.success_rate {
+            Ok(Some(vec![Loot::new(
+                "bench",
+                &creds.target,
+                [
+                    ("username".to_owned(), creds.username.to_owned()),
+                    ("password".to_owned(), creds.password.to_owned()),
+                ],
+            )]))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::Bench;
+    use crate::creds::Credentials;
+    use crate::session::Error;
+    use crate::Options;
+    use crate::Plugin;
+
+    fn creds() -> Credentials {
+        Credentials {
+            target: "localhost".to_owned(),
+            username: "admin".to_owned(),
+            password: "admin".to_owned(),
+        }
+    }
+
+    #[tokio::test]
+    async fn honors_success_rate() {
+        let mut opts = Options::default();
+        let timeout = Duration::from_secs(5);
+
+        let mut bench = Bench::new();
+        assert_eq!(Ok(()), bench.setup(&opts));
+        assert_eq!(bench.attempt(&creds(), timeout).await, Ok(None));
+
+        opts.bench.bench_success_rate = 1.0;
+        assert_eq!(Ok(()), bench.setup(&opts));
+        assert!(bench.attempt(&creds(), timeout).await.unwrap().is_some());
+
+        opts.bench.bench_success_rate = 1.5;
+        assert!(bench.setup(&opts).is_err());
+    }
+
+    #[tokio::test]
+    async fn delay_can_time_out() {
+        let mut opts = Options::default();
+        opts.bench.bench_delay = Some(Duration::from_millis(200));
+
+        let mut bench = Bench::new();
+        assert_eq!(Ok(()), bench.setup(&opts));
+        assert_eq!(
+            bench.attempt(&creds(), Duration::from_millis(10)).await,
+            Err(Error::Timeout)
+        );
+        assert_eq!(
+            bench.attempt(&creds(), Duration::from_secs(5)).await,
+            Ok(None)
+        );
+    }
+}
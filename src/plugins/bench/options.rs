@@ -0,0 +1,14 @@
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+
+#[derive(Parser, Debug, Serialize, Deserialize, Clone, Default)]
+#[group(skip)]
+pub(crate) struct Options {
+    #[clap(long, value_parser = crate::utils::parse_duration)]
+    /// Simulated duration of every attempt, like 50ms. Attempts taking longer than --timeout fail with a timeout.
+    pub bench_delay: Option<std::time::Duration>,
+
+    #[clap(long, default_value_t = 0.0)]
+    /// Fraction of the attempts reported as successful, from 0.0 to 1.0.
+    pub bench_success_rate: f64,
+}
@@ -0,0 +1,74 @@
+use serde_json::Value;
+
+// value at a dotted path of a JSON document, optionally compared with an expected value
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct JsonMatch {
+    pointer: String,
+    expected: Option<String>,
+}
+
+impl JsonMatch {
+    // parse 'a.b[0].c' or 'a.b[0].c=value', the leading '$.' of JSONPath expressions is optional
+    pub fn parse(expr: &str) -> Self {
+        let (path, expected) = match expr.split_once('=') {
+            Some((path, expected)) => (path, Some(expected.to_owned())),
+            None => (expr, None),
+        };
+
+        let path = path
+            .trim()
+            .trim_start_matches('$')
+            .replace('[', ".")
+            .replace(']', "");
+        let pointer = path
+            .split('.')
+            .filter(|segment| !segment.is_empty())
+            .map(|segment| format!("/{}", segment.replace('~', "~0").replace('/', "~1")))
+            .collect();
+
+        Self { pointer, expected }
+    }
+
+    pub fn is_match(&self, output: &str) -> bool {
+        let Ok(doc) = serde_json::from_str::<Value>(output.trim()) else {
+            return false;
+        };
+
+        match (doc.pointer(&self.pointer), &self.expected) {
+            (None, _) => false,
+            (Some(Value::String(value)), Some(expected)) => value == expected,
+            (Some(value), Some(expected)) => {
+                serde_json::from_str::<Value>(expected).is_ok_and(|expected| expected == *value)
+            }
+            (Some(value), None) => !matches!(value, Value::Null | Value::Bool(false)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::JsonMatch;
+
+    const OUTPUT: &str = r#"{"result": {"authenticated": true, "users": [{"name": "admin", "id": 1}]}, "error": null}"#;
+
+    #[test]
+    fn can_match_existing_values() {
+        assert!(JsonMatch::parse("result.authenticated").is_match(OUTPUT));
+        assert!(JsonMatch::parse("$.result.users[0].name").is_match(OUTPUT));
+        assert!(!JsonMatch::parse("error").is_match(OUTPUT));
+        assert!(!JsonMatch::parse("result.missing").is_match(OUTPUT));
+    }
+
+    #[test]
+    fn can_match_expected_values() {
+        assert!(JsonMatch::parse("result.users[0].name=admin").is_match(OUTPUT));
+        assert!(JsonMatch::parse("result.users.0.id=1").is_match(OUTPUT));
+        assert!(JsonMatch::parse("result.authenticated=true").is_match(OUTPUT));
+        assert!(!JsonMatch::parse("result.users[0].name=root").is_match(OUTPUT));
+    }
+
+    #[test]
+    fn invalid_json_does_not_match() {
+        assert!(!JsonMatch::parse("result").is_match("login failed"));
+    }
+}
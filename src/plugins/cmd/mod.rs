@@ -2,53 +2,116 @@ use std::process::Stdio;
 use std::time::Duration;
 
 use async_trait::async_trait;
+use lazy_regex::{lazy_regex, Lazy};
+use regex::{Captures, Regex};
 
 use crate::session::{Error, Loot};
+use crate::utils;
 use crate::Options;
 use crate::Plugin;
 
 use crate::creds::Credentials;
 
+mod json;
 pub(crate) mod options;
 
 super::manager::register_plugin! {
     "cmd" => Command::new()
 }
 
+static PLACEHOLDER_PARSER: Lazy<Regex> =
+    lazy_regex!(r"\{(USERNAME|PASSWORD|TARGET|PORT|username|password|target|port)\}");
+
 #[derive(Clone)]
 pub(crate) struct Command {
     opts: options::Options,
+    args: Vec<String>,
+    success_regex: Option<Regex>,
+    stderr_regex: Option<Regex>,
+    success_json: Option<json::JsonMatch>,
 }
 
 impl Command {
     pub fn new() -> Self {
         Command {
             opts: options::Options::default(),
+            args: vec![],
+            success_regex: None,
+            stderr_regex: None,
+            success_json: None,
         }
     }
 
+    fn interpolate(arg: &str, creds: &Credentials) -> String {
+        let port = match utils::parse_target(&creds.target, 0) {
+            Ok((_, port)) if port > 0 => port.to_string(),
+            _ => String::new(),
+        };
+
+        // single pass, so that placeholders in the values themselves are not expanded
+        PLACEHOLDER_PARSER
+            .replace_all(arg, |caps: &Captures| {
+                match caps[1].to_uppercase().as_str() {
+                    "USERNAME" => creds.username.to_owned(),
+                    "PASSWORD" => creds.password.to_owned(),
+                    "TARGET" => creds.target.to_owned(),
+                    _ => port.to_owned(),
+                }
+            })
+            .into_owned()
+    }
+
+    // check the process output against every configured matcher, succeeds if none is configured
+    fn output_matches(&self, stdout: &str, stderr: &str) -> bool {
+        let results: Vec<bool> = [
+            self.opts.cmd_success_match.as_ref().map(|pattern| {
+                if pattern.is_empty() {
+                    stdout.is_empty()
+                } else {
+                    stdout.contains(pattern)
+                }
+            }),
+            self.success_regex
+                .as_ref()
+                .map(|regex| regex.is_match(stdout)),
+            self.stderr_regex
+                .as_ref()
+                .map(|regex| regex.is_match(stderr)),
+            self.success_json.as_ref().map(|json| json.is_match(stdout)),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+
+        results.is_empty()
+            || match self.opts.cmd_match_mode {
+                options::MatchMode::All => results.iter().all(|ok| *ok),
+                options::MatchMode::Any => results.iter().any(|ok| *ok),
+            }
+    }
+
     async fn run(&self, creds: &Credentials) -> Result<std::process::Output, Error> {
-        let args = shell_words::split(
-            &self
-                .opts
-                .cmd_args
-                .replace("{USERNAME}", &creds.username)
-                .replace("{PASSWORD}", &creds.password)
-                .replace("{TARGET}", &creds.target),
-        )
-        .unwrap();
+        // arguments are split before the interpolation so that payloads containing spaces,
+        // quotes or other special characters are always passed as a single argv element
+        let args: Vec<String> = self
+            .args
+            .iter()
+            .map(|arg| Self::interpolate(arg, creds))
+            .collect();
 
         log::debug!("{} {}", &self.opts.cmd_binary, args.join(" "));
 
-        let child = std::process::Command::new(&self.opts.cmd_binary)
+        // kill_on_drop makes sure the child is terminated if the timeout future is dropped
+        let child = tokio::process::Command::new(&self.opts.cmd_binary)
             .args(&args)
             .stdin(Stdio::null())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
+            .kill_on_drop(true)
             .spawn()
             .map_err(|e| e.to_string())?;
 
-        child.wait_with_output().map_err(|e| e.to_string())
+        child.wait_with_output().await.map_err(Error::from)
     }
 }
 
@@ -58,13 +121,37 @@ impl Plugin for Command {
         "Command execution."
     }
 
+    fn loot_schema(&self) -> &[&str] {
+        &["username", "password", "output"]
+    }
+
+    fn required_options(&self) -> &[&str] {
+        &["cmd-binary"]
+    }
+
     fn setup(&mut self, opts: &Options) -> Result<(), Error> {
         self.opts = opts.cmd.clone();
         if self.opts.cmd_binary.is_empty() {
-            Err("no --cmd-binary provided".to_owned())
-        } else {
-            Ok(())
+            return Err("no --cmd-binary provided".into());
+        }
+
+        self.args = shell_words::split(&self.opts.cmd_args).map_err(|e| e.to_string())?;
+
+        if let Some(regex) = &self.opts.cmd_success_regex {
+            self.success_regex = Some(Regex::new(regex).map_err(|e| e.to_string())?);
+        }
+
+        if let Some(regex) = &self.opts.cmd_stderr_regex {
+            self.stderr_regex = Some(Regex::new(regex).map_err(|e| e.to_string())?);
         }
+
+        self.success_json = self
+            .opts
+            .cmd_success_json
+            .as_ref()
+            .map(|expr| json::JsonMatch::parse(expr));
+
+        Ok(())
     }
 
     async fn attempt(
@@ -72,47 +159,117 @@ impl Plugin for Command {
         creds: &Credentials,
         timeout: Duration,
     ) -> Result<Option<Vec<Loot>>, Error> {
-        let output = tokio::time::timeout(timeout, self.run(creds))
-            .await
-            .map_err(|e| e.to_string())?;
+        let out = tokio::time::timeout(timeout, self.run(creds)).await??;
 
-        if let Ok(out) = output {
-            let stdout = String::from_utf8_lossy(&out.stdout);
-            let stderr = String::from_utf8_lossy(&out.stderr);
-            if !stderr.is_empty() {
-                log::debug!("STDERR: {}", stderr);
-            }
+        let stdout = String::from_utf8_lossy(&out.stdout);
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        if !stderr.is_empty() {
+            log::debug!("STDERR: {}", stderr);
+        }
 
-            log::debug!("STDOUT: {}", &stdout);
-
-            // check exit code first
-            if out.status.code().unwrap_or(-1) == self.opts.cmd_success_exit_code {
-                // then output if needed
-                let ok = if let Some(pattern) = &self.opts.cmd_success_match {
-                    if pattern.is_empty() {
-                        stdout.is_empty()
-                    } else {
-                        stdout.contains(pattern)
-                    }
-                } else {
-                    true
-                };
-
-                if ok {
-                    return Ok(Some(vec![Loot::new(
-                        "command",
-                        &creds.target,
-                        [
-                            ("username".to_owned(), creds.username.to_owned()),
-                            ("password".to_owned(), creds.password.to_owned()),
-                        ],
-                    )]));
-                }
+        log::debug!("STDOUT: {}", &stdout);
+
+        let exit_code = out.status.code().unwrap_or(-1);
+        if self.opts.cmd_error_exit_codes.contains(&exit_code) {
+            return Err(format!("process exited with error code {}", exit_code).into());
+        }
+
+        // check exit code first, then output if needed
+        if exit_code == self.opts.cmd_success_exit_code && self.output_matches(&stdout, &stderr) {
+            let mut data = vec![
+                ("username".to_owned(), creds.username.to_owned()),
+                ("password".to_owned(), creds.password.to_owned()),
+            ];
+
+            let stdout = stdout.trim();
+            if !stdout.is_empty() {
+                data.push(("output".to_owned(), stdout.to_owned()));
             }
 
-            return Ok(None);
-        } else {
-            return Err(output.err().unwrap().to_string());
+            return Ok(Some(vec![Loot::new("command", &creds.target, data)]));
         }
+
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{options::MatchMode, Command};
+    use crate::creds::Credentials;
+    use crate::Options;
+    use crate::Plugin;
+
+    #[test]
+    fn interpolates_placeholders() {
+        let creds = Credentials {
+            target: "127.0.0.1:2222".to_owned(),
+            username: "admin".to_owned(),
+            password: "hunter2".to_owned(),
+        };
+
+        assert_eq!(
+            Command::interpolate("{USERNAME}:{password}@{target} -p {PORT}", &creds),
+            "admin:hunter2@127.0.0.1:2222 -p 2222"
+        );
+    }
+
+    #[test]
+    fn placeholders_in_values_are_not_expanded() {
+        let creds = Credentials {
+            target: "127.0.0.1".to_owned(),
+            username: "{PASSWORD}".to_owned(),
+            password: "{username}".to_owned(),
+        };
+
+        assert_eq!(
+            Command::interpolate("{USERNAME}:{PASSWORD}", &creds),
+            "{PASSWORD}:{username}"
+        );
+    }
+
+    #[test]
+    fn payloads_are_not_split() {
+        let creds = Credentials {
+            target: "127.0.0.1".to_owned(),
+            username: "admin".to_owned(),
+            password: "a b'; rm -rf /".to_owned(),
+        };
+
+        let args: Vec<String> = shell_words::split("-u {USERNAME} -p {PASSWORD}")
+            .unwrap()
+            .iter()
+            .map(|arg| Command::interpolate(arg, &creds))
+            .collect();
+
+        assert_eq!(args, vec!["-u", "admin", "-p", "a b'; rm -rf /"]);
+    }
+
+    #[test]
+    fn combines_output_matchers() {
+        let mut opts = Options::default();
+        opts.cmd.cmd_binary = "/bin/true".to_owned();
+        opts.cmd.cmd_success_regex = Some("^welcome".to_owned());
+        opts.cmd.cmd_stderr_regex = Some("(?i)warning".to_owned());
+
+        let mut cmd = Command::new();
+        cmd.setup(&opts).unwrap();
+        assert!(cmd.output_matches("welcome admin", "WARNING: weak password"));
+        assert!(!cmd.output_matches("welcome admin", ""));
+
+        opts.cmd.cmd_match_mode = MatchMode::Any;
+        cmd.setup(&opts).unwrap();
+        assert!(cmd.output_matches("welcome admin", ""));
+        assert!(!cmd.output_matches("denied", ""));
+    }
+
+    #[test]
+    fn no_matchers_always_match() {
+        let mut opts = Options::default();
+        opts.cmd.cmd_binary = "/bin/true".to_owned();
+
+        let mut cmd = Command::new();
+        cmd.setup(&opts).unwrap();
+        assert!(cmd.output_matches("", ""));
     }
 }
@@ -1,22 +1,49 @@
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use serde::{Deserialize, Serialize};
 
+#[derive(Default, Clone, Serialize, Deserialize, Debug, ValueEnum)]
+pub(crate) enum MatchMode {
+    #[default]
+    All,
+    Any,
+}
+
 #[derive(Parser, Debug, Serialize, Deserialize, Clone, Default)]
 #[group(skip)]
 pub(crate) struct Options {
     #[clap(long, default_value = "")]
-    /// Command binary.
+    /// Command binary. The process is killed if it doesn't exit within the attempt timeout.
     pub cmd_binary: String,
 
     #[clap(long, default_value = "")]
-    /// Command arguments. {USERNAME}, {PASSWORD}, {TARGET} and {PORT} can be used as placeholders.
+    /// Command arguments. {USERNAME}, {PASSWORD}, {TARGET} and {PORT} can be used as placeholders, they are replaced after the arguments are split so every value is passed as a single argument and never interpreted by a shell.
     pub cmd_args: String,
 
+    #[clap(long, value_delimiter = ',')]
+    /// Comma separated list of process exit codes to be considered as errors (the attempt will be retried).
+    pub cmd_error_exit_codes: Vec<i32>,
+
     #[clap(long, default_value_t = 0)]
-    /// Process exit code to be considered as a positive match.
+    /// Process exit code to be considered as a positive match, required regardless of --cmd-match-mode.
     pub cmd_success_exit_code: i32,
 
     #[clap(long)]
     /// String to look for in the process standard output to be considered as a positive match.
     pub cmd_success_match: Option<String>,
+
+    #[clap(long)]
+    /// Regular expression to match against the process standard output to be considered as a positive match.
+    pub cmd_success_regex: Option<String>,
+
+    #[clap(long)]
+    /// Regular expression to match against the process standard error to be considered as a positive match.
+    pub cmd_stderr_regex: Option<String>,
+
+    #[clap(long)]
+    /// Parse the process standard output as JSON and check the value at this path, like 'result.authenticated' (must be set and not null or false) or 'data.users[0].status=ok'.
+    pub cmd_success_json: Option<String>,
+
+    #[clap(long, value_enum, default_value_t = MatchMode::All)]
+    /// Whether all or any of the standard output and error matchers must succeed.
+    pub cmd_match_mode: MatchMode,
 }
@@ -0,0 +1,137 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use reqwest::Client;
+
+use crate::creds::Credentials;
+use crate::session::{Error, Loot};
+use crate::utils;
+use crate::Options;
+use crate::Plugin;
+
+pub(crate) mod options;
+
+super::manager::register_plugin! {
+    "couchdb" => CouchDB::new()
+}
+
+const DEFAULT_PORT: u16 = 5984;
+
+#[derive(Clone)]
+pub(crate) struct CouchDB {
+    client: Client,
+    path: String,
+    ssl: bool,
+}
+
+impl CouchDB {
+    pub fn new() -> Self {
+        CouchDB {
+            client: Client::new(),
+            path: "/_session".to_owned(),
+            ssl: false,
+        }
+    }
+
+    fn get_base_url(&self, target: &str) -> Result<String, Error> {
+        let (host, port) = utils::parse_target(target, DEFAULT_PORT)?;
+        let host = if host.contains(':') {
+            // ipv6
+            format!("[{}]", host)
+        } else {
+            host
+        };
+
+        Ok(format!(
+            "{}://{}:{}",
+            if self.ssl { "https" } else { "http" },
+            host,
+            port
+        ))
+    }
+
+    // grab the server version from the welcome message
+    async fn get_version(&self, base_url: &str, timeout: Duration) -> Option<String> {
+        let res = self
+            .client
+            .get(format!("{}/", base_url))
+            .timeout(timeout)
+            .send()
+            .await
+            .ok()?;
+        let body = res.text().await.ok()?;
+        let welcome: serde_json::Value = serde_json::from_str(&body).ok()?;
+
+        welcome
+            .get("version")
+            .and_then(|v| v.as_str())
+            .map(|v| v.to_owned())
+    }
+}
+
+#[async_trait]
+impl Plugin for CouchDB {
+    fn description(&self) -> &'static str {
+        "CouchDB password authentication."
+    }
+
+    fn loot_schema(&self) -> &[&str] {
+        &["username", "password", "version"]
+    }
+
+    fn default_port(&self) -> Option<u16> {
+        Some(DEFAULT_PORT)
+    }
+
+    fn setup(&mut self, opts: &Options) -> Result<(), Error> {
+        self.path.clone_from(&opts.couchdb.couchdb_path);
+        self.ssl = opts.couchdb.couchdb_ssl;
+        self.client = Client::builder()
+            .no_proxy() // used to set auto_sys_proxy to false, see https://github.com/evilsocket/legba/issues/8
+            .danger_accept_invalid_certs(true)
+            .build()
+            .map_err(|e| e.to_string())?;
+
+        Ok(())
+    }
+
+    async fn attempt(
+        &self,
+        creds: &Credentials,
+        timeout: Duration,
+    ) -> Result<Option<Vec<Loot>>, Error> {
+        let base_url = self.get_base_url(&creds.target)?;
+        let url = format!("{}/{}", base_url, self.path.trim_start_matches('/'));
+
+        let res = self
+            .client
+            .get(&url)
+            .basic_auth(&creds.username, Some(&creds.password))
+            .timeout(timeout)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        utils::http::check_rate_limit(&res)?;
+
+        let status = res.status().as_u16();
+        log::debug!("status={}", status);
+
+        match status {
+            200 => {
+                let mut data = vec![
+                    ("username".to_owned(), creds.username.to_owned()),
+                    ("password".to_owned(), creds.password.to_owned()),
+                ];
+
+                if let Some(version) = self.get_version(&base_url, timeout).await {
+                    data.push(("version".to_owned(), version));
+                }
+
+                Ok(Some(vec![Loot::new("couchdb", &base_url, data)]))
+            }
+            401 | 403 => Ok(None),
+            _ => Err(format!("unexpected status code {} from {}", status, &url).into()),
+        }
+    }
+}
@@ -0,0 +1,13 @@
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+
+#[derive(Parser, Debug, Serialize, Deserialize, Clone, Default)]
+#[group(skip)]
+pub(crate) struct Options {
+    #[clap(long, default_value = "/_session")]
+    /// CouchDB path to authenticate against.
+    pub couchdb_path: String,
+    #[clap(long, default_value_t = false)]
+    /// Enable HTTPS for CouchDB (certificates are not verified).
+    pub couchdb_ssl: bool,
+}
@@ -4,7 +4,9 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use async_trait::async_trait;
+use rand::{distributions::Alphanumeric, Rng};
 use tokio::sync::Mutex;
+use trust_dns_resolver::proto::rr::RecordType;
 use trust_dns_resolver::{config::*, AsyncResolver, TokioAsyncResolver};
 use x509_parser::prelude::{FromDer, GeneralName, X509Certificate};
 
@@ -23,12 +25,69 @@ super::manager::register_plugin! {
     "dns" => DNS::new()
 }
 
+fn parse_record_types(types: &str) -> Result<Vec<RecordType>, Error> {
+    let mut parsed = vec![];
+    for name in types.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+        let record_type = match name.to_ascii_uppercase().as_str() {
+            "A" => RecordType::A,
+            "AAAA" => RecordType::AAAA,
+            "CNAME" => RecordType::CNAME,
+            _ => {
+                return Err(
+                    format!("unsupported record type '{}', use A, AAAA or CNAME", name).into(),
+                )
+            }
+        };
+        if !parsed.contains(&record_type) {
+            parsed.push(record_type);
+        }
+    }
+
+    if parsed.is_empty() {
+        return Err("no record types specified".into());
+    }
+
+    Ok(parsed)
+}
+
+// what a name resolves to
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Resolution {
+    addresses: Vec<IpAddr>,
+    aliases: Vec<String>,
+}
+
+impl Resolution {
+    fn is_empty(&self) -> bool {
+        self.addresses.is_empty() && self.aliases.is_empty()
+    }
+
+    // remove what a random subdomain resolves to as well
+    fn without(self, wildcard: &Resolution) -> Self {
+        Self {
+            addresses: self
+                .addresses
+                .into_iter()
+                .filter(|ip| !wildcard.addresses.contains(ip))
+                .collect(),
+            aliases: self
+                .aliases
+                .into_iter()
+                .filter(|alias| !wildcard.aliases.contains(alias))
+                .collect(),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub(crate) struct DNS {
     resolver: Option<TokioAsyncResolver>,
     opts: options::Options,
+    record_types: Vec<RecordType>,
     hits: Arc<Mutex<HashMap<IpAddr, usize>>>,
     domains: Arc<Mutex<HashMap<String, u8>>>,
+    // what a random subdomain of each target resolves to
+    wildcards: Arc<Mutex<HashMap<String, Resolution>>>,
 }
 
 impl DNS {
@@ -36,9 +95,69 @@ impl DNS {
         DNS {
             resolver: None,
             opts: options::Options::default(),
+            record_types: vec![],
             hits: Arc::new(Mutex::new(HashMap::default())),
             domains: Arc::new(Mutex::new(HashMap::default())),
+            wildcards: Arc::new(Mutex::new(HashMap::default())),
+        }
+    }
+
+    async fn resolve(&self, name: &str) -> Resolution {
+        let resolver = self.resolver.as_ref().unwrap();
+        let mut resolution = Resolution::default();
+
+        if self.record_types.is_empty() {
+            if let Ok(response) = resolver.lookup_ip(name).await {
+                resolution.addresses = response.iter().collect();
+            }
+        } else {
+            for record_type in &self.record_types {
+                if let Ok(response) = resolver.lookup(name, *record_type).await {
+                    // the answer can include the records of the whole CNAME chain
+                    for data in response.iter() {
+                        if let Some(ip) = data.ip_addr() {
+                            if !resolution.addresses.contains(&ip) {
+                                resolution.addresses.push(ip);
+                            }
+                        } else if let Some(cname) = data.as_cname() {
+                            let alias = cname.0.to_utf8().trim_end_matches('.').to_lowercase();
+                            if !resolution.aliases.contains(&alias) {
+                                resolution.aliases.push(alias);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        resolution.addresses.retain(|ip| !ip.is_loopback());
+        resolution
+    }
+
+    // resolve a random subdomain of the domain once, anything it resolves to is a wildcard answer
+    async fn wildcard(&self, domain: &str) -> Resolution {
+        let mut wildcards = self.wildcards.lock().await;
+        if let Some(wildcard) = wildcards.get(domain) {
+            return wildcard.clone();
+        }
+
+        let label: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(16)
+            .map(|c| char::from(c).to_ascii_lowercase())
+            .collect();
+        let wildcard = self.resolve(&format!("{}.{}", label, domain)).await;
+        if !wildcard.is_empty() {
+            log::warn!(
+                "{} has wildcard DNS records ({:?} {:?}), they will be filtered out",
+                domain,
+                &wildcard.addresses,
+                &wildcard.aliases
+            );
         }
+
+        wildcards.insert(domain.to_owned(), wildcard.clone());
+        wildcard
     }
 
     async fn filter(&self, addresses: Vec<IpAddr>) -> Vec<IpAddr> {
@@ -83,7 +202,7 @@ impl DNS {
             Err(_) => return vec![],
         };
         // upgrade to TLS
-        let tls = match upgrade_tcp_stream_to_tls(stream, timeout).await {
+        let tls = match upgrade_tcp_stream_to_tls(stream, &https_address, timeout).await {
             Ok(t) => t,
             Err(_) => return vec![],
         };
@@ -163,12 +282,19 @@ impl Plugin for DNS {
         "DNS subdomain enumeration."
     }
 
+    fn loot_schema(&self) -> &[&str] {
+        &["addresses", "aliases", "alt_name_of"]
+    }
+
     fn payload_strategy(&self) -> PayloadStrategy {
         PayloadStrategy::Single
     }
 
     fn setup(&mut self, opts: &Options) -> Result<(), Error> {
         self.opts = opts.dns.clone();
+        if let Some(types) = opts.dns.dns_record_types.as_ref() {
+            self.record_types = parse_record_types(types)?;
+        }
         self.resolver = Some(if let Some(resolvers) = opts.dns.dns_resolvers.as_ref() {
             let ips: Vec<IpAddr> = resolvers
                 .split(',')
@@ -184,7 +310,7 @@ impl Plugin for DNS {
 
             let mut options = ResolverOpts::default();
 
-            options.num_concurrent_reqs = opts.concurrency;
+            options.num_concurrent_reqs = opts.concurrency();
             options.attempts = opts.dns.dns_attempts;
             options.timeout = Duration::from_millis(opts.timeout);
             options.shuffle_dns_servers = true;
@@ -213,65 +339,112 @@ impl Plugin for DNS {
             return Ok(None);
         }
 
-        // attempt resolving this subdomain to a one or more IP addresses
-        if let Ok(response) = self.resolver.as_ref().unwrap().lookup_ip(&subdomain).await {
-            // collect valid IPs
-            let addresses: Vec<IpAddr> = response.iter().filter(|ip| !ip.is_loopback()).collect();
-            // Some domains are configured to resolve any subdomain, whatever it is, to the same IP. We do
-            // this filtering in order too many positives for an address and work around this behaviour.
-            let addresses = self.filter(addresses).await;
-            if !addresses.is_empty() {
-                let mut loot_data = vec![];
-                let addr_data = if self.opts.dns_ip_lookup {
-                    // perform reverse lookup of the IPs if we have to
-                    let mut parts = vec![];
-                    for ip in &addresses {
-                        if let Ok(hostname) = dns_lookup::lookup_addr(ip) {
-                            if hostname != subdomain {
-                                parts.push(format!("{} ({})", ip, hostname));
-                            }
-                        } else {
-                            parts.push(ip.to_string());
+        // attempt resolving this subdomain to a one or more IP addresses or aliases
+        let mut resolution = self.resolve(&subdomain).await;
+        if !resolution.is_empty() && !self.opts.dns_no_wildcard_check {
+            resolution = resolution.without(&self.wildcard(&creds.target.to_lowercase()).await);
+        }
+
+        // Some domains are configured to resolve any subdomain, whatever it is, to the same IP. We do
+        // this filtering in order too many positives for an address and work around this behaviour.
+        let addresses = self.filter(resolution.addresses).await;
+        if !addresses.is_empty() || !resolution.aliases.is_empty() {
+            let mut loot_data = vec![];
+            let addr_data = if self.opts.dns_ip_lookup {
+                // perform reverse lookup of the IPs if we have to
+                let mut parts = vec![];
+                for ip in &addresses {
+                    if let Ok(hostname) = dns_lookup::lookup_addr(ip) {
+                        if hostname != subdomain {
+                            parts.push(format!("{} ({})", ip, hostname));
                         }
+                    } else {
+                        parts.push(ip.to_string());
                     }
+                }
 
-                    parts.join(", ")
-                } else {
-                    // just join the IPs
-                    addresses
-                        .iter()
-                        .map(|a| a.to_string())
-                        .collect::<Vec<String>>()
-                        .join(", ")
-                };
+                parts.join(", ")
+            } else {
+                // just join the IPs
+                addresses
+                    .iter()
+                    .map(|a| a.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            };
 
+            if !addresses.is_empty() {
                 loot_data.push(("addresses".to_owned(), addr_data));
+            }
+            if !resolution.aliases.is_empty() {
+                loot_data.push(("aliases".to_owned(), resolution.aliases.join(", ")));
+            }
 
-                let mut loot = vec![Loot::new("dns", &subdomain, loot_data)];
+            let mut loot = vec![Loot::new("dns", &subdomain, loot_data)];
 
-                // keep track of domains we processed already
-                self.domains.lock().await.insert(subdomain.to_owned(), 1);
-
-                if !self.opts.dns_no_https {
-                    let more_loot = self
-                        .get_additional_tls_loot(&creds.target, &subdomain, timeout)
-                        .await;
-
-                    // keep track of domains we processed already
-                    for item in more_loot.iter() {
-                        self.domains
-                            .lock()
-                            .await
-                            .insert(item.get_target().to_string(), 1);
-                    }
+            // keep track of domains we processed already
+            self.domains.lock().await.insert(subdomain.to_owned(), 1);
+
+            if !self.opts.dns_no_https {
+                let more_loot = self
+                    .get_additional_tls_loot(&creds.target, &subdomain, timeout)
+                    .await;
 
-                    loot.extend(more_loot);
+                // keep track of domains we processed already
+                for item in more_loot.iter() {
+                    self.domains
+                        .lock()
+                        .await
+                        .insert(item.get_target().to_string(), 1);
                 }
 
-                return Ok(Some(loot));
+                loot.extend(more_loot);
             }
+
+            return Ok(Some(loot));
         }
 
         Ok(None)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    use trust_dns_resolver::proto::rr::RecordType;
+
+    use super::{parse_record_types, Resolution};
+
+    #[test]
+    fn can_parse_record_types() {
+        assert_eq!(
+            parse_record_types("a, cname,A").unwrap(),
+            vec![RecordType::A, RecordType::CNAME]
+        );
+        assert!(parse_record_types("MX").is_err());
+        assert!(parse_record_types(" , ").is_err());
+    }
+
+    #[test]
+    fn wildcard_answers_are_filtered() {
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+        let wildcard = Resolution {
+            addresses: vec![ip("10.0.0.1")],
+            aliases: vec!["catchall.example.com".to_owned()],
+        };
+        let found = Resolution {
+            addresses: vec![ip("10.0.0.1"), ip("10.0.0.2")],
+            aliases: vec!["catchall.example.com".to_owned()],
+        };
+
+        assert_eq!(
+            found.without(&wildcard),
+            Resolution {
+                addresses: vec![ip("10.0.0.2")],
+                aliases: vec![],
+            }
+        );
+        assert!(wildcard.clone().without(&wildcard).is_empty());
+    }
+}
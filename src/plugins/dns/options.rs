@@ -22,4 +22,10 @@ pub(crate) struct Options {
     #[clap(long, default_value_t = false)]
     /// Do not fetch HTTPS certificates for new domains.
     pub dns_no_https: bool,
+    #[clap(long)]
+    /// Comma separated list of record types to query among A, AAAA and CNAME, by default the addresses are resolved with A and AAAA only if no A record is found.
+    pub dns_record_types: Option<String>,
+    #[clap(long, default_value_t = false)]
+    /// Do not resolve a random subdomain of the target to detect and filter out wildcard DNS responses.
+    pub dns_no_wildcard_check: bool,
 }
@@ -0,0 +1,450 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use reqwest::{
+    header::{AUTHORIZATION, CONTENT_TYPE},
+    Client, Identity,
+};
+use serde_json::{json, Value};
+
+use crate::creds::Credentials;
+use crate::session::{Error, Loot};
+use crate::utils;
+use crate::utils::net::TlsVersion;
+use crate::Options;
+use crate::Plugin;
+
+pub(crate) mod options;
+
+super::manager::register_plugin! {
+    "etcd" => Etcd::new()
+}
+
+const DEFAULT_PORT: u16 = 2379;
+
+fn tls_version(version: TlsVersion) -> reqwest::tls::Version {
+    match version {
+        TlsVersion::Tls10 => reqwest::tls::Version::TLS_1_0,
+        TlsVersion::Tls11 => reqwest::tls::Version::TLS_1_1,
+        TlsVersion::Tls12 => reqwest::tls::Version::TLS_1_2,
+    }
+}
+
+// etcd values are either strings or, depending on the gateway version, numbers
+fn as_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.to_owned()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+// the gateway reports grpc errors as {"error": "...", "code": N, "message": "..."}
+fn error_message(body: &Value) -> String {
+    body.get("message")
+        .or_else(|| body.get("error"))
+        .and_then(|v| v.as_str())
+        .unwrap_or_default()
+        .to_owned()
+}
+
+#[derive(Clone)]
+pub(crate) struct Etcd {
+    client: Client,
+    prefix: String,
+    ssl: bool,
+}
+
+impl Etcd {
+    pub fn new() -> Self {
+        Etcd {
+            client: Client::new(),
+            prefix: "/v3".to_owned(),
+            ssl: false,
+        }
+    }
+
+    fn get_base_url(&self, target: &str) -> Result<String, Error> {
+        let (host, port) = utils::parse_target(target, DEFAULT_PORT)?;
+        let host = if host.contains(':') {
+            // ipv6
+            format!("[{}]", host)
+        } else {
+            host
+        };
+
+        Ok(format!(
+            "{}://{}:{}",
+            if self.ssl { "https" } else { "http" },
+            host,
+            port
+        ))
+    }
+
+    fn request_error(&self, error: reqwest::Error) -> Error {
+        let mut message = error.to_string();
+        let mut source = std::error::Error::source(&error);
+        while let Some(err) = source {
+            message = format!("{}: {}", message, err);
+            source = err.source();
+        }
+
+        let lower = message.to_lowercase();
+        if error.is_timeout() {
+            Error::Timeout
+        } else if self.ssl
+            && error.is_connect()
+            && (lower.contains("ssl") || lower.contains("tls") || lower.contains("handshake"))
+        {
+            utils::net::tls_error(message)
+        } else {
+            message.into()
+        }
+    }
+
+    // POST a JSON request to the gateway, returns the status code and the parsed response
+    async fn call(
+        &self,
+        base_url: &str,
+        endpoint: &str,
+        token: Option<&str>,
+        body: Value,
+        timeout: Duration,
+    ) -> Result<(u16, Value), Error> {
+        let mut request = self
+            .client
+            .post(format!("{}{}/{}", base_url, self.prefix, endpoint))
+            .header(CONTENT_TYPE, "application/json")
+            .body(body.to_string())
+            .timeout(timeout);
+        if let Some(token) = token {
+            request = request.header(AUTHORIZATION, token);
+        }
+
+        let res = request.send().await.map_err(|e| self.request_error(e))?;
+        utils::http::check_rate_limit(&res)?;
+
+        let status = res.status().as_u16();
+        let body = res.text().await.map_err(|e| self.request_error(e))?;
+
+        Ok((status, serde_json::from_str(&body).unwrap_or(Value::Null)))
+    }
+
+    // version and members of the cluster, best effort since the user might lack the permissions
+    async fn get_cluster_info(
+        &self,
+        base_url: &str,
+        token: &str,
+        timeout: Duration,
+    ) -> Vec<(String, String)> {
+        let mut data = vec![];
+
+        if let Ok((200, status)) = self
+            .call(
+                base_url,
+                "maintenance/status",
+                Some(token),
+                json!({}),
+                timeout,
+            )
+            .await
+        {
+            if let Some(version) = status.get("version").and_then(as_string) {
+                data.push(("version".to_owned(), version));
+            }
+        }
+
+        if let Ok((200, list)) = self
+            .call(
+                base_url,
+                "cluster/member/list",
+                Some(token),
+                json!({}),
+                timeout,
+            )
+            .await
+        {
+            let members: Vec<String> = list
+                .get("members")
+                .and_then(|v| v.as_array())
+                .map(|members| {
+                    members
+                        .iter()
+                        .map(|member| {
+                            let name = member.get("name").and_then(as_string).unwrap_or_default();
+                            let urls: Vec<String> = member
+                                .get("clientURLs")
+                                .and_then(|v| v.as_array())
+                                .map(|urls| urls.iter().filter_map(as_string).collect())
+                                .unwrap_or_default();
+                            format!("{} ({})", name, urls.join(" "))
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+
+            if !members.is_empty() {
+                data.push(("members".to_owned(), members.join(", ")));
+            }
+        }
+
+        data
+    }
+}
+
+#[async_trait]
+impl Plugin for Etcd {
+    fn description(&self) -> &'static str {
+        "etcd v3 password authentication."
+    }
+
+    fn loot_schema(&self) -> &[&str] {
+        &[
+            "username",
+            "password",
+            "token",
+            "cluster_id",
+            "member_id",
+            "version",
+            "members",
+        ]
+    }
+
+    fn default_port(&self) -> Option<u16> {
+        Some(DEFAULT_PORT)
+    }
+
+    fn setup(&mut self, opts: &Options) -> Result<(), Error> {
+        self.prefix = format!("/{}", opts.etcd.etcd_api_prefix.trim_matches('/'));
+        self.ssl = opts.etcd.etcd_ssl;
+
+        let mut builder = Client::builder()
+            .no_proxy() // used to set auto_sys_proxy to false, see https://github.com/evilsocket/legba/issues/8
+            .danger_accept_invalid_certs(true);
+
+        if let Some(timeout) = opts.connect_timeout.map(Duration::from_millis) {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(min) = opts.tls_min_version {
+            builder = builder.min_tls_version(tls_version(min));
+        }
+        if let Some(max) = opts.tls_max_version {
+            builder = builder.max_tls_version(tls_version(max));
+        }
+
+        match (&opts.etcd.etcd_cert, &opts.etcd.etcd_key) {
+            (Some(cert), Some(key)) => {
+                let cert = std::fs::read(cert)
+                    .map_err(|e| format!("can't read client certificate {}: {}", cert, e))?;
+                let key = std::fs::read(key)
+                    .map_err(|e| format!("can't read client key {}: {}", key, e))?;
+                let identity = Identity::from_pkcs8_pem(&cert, &key)
+                    .map_err(|e| format!("invalid client certificate or key: {}", e))?;
+
+                builder = builder.identity(identity);
+                self.ssl = true;
+            }
+            (None, None) => {}
+            _ => {
+                return Err("--etcd-cert and --etcd-key must be used together"
+                    .to_owned()
+                    .into())
+            }
+        }
+
+        self.client = builder.build().map_err(|e| e.to_string())?;
+
+        Ok(())
+    }
+
+    async fn attempt(
+        &self,
+        creds: &Credentials,
+        timeout: Duration,
+    ) -> Result<Option<Vec<Loot>>, Error> {
+        let base_url = self.get_base_url(&creds.target)?;
+        let (status, body) = self
+            .call(
+                &base_url,
+                "auth/authenticate",
+                None,
+                json!({ "name": creds.username, "password": creds.password }),
+                timeout,
+            )
+            .await?;
+
+        log::debug!("status={} body={}", status, body);
+
+        let message = error_message(&body);
+        let lower = message.to_lowercase();
+
+        if let (200, Some(token)) = (status, body.get("token").and_then(|v| v.as_str())) {
+            let mut data = vec![
+                ("username".to_owned(), creds.username.to_owned()),
+                ("password".to_owned(), creds.password.to_owned()),
+                ("token".to_owned(), token.to_owned()),
+            ];
+
+            if let Some(header) = body.get("header") {
+                for key in ["cluster_id", "member_id"] {
+                    if let Some(value) = header.get(key).and_then(as_string) {
+                        data.push((key.to_owned(), value));
+                    }
+                }
+            }
+
+            data.extend(self.get_cluster_info(&base_url, token, timeout).await);
+
+            Ok(Some(vec![Loot::new("etcd", &base_url, data)]))
+        } else if lower.contains("too many requests") {
+            Err(Error::rate_limited(None))
+        } else if lower.contains("authentication is not enabled") {
+            Err(Error::Auth(format!(
+                "authentication is not enabled on {}, no credentials required",
+                &base_url
+            )))
+        } else if status == 401
+            || lower.contains("authentication failed")
+            || lower.contains("invalid user id or password")
+        {
+            Ok(None)
+        } else {
+            Err(format!(
+                "unexpected status code {} from {}: {}",
+                status, &base_url, message
+            )
+            .into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::Etcd;
+    use crate::creds::Credentials;
+    use crate::session::Error;
+    use crate::Options;
+    use crate::Plugin;
+
+    async fn read_request(stream: &mut tokio::net::TcpStream) -> String {
+        let mut request = vec![];
+        let mut buf = [0u8; 1024];
+
+        loop {
+            let n = stream.read(&mut buf).await.unwrap();
+            if n == 0 {
+                break;
+            }
+            request.extend_from_slice(&buf[..n]);
+
+            let text = String::from_utf8_lossy(&request).to_string();
+            if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                let length = head
+                    .lines()
+                    .find_map(|line| {
+                        let (name, value) = line.split_once(':')?;
+                        name.eq_ignore_ascii_case("content-length")
+                            .then(|| value.trim().parse::<usize>().ok())?
+                    })
+                    .unwrap_or(0);
+                if body.len() >= length {
+                    return text;
+                }
+            }
+        }
+
+        String::from_utf8_lossy(&request).to_string()
+    }
+
+    async fn etcd_server() -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let request = read_request(&mut stream).await;
+                let authorized = request.contains("authorization: tok3n");
+
+                let (status, body) = if request.starts_with("POST /v3/auth/authenticate ") {
+                    if request.contains(r#""password":"s3cret""#) {
+                        (
+                            "200 OK",
+                            r#"{"header":{"cluster_id":"14841639068965178418","member_id":"10276657743932975437","revision":"5","raft_term":"2"},"token":"tok3n"}"#,
+                        )
+                    } else {
+                        (
+                            "400 Bad Request",
+                            r#"{"error":"etcdserver: authentication failed, invalid user ID or password","code":3,"message":"etcdserver: authentication failed, invalid user ID or password"}"#,
+                        )
+                    }
+                } else if request.starts_with("POST /v3/maintenance/status ") && authorized {
+                    ("200 OK", r#"{"version":"3.5.9","dbSize":"20480"}"#)
+                } else if request.starts_with("POST /v3/cluster/member/list ") && authorized {
+                    (
+                        "200 OK",
+                        r#"{"members":[{"ID":"10276657743932975437","name":"default","clientURLs":["http://localhost:2379"]}]}"#,
+                    )
+                } else {
+                    (
+                        "401 Unauthorized",
+                        r#"{"error":"etcdserver: user name is empty","code":16}"#,
+                    )
+                };
+
+                let response = format!(
+                    "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+
+        address
+    }
+
+    fn creds(target: &str, password: &str) -> Credentials {
+        Credentials {
+            target: target.to_owned(),
+            username: "root".to_owned(),
+            password: password.to_owned(),
+        }
+    }
+
+    #[tokio::test]
+    async fn authenticates_and_grabs_cluster_info() {
+        let target = etcd_server().await;
+        let mut etcd = Etcd::new();
+        let mut opts = Options::default();
+        opts.etcd.etcd_api_prefix = "/v3".to_owned();
+        assert_eq!(Ok(()), etcd.setup(&opts));
+
+        let timeout = std::time::Duration::from_secs(5);
+        assert!(matches!(
+            etcd.attempt(&creds(&target, "wrong"), timeout).await,
+            Ok(None)
+        ));
+
+        let loot = etcd
+            .attempt(&creds(&target, "s3cret"), timeout)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            loot[0].format_template("{token} {cluster_id} {version} {members}"),
+            "tok3n 14841639068965178418 3.5.9 default (http://localhost:2379)"
+        );
+    }
+
+    #[test]
+    fn requires_cert_and_key_together() {
+        let mut opts = Options::default();
+        opts.etcd.etcd_cert = Some("client.pem".to_owned());
+
+        let mut etcd = Etcd::new();
+        assert!(matches!(etcd.setup(&opts), Err(Error::Other(_))));
+    }
+}
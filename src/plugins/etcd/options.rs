@@ -0,0 +1,19 @@
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+
+#[derive(Parser, Debug, Serialize, Deserialize, Clone, Default)]
+#[group(skip)]
+pub(crate) struct Options {
+    #[clap(long, default_value_t = false)]
+    /// Enable HTTPS for etcd (certificates are not verified), implied by --etcd-cert.
+    pub etcd_ssl: bool,
+    #[clap(long)]
+    /// PEM client certificate for etcd servers requiring mutual TLS.
+    pub etcd_cert: Option<String>,
+    #[clap(long)]
+    /// PEM PKCS#8 private key of --etcd-cert, convert other formats with openssl pkcs8 -topk8 -nocrypt.
+    pub etcd_key: Option<String>,
+    #[clap(long, default_value = "/v3")]
+    /// Prefix of the etcd JSON gateway API, /v3beta for etcd 3.3 and /v3alpha for etcd 3.2.
+    pub etcd_api_prefix: String,
+}
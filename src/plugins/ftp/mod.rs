@@ -5,22 +5,87 @@ use std::time::Duration;
 use async_trait::async_trait;
 
 use crate::session::{Error, Loot};
-use crate::utils;
+use crate::utils::{self, net};
 use crate::Options;
 use crate::Plugin;
 
 use crate::creds::Credentials;
 
+pub(crate) mod options;
+
 super::manager::register_plugin! {
     "ftp" => FTP::new()
 }
 
+const DEFAULT_PORT: u16 = 21;
+
 #[derive(Clone)]
-pub(crate) struct FTP {}
+pub(crate) struct FTP {
+    // check the session is actually usable after login
+    confirm: bool,
+}
 
 impl FTP {
     pub fn new() -> Self {
-        FTP {}
+        FTP { confirm: false }
+    }
+
+    // some servers accept any login and then deny everything, get the working directory and try
+    // to list it to find out
+    async fn confirm(stream: &mut FtpStream) -> Vec<(String, String)> {
+        let mut data = vec![];
+
+        match stream.pwd().await {
+            Ok(cwd) => data.push(("cwd".to_owned(), cwd)),
+            Err(e) => log::debug!("PWD failed: {}", e),
+        }
+
+        // async_ftp only opens the data channel in passive mode
+        match stream.list(None).await {
+            Ok(entries) => {
+                data.push(("list".to_owned(), "true".to_owned()));
+                data.push(("entries".to_owned(), entries.len().to_string()));
+            }
+            Err(e) => {
+                log::debug!("LIST failed: {}", e);
+                data.push(("list".to_owned(), "false".to_owned()));
+            }
+        }
+
+        data
+    }
+
+    // async_ftp doesn't expose the welcome message, read it from a new connection
+    async fn banner(address: &str, timeout: Duration) -> Result<String, Error> {
+        let mut stream = net::async_tcp_stream(address, timeout, false).await?;
+        // multiline replies end with the line starting with the code and a space
+        let lines = tokio::time::timeout(
+            timeout,
+            net::read_lines_until(&mut stream, |line| {
+                reply_continues(line).is_some_and(|more| !more)
+            }),
+        )
+        .await??;
+
+        Ok(lines
+            .iter()
+            .map(|line| match reply_continues(line) {
+                Some(_) => line[3..].trim_start_matches('-').trim(),
+                None => line.trim(),
+            })
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<&str>>()
+            .join(" "))
+    }
+}
+
+// if the line starts with a reply code, whether more lines of the same reply follow
+fn reply_continues(line: &str) -> Option<bool> {
+    let code = line.get(..3)?;
+    if code.bytes().all(|b| b.is_ascii_digit()) {
+        Some(line[3..].starts_with('-'))
+    } else {
+        None
     }
 }
 
@@ -30,7 +95,16 @@ impl Plugin for FTP {
         "FTP password authentication."
     }
 
-    fn setup(&mut self, _opts: &Options) -> Result<(), Error> {
+    fn loot_schema(&self) -> &[&str] {
+        &["username", "password", "banner", "cwd", "list", "entries"]
+    }
+
+    fn default_port(&self) -> Option<u16> {
+        Some(DEFAULT_PORT)
+    }
+
+    fn setup(&mut self, opts: &Options) -> Result<(), Error> {
+        self.confirm = opts.ftp.ftp_confirm;
         Ok(())
     }
 
@@ -39,24 +113,61 @@ impl Plugin for FTP {
         creds: &Credentials,
         timeout: Duration,
     ) -> Result<Option<Vec<Loot>>, Error> {
-        let address = utils::parse_target_address(&creds.target, 21)?;
+        let address = utils::parse_target_address(&creds.target, DEFAULT_PORT)?;
 
         let mut stream = tokio::time::timeout(timeout, FtpStream::connect(&address))
-            .await
-            .map_err(|e| e.to_string())?
+            .await?
             .map_err(|e| e.to_string())?;
 
         if stream.login(&creds.username, &creds.password).await.is_ok() {
-            Ok(Some(vec![Loot::new(
-                "ftp",
-                &address,
-                [
-                    ("username".to_owned(), creds.username.to_owned()),
-                    ("password".to_owned(), creds.password.to_owned()),
-                ],
-            )]))
+            let mut data = vec![
+                ("username".to_owned(), creds.username.to_owned()),
+                ("password".to_owned(), creds.password.to_owned()),
+            ];
+
+            match Self::banner(&address, timeout).await {
+                Ok(banner) => data.push(("banner".to_owned(), banner)),
+                Err(e) => log::debug!("[{}] could not read the banner: {}", &address, e),
+            }
+
+            if self.confirm {
+                // the login already happened, a slow listing shouldn't turn it into an error
+                match tokio::time::timeout(timeout, Self::confirm(&mut stream)).await {
+                    Ok(confirmation) => data.extend(confirmation),
+                    Err(_) => data.push(("list".to_owned(), "false".to_owned())),
+                }
+            }
+
+            Ok(Some(vec![Loot::new("ftp", &address, data)]))
         } else {
             Ok(None)
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpListener;
+
+    use super::FTP;
+
+    #[tokio::test]
+    async fn can_read_multiline_banner() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            stream
+                .write_all(b"220-ProFTPD 1.3.8 Server\r\n Authorized use only\r\n220 ready\r\n")
+                .await
+                .unwrap();
+        });
+
+        let banner = FTP::banner(&address, Duration::from_secs(5)).await.unwrap();
+        assert_eq!(banner, "ProFTPD 1.3.8 Server Authorized use only ready");
+    }
+}
@@ -0,0 +1,10 @@
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+
+#[derive(Parser, Debug, Serialize, Deserialize, Clone, Default)]
+#[group(skip)]
+pub(crate) struct Options {
+    #[clap(long, default_value_t = false)]
+    /// After a successful login, get the working directory and try to list it (the data channel is opened in passive mode), to tell apart usable credentials from servers accepting any login.
+    pub ftp_confirm: bool,
+}
@@ -0,0 +1,271 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use reqwest::{
+    header::{HeaderMap, CONTENT_TYPE, SERVER, SET_COOKIE},
+    Client,
+};
+use url::Url;
+
+use crate::creds::Credentials;
+use crate::session::{Error, Loot};
+use crate::utils;
+use crate::Options;
+use crate::Plugin;
+
+super::manager::register_plugin! {
+    "git.http" => Git::new()
+}
+
+// content type of the refs advertisement returned by the smart HTTP protocol
+const UPLOAD_PACK_ADVERTISEMENT: &str = "application/x-git-upload-pack-advertisement";
+
+fn with_default_schema(target: &str) -> String {
+    if !target.contains("://") {
+        format!("http://{}", target)
+    } else {
+        target.to_owned()
+    }
+}
+
+// the repository url without the smart HTTP endpoint, if it was part of the target
+fn repo_url(target: &str) -> Result<String, Error> {
+    let url = Url::parse(&with_default_schema(target))
+        .map_err(|e| format!("'{}' is not a valid URL: {}", target, e))?;
+
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return Err(format!("'{}' is not an http:// or https:// URL", target).into());
+    } else if url.host().is_none() {
+        return Err(format!("'{}' has no host", target).into());
+    }
+
+    let mut repo = url;
+    repo.set_query(None);
+    repo.set_fragment(None);
+
+    Ok(repo
+        .as_str()
+        .trim_end_matches('/')
+        .trim_end_matches("/info/refs")
+        .to_owned())
+}
+
+// the advertisement starts with a "# service=git-upload-pack" pkt-line
+fn is_refs_advertisement(content_type: &str, body: &str) -> bool {
+    content_type.starts_with(UPLOAD_PACK_ADVERTISEMENT)
+        && body
+            .get(4..)
+            .is_some_and(|line| line.starts_with("# service=git-upload-pack"))
+}
+
+// guess the hosting software from its headers, cookies and the advertised git agent
+fn detect_server(headers: &HeaderMap, body: &str) -> Option<&'static str> {
+    let server = headers
+        .get(SERVER)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_lowercase();
+    let cookies = headers
+        .get_all(SET_COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .collect::<Vec<&str>>()
+        .join(";");
+
+    if server.contains("github") || body.contains("agent=git/github") {
+        Some("GitHub")
+    } else if cookies.contains("_gitlab_session")
+        || headers
+            .keys()
+            .any(|name| name.as_str().starts_with("x-gitlab"))
+    {
+        Some("GitLab")
+    } else if cookies.contains("i_like_gitea") || body.contains("agent=gitea") {
+        Some("Gitea")
+    } else if cookies.contains("i_like_gogs") {
+        Some("Gogs")
+    } else {
+        None
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct Git {
+    client: Client,
+}
+
+impl Git {
+    pub fn new() -> Self {
+        Git {
+            client: Client::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl Plugin for Git {
+    fn description(&self) -> &'static str {
+        "Git smart HTTP basic authentication."
+    }
+
+    fn loot_schema(&self) -> &[&str] {
+        &["username", "password", "repo", "server"]
+    }
+
+    fn validate_target(&self, target: &str) -> Result<(), Error> {
+        repo_url(target).map(|_| ())
+    }
+
+    fn setup(&mut self, opts: &Options) -> Result<(), Error> {
+        let mut builder = Client::builder()
+            .no_proxy() // used to set auto_sys_proxy to false, see https://github.com/evilsocket/legba/issues/8
+            .danger_accept_invalid_certs(true);
+
+        if let Some(timeout) = opts.connect_timeout.map(Duration::from_millis) {
+            builder = builder.connect_timeout(timeout);
+        }
+
+        self.client = builder.build().map_err(|e| e.to_string())?;
+
+        Ok(())
+    }
+
+    async fn attempt(
+        &self,
+        creds: &Credentials,
+        timeout: Duration,
+    ) -> Result<Option<Vec<Loot>>, Error> {
+        let repo = repo_url(&creds.target)?;
+        let url = format!("{}/info/refs?service=git-upload-pack", &repo);
+
+        let res = self
+            .client
+            .get(&url)
+            .basic_auth(&creds.username, Some(&creds.password))
+            .header("Git-Protocol", "version=2")
+            .timeout(timeout)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        utils::http::check_rate_limit(&res)?;
+
+        let status = res.status().as_u16();
+        log::debug!("status={}", status);
+
+        match status {
+            200 => {
+                let headers = res.headers().clone();
+                let content_type = headers
+                    .get(CONTENT_TYPE)
+                    .and_then(|v| v.to_str().ok())
+                    .unwrap_or_default()
+                    .to_owned();
+                let body = res.text().await.map_err(|e| e.to_string())?;
+
+                if !is_refs_advertisement(&content_type, &body) {
+                    // most likely a login page, the target is not a smart HTTP endpoint
+                    return Err(Error::ProtocolMismatch(format!(
+                        "{} is not a git smart HTTP repository (content type '{}')",
+                        &repo, content_type
+                    )));
+                }
+
+                let mut data = vec![
+                    ("username".to_owned(), creds.username.to_owned()),
+                    ("password".to_owned(), creds.password.to_owned()),
+                    ("repo".to_owned(), repo.to_owned()),
+                ];
+                if let Some(server) = detect_server(&headers, &body) {
+                    data.push(("server".to_owned(), server.to_owned()));
+                }
+
+                Ok(Some(vec![Loot::new("git.http", &repo, data)]))
+            }
+            401 | 403 => Ok(None),
+            _ => Err(format!("unexpected status code {} from {}", status, &url).into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::{repo_url, Git};
+    use crate::creds::Credentials;
+    use crate::Options;
+    use crate::Plugin;
+
+    // "admin:s3cret"
+    const AUTHORIZATION: &str = "authorization: Basic YWRtaW46czNjcmV0";
+
+    async fn gitea_server() -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = [0u8; 1024];
+                let n = stream.read(&mut request).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&request[..n]).to_string();
+
+                let response = if !request
+                    .starts_with("GET /org/repo.git/info/refs?service=git-upload-pack ")
+                {
+                    "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                        .to_owned()
+                } else if request.contains(AUTHORIZATION) {
+                    let body = "001e# service=git-upload-pack\n0000000eversion 2\n0016agent=gitea/1.21\n0000";
+                    format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/x-git-upload-pack-advertisement\r\nSet-Cookie: i_like_gitea=abcd; Path=/\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    )
+                } else {
+                    "HTTP/1.1 401 Unauthorized\r\nWWW-Authenticate: Basic realm=\"Gitea\"\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_owned()
+                };
+
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+
+        address
+    }
+
+    #[test]
+    fn strips_the_smart_http_endpoint() {
+        assert_eq!(
+            repo_url("git.example.com/org/repo.git/").unwrap(),
+            "http://git.example.com/org/repo.git"
+        );
+        assert_eq!(
+            repo_url("https://git.example.com/org/repo.git/info/refs?service=git-upload-pack")
+                .unwrap(),
+            "https://git.example.com/org/repo.git"
+        );
+        assert!(repo_url("ftp://git.example.com/org/repo.git").is_err());
+    }
+
+    #[tokio::test]
+    async fn can_authenticate() {
+        let target = format!("http://{}/org/repo.git", gitea_server().await);
+        let mut git = Git::new();
+        assert_eq!(Ok(()), git.setup(&Options::default()));
+
+        let timeout = std::time::Duration::from_secs(5);
+        let mut creds = Credentials {
+            target: target.clone(),
+            username: "admin".to_owned(),
+            password: "wrong".to_owned(),
+        };
+        assert!(matches!(git.attempt(&creds, timeout).await, Ok(None)));
+
+        creds.password = "s3cret".to_owned();
+        let loot = git.attempt(&creds, timeout).await.unwrap().unwrap();
+        assert_eq!(
+            loot[0].format_template("{username} {repo} {server}"),
+            format!("admin {} Gitea", target)
+        );
+    }
+}
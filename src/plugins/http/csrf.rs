@@ -13,10 +13,11 @@ use crate::session::Error;
 pub(crate) struct Config {
     page: String,
     re: Regex,
+    max_body: usize,
 }
 
 impl Config {
-    pub fn new(page: &str, re: &str) -> Result<Self, Error> {
+    pub fn new(page: &str, re: &str, max_body: usize) -> Result<Self, Error> {
         let re = Regex::new(re).map_err(|e| e.to_string())?;
         // first group = whole string
         // second group = token name
@@ -25,13 +26,13 @@ impl Config {
             return Err(format!(
                 "csrf expression expected to have 2 capture groups, got {}",
                 re.captures_len() - 1
-            ));
+            ).into());
         }
 
         let page = Url::parse(page).map_err(|e| e.to_string())?.to_string();
         let re = re;
 
-        Ok(Self { page, re })
+        Ok(Self { page, re, max_body })
     }
 }
 
@@ -58,7 +59,7 @@ pub(crate) async fn handle(
     {
         Err(e) => {
             log::debug!("error requesting csrf token from {}: {:?}", config.page, e);
-            Err(e.to_string())
+            Err(e.to_string().into())
         }
         Ok(res) => {
             if res.status().is_success() {
@@ -69,7 +70,7 @@ pub(crate) async fn handle(
                     log::warn!("csrf page unexpectetly did not return any cookie");
                 }
 
-                let body = res.text().await;
+                let body = super::read_body(res, config.max_body).await;
                 if let Ok(body) = body {
                     if let Some(captures) = config.re.captures(&body) {
                         if captures.len() == 3 {
@@ -91,7 +92,7 @@ pub(crate) async fn handle(
                 } else {
                     let err = body.err().unwrap().to_string();
                     log::error!("error fetching csrf page body: {}", &err);
-                    Err(err)
+                    Err(err.into())
                 }
             } else {
                 log::error!("csrf token page returned status: {:?}", res.status());
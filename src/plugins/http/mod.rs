@@ -1,14 +1,19 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use async_trait::async_trait;
+use base64::prelude::{Engine, BASE64_STANDARD};
 use rand::seq::SliceRandom;
 use reqwest::{
     header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE, COOKIE, HOST, USER_AGENT},
-    multipart, redirect, Client, Method, RequestBuilder, Response,
+    multipart, redirect, Client, Method, RequestBuilder, Response, StatusCode,
 };
 use url::Url;
 
 use crate::session::{Error, Loot};
+use crate::utils::{self, net};
 use crate::Options;
 
 use crate::creds::Credentials;
@@ -23,6 +28,9 @@ mod payload;
 mod placeholders;
 mod ua;
 
+// Body of http.json requests when no --http-payload is given
+const DEFAULT_JSON_PAYLOAD: &str = r#"{"username":"{USERNAME}","password":"{PASSWORD}"}"#;
+
 // Placeholders used for interpolating --http-success-string
 const HTTP_USERNAME_VAR: &str = "{$username}";
 const HTTP_PASSWORD_VAR: &str = "{$password}";
@@ -31,21 +39,56 @@ const HTTP_PAYLOAD_VAR: &str = "{$payload}";
 super::manager::register_plugin! {
     "http" => HTTP::new(Strategy::Request),
     "http.form" => HTTP::new(Strategy::Form),
+    "http.json" => HTTP::new(Strategy::Json),
     "http.basic" => HTTP::new(Strategy::BasicAuth),
     "http.ntlm1" => HTTP::new(Strategy::NLTMv1),
     "http.ntlm2" => HTTP::new(Strategy::NLTMv2),
+    "http.ntlm" => HTTP::new(Strategy::NLTMv2),
     "http.enum" => HTTP::new(Strategy::Enumeration),
     "http.vhost" => HTTP::new(Strategy::VHostEnum)
 }
 
+const DEFAULT_PORT: u16 = 80;
+
+// read at most max_body bytes of the response body, the rest is never buffered
+async fn read_body(mut response: Response, max_body: usize) -> Result<String, reqwest::Error> {
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        let left = max_body - body.len();
+        if chunk.len() > left {
+            body.extend_from_slice(&chunk[..left]);
+            log::debug!("response body truncated to {} bytes", max_body);
+            break;
+        }
+        body.extend_from_slice(&chunk);
+    }
+
+    Ok(String::from_utf8_lossy(&body).into_owned())
+}
+
 fn method_requires_payload(method: &Method) -> bool {
     matches!(method, &Method::POST | &Method::PUT | &Method::PATCH)
 }
 
+// append a cookie to the Cookie header, creating it if not set yet
+fn merge_cookie(headers: &mut HeaderMap, cookie: &str) -> Result<(), Error> {
+    let cookie = if let Some(existing) = headers.get(COOKIE) {
+        format!("{}; {}", existing.to_str().unwrap_or(""), cookie)
+    } else {
+        cookie.to_owned()
+    };
+    headers.insert(
+        COOKIE,
+        HeaderValue::from_str(&cookie).map_err(|e| e.to_string())?,
+    );
+    Ok(())
+}
+
 #[derive(Clone, PartialEq, Debug)]
 pub(crate) enum Strategy {
     Request,
     Form,
+    Json,
     BasicAuth,
     NLTMv1,
     NLTMv2,
@@ -59,11 +102,22 @@ struct Success {
     pub content_length: usize,
 }
 
+// addresses of a target and the client connecting to them for every --http-vhosts entry
+#[derive(Clone)]
+struct Pinned {
+    addrs: Vec<SocketAddr>,
+    client: Client,
+}
+
 #[derive(Clone)]
 pub(crate) struct HTTP {
     strategy: Strategy,
     client: Client,
 
+    vhosts: Vec<String>,
+    // by target host and port, resolved once
+    pinned: Arc<Mutex<HashMap<String, Pinned>>>,
+
     csrf: Option<csrf::Config>,
 
     domain: String,
@@ -73,6 +127,8 @@ pub(crate) struct HTTP {
     success_codes: Vec<u16>,
     success_string: Option<String>,
     failure_string: Option<String>,
+    success_json: Option<String>,
+    max_body: usize,
 
     enum_ext: String,
     enum_ext_placeholder: String,
@@ -84,6 +140,12 @@ pub(crate) struct HTTP {
     proxy: Option<String>,
     proxy_user: Option<String>,
     proxy_pass: Option<String>,
+    proxy_env: bool,
+
+    follow_redirects: bool,
+    version: options::Version,
+    decompress: bool,
+    connect_timeout: Option<Duration>,
 
     payload: Option<String>,
 }
@@ -93,12 +155,16 @@ impl HTTP {
         HTTP {
             strategy,
             client: Client::builder().no_proxy().build().unwrap(),
+            vhosts: vec![],
+            pinned: Arc::new(Mutex::new(HashMap::new())),
             csrf: None,
             domain: String::new(),
             workstation: String::new(),
             success_codes: vec![200],
             success_string: None,
             failure_string: None,
+            success_json: None,
+            max_body: 10485760,
             enum_ext: String::new(),
             enum_ext_placeholder: String::new(),
             method: Method::GET,
@@ -108,17 +174,172 @@ impl HTTP {
             proxy: None,
             proxy_user: None,
             proxy_pass: None,
+            proxy_env: true,
+            follow_redirects: false,
+            version: options::Version::Auto,
+            decompress: true,
+            connect_timeout: None,
         }
     }
 
-    fn get_target_url(&self, creds: &Credentials) -> Result<String, Error> {
-        // add default schema if not present
-        let target = if !creds.target.contains("://") {
-            format!("http://{}", creds.target)
+    // address of --proxy and the value of the Proxy-Authorization header, if it's an HTTP proxy
+    fn http_proxy(&self) -> Option<(String, Option<String>)> {
+        let proxy = self.proxy.as_ref()?;
+        let url = if proxy.contains("://") {
+            Url::parse(proxy)
         } else {
-            creds.target.to_owned()
+            Url::parse(&format!("http://{}", proxy))
+        }
+        .ok()
+        .filter(|url| url.scheme() == "http")?;
+
+        let address = format!("{}:{}", url.host_str()?, url.port_or_known_default()?);
+        let credentials = match (&self.proxy_user, &self.proxy_pass) {
+            (Some(user), Some(pass)) => Some(format!("{}:{}", user, pass)),
+            _ if !url.username().is_empty() => Some(format!(
+                "{}:{}",
+                url.username(),
+                url.password().unwrap_or_default()
+            )),
+            _ => None,
         };
 
+        Some((
+            address,
+            credentials.map(|credentials| format!("Basic {}", BASE64_STANDARD.encode(credentials))),
+        ))
+    }
+
+    // the proxy failing to reach a plain HTTP target replies itself, a 407 is always the proxy's
+    fn check_proxy(&self, response: &Response) -> Result<(), Error> {
+        match response.status() {
+            StatusCode::PROXY_AUTHENTICATION_REQUIRED => Err(Error::ProxyAuth),
+            StatusCode::BAD_GATEWAY | StatusCode::GATEWAY_TIMEOUT
+                if self.proxy.is_some() && response.url().scheme() == "http" =>
+            {
+                Err(Error::Proxy(format!(
+                    "could not reach the target: {}",
+                    response.status()
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // reqwest doesn't tell why the proxy refused to open the tunnel to a TLS target, open it
+    // again to find out whether the proxy or the target failed
+    async fn request_error(&self, e: reqwest::Error, timeout: Duration) -> Error {
+        if let (true, Some(url), Some((proxy, authorization))) =
+            (e.is_connect(), e.url(), self.http_proxy())
+        {
+            if let (Some(host), Some(port), "https") =
+                (url.host_str(), url.port_or_known_default(), url.scheme())
+            {
+                let address = format!("{}:{}", host, port);
+                if let Err(err @ (Error::Proxy(_) | Error::ProxyAuth)) =
+                    net::http_proxy_tunnel(&proxy, authorization.as_deref(), &address, timeout)
+                        .await
+                {
+                    return err;
+                }
+            }
+        }
+
+        e.to_string().into()
+    }
+
+    // requests for any of the vhosts go to the pinned addresses, if given
+    fn build_client(
+        &self,
+        keep_alive: bool,
+        pinned: Option<&[SocketAddr]>,
+    ) -> Result<Client, Error> {
+        let redirect_policy = if self.follow_redirects {
+            redirect::Policy::limited(255)
+        } else {
+            redirect::Policy::none()
+        };
+
+        let mut builder = reqwest::Client::builder()
+            .danger_accept_invalid_certs(true)
+            .redirect(redirect_policy);
+
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+
+        if keep_alive {
+            // force a single persistent HTTP/1.1 connection
+            builder = builder.http1_only().pool_max_idle_per_host(1);
+        } else {
+            builder = match self.version {
+                options::Version::Auto => builder,
+                options::Version::Http1 => builder.http1_only(),
+                options::Version::Http2 => builder.http2_prior_knowledge(),
+            };
+        }
+
+        if !self.decompress {
+            builder = builder.no_gzip().no_deflate().no_brotli();
+        }
+
+        // nothing is pinned when going through a proxy
+        if let Some(addrs) = pinned.filter(|addrs| !addrs.is_empty()) {
+            for vhost in &self.vhosts {
+                builder = builder.resolve_to_addrs(vhost, addrs);
+            }
+        }
+
+        builder = if let Some(proxy) = &self.proxy {
+            // add proxy if specified
+            let mut proxy = reqwest::Proxy::all(proxy).map_err(|e| e.to_string())?;
+            if let (Some(user), Some(pass)) = (&self.proxy_user, &self.proxy_pass) {
+                // set proxy authentication
+                proxy = proxy.basic_auth(user, pass);
+            }
+
+            builder.proxy(proxy) // sets auto_sys_proxy to false, see https://github.com/evilsocket/legba/issues/8
+        } else if self.proxy_env {
+            // only the environment, not the system proxy settings reqwest would also look at
+            let proxies = ProxyEnv::from(|name| std::env::var(name).ok()).proxies()?;
+            if proxies.is_empty() {
+                builder.no_proxy()
+            } else {
+                proxies
+                    .into_iter()
+                    .fold(builder, |builder, proxy| builder.proxy(proxy))
+            }
+        } else {
+            builder.no_proxy() // used to set auto_sys_proxy to false, see https://github.com/evilsocket/legba/issues/8
+        };
+
+        builder.build().map_err(|e| e.to_string().into())
+    }
+
+    fn headers_string(&self) -> String {
+        self.headers
+            .iter()
+            .map(|(name, value)| format!("{}: {}", name, value.to_str().unwrap_or("")))
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+
+    fn is_ntlm(&self) -> bool {
+        matches!(self.strategy, Strategy::NLTMv1 | Strategy::NLTMv2)
+    }
+
+    // add default schema if not present
+    fn with_default_schema(target: &str) -> String {
+        if !target.contains("://") {
+            format!("http://{}", target)
+        } else {
+            target.to_owned()
+        }
+    }
+
+    fn get_target_url(&self, creds: &Credentials) -> Result<String, Error> {
+        let target = Self::with_default_schema(&creds.target);
+
         // parse as url
         let target_url = Url::parse(&target).map_err(|e| e.to_string())?;
         // more logic
@@ -179,6 +400,22 @@ impl HTTP {
 
             // we already added the --http-body value as fields
             do_body = false;
+        } else if self.strategy == Strategy::Json {
+            let mut body = payload::parse_json(self.payload.as_ref(), creds).unwrap();
+
+            // handle csrf
+            if let Some(token) = csrf.as_ref() {
+                if let Ok(serde_json::Value::Object(mut object)) = serde_json::from_str(&body) {
+                    object.insert(
+                        token.name.clone(),
+                        serde_json::Value::String(token.value.clone()),
+                    );
+                    body = serde_json::Value::Object(object).to_string();
+                }
+            }
+
+            builder = builder.body(body);
+            do_body = false;
         }
 
         // do we have any fields left to add?
@@ -235,7 +472,9 @@ impl HTTP {
             String::new()
         };
         let headers = format!("{:?}", response.headers());
-        let body = response.text().await.unwrap_or(String::new());
+        let body = read_body(response, self.max_body)
+            .await
+            .unwrap_or(String::new());
         let content_length = body.len();
 
         self.is_success(creds, status, content_type, content_length, headers, body)
@@ -251,11 +490,22 @@ impl HTTP {
         headers: String,
         body: String,
     ) -> Option<Success> {
-        // check status first
-        if !self.success_codes.contains(&status) {
+        // check status first, for NTLM anything but a new challenge means we're in
+        if self.is_ntlm() {
+            if status == 401 {
+                return None;
+            }
+        } else if !self.success_codes.contains(&status) {
             return None;
         }
 
+        // if --http-success-json was provided, the response must be JSON with that field set
+        if let Some(path) = self.success_json.as_ref() {
+            if !payload::json_path_exists(&body, path) {
+                return None;
+            }
+        }
+
         // if --http-success-string was provided, check for matches in the response
         let success_match = if let Some(success_string) = self.success_string.as_ref() {
             // perform interpolation
@@ -307,16 +557,103 @@ impl HTTP {
         headers
     }
 
+    // resolve the target host once and build the client sending every vhost to it
+    async fn pinned(&self, target: &str) -> Result<Pinned, Error> {
+        let url = Url::parse(target).map_err(|e| e.to_string())?;
+        let host = url.host_str().unwrap_or_default().to_owned();
+        let port = url.port_or_known_default().unwrap_or(DEFAULT_PORT);
+        let key = format!("{}:{}", &host, port);
+
+        if let Some(pinned) = self.pinned.lock().unwrap().get(&key) {
+            return Ok(pinned.clone());
+        }
+
+        // the proxy resolves the vhosts itself, the target host might not even resolve here
+        if self.proxy.is_some() {
+            let pinned = Pinned {
+                client: self.build_client(false, None)?,
+                addrs: vec![],
+            };
+            self.pinned.lock().unwrap().insert(key, pinned.clone());
+            return Ok(pinned);
+        }
+
+        // brackets of IPv6 literals must go
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port)).await?.collect();
+        if addrs.is_empty() {
+            return Err(Error::Connection(format!("could not resolve {}", host)));
+        }
+
+        let pinned = Pinned {
+            client: self.build_client(false, Some(&addrs))?,
+            addrs,
+        };
+        self.pinned.lock().unwrap().insert(key, pinned.clone());
+
+        Ok(pinned)
+    }
+
+    // try the credentials against every vhost of the target, reporting all the matching ones
+    async fn http_vhosts_attempt(
+        &self,
+        creds: &Credentials,
+        timeout: Duration,
+    ) -> Result<Option<Vec<Loot>>, Error> {
+        let pinned = self.pinned(&self.get_target_url(creds)?).await?;
+        let mut found = vec![];
+        let mut error = None;
+
+        // a vhost failing doesn't prevent the others from being tried
+        for vhost in &self.vhosts {
+            match self
+                .http_request_attempt(creds, timeout, Some((vhost.as_str(), &pinned)))
+                .await
+            {
+                Ok(Some(loot)) => found.extend(loot),
+                Ok(None) => {}
+                Err(e) => {
+                    log::debug!("vhost {}: {}", vhost, &e);
+                    error.get_or_insert(e);
+                }
+            }
+        }
+
+        // the matches are reported even if some of the vhosts failed
+        match error {
+            Some(e) if found.is_empty() => Err(e),
+            _ => Ok(if found.is_empty() { None } else { Some(found) }),
+        }
+    }
+
     async fn http_request_attempt(
         &self,
         creds: &Credentials,
         timeout: Duration,
+        vhost: Option<(&str, &Pinned)>,
     ) -> Result<Option<Vec<Loot>>, Error> {
-        let target = self.get_target_url(creds)?;
+        let mut target = self.get_target_url(creds)?;
         let mut headers = self.setup_headers();
 
+        if let Some((vhost, _)) = vhost {
+            let mut url = Url::parse(&target).map_err(|e| e.to_string())?;
+            url.set_host(Some(vhost))
+                .map_err(|e| format!("invalid vhost '{}': {}", vhost, e))?;
+            target = url.to_string();
+        }
+
+        // NTLM authenticates the connection rather than the request, so the whole handshake
+        // must go through a dedicated client that keeps a single connection alive
+        let client = match vhost {
+            _ if self.is_ntlm() => {
+                self.build_client(true, vhost.map(|(_, pinned)| pinned.addrs.as_slice()))?
+            }
+            Some((_, pinned)) => pinned.client.clone(),
+            None => self.client.clone(),
+        };
+
         // check if we are in a ntlm auth challenge context
-        if matches!(self.strategy, Strategy::NLTMv1 | Strategy::NLTMv2) {
+        if self.is_ntlm() {
             let auth = ntlm::handle(
                 if self.strategy == Strategy::NLTMv1 {
                     1
@@ -324,7 +661,7 @@ impl HTTP {
                     2
                 },
                 &target,
-                self.client.clone(),
+                client.clone(),
                 creds,
                 &self.domain,
                 &self.workstation,
@@ -339,12 +676,12 @@ impl HTTP {
         // check if we have to grab a CSRF token first
         let csrf_token = if let Some(csrf_config) = self.csrf.as_ref() {
             let token =
-                csrf::handle(csrf_config, self.client.clone(), headers.clone(), timeout).await?;
+                csrf::handle(csrf_config, client.clone(), headers.clone(), timeout).await?;
 
             if let Some(token) = token.as_ref() {
-                // set session cookie for CSRF
+                // set session cookie for CSRF, merging it with the user provided one if needed
                 if !token.cookie.is_empty() {
-                    headers.append(COOKIE, HeaderValue::from_str(&token.cookie).unwrap());
+                    merge_cookie(&mut headers, &token.cookie)?;
                 }
             }
 
@@ -356,8 +693,7 @@ impl HTTP {
         log::debug!("target={}", &target);
 
         // build base request object
-        let mut request = self
-            .client
+        let mut request = client
             .request(self.method.clone(), &target)
             .headers(headers)
             .timeout(timeout);
@@ -366,23 +702,32 @@ impl HTTP {
         request = self.setup_request_body(creds, csrf_token, request);
         // execute
         match request.send().await {
-            Err(e) => Err(e.to_string()),
+            Err(e) => Err(self.request_error(e, timeout).await),
             Ok(res) => {
+                utils::http::check_rate_limit(&res)?;
+                self.check_proxy(&res)?;
                 let cookie = if let Some(cookie) = res.headers().get(COOKIE) {
                     cookie.to_str().unwrap().to_owned()
                 } else {
                     "".to_owned()
                 };
                 Ok(if self.is_success_response(creds, res).await.is_some() {
-                    Some(vec![Loot::new(
-                        "http",
-                        &target,
-                        [
-                            ("username".to_owned(), creds.username.to_owned()),
-                            ("password".to_owned(), creds.password.to_owned()),
-                            ("cookie".to_owned(), cookie),
-                        ],
-                    )])
+                    let mut data = vec![
+                        ("username".to_owned(), creds.username.to_owned()),
+                        ("password".to_owned(), creds.password.to_owned()),
+                        ("cookie".to_owned(), cookie),
+                    ];
+
+                    // record the custom headers for reproducibility
+                    if !self.headers.is_empty() {
+                        data.push(("headers".to_owned(), self.headers_string()));
+                    }
+
+                    if let Some((vhost, _)) = vhost {
+                        data.push(("vhost".to_owned(), vhost.to_owned()));
+                    }
+
+                    Some(vec![Loot::new("http", &target, data)])
                 } else {
                     None
                 })
@@ -439,8 +784,10 @@ impl HTTP {
 
         // execute
         match request.send().await {
-            Err(e) => Err(e.to_string()),
+            Err(e) => Err(self.request_error(e, timeout).await),
             Ok(res) => {
+                utils::http::check_rate_limit(&res)?;
+                self.check_proxy(&res)?;
                 if let Some(success) = self.is_success_response(creds, res).await {
                     Ok(Some(vec![Loot::new(
                         "http.enum",
@@ -480,8 +827,10 @@ impl HTTP {
 
         // execute
         match request.send().await {
-            Err(e) => Err(e.to_string()),
+            Err(e) => Err(self.request_error(e, timeout).await),
             Ok(res) => {
+                utils::http::check_rate_limit(&res)?;
+                self.check_proxy(&res)?;
                 if let Some(success) = self.is_success_response(creds, res).await {
                     Ok(Some(vec![Loot::new(
                         "http.vhost",
@@ -501,12 +850,73 @@ impl HTTP {
     }
 }
 
+// socks5h makes the proxy resolve hostnames, avoiding local DNS leaks and allowing names that
+// only resolve on the other side of the proxy, other proxy types already do it
+fn remote_dns_proxy(proxy: &str) -> String {
+    match proxy.strip_prefix("socks5://") {
+        Some(rest) => format!("socks5h://{}", rest),
+        None => proxy.to_owned(),
+    }
+}
+
+// proxy settings from the environment, the lowercase variables take precedence like with curl
+#[derive(Debug, Default, PartialEq)]
+struct ProxyEnv {
+    http: Option<String>,
+    https: Option<String>,
+    no_proxy: Option<String>,
+}
+
+impl ProxyEnv {
+    fn from(get: impl Fn(&str) -> Option<String>) -> Self {
+        let var = |name: &str| {
+            let set = |value: &String| !value.trim().is_empty();
+            get(&name.to_lowercase())
+                .filter(set)
+                .or_else(|| get(name).filter(set))
+        };
+
+        Self {
+            http: var("HTTP_PROXY"),
+            https: var("HTTPS_PROXY"),
+            no_proxy: var("NO_PROXY"),
+        }
+    }
+
+    fn proxies(&self) -> Result<Vec<reqwest::Proxy>, Error> {
+        let no_proxy = self
+            .no_proxy
+            .as_ref()
+            .and_then(|hosts| reqwest::NoProxy::from_string(hosts));
+        let mut proxies = vec![];
+
+        if let Some(url) = &self.http {
+            proxies.push(
+                reqwest::Proxy::http(url)
+                    .map_err(|e| format!("invalid HTTP_PROXY {}: {}", url, e))?
+                    .no_proxy(no_proxy.clone()),
+            );
+        }
+
+        if let Some(url) = &self.https {
+            proxies.push(
+                reqwest::Proxy::https(url)
+                    .map_err(|e| format!("invalid HTTPS_PROXY {}: {}", url, e))?
+                    .no_proxy(no_proxy),
+            );
+        }
+
+        Ok(proxies)
+    }
+}
+
 #[async_trait]
 impl Plugin for HTTP {
     fn description(&self) -> &'static str {
         match self.strategy {
             Strategy::Request => "HTTP request.",
             Strategy::Form => "HTTP multipart form request.",
+            Strategy::Json => "HTTP JSON API request.",
             Strategy::BasicAuth => "HTTP basic authentication.",
             Strategy::NLTMv1 => "NTLMv1 authentication over HTTP.",
             Strategy::NLTMv2 => "NTLMv2 authentication over HTTP.",
@@ -515,6 +925,18 @@ impl Plugin for HTTP {
         }
     }
 
+    fn loot_schema(&self) -> &[&str] {
+        match self.strategy {
+            Strategy::Enumeration => &["page", "status", "size", "type"],
+            Strategy::VHostEnum => &["vhost", "status", "size", "type"],
+            _ => &["username", "password", "cookie", "headers", "vhost"],
+        }
+    }
+
+    fn requests_per_attempt(&self) -> usize {
+        self.vhosts.len().max(1)
+    }
+
     fn payload_strategy(&self) -> PayloadStrategy {
         match self.strategy {
             Strategy::Enumeration | Strategy::VHostEnum => PayloadStrategy::Single,
@@ -522,41 +944,99 @@ impl Plugin for HTTP {
         }
     }
 
+    fn default_port(&self) -> Option<u16> {
+        Some(DEFAULT_PORT)
+    }
+
+    fn required_options(&self) -> &[&str] {
+        if self.is_ntlm() {
+            &["http-ntlm-domain"]
+        } else {
+            &[]
+        }
+    }
+
+    fn validate_target(&self, target: &str) -> Result<(), Error> {
+        let url = Url::parse(&Self::with_default_schema(target))
+            .map_err(|e| format!("'{}' is not a valid URL: {}", target, e))?;
+
+        if url.scheme() != "http" && url.scheme() != "https" {
+            return Err(format!(
+                "'{}' is not an http:// or https:// URL",
+                target
+            )
+            .into());
+        } else if url.host().is_none() {
+            return Err(format!("'{}' has no host", target).into());
+        }
+
+        Ok(())
+    }
+
     fn setup(&mut self, opts: &Options) -> Result<(), Error> {
         self.user_agent = opts.http.http_ua.clone();
+        self.connect_timeout = opts.connect_timeout.map(Duration::from_millis);
+
+        self.max_body = match opts.http.http_max_body {
+            0 => usize::MAX,
+            max_body => max_body,
+        };
 
         self.csrf = if let Some(csrf_page) = opts.http.http_csrf_page.as_ref() {
-            Some(csrf::Config::new(csrf_page, &opts.http.http_csrf_regexp)?)
+            Some(csrf::Config::new(
+                csrf_page,
+                &opts.http.http_csrf_regexp,
+                self.max_body,
+            )?)
         } else {
             None
         };
 
-        if matches!(self.strategy, Strategy::NLTMv1 | Strategy::NLTMv2) {
+        if self.is_ntlm() {
             self.workstation = opts.http.http_ntlm_workstation.clone();
             if let Some(domain) = &opts.http.http_ntlm_domain {
                 self.domain = domain.clone();
             } else {
-                return Err("no --http-ntlm-domain specified".to_string());
+                return Err("no --http-ntlm-domain specified".into());
             }
         }
 
         self.method =
             Method::from_bytes(opts.http.http_method.as_bytes()).map_err(|e| e.to_string())?;
+        if self.strategy == Strategy::Json && !method_requires_payload(&self.method) {
+            // a JSON body needs a method that carries one
+            self.method = Method::POST;
+        }
 
-        for keyvalue in &opts.http.http_headers {
-            let parts: Vec<&str> = keyvalue.splitn(2, '=').collect();
+        for header in &opts.http.http_headers {
+            // split on whichever separator comes first, so that either can be part of the value
+            let separator = header.find(['=', ':']).ok_or_else(|| {
+                format!(
+                    "invalid header '{}', expected 'Name=Value' or 'Name: Value'",
+                    header
+                )
+            })?;
+            let (name, value) = (&header[..separator], &header[separator + 1..]);
             self.headers.insert(
-                HeaderName::from_bytes(parts[0].as_bytes()).map_err(|e| e.to_string())?,
-                HeaderValue::from_str(parts[1]).map_err(|e| e.to_string())?,
+                HeaderName::from_bytes(name.trim().as_bytes()).map_err(|e| e.to_string())?,
+                HeaderValue::from_str(value.trim()).map_err(|e| e.to_string())?,
             );
         }
 
+        if let Some(cookie) = opts.http.http_cookie.as_ref() {
+            merge_cookie(&mut self.headers, cookie)?;
+        }
+
         if method_requires_payload(&self.method) {
             // check if Content-Type is set already, if not set default (tnx to @zip609)
             if !self.headers.contains_key("Content-Type") {
                 self.headers.insert(
                     CONTENT_TYPE,
-                    HeaderValue::from_static("application/x-www-form-urlencoded"),
+                    HeaderValue::from_static(if self.strategy == Strategy::Json {
+                        "application/json"
+                    } else {
+                        "application/x-www-form-urlencoded"
+                    }),
                 );
             }
         }
@@ -571,11 +1051,21 @@ impl Plugin for HTTP {
             } else {
                 Some(payload.clone())
             }
+        } else if self.strategy == Strategy::Json {
+            Some(DEFAULT_JSON_PAYLOAD.to_owned())
         } else {
             None
         };
 
+        if self.strategy == Strategy::Json {
+            // catch broken templates now rather than sending them for every attempt
+            let body = payload::parse_json(self.payload.as_ref(), &Credentials::default()).unwrap();
+            serde_json::from_str::<serde_json::Value>(&body)
+                .map_err(|e| format!("--http-payload is not valid JSON: {}", e))?;
+        }
+
         self.success_string = opts.http.http_success_string.clone();
+        self.success_json = opts.http.http_success_json.clone();
         self.failure_string = opts.http.http_failure_string.clone();
         self.success_codes = opts
             .http
@@ -589,8 +1079,13 @@ impl Plugin for HTTP {
         self.enum_ext = opts.http.http_enum_ext.clone();
         self.enum_ext_placeholder = opts.http.http_enum_ext_placeholder.clone();
 
+        self.proxy_env = !opts.http.no_proxy_env;
         if let Some(proxy) = &opts.http.proxy {
-            self.proxy = Some(proxy.to_owned());
+            self.proxy = Some(if opts.http.proxy_dns {
+                remote_dns_proxy(proxy)
+            } else {
+                proxy.to_owned()
+            });
             if let Some(auth) = &opts.http.proxy_auth {
                 let parts: Vec<&str> = auth.splitn(2, ':').collect();
                 self.proxy_user = Some(parts[0].to_owned());
@@ -598,39 +1093,30 @@ impl Plugin for HTTP {
             }
         }
 
-        // build the client
-        let redirect_policy = if opts.http.http_follow_redirects {
-            redirect::Policy::limited(255)
-        } else {
-            redirect::Policy::none()
-        };
-
-        self.client = if let Some(proxy) = &self.proxy {
-            // add proxy if specified
-            let mut proxy = reqwest::Proxy::all(proxy).map_err(|e| e.to_string())?;
-            if self.proxy_user.is_some() && self.proxy_pass.is_some() {
-                // set proxy authentication
-                proxy = proxy.basic_auth(
-                    self.proxy_user.as_ref().unwrap(),
-                    self.proxy_pass.as_ref().unwrap(),
+        if let Some(path) = &opts.http.http_vhosts {
+            if matches!(self.strategy, Strategy::Enumeration | Strategy::VHostEnum) {
+                return Err(
+                    format!("--http-vhosts can't be used with {}", self.description()).into(),
                 );
             }
 
-            reqwest::Client::builder()
-                .proxy(proxy) // sets auto_sys_proxy to false, see https://github.com/evilsocket/legba/issues/8
-                .danger_accept_invalid_certs(true)
-                .redirect(redirect_policy)
-                .build()
-                .map_err(|e| e.to_string())?
-        } else {
-            // plain client
-            reqwest::Client::builder()
-                .no_proxy() // used to set auto_sys_proxy to false, see https://github.com/evilsocket/legba/issues/8
-                .danger_accept_invalid_certs(true)
-                .redirect(redirect_policy)
-                .build()
-                .map_err(|e| e.to_string())?
-        };
+            self.vhosts = std::fs::read_to_string(path)
+                .map_err(|e| format!("could not load {}: {}", path, e))?
+                .lines()
+                .map(|line| line.trim())
+                .filter(|line| !line.is_empty())
+                .map(|line| line.to_owned())
+                .collect();
+            if self.vhosts.is_empty() {
+                return Err(format!("{} is empty", path).into());
+            }
+        }
+
+        // build the client
+        self.follow_redirects = opts.http.http_follow_redirects;
+        self.version = opts.http.http_version.clone();
+        self.decompress = !opts.http.no_decompress;
+        self.client = self.build_client(false, None)?;
 
         Ok(())
     }
@@ -643,7 +1129,8 @@ impl Plugin for HTTP {
         match self.strategy {
             Strategy::Enumeration => self.http_enum_attempt(creds, timeout).await,
             Strategy::VHostEnum => self.http_vhost_enum_attempt(creds, timeout).await,
-            _ => self.http_request_attempt(creds, timeout).await,
+            _ if !self.vhosts.is_empty() => self.http_vhosts_attempt(creds, timeout).await,
+            _ => self.http_request_attempt(creds, timeout, None).await,
         }
     }
 }
@@ -660,9 +1147,276 @@ mod tests {
             http::{HTTP_PASSWORD_VAR, HTTP_PAYLOAD_VAR, HTTP_USERNAME_VAR},
             Plugin,
         },
+        session::Error,
     };
 
-    use super::{Strategy, HTTP};
+    use super::{payload, remote_dns_proxy, ProxyEnv, Strategy, DEFAULT_JSON_PAYLOAD, HTTP};
+
+    // serve a single gzip compressed "login ok" response
+    async fn gzip_server() -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        const BODY: &[u8] = b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x02\x03\xcb\xc9\x4f\xcf\xcc\x53\xc8\xcf\x06\x00\x27\x75\xfa\xc5\x08\x00\x00\x00";
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = [0u8; 1024];
+            let _ = stream.read(&mut request).await.unwrap();
+
+            let head = format!(
+                "HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                BODY.len()
+            );
+            stream.write_all(head.as_bytes()).await.unwrap();
+            stream.write_all(BODY).await.unwrap();
+        });
+
+        format!("http://{}/", address)
+    }
+
+    #[tokio::test]
+    async fn decompresses_responses() {
+        let mut http = HTTP::new(Strategy::Request);
+        let mut opts = Options::default();
+        opts.http.http_method = "GET".to_owned();
+        http.setup(&opts).unwrap();
+
+        let url = gzip_server().await;
+        let body = http.client.get(&url).send().await.unwrap().text().await.unwrap();
+        assert_eq!(body, "login ok");
+
+        opts.http.no_decompress = true;
+        http.setup(&opts).unwrap();
+
+        let url = gzip_server().await;
+        let body = http.client.get(&url).send().await.unwrap().bytes().await.unwrap();
+        assert_ne!(&body[..], b"login ok");
+    }
+
+    // serve "login ok" followed by a body way larger than anything a login page would return
+    async fn oversized_server() -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = [0u8; 1024];
+                let _ = stream.read(&mut request).await;
+
+                let body = format!("login ok{}denied", "A".repeat(4 * 1024 * 1024));
+                let head = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                );
+                let _ = stream.write_all(head.as_bytes()).await;
+                let _ = stream.write_all(body.as_bytes()).await;
+            }
+        });
+
+        format!("http://{}/", address)
+    }
+
+    #[tokio::test]
+    async fn truncates_oversized_responses() {
+        let url = oversized_server().await;
+        let creds = Credentials {
+            target: url.clone(),
+            username: "admin".to_owned(),
+            password: "admin".to_owned(),
+        };
+
+        let mut opts = Options::default();
+        opts.http.http_success_codes = "200".to_owned();
+        opts.http.http_failure_string = Some("denied".to_owned());
+        opts.http.http_method = "GET".to_owned();
+
+        // the whole body is read and the failure string found at its end
+        let mut http = HTTP::new(Strategy::Request);
+        http.setup(&opts).unwrap();
+        let res = http.client.get(&url).send().await.unwrap();
+        assert!(http.is_success_response(&creds, res).await.is_none());
+
+        // only the prefix is read, the failure string is never seen
+        opts.http.http_max_body = 1024;
+        http.setup(&opts).unwrap();
+        let res = http.client.get(&url).send().await.unwrap();
+        let success = http.is_success_response(&creds, res).await.unwrap();
+        assert_eq!(success.content_length, 1024);
+    }
+
+    // only the admin vhost accepts the credentials
+    async fn vhosts_server() -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = [0u8; 1024];
+                let n = stream.read(&mut request).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&request[..n]).to_lowercase();
+                let status = if request.contains("\r\nhost: admin.example.test:") {
+                    "200 OK"
+                } else {
+                    "401 Unauthorized"
+                };
+                let _ = stream
+                    .write_all(
+                        format!(
+                            "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                            status
+                        )
+                        .as_bytes(),
+                    )
+                    .await;
+            }
+        });
+
+        address
+    }
+
+    #[tokio::test]
+    async fn tries_every_vhost() {
+        let address = vhosts_server().await;
+        let tmpdir = tempfile::tempdir().unwrap();
+        let vhosts = tmpdir.path().join("vhosts.txt");
+        std::fs::write(&vhosts, "www.example.test\n\nadmin.example.test\n").unwrap();
+
+        let mut opts = Options::default();
+        opts.http.http_success_codes = "200".to_owned();
+        opts.http.http_method = "GET".to_owned();
+        opts.http.http_vhosts = Some(vhosts.to_str().unwrap().to_owned());
+
+        let mut http = HTTP::new(Strategy::BasicAuth);
+        http.setup(&opts).unwrap();
+        assert_eq!(http.vhosts, vec!["www.example.test", "admin.example.test"]);
+        assert_eq!(http.requests_per_attempt(), 2);
+
+        let creds = Credentials {
+            target: format!("http://{}/", address),
+            username: "admin".to_owned(),
+            password: "admin".to_owned(),
+        };
+        let loot = http
+            .attempt(&creds, std::time::Duration::from_secs(5))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(loot.len(), 1);
+        assert_eq!(loot[0].format_template("{vhost}"), "admin.example.test");
+    }
+
+    #[tokio::test]
+    async fn reports_the_matching_vhost_when_others_fail() {
+        let address = vhosts_server().await;
+        let tmpdir = tempfile::tempdir().unwrap();
+        let vhosts = tmpdir.path().join("vhosts.txt");
+        std::fs::write(&vhosts, "not a host\nadmin.example.test\n").unwrap();
+
+        let mut opts = Options::default();
+        opts.http.http_success_codes = "200".to_owned();
+        opts.http.http_method = "GET".to_owned();
+        opts.http.http_vhosts = Some(vhosts.to_str().unwrap().to_owned());
+
+        let mut http = HTTP::new(Strategy::BasicAuth);
+        http.setup(&opts).unwrap();
+
+        let creds = Credentials {
+            target: format!("http://{}/", address),
+            username: "admin".to_owned(),
+            password: "admin".to_owned(),
+        };
+        let loot = http
+            .attempt(&creds, std::time::Duration::from_secs(5))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(loot.len(), 1);
+        assert_eq!(loot[0].format_template("{vhost}"), "admin.example.test");
+
+        // nothing matched, the error is reported
+        std::fs::write(&vhosts, "not a host\nwww.example.test\n").unwrap();
+        http.setup(&opts).unwrap();
+        assert!(http
+            .attempt(&creds, std::time::Duration::from_secs(5))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn vhosts_are_resolved_by_the_proxy() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let vhosts = tmpdir.path().join("vhosts.txt");
+        std::fs::write(&vhosts, "www.example.test\nadmin.example.test\n").unwrap();
+
+        let mut opts = Options::default();
+        opts.http.http_success_codes = "200".to_owned();
+        opts.http.http_method = "GET".to_owned();
+        opts.http.http_vhosts = Some(vhosts.to_str().unwrap().to_owned());
+        opts.http.proxy = Some(format!("http://{}", vhosts_server().await));
+
+        let mut http = HTTP::new(Strategy::BasicAuth);
+        http.setup(&opts).unwrap();
+
+        // the target host doesn't resolve, only the proxy can reach it
+        let creds = Credentials {
+            target: "http://legba.invalid:8080/".to_owned(),
+            username: "admin".to_owned(),
+            password: "admin".to_owned(),
+        };
+        let loot = http
+            .attempt(&creds, std::time::Duration::from_secs(5))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(loot.len(), 1);
+        assert_eq!(loot[0].format_template("{vhost}"), "admin.example.test");
+    }
+
+    #[test]
+    fn vhosts_are_not_used_for_enumeration() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let vhosts = tmpdir.path().join("vhosts.txt");
+        std::fs::write(&vhosts, "www.example.test\n").unwrap();
+
+        let mut opts = Options::default();
+        opts.http.http_success_codes = "200".to_owned();
+        opts.http.http_method = "GET".to_owned();
+        opts.http.http_vhosts = Some(vhosts.to_str().unwrap().to_owned());
+
+        assert!(HTTP::new(Strategy::Enumeration).setup(&opts).is_err());
+    }
+
+    #[test]
+    fn validates_targets() {
+        let http = HTTP::new(Strategy::Request);
+        assert!(http.validate_target("localhost:8080").is_ok());
+        assert!(http.validate_target("https://localhost/login?next={USERNAME}").is_ok());
+        assert!(http.validate_target("ftp://localhost").is_err());
+        assert!(http.validate_target("http://").is_err());
+    }
+
+    #[test]
+    fn proxy_dns_uses_socks5h() {
+        assert_eq!(
+            remote_dns_proxy("socks5://127.0.0.1:1080"),
+            "socks5h://127.0.0.1:1080"
+        );
+        assert_eq!(
+            remote_dns_proxy("socks5h://127.0.0.1:1080"),
+            "socks5h://127.0.0.1:1080"
+        );
+        assert_eq!(
+            remote_dns_proxy("http://127.0.0.1:8080"),
+            "http://127.0.0.1:8080"
+        );
+    }
 
     #[test]
     fn test_get_target_url_adds_default_schema_and_path() {
@@ -1143,4 +1897,335 @@ mod tests {
             .await
             .is_some());
     }
+
+    #[tokio::test]
+    async fn test_is_success_with_ntlm() {
+        let mut http = HTTP::new(Strategy::NLTMv2);
+        let mut opts = Options::default();
+
+        opts.http.http_ntlm_domain = Some("CONTOSO".to_owned());
+        "GET".clone_into(&mut opts.http.http_method);
+
+        let creds = Credentials {
+            target: String::new(),
+            username: "admin".to_owned(),
+            password: "admin".to_owned(),
+        };
+
+        assert_eq!(Ok(()), http.setup(&opts));
+
+        assert!(http
+            .is_success(&creds, 302, String::new(), 0, String::new(), String::new())
+            .await
+            .is_some());
+
+        assert!(http
+            .is_success(&creds, 401, String::new(), 0, String::new(), String::new())
+            .await
+            .is_none());
+    }
+
+    #[test]
+    fn test_custom_headers_and_cookie() {
+        let mut http = HTTP::new(Strategy::Form);
+        let mut opts = Options::default();
+
+        "POST".clone_into(&mut opts.http.http_method);
+        opts.http.http_headers = vec![
+            "X-Requested-With: XMLHttpRequest".to_owned(),
+            "Authorization:Bearer foo:bar".to_owned(),
+            "Cookie: session=1234; theme=dark".to_owned(),
+            "X-Token=a:b".to_owned(),
+        ];
+
+        assert_eq!(Ok(()), http.setup(&opts));
+
+        assert_eq!(
+            http.headers.get("x-requested-with"),
+            Some(&HeaderValue::from_static("XMLHttpRequest"))
+        );
+        assert_eq!(
+            http.headers.get("authorization"),
+            Some(&HeaderValue::from_static("Bearer foo:bar"))
+        );
+        assert_eq!(
+            http.headers.get("cookie"),
+            Some(&HeaderValue::from_static("session=1234; theme=dark"))
+        );
+        assert_eq!(
+            http.headers.get("x-token"),
+            Some(&HeaderValue::from_static("a:b"))
+        );
+    }
+
+    #[test]
+    fn merges_cookie_option_with_cookie_header() {
+        let mut http = HTTP::new(Strategy::Form);
+        let mut opts = Options::default();
+
+        "POST".clone_into(&mut opts.http.http_method);
+        opts.http.http_cookie = Some("lang=en".to_owned());
+        assert_eq!(Ok(()), http.setup(&opts));
+        assert_eq!(
+            http.headers.get("cookie"),
+            Some(&HeaderValue::from_static("lang=en"))
+        );
+
+        opts.http.http_headers = vec!["Cookie: session=1234".to_owned()];
+        assert_eq!(Ok(()), http.setup(&opts));
+        assert_eq!(
+            http.headers.get_all("cookie").iter().count(),
+            1,
+            "cookies must be sent as a single header"
+        );
+        assert_eq!(
+            http.headers.get("cookie"),
+            Some(&HeaderValue::from_static("session=1234; lang=en"))
+        );
+    }
+
+    // the csrf page sets a cookie, the login page only accepts it together with the user's one
+    async fn csrf_server() -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = [0u8; 4096];
+                let n = stream.read(&mut request).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&request[..n]).to_lowercase();
+                let response = if request.starts_with("get /csrf ") {
+                    let body = r#"<input type="hidden" name="token" value="t0k3n">"#;
+                    format!(
+                        "HTTP/1.1 200 OK\r\nSet-Cookie: csrf=abcd\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    )
+                } else if request.contains("\r\ncookie: lang=en; csrf=abcd\r\n") {
+                    "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_owned()
+                } else {
+                    "HTTP/1.1 401 Unauthorized\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                        .to_owned()
+                };
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+
+        address
+    }
+
+    #[tokio::test]
+    async fn sends_cookie_option_together_with_csrf_cookie() {
+        let address = csrf_server().await;
+
+        let mut opts = Options::default();
+        opts.http.http_success_codes = "200".to_owned();
+        opts.http.http_method = "POST".to_owned();
+        opts.http.http_payload = Some("user={USERNAME}&pass={PASSWORD}".to_owned());
+        opts.http.http_cookie = Some("lang=en".to_owned());
+        opts.http.http_csrf_page = Some(format!("http://{}/csrf", address));
+        opts.http.http_csrf_regexp =
+            r#"<input type="hidden" name="(token)" value="([^"]+)""#.to_owned();
+
+        let mut http = HTTP::new(Strategy::Form);
+        http.setup(&opts).unwrap();
+
+        let creds = Credentials {
+            target: format!("http://{}/login", address),
+            username: "admin".to_owned(),
+            password: "admin".to_owned(),
+        };
+        let loot = http
+            .attempt(&creds, std::time::Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert!(loot.is_some());
+    }
+
+    #[test]
+    fn test_invalid_custom_header() {
+        let mut http = HTTP::new(Strategy::Form);
+        let mut opts = Options::default();
+
+        "GET".clone_into(&mut opts.http.http_method);
+        opts.http.http_headers = vec!["X-Requested-With".to_owned()];
+
+        assert!(http.setup(&opts).is_err());
+    }
+
+    #[test]
+    fn can_read_proxy_env() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            ProxyEnv::from(move |name| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| value.to_string())
+            })
+        };
+
+        assert_eq!(env(&[]), ProxyEnv::default());
+        assert_eq!(
+            env(&[
+                ("HTTP_PROXY", "http://upper:8080"),
+                ("http_proxy", "http://lower:8080"),
+                ("HTTPS_PROXY", "http://secure:8080"),
+                ("NO_PROXY", "localhost,.internal"),
+                ("no_proxy", ""),
+            ]),
+            ProxyEnv {
+                http: Some("http://lower:8080".to_owned()),
+                https: Some("http://secure:8080".to_owned()),
+                no_proxy: Some("localhost,.internal".to_owned()),
+            }
+        );
+
+        assert_eq!(
+            env(&[("HTTP_PROXY", "http://proxy:8080"), ("HTTPS_PROXY", "http://proxy:8080")])
+                .proxies()
+                .unwrap()
+                .len(),
+            2
+        );
+        assert!(env(&[("HTTP_PROXY", "not a url")]).proxies().is_err());
+    }
+
+    #[test]
+    fn test_json_payload_escapes_credentials() {
+        let creds = Credentials {
+            target: String::new(),
+            username: "ad\"min".to_owned(),
+            password: "p\\a\nss".to_owned(),
+        };
+        let body = payload::parse_json(Some(&DEFAULT_JSON_PAYLOAD.to_owned()), &creds).unwrap();
+        let parsed: serde_json::Value = serde_json::from_str(&body).unwrap();
+
+        assert_eq!(parsed["username"], "ad\"min");
+        assert_eq!(parsed["password"], "p\\a\nss");
+    }
+
+    #[test]
+    fn test_json_path_exists() {
+        let body = r#"{"token":"abc","data":{"roles":[{"name":"admin"}],"error":null}}"#;
+
+        assert!(payload::json_path_exists(body, "token"));
+        assert!(payload::json_path_exists(body, "data.roles.0.name"));
+        assert!(!payload::json_path_exists(body, "data.roles.1"));
+        assert!(!payload::json_path_exists(body, "data.error"));
+        assert!(!payload::json_path_exists(body, "session"));
+        assert!(!payload::json_path_exists("not json", "token"));
+    }
+
+    #[test]
+    fn test_json_plugin_defaults() {
+        let mut http = HTTP::new(Strategy::Json);
+        let mut opts = Options::default();
+        opts.http.http_method = "GET".to_owned();
+
+        assert_eq!(Ok(()), http.setup(&opts));
+        assert_eq!(http.method, reqwest::Method::POST);
+        assert_eq!(
+            http.headers.get(CONTENT_TYPE),
+            Some(&HeaderValue::from_static("application/json"))
+        );
+        assert_eq!(http.payload, Some(DEFAULT_JSON_PAYLOAD.to_owned()));
+
+        opts.http.http_payload = Some(r#"{"user":"{USERNAME}""#.to_owned());
+        assert!(HTTP::new(Strategy::Json).setup(&opts).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_is_success_with_json_path() {
+        let mut http = HTTP::new(Strategy::Json);
+        let mut opts = Options::default();
+
+        opts.http.http_method = "GET".to_owned();
+        opts.http.http_success_codes = "200".to_owned();
+        opts.http.http_success_json = Some("token".to_owned());
+
+        assert_eq!(Ok(()), http.setup(&opts));
+
+        let creds = Credentials::default();
+        let success = |body: &str| {
+            http.is_success(
+                &creds,
+                200,
+                String::new(),
+                body.len(),
+                String::new(),
+                body.to_owned(),
+            )
+        };
+
+        assert!(success(r#"{"token":"eyJhbGciOi"}"#).await.is_some());
+        assert!(success(r#"{"error":"invalid credentials"}"#)
+            .await
+            .is_none());
+        assert!(success("<html>welcome</html>").await.is_none());
+    }
+
+    // a proxy replying with this status to every request, both plain and CONNECT ones
+    async fn proxy_replying(status: &'static str) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = [0u8; 1024];
+                let _ = stream.read(&mut request).await;
+                let reply = format!(
+                    "HTTP/1.1 {}\r\nProxy-Authenticate: Basic realm=\"proxy\"\r\nContent-Length: 0\r\n\r\n",
+                    status
+                );
+                let _ = stream.write_all(reply.as_bytes()).await;
+            }
+        });
+
+        format!("http://{}", address)
+    }
+
+    #[tokio::test]
+    async fn reports_proxy_auth_required() {
+        let mut http = HTTP::new(Strategy::Request);
+        let mut opts = Options::default();
+        opts.http.http_method = "GET".to_owned();
+        opts.http.proxy = Some(proxy_replying("407 Proxy Authentication Required").await);
+        assert_eq!(Ok(()), http.setup(&opts));
+
+        for target in ["http://127.0.0.1:1/", "https://127.0.0.1:1/"] {
+            let creds = Credentials {
+                target: target.to_owned(),
+                username: "admin".to_owned(),
+                password: "admin".to_owned(),
+            };
+            let res = http
+                .attempt(&creds, std::time::Duration::from_secs(5))
+                .await;
+            assert_eq!(res, Err(Error::ProxyAuth), "{}", target);
+        }
+    }
+
+    #[tokio::test]
+    async fn reports_proxy_bad_gateway() {
+        let mut http = HTTP::new(Strategy::Request);
+        let mut opts = Options::default();
+        opts.http.http_method = "GET".to_owned();
+        opts.http.proxy = Some(proxy_replying("502 Bad Gateway").await);
+        assert_eq!(Ok(()), http.setup(&opts));
+
+        for target in ["http://127.0.0.1:1/", "https://127.0.0.1:1/"] {
+            let creds = Credentials {
+                target: target.to_owned(),
+                username: "admin".to_owned(),
+                password: "admin".to_owned(),
+            };
+            let res = http
+                .attempt(&creds, std::time::Duration::from_secs(5))
+                .await;
+            assert!(matches!(res, Err(Error::Proxy(_))), "{}: {:?}", target, res);
+        }
+    }
 }
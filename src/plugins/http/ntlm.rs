@@ -38,11 +38,15 @@ pub(crate) async fn handle(
         .await
         .map_err(|e| e.to_string())?;
     let challenge_header = if let Some(header) = resp.headers().get("www-authenticate") {
-        header
+        header.clone()
     } else {
-        return Err("response missing challenge header".to_string());
+        return Err("response missing challenge header".into());
     };
 
+    // consume the body so that the connection goes back to the pool and gets reused for the
+    // authentication message, NTLM is connection oriented
+    let _ = resp.bytes().await;
+
     let challenge_b64 = if let Some(challenge) = challenge_header
         .to_str()
         .map_err(|e| e.to_string())?
@@ -51,7 +55,7 @@ pub(crate) async fn handle(
     {
         challenge
     } else {
-        return Err("second chunk of challenge header missing".to_string());
+        return Err("second chunk of challenge header missing".into());
     };
 
     let challenge_bytes = BASE64_STANDARD
@@ -61,7 +65,7 @@ pub(crate) async fn handle(
         ntlmclient::Message::try_from(challenge_bytes.as_slice()).map_err(|e| e.to_string())?;
     let challenge_content = match challenge {
         ntlmclient::Message::Challenge(c) => c,
-        other => return Err(format!("wrong challenge message: {:?}", other)),
+        other => return Err(Error::Protocol(format!("wrong challenge message: {:?}", other))),
     };
     let target_info_bytes: Vec<u8> = challenge_content
         .target_information
@@ -1,6 +1,18 @@
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use serde::{Deserialize, Serialize};
 
+#[derive(Default, Clone, Serialize, Deserialize, Debug, ValueEnum, PartialEq)]
+pub(crate) enum Version {
+    // HTTP/2 when negotiated via TLS ALPN, HTTP/1.1 otherwise
+    #[default]
+    Auto,
+    #[clap(name = "1.1")]
+    Http1,
+    // HTTP/2 with prior knowledge, also over plain text
+    #[clap(name = "2")]
+    Http2,
+}
+
 #[derive(Parser, Debug, Serialize, Deserialize, Clone, Default)]
 #[group(skip)]
 pub(crate) struct Options {
@@ -16,15 +28,27 @@ pub(crate) struct Options {
     #[clap(long)]
     /// Check for the presence of this string in the response in order to recognize a failed attempt.
     pub http_failure_string: Option<String>,
+    #[clap(long)]
+    /// Dotted path of a field that must be set in the JSON response of a succesful attempt, for instance data.token.
+    pub http_success_json: Option<String>,
+    #[clap(long, default_value_t = 10485760)]
+    /// Maximum number of bytes of the response body to read, the rest is discarded and the success and failure checks only see this prefix. 0 for no limit.
+    pub http_max_body: usize,
     #[clap(long, default_value_t = false)]
     /// Follow HTTP redirects.
     pub http_follow_redirects: bool,
     #[clap(long, default_value = "GET")]
     /// Request method for HTTP based plugins.
     pub http_method: String,
-    #[clap(long, num_args = 1..)]
-    /// Request headers for HTTP based plugins.
+    #[clap(long, num_args = 1.., visible_alias = "header")]
+    /// Request headers for HTTP based plugins, either as 'Name=Value' or 'Name: Value' (like 'Cookie: session=1234').
     pub http_headers: Vec<String>,
+    #[clap(long, visible_alias = "cookie")]
+    /// Cookie to send with every request for HTTP based plugins, merged with any Cookie header.
+    pub http_cookie: Option<String>,
+    #[clap(long, visible_alias = "vhost")]
+    /// File with one virtual host per line, every credential is tried against each of them by setting the Host header and the TLS SNI while still connecting to the target address.
+    pub http_vhosts: Option<String>,
     #[clap(long)]
     /// For each request grab a CSRF token from this page.
     pub http_csrf_page: Option<String>,
@@ -50,10 +74,22 @@ pub(crate) struct Options {
     #[clap(long, default_value = "CLIENT")]
     /// Workstation name for NTLM authentication over HTTP.
     pub http_ntlm_workstation: String,
+    #[clap(long, value_enum, default_value_t = Version::Auto)]
+    /// HTTP version for HTTP based plugins, auto uses HTTP/2 when the server supports it over TLS.
+    pub http_version: Version,
+    #[clap(long, default_value_t = false)]
+    /// Don't decode gzip, deflate and brotli compressed responses of HTTP based plugins.
+    pub no_decompress: bool,
     #[clap(long)]
-    /// Proxy URL.
+    /// Proxy URL. When set, the HTTP_PROXY, HTTPS_PROXY and NO_PROXY environment variables are ignored.
     pub proxy: Option<String>,
+    #[clap(long, default_value_t = false)]
+    /// Don't use the HTTP_PROXY, HTTPS_PROXY and NO_PROXY environment variables for HTTP based plugins.
+    pub no_proxy_env: bool,
     #[clap(long)]
     /// Proxy authentication as username:password.
     pub proxy_auth: Option<String>,
+    #[clap(long, default_value_t = false)]
+    /// Let the SOCKS5 proxy resolve the target hostnames instead of resolving them locally.
+    pub proxy_dns: bool,
 }
@@ -25,3 +25,38 @@ pub(crate) fn parse_fields(
 pub(crate) fn parse_body(payload: Option<&String>, creds: &Credentials) -> Option<String> {
     payload.map(|raw| placeholders::interpolate(raw, creds))
 }
+
+// JSON string contents of a credential, so that quotes and backslashes can't break the document
+fn json_escape(value: &str) -> String {
+    let quoted = serde_json::to_string(value).unwrap();
+    quoted[1..quoted.len() - 1].to_owned()
+}
+
+pub(crate) fn parse_json(payload: Option<&String>, creds: &Credentials) -> Option<String> {
+    let escaped = Credentials {
+        target: creds.target.clone(),
+        username: json_escape(&creds.username),
+        password: json_escape(&creds.password),
+    };
+    parse_body(payload, &escaped)
+}
+
+// check that the value at a dotted path (object keys or array indexes) is in the JSON body
+pub(crate) fn json_path_exists(body: &str, path: &str) -> bool {
+    let Ok(mut value) = serde_json::from_str::<serde_json::Value>(body) else {
+        return false;
+    };
+
+    for key in path.split('.').filter(|key| !key.is_empty()) {
+        let next = match &value {
+            serde_json::Value::Array(items) => key.parse::<usize>().ok().and_then(|i| items.get(i)),
+            _ => value.get(key),
+        };
+        match next {
+            Some(next) => value = next.clone(),
+            None => return false,
+        }
+    }
+
+    !value.is_null()
+}
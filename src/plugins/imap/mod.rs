@@ -1,24 +1,148 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use async_trait::async_trait;
 
 use crate::session::{Error, Loot};
+use crate::utils::net::{self, StreamLike};
 use crate::Options;
 use crate::Plugin;
 
 use crate::creds::Credentials;
 use crate::utils;
 
+pub(crate) mod options;
+
 super::manager::register_plugin! {
     "imap" => IMAP::new()
 }
 
+const DEFAULT_PORT: u16 = 993;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Mechanism {
+    // the LOGIN command
+    Login,
+    // AUTHENTICATE PLAIN (RFC4616)
+    Plain,
+}
+
+impl Mechanism {
+    fn parse(name: &str) -> Result<Self, Error> {
+        match name.to_uppercase().as_str() {
+            "LOGIN" => Ok(Mechanism::Login),
+            "PLAIN" => Ok(Mechanism::Plain),
+            _ => Err(format!(
+                "'{}' is not a supported IMAP authentication mechanism, only LOGIN or PLAIN are accepted.",
+                name
+            )
+            .into()),
+        }
+    }
+}
+
+// AUTHENTICATE PLAIN sends the whole identity in the initial response
+struct PlainAuthenticator {
+    username: String,
+    password: String,
+}
+
+impl async_imap::Authenticator for PlainAuthenticator {
+    type Response = String;
+
+    fn process(&mut self, _challenge: &[u8]) -> Self::Response {
+        format!("\0{}\0{}", &self.username, &self.password)
+    }
+}
+
+async fn capabilities(stream: &mut Box<dyn StreamLike>, tag: &str) -> Result<Vec<String>, Error> {
+    net::write_line(stream, &format!("{} CAPABILITY", tag)).await?;
+    let prefix = format!("{} ", tag);
+    Ok(
+        net::read_lines_until(stream, |line| line.starts_with(&prefix))
+            .await?
+            .iter()
+            .filter(|line| line.starts_with("* CAPABILITY "))
+            .flat_map(|line| line.split_whitespace().skip(2))
+            .map(|capability| capability.to_uppercase())
+            .collect(),
+    )
+}
+
+// read the greeting and the server capabilities, upgrading the connection to TLS if LOGIN is
+// disabled in plaintext, returns the stream, whether it has been upgraded and the capabilities
+async fn negotiate(
+    mut stream: Box<dyn StreamLike>,
+    address: &str,
+    timeout: Duration,
+    starttls: bool,
+) -> Result<(Box<dyn StreamLike>, bool, Vec<String>), Error> {
+    let greeting = net::read_line(&mut stream, net::MAX_LINE_SIZE).await?;
+    if !greeting.starts_with("* OK") && !greeting.starts_with("* PREAUTH") {
+        return Err(Error::protocol_mismatch("imap", greeting.as_bytes()));
+    }
+
+    let caps = capabilities(&mut stream, "a1").await?;
+    let has = |name: &str| caps.iter().any(|cap| cap == name);
+    if !starttls || !has("LOGINDISABLED") || !has("STARTTLS") {
+        return Ok((stream, false, caps));
+    }
+
+    net::write_line(&mut stream, "a2 STARTTLS").await?;
+    let reply = net::read_lines_until(&mut stream, |line| line.starts_with("a2 ")).await?;
+    if !reply.last().is_some_and(|line| line.starts_with("a2 OK")) {
+        return Err(Error::Protocol(format!("STARTTLS failed: {:?}", reply)));
+    }
+
+    let mut stream = net::upgrade_tcp_stream_to_ssl(stream, address, timeout).await?;
+    // capabilities can change once the connection is encrypted
+    let caps = capabilities(&mut stream, "a3").await?;
+
+    Ok((stream, true, caps))
+}
+
+// the LOGIN command unless the server disabled it, then AUTHENTICATE PLAIN if advertised
+fn select_mechanism(capabilities: &[String]) -> Result<Mechanism, Error> {
+    if !capabilities.iter().any(|cap| cap == "LOGINDISABLED") {
+        return Ok(Mechanism::Login);
+    }
+
+    let advertised: Vec<&str> = capabilities
+        .iter()
+        .filter_map(|cap| cap.strip_prefix("AUTH="))
+        .collect();
+    if advertised.contains(&"PLAIN") {
+        Ok(Mechanism::Plain)
+    } else if advertised.is_empty() {
+        Err(Error::Auth(
+            "LOGIN is disabled and no other authentication mechanism is advertised".to_owned(),
+        ))
+    } else {
+        Err(Error::Auth(format!(
+            "LOGIN is disabled and the advertised authentication mechanisms are not supported: {} (use --auth-mech to force one)",
+            advertised.join(", ")
+        )))
+    }
+}
+
 #[derive(Clone)]
-pub(crate) struct IMAP {}
+pub(crate) struct IMAP {
+    plain: bool,
+    starttls: bool,
+    mechanism: Option<Mechanism>,
+    // targets without any supported mechanism, there's no point connecting to them again
+    unsupported: Arc<Mutex<HashMap<String, Error>>>,
+}
 
 impl IMAP {
     pub fn new() -> Self {
-        IMAP {}
+        IMAP {
+            plain: false,
+            starttls: true,
+            mechanism: None,
+            unsupported: Arc::new(Mutex::new(HashMap::new())),
+        }
     }
 }
 
@@ -28,7 +152,21 @@ impl Plugin for IMAP {
         "IMAP password authentication."
     }
 
-    fn setup(&mut self, _opts: &Options) -> Result<(), Error> {
+    fn loot_schema(&self) -> &[&str] {
+        &["username", "password", "tls"]
+    }
+
+    fn default_port(&self) -> Option<u16> {
+        Some(DEFAULT_PORT)
+    }
+
+    fn setup(&mut self, opts: &Options) -> Result<(), Error> {
+        self.plain = opts.imap.imap_plain;
+        self.starttls = !opts.no_starttls;
+        self.mechanism = match &opts.auth_mech {
+            Some(name) => Some(Mechanism::parse(name)?),
+            None => None,
+        };
         Ok(())
     }
 
@@ -37,16 +175,52 @@ impl Plugin for IMAP {
         creds: &Credentials,
         timeout: Duration,
     ) -> Result<Option<Vec<Loot>>, Error> {
-        let address = utils::parse_target_address(&creds.target, 993)?;
-        let stream = crate::utils::net::async_tcp_stream(&address, timeout, true).await?;
+        let address = utils::parse_target_address(&creds.target, DEFAULT_PORT)?;
+        if let Some(err) = self.unsupported.lock().unwrap().get(&address) {
+            return Err(err.clone());
+        }
+
+        let stream = net::async_tcp_stream(&address, timeout, !self.plain).await?;
+        let (stream, upgraded, capabilities) = tokio::time::timeout(
+            timeout,
+            negotiate(stream, &address, timeout, self.plain && self.starttls),
+        )
+        .await??;
+        let tls = !self.plain || upgraded;
+
+        let mechanism = match self.mechanism {
+            Some(mechanism) => mechanism,
+            None => select_mechanism(&capabilities).inspect_err(|err| {
+                self.unsupported
+                    .lock()
+                    .unwrap()
+                    .insert(address.to_owned(), err.clone());
+            })?,
+        };
+
         let client = async_imap::Client::new(stream);
-        if client.login(&creds.username, &creds.password).await.is_ok() {
+        let authenticated = match mechanism {
+            Mechanism::Login => client.login(&creds.username, &creds.password).await.is_ok(),
+            Mechanism::Plain => client
+                .authenticate(
+                    "PLAIN",
+                    PlainAuthenticator {
+                        username: creds.username.to_owned(),
+                        password: creds.password.to_owned(),
+                    },
+                )
+                .await
+                .is_ok(),
+        };
+
+        if authenticated {
             return Ok(Some(vec![Loot::new(
                 "imap",
                 &address,
                 [
                     ("username".to_owned(), creds.username.to_owned()),
                     ("password".to_owned(), creds.password.to_owned()),
+                    ("tls".to_owned(), tls.to_string()),
                 ],
             )]));
         }
@@ -54,3 +228,87 @@ impl Plugin for IMAP {
         Ok(None)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::{select_mechanism, Mechanism, IMAP};
+    use crate::creds::Credentials;
+    use crate::session::Error;
+    use crate::Options;
+    use crate::Plugin;
+
+    fn caps(capabilities: &str) -> Vec<String> {
+        capabilities.split(' ').map(|c| c.to_owned()).collect()
+    }
+
+    #[test]
+    fn can_select_mechanism() {
+        assert_eq!(
+            select_mechanism(&caps("IMAP4REV1 AUTH=PLAIN")),
+            Ok(Mechanism::Login)
+        );
+        assert_eq!(
+            select_mechanism(&caps("IMAP4REV1 LOGINDISABLED AUTH=XOAUTH2 AUTH=PLAIN")),
+            Ok(Mechanism::Plain)
+        );
+        assert!(matches!(
+            select_mechanism(&caps("IMAP4REV1 LOGINDISABLED AUTH=XOAUTH2 AUTH=GSSAPI")),
+            Err(Error::Auth(msg)) if msg.contains("XOAUTH2, GSSAPI")
+        ));
+    }
+
+    #[tokio::test]
+    async fn reports_unsupported_mechanisms() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        // a single connection is accepted, the second attempt must not connect again
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            stream.write_all(b"* OK IMAP4rev1 ready\r\n").await.unwrap();
+            let mut buf = [0u8; 128];
+            let _ = stream.read(&mut buf).await.unwrap();
+            stream
+                .write_all(
+                    b"* CAPABILITY IMAP4rev1 LOGINDISABLED AUTH=XOAUTH2\r\na1 OK CAPABILITY completed\r\n",
+                )
+                .await
+                .unwrap();
+            let _ = stream.read(&mut buf).await;
+        });
+
+        let mut opts = Options::default();
+        opts.imap.imap_plain = true;
+        opts.no_starttls = true;
+
+        let mut imap = IMAP::new();
+        assert_eq!(Ok(()), imap.setup(&opts));
+
+        let creds = Credentials {
+            target: address,
+            username: "admin".to_owned(),
+            password: "admin".to_owned(),
+        };
+        let timeout = std::time::Duration::from_secs(5);
+        for _ in 0..2 {
+            assert!(matches!(
+                imap.attempt(&creds, timeout).await,
+                Err(Error::Auth(msg)) if msg.contains("XOAUTH2")
+            ));
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_forced_mechanism() {
+        let mut opts = Options::default();
+        opts.auth_mech = Some("GSSAPI".to_owned());
+        assert!(IMAP::new().setup(&opts).is_err());
+
+        opts.auth_mech = Some("plain".to_owned());
+        let mut imap = IMAP::new();
+        assert_eq!(Ok(()), imap.setup(&opts));
+        assert_eq!(imap.mechanism, Some(Mechanism::Plain));
+    }
+}
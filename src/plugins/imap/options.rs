@@ -0,0 +1,10 @@
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+
+#[derive(Parser, Debug, Serialize, Deserialize, Clone, Default)]
+#[group(skip)]
+pub(crate) struct Options {
+    #[clap(long, default_value_t = false)]
+    /// Connect to IMAP in plaintext (usually port 143) instead of TLS, STARTTLS is still used if the server disables LOGIN.
+    pub imap_plain: bool,
+}
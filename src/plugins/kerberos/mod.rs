@@ -24,6 +24,8 @@ super::manager::register_plugin! {
     "kerberos" => Kerberos::new()
 }
 
+const DEFAULT_PORT: u16 = 88;
+
 #[derive(Clone)]
 pub(crate) struct Kerberos {
     realm: String,
@@ -135,11 +137,39 @@ impl Plugin for Kerberos {
         "Kerberos 5 (pre)authentication and users enumeration."
     }
 
+    fn loot_schema(&self) -> &[&str] {
+        &[
+            "username",
+            "password",
+            "expired_password",
+            "revoked_password",
+        ]
+    }
+
+    fn default_port(&self) -> Option<u16> {
+        Some(DEFAULT_PORT)
+    }
+
+    fn srv_service(&self) -> Option<&'static str> {
+        Some(match self.proto {
+            Protocol::TCP => "_kerberos._tcp",
+            Protocol::UDP => "_kerberos._udp",
+        })
+    }
+
+    fn uses_tcp(&self) -> bool {
+        matches!(self.proto, Protocol::TCP)
+    }
+
+    fn required_options(&self) -> &[&str] {
+        &["kerberos-realm"]
+    }
+
     fn setup(&mut self, opts: &Options) -> Result<(), Error> {
         self.realm = if let Some(realm) = &opts.kerberos.kerberos_realm {
             realm.clone()
         } else {
-            return Err("no --kerberos-realm argument provided".to_owned());
+            return Err("no --kerberos-realm argument provided".into());
         };
         self.linux = opts.kerberos.kerberos_linux;
         self.proto = opts.kerberos.kerberos_protocol.clone();
@@ -156,7 +186,7 @@ impl Plugin for Kerberos {
             return Ok(None);
         }
 
-        let address = utils::parse_target_address(&creds.target, 88)?;
+        let address = utils::parse_target_address(&creds.target, DEFAULT_PORT)?;
         let server = address
             .to_socket_addrs()
             .map_err(|e| e.to_string())?
@@ -1,7 +1,8 @@
 use std::time::Duration;
 
 use async_trait::async_trait;
-use ldap3::{LdapConnAsync, LdapConnSettings};
+use ldap3::controls::{PagedResults, RawControl};
+use ldap3::{Ldap, LdapConnAsync, LdapConnSettings, Scope, SearchEntry};
 
 use crate::session::{Error, Loot};
 use crate::Options;
@@ -16,17 +17,105 @@ super::manager::register_plugin! {
     "ldap" => LDAP::new()
 }
 
+const DEFAULT_PORT: u16 = 389;
+
+// number of entries requested by the recon search
+const RECON_PAGE_SIZE: i32 = 10;
+
+// invalid credentials, with the Active Directory sub-error for locked out accounts in the
+// diagnostic message, like "80090308: LdapErr: DSID-0C09044E, comment: AcceptSecurityContext
+// error, data 775, v4563"
+fn is_locked_out(rc: u32, text: &str) -> bool {
+    rc == 49 && text.contains("data 775")
+}
+
 #[derive(Clone)]
 pub(crate) struct LDAP {
     domain: String,
+    recon: bool,
 }
 
 impl LDAP {
     pub fn new() -> Self {
         LDAP {
             domain: String::new(),
+            recon: false,
+        }
+    }
+
+    async fn bind(&self, ldap: &mut Ldap, creds: &Credentials) -> Result<bool, Error> {
+        // attempts a simple bind using the passed in values of username and password
+        let res = ldap
+            .simple_bind(
+                &format!("cn={},{}", &creds.username, &self.domain),
+                &creds.password,
+            )
+            .await;
+
+        match res {
+            Ok(res) if is_locked_out(res.rc, &res.text) => Err(Error::AccountLocked(res.text)),
+            Ok(res) => Ok(res.success().is_ok()),
+            Err(e) => {
+                log::debug!("bind error for {}: {}", &creds.username, e);
+                Ok(false)
+            }
         }
     }
+
+    // read the naming contexts from the root DSE and request a single page of entries from the
+    // default one to check if the bound user can read the directory
+    async fn recon(&self, ldap: &mut Ldap) -> Result<Vec<(String, String)>, Error> {
+        let (entries, _) = ldap
+            .search(
+                "",
+                Scope::Base,
+                "(objectClass=*)",
+                vec!["namingContexts", "defaultNamingContext"],
+            )
+            .await
+            .map_err(|e| e.to_string())?
+            .success()
+            .map_err(|e| e.to_string())?;
+
+        let Some(root_dse) = entries.into_iter().next().map(SearchEntry::construct) else {
+            return Err(Error::Protocol("empty root DSE".to_owned()));
+        };
+
+        let naming_contexts = root_dse
+            .attrs
+            .get("namingContexts")
+            .cloned()
+            .unwrap_or_default();
+        let base = root_dse
+            .attrs
+            .get("defaultNamingContext")
+            .and_then(|values| values.first())
+            .cloned()
+            .unwrap_or_else(|| self.domain.to_owned());
+
+        let paged = PagedResults {
+            size: RECON_PAGE_SIZE,
+            cookie: vec![],
+        };
+        let readable = match ldap
+            .with_controls(vec![RawControl::from(paged)])
+            .search(&base, Scope::Subtree, "(objectClass=*)", vec!["1.1"])
+            .await
+            .map_err(|e| e.to_string())?
+            .success()
+        {
+            Ok((entries, _)) => entries.len(),
+            Err(e) => {
+                log::debug!("search on {} failed: {}", &base, e);
+                0
+            }
+        };
+
+        Ok(vec![
+            ("naming_contexts".to_owned(), naming_contexts.join(";")),
+            ("readable_entries".to_owned(), readable.to_string()),
+        ])
+    }
 }
 
 #[async_trait]
@@ -35,6 +124,27 @@ impl Plugin for LDAP {
         "LDAP password authentication."
     }
 
+    fn loot_schema(&self) -> &[&str] {
+        &[
+            "username",
+            "password",
+            "naming_contexts",
+            "readable_entries",
+        ]
+    }
+
+    fn default_port(&self) -> Option<u16> {
+        Some(DEFAULT_PORT)
+    }
+
+    fn srv_service(&self) -> Option<&'static str> {
+        Some("_ldap._tcp")
+    }
+
+    fn required_options(&self) -> &[&str] {
+        &["ldap-domain"]
+    }
+
     fn setup(&mut self, opts: &Options) -> Result<(), Error> {
         self.domain = if let Some(domain) = &opts.ldap.ldap_domain {
             // example.org -> dc=example,dc=org
@@ -43,8 +153,9 @@ impl Plugin for LDAP {
                 domain.split('.').collect::<Vec<&str>>().join(",dc=")
             )
         } else {
-            return Err("no --ldap-domain specified".to_string());
+            return Err("no --ldap-domain specified".into());
         };
+        self.recon = opts.ldap.ldap_recon;
 
         Ok(())
     }
@@ -54,7 +165,7 @@ impl Plugin for LDAP {
         creds: &Credentials,
         timeout: Duration,
     ) -> Result<Option<Vec<Loot>>, Error> {
-        let address = utils::parse_target_address(&creds.target, 389)?;
+        let address = utils::parse_target_address(&creds.target, DEFAULT_PORT)?;
         let url = format!("ldap://{}", address);
 
         let (conn, mut ldap) = LdapConnAsync::with_settings(
@@ -69,28 +180,43 @@ impl Plugin for LDAP {
 
         ldap3::drive!(conn);
 
-        // attempts a simple bind using the passed in values of username and password
-        if let Ok(res) = ldap
-            .simple_bind(
-                &format!("cn={},{}", &creds.username, &self.domain),
-                &creds.password,
-            )
-            .await
-        {
-            return Ok(if res.success().is_ok() {
-                Some(vec![Loot::new(
-                    "ldap",
-                    &address,
-                    [
-                        ("username".to_owned(), creds.username.to_owned()),
-                        ("password".to_owned(), creds.password.to_owned()),
-                    ],
-                )])
-            } else {
-                None
-            });
+        if !self.bind(&mut ldap, creds).await? {
+            return Ok(None);
+        }
+
+        let mut data = vec![
+            ("username".to_owned(), creds.username.to_owned()),
+            ("password".to_owned(), creds.password.to_owned()),
+        ];
+
+        if self.recon {
+            match self.recon(&mut ldap).await {
+                Ok(recon) => data.extend(recon),
+                Err(e) => log::debug!("recon failed for {}: {}", &creds.username, e),
+            }
         }
 
-        Ok(None)
+        let _ = ldap.unbind().await;
+
+        Ok(Some(vec![Loot::new("ldap", &address, data)]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_locked_out;
+
+    #[test]
+    fn detects_active_directory_lockout() {
+        assert!(is_locked_out(
+            49,
+            "80090308: LdapErr: DSID-0C09044E, comment: AcceptSecurityContext error, data 775, v4563"
+        ));
+        // wrong password
+        assert!(!is_locked_out(
+            49,
+            "80090308: LdapErr: DSID-0C09044E, comment: AcceptSecurityContext error, data 52e, v4563"
+        ));
+        assert!(!is_locked_out(0, ""));
     }
 }
@@ -7,4 +7,7 @@ pub(crate) struct Options {
     #[clap(long)]
     /// LDAP domain.
     pub ldap_domain: Option<String>,
+    #[clap(long, default_value_t = false)]
+    /// After a successful bind, report the naming contexts and check for read access with a paged search.
+    pub ldap_recon: bool,
 }
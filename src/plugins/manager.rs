@@ -1,14 +1,19 @@
 use std::collections::BTreeMap;
-use std::sync::{LazyLock, Mutex, RwLock};
+use std::sync::{LazyLock, Mutex};
 use std::time;
 
-use ahash::HashSet;
 use ansi_term::Style;
+use itertools::Itertools;
 use rand::Rng;
+use regex::Regex;
+use serde::Deserialize;
 use std::sync::Arc;
+use tokio::sync::Semaphore;
 use tokio::task;
 
-use crate::session::{Error, Session};
+use crate::creds::{Combinator, Credentials, Expression, Provenance};
+use crate::session::{Error, LockoutPolicy, Loot, Session};
+use crate::utils::{self, net};
 use crate::Plugin;
 use crate::{report, Options};
 
@@ -16,6 +21,31 @@ use super::plugin::PayloadStrategy;
 
 type Inventory = BTreeMap<&'static str, Box<dyn Plugin>>;
 
+pub(crate) type Plugins = Vec<(&'static str, Arc<dyn Plugin>)>;
+
+// how often the --schedule windows are checked
+const SCHEDULE_INTERVAL: time::Duration = time::Duration::from_secs(1);
+
+// the --only-open sweep only looks for a connection, don't wait for slow hosts as long as attempts
+const SWEEP_TIMEOUT: time::Duration = time::Duration::from_secs(1);
+
+// how many times a credential is attempted again after the target asked us to slow down
+const MAX_RATE_LIMITED_RETRIES: usize = 10;
+
+// base jitter in milliseconds used by adaptive jitter if --jitter-max was not specified
+const DEFAULT_ADAPTIVE_JITTER: u64 = 250;
+// maximum adaptive jitter multiplier as a power of two
+const MAX_ADAPTIVE_JITTER_SHIFT: usize = 6;
+
+// loot keys the manager can add to the loot of any plugin
+const COMMON_LOOT_KEYS: &[&str] = &[
+    "username_line",
+    "password_line",
+    "raw_username",
+    "verified",
+    "certificate.*",
+];
+
 macro_rules! register_plugin {
     ($($name:literal => $instance:expr),+) => {
         pub(super) fn register(registrar: &mut impl $crate::plugins::manager::PluginRegistrar) {
@@ -68,141 +98,2059 @@ pub(crate) fn list() {
     }
 }
 
-pub(crate) fn setup(options: &Options) -> Result<&'static mut dyn Plugin, Error> {
-    let Some(plugin_name) = options.plugin.as_ref() else {
-        return Err("no plugin selected".to_owned());
-    };
-    let Some(plugin) = INVENTORY
+// the keys declared by a plugin followed by the common ones it doesn't declare itself
+fn loot_keys<'a>(schema: &[&'a str]) -> Vec<&'a str> {
+    schema
+        .iter()
+        .chain(COMMON_LOOT_KEYS.iter().filter(|key| !schema.contains(key)))
+        .copied()
+        .collect()
+}
+
+pub(crate) fn list_json() -> Result<(), Error> {
+    let plugins: Vec<serde_json::Value> = INVENTORY
         .lock()
         .unwrap()
-        .remove(plugin_name.as_str())
-        .map(Box::leak)
-    else {
-        return Err(format!("{} is not a valid plugin name, run with --list-plugins to see the list of available plugins", plugin_name));
+        .iter()
+        .map(|(key, plugin)| {
+            serde_json::json!({
+                "name": key,
+                "description": plugin.description(),
+                "payload_strategy": plugin.payload_strategy().to_string(),
+                "default_port": plugin.default_port(),
+                "loot": loot_keys(plugin.loot_schema()),
+            })
+        })
+        .collect();
+
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&plugins).map_err(|e| e.to_string())?
+    );
+
+    Ok(())
+}
+
+pub(crate) fn setup(options: &Options) -> Result<Plugins, Error> {
+    let Some(plugin_names) = options.plugin.as_ref() else {
+        return Err("no plugin selected".into());
+    };
+
+    let mut plugins: Plugins = vec![];
+    for plugin_name in plugin_names
+        .split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+    {
+        if plugins.iter().any(|(name, _)| *name == plugin_name) {
+            return Err(format!("plugin {} selected more than once", plugin_name).into());
+        }
+
+        // the inventory is left untouched so that it can be listed or set up again
+        let Some((name, mut plugin)) = INVENTORY
+            .lock()
+            .unwrap()
+            .get_key_value(plugin_name)
+            .map(|(name, plugin)| (*name, plugin.clone_box()))
+        else {
+            return Err(format!("{} is not a valid plugin name, run with --list-plugins to see the list of available plugins", plugin_name).into());
+        };
+
+        validate(name, plugin.as_ref(), options)?;
+
+        plugin.setup(options)?;
+
+        plugins.push((name, Arc::from(plugin)));
+    }
+
+    let Some((first_name, first)) = plugins.first() else {
+        return Err("no plugin selected".into());
     };
 
-    plugin.setup(options)?;
+    // all plugins are going to consume the same credentials
+    for (name, plugin) in plugins.iter().skip(1) {
+        if plugin.payload_strategy() != first.payload_strategy() {
+            return Err(format!(
+                "{} and {} use different payload strategies and can't be used together",
+                first_name, name
+            )
+            .into());
+        } else if plugin.override_payload() != first.override_payload() {
+            return Err(format!(
+                "{} and {} use different payloads and can't be used together",
+                first_name, name
+            )
+            .into());
+        }
+    }
+
+    Ok(plugins)
+}
+
+// check for invalid or mutually exclusive options before any connection is made
+fn validate(plugin_name: &str, plugin: &dyn Plugin, options: &Options) -> Result<(), Error> {
+    if options.concurrency == Some(0) {
+        return Err("--concurrency must be greater than zero".into());
+    }
+
+    if options.queue_size == Some(0) {
+        return Err("--queue-size must be greater than zero".into());
+    }
+
+    if let Some(sample) = options.sample {
+        if !(sample > 0.0 && sample <= 100.0) {
+            return Err("--sample must be a percentage greater than 0 and up to 100".into());
+        }
+    }
+
+    if options.tcp_keepalive == Some(0) {
+        return Err("--tcp-keepalive must be greater than zero".into());
+    }
+
+    if let (Some(min), Some(max)) = (options.tls_min_version, options.tls_max_version) {
+        if min > max {
+            return Err(format!(
+                "--tls-min-version ({}) can't be greater than --tls-max-version ({})",
+                min, max
+            )
+            .into());
+        }
+    }
+
+    if let Some(schedule) = &options.schedule {
+        utils::Schedule::parse(schedule, options.schedule_timezone.as_deref())?;
+    } else if options.schedule_timezone.is_some() {
+        return Err("--schedule-timezone requires --schedule".into());
+    }
+
+    if options.retries == 0 {
+        return Err("--retries must be greater than zero".into());
+    }
+
+    if options.jitter_min > options.jitter_max {
+        return Err(format!(
+            "--jitter-min ({}) can't be greater than --jitter-max ({})",
+            options.jitter_min, options.jitter_max
+        )
+        .into());
+    }
+
+    if options.username.is_some() && options.username_env.is_some() {
+        return Err("--username/--payloads can't be used together with --username-env".into());
+    }
+
+    if options.password.is_some() && options.password_env.is_some() {
+        return Err("--password can't be used together with --password-env".into());
+    }
+
+    let has_username = options.username.is_some() || options.username_env.is_some();
+    let has_password = options.password.is_some() || options.password_env.is_some();
+
+    if options.combinations.is_some() && (has_username || has_password) {
+        return Err(
+            "--combinations can't be used together with --username/--payloads or --password".into(),
+        );
+    }
+
+    if options.validate.is_some()
+        && (options.target.is_some()
+            || options.targets.is_some()
+            || options.combinations.is_some()
+            || has_username
+            || has_password
+            || options.add_empty_username
+            || options.add_empty_password)
+    {
+        return Err(
+            "--validate already provides targets and credentials and can't be used together with --target, --targets, --combinations, --username/--payloads or --password".into(),
+        );
+    }
+
+    if options.combinations.is_some() && (options.add_empty_username || options.add_empty_password)
+    {
+        return Err(
+            "--add-empty-username and --add-empty-password can't be used together with --combinations"
+                .into(),
+        );
+    }
+
+    if plugin.payload_strategy() != PayloadStrategy::UsernamePassword {
+        if options.combinations.is_some() {
+            return Err(format!(
+                "the {} plugin uses a single payload and does not support --combinations, use --payloads instead",
+                plugin_name
+            ).into());
+        }
+
+        if has_username && has_password {
+            return Err(format!(
+                "the {} plugin uses a single payload, use either --payloads or --password but not both",
+                plugin_name
+            ).into());
+        }
+    }
+
+    let missing = missing_options(plugin, options);
+    if !missing.is_empty() {
+        return Err(format!(
+            "plugin {} requires {}",
+            plugin_name,
+            missing
+                .iter()
+                .map(|name| format!("--{}", name))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+// a serialized option, the plugin specific ones are grouped in an object per plugin
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OptionValue {
+    Group(BTreeMap<String, serde_json::Value>),
+    Value(serde_json::Value),
+}
+
+// get the options required by the plugin that have not been set
+fn missing_options<'a>(plugin: &'a dyn Plugin, options: &Options) -> Vec<&'a str> {
+    let required = plugin.required_options();
+    if required.is_empty() {
+        return vec![];
+    }
+
+    let serialized: BTreeMap<String, OptionValue> = serde_json::to_value(options)
+        .and_then(serde_json::from_value)
+        .unwrap_or_default();
+    let mut values = BTreeMap::new();
+    for (name, value) in serialized {
+        match value {
+            OptionValue::Group(group) => values.extend(group),
+            OptionValue::Value(value) => {
+                values.insert(name, value);
+            }
+        }
+    }
+
+    required
+        .iter()
+        .copied()
+        .filter(|name| match values.get(&name.replace('-', "_")) {
+            None | Some(serde_json::Value::Null) => true,
+            Some(serde_json::Value::String(value)) => value.is_empty(),
+            Some(_) => false,
+        })
+        .collect()
+}
+
+// replace the target domains with the servers found in the SRV records of the selected plugins
+pub(crate) async fn discover_srv(options: &mut Options) -> Result<(), Error> {
+    if options.ports.is_some() {
+        return Err("--srv can't be used with --ports, the ports come from the SRV records".into());
+    }
+
+    let domains =
+        utils::parse_targets(options.target.as_ref(), options.targets.as_ref(), None, &[])?;
+    let plugins = setup(options)?;
+
+    let mut targets = vec![];
+    for (plugin_name, plugin) in &plugins {
+        let Some(service) = plugin.srv_service() else {
+            return Err(format!("plugin {} doesn't support --srv", plugin_name).into());
+        };
+        targets.extend(utils::resolve_srv(&domains, service).await?);
+    }
+
+    options.target = Some(targets.into_iter().unique().join(","));
+    options.targets = None;
+
+    Ok(())
+}
+
+// make sure every target has a format all the selected plugins can handle
+pub(crate) fn validate_targets(plugins: &Plugins, session: &Session) -> Result<(), Error> {
+    for target in &session.targets {
+        for (plugin_name, plugin) in plugins {
+            plugin.validate_target(target).map_err(|e| {
+                format!(
+                    "plugin {} can't handle target {}: {}",
+                    plugin_name, target, e
+                )
+            })?;
+        }
+    }
+
+    Ok(())
+}
+
+// let the plugins connect to the services they depend on, failing before any attempt if they
+// can't
+pub(crate) async fn prepare(plugins: &Plugins, session: &Session) -> Result<(), Error> {
+    let timeout = time::Duration::from_millis(session.options.timeout);
+    for (plugin_name, plugin) in plugins {
+        net::scope(session.get_net_context(), plugin.prepare(timeout))
+            .await
+            .map_err(|e| format!("plugin {} could not be prepared: {}", plugin_name, e))?;
+    }
+
+    Ok(())
+}
+
+// check every target with the plugins implementing a protocol check, without authenticating,
+// targets found unreachable are flagged before the attempts begin
+pub(crate) async fn check(plugins: &Plugins, session: Arc<Session>) {
+    let timeout = time::Duration::from_millis(session.options.timeout);
+    let semaphore = Arc::new(Semaphore::new(concurrency(plugins, &session.options)));
+    let mut checks = task::JoinSet::new();
+
+    log::info!("checking {} target(s) ...", session.targets.len());
+
+    for target in session.targets.clone() {
+        for (plugin_name, plugin) in plugins.clone() {
+            let target = target.clone();
+            let semaphore = semaphore.clone();
+            let session = session.clone();
+
+            checks.spawn(net::scope(session.get_net_context(), async move {
+                let _permit = semaphore.acquire().await;
+                match plugin.check(&target, timeout).await {
+                    Ok(()) => {
+                        log::info!("[{}] {}: ok", &target, plugin_name);
+                        true
+                    }
+                    Err(e) => {
+                        log::warn!("[{}] {}: {}", &target, plugin_name, e);
+                        // same key and errors used by the attempts to flag the target
+                        if e.is_transport() || matches!(e, Error::ProtocolMismatch(_)) {
+                            session.add_unreachable(&format!("{}://{}", plugin_name, &target));
+                        }
+                        false
+                    }
+                }
+            }));
+        }
+    }
+
+    let total = checks.len();
+    let mut ok = 0;
+    while let Some(res) = checks.join_next().await {
+        if matches!(res, Ok(true)) {
+            ok += 1;
+        }
+    }
+
+    log::info!("{}/{} checks passed\n", ok, total);
+}
+
+// connect to the port of every target without speaking any protocol, the targets with a closed
+// port are flagged as unreachable before the attempts begin
+pub(crate) async fn sweep(plugins: &Plugins, session: Arc<Session>) {
+    let timeout = time::Duration::from_millis(session.options.timeout).min(SWEEP_TIMEOUT);
+    let semaphore = Arc::new(Semaphore::new(session.options.sweep_concurrency.max(1)));
+
+    // plugins sharing a port only need one connection, flagging all of them
+    let mut addresses: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for target in &session.targets {
+        for (plugin_name, plugin) in plugins {
+            let Some(port) = plugin.default_port().filter(|_| plugin.uses_tcp()) else {
+                continue;
+            };
+            // urls without a port use the one of their scheme
+            let port = if target.starts_with("https://") {
+                443
+            } else {
+                port
+            };
+            if let Ok(address) = utils::parse_target_address(target, port) {
+                addresses
+                    .entry(address)
+                    .or_default()
+                    .push(format!("{}://{}", plugin_name, target));
+            }
+        }
+    }
+
+    log::info!("sweeping {} address(es) ...", addresses.len());
+
+    let mut sweeps = task::JoinSet::new();
+    for (address, keys) in addresses {
+        let semaphore = semaphore.clone();
+        let session = session.clone();
+
+        sweeps.spawn(net::scope(session.get_net_context(), async move {
+            let _permit = semaphore.acquire().await;
+            match net::async_tcp_stream(&address, timeout, false).await {
+                Ok(_) => true,
+                Err(e) => {
+                    log::debug!("[{}] closed: {}", &address, e);
+                    // same keys used by the attempts
+                    for key in keys {
+                        session.add_unreachable(&key);
+                    }
+                    false
+                }
+            }
+        }));
+    }
+
+    let total = sweeps.len();
+    let mut open = 0;
+    while let Some(res) = sweeps.join_next().await {
+        if matches!(res, Ok(true)) {
+            open += 1;
+        }
+    }
+
+    log::info!("{}/{} address(es) open\n", open, total);
+}
+
+// pause the session outside of the --schedule windows and resume it inside them
+async fn scheduler(session: Arc<Session>, schedule: utils::Schedule) {
+    loop {
+        let open = schedule.is_open();
+        if open == session.is_paused() {
+            if open {
+                log::info!("inside the scheduled time windows, resuming");
+            } else {
+                log::info!("outside of the scheduled time windows, pausing");
+            }
+            session.set_paused(!open);
+        }
+
+        tokio::select! {
+            _ = session.wait_stop() => break,
+            _ = tokio::time::sleep(SCHEDULE_INTERVAL) => {}
+        }
+    }
+}
+
+// cancels a run from any task, workers exit once their current attempt is over
+#[allow(dead_code)] // only meant for code embedding the manager
+#[derive(Clone)]
+pub(crate) struct Canceller(Arc<Session>);
 
-    Ok(plugin)
+#[allow(dead_code)] // only meant for code embedding the manager
+impl Canceller {
+    pub fn cancel(&self) {
+        self.0.set_stop()
+    }
 }
 
-pub(crate) async fn run(
-    plugin: &'static mut dyn Plugin,
+// a started run, the workers are already waiting for credentials which are produced while
+// wait() is awaited as the combinations can't be moved to another task
+pub(crate) struct RunHandle {
     session: Arc<Session>,
-) -> Result<(), Error> {
-    let single = matches!(plugin.payload_strategy(), PayloadStrategy::Single);
-    let override_payload = plugin.override_payload();
-    let combinations = session.combinations(override_payload, single)?;
-    let unreachables: Arc<RwLock<HashSet<String>>> = Arc::new(RwLock::new(HashSet::default()));
+    combinations: Combinator,
+    workers: Vec<task::JoinHandle<()>>,
+}
 
-    // spawn worker threads
-    for _ in 0..session.options.concurrency {
-        task::spawn(worker(plugin, unreachables.clone(), session.clone()));
+impl RunHandle {
+    #[allow(dead_code)] // only meant for code embedding the manager
+    pub fn canceller(&self) -> Canceller {
+        Canceller(self.session.clone())
     }
 
+    // resolves once all the credentials have been attempted, or after a cancellation once the
+    // in-flight attempts are over, with every worker gone
+    pub async fn wait(mut self) -> Result<(), Error> {
+        let session = self.session;
+
+        // loop credentials for this session
+        while let Some((creds, provenance)) = self.combinations.next_with_provenance() {
+            session.wait_resume().await;
+
+            // exit on ctrl-c if we have to, otherwise send the new credentials to the workers
+            if session.is_stop() {
+                log::debug!("exiting loop");
+                break;
+            } else if let Err(e) = session.send_credentials(creds, provenance).await {
+                // the queue is closed when stopping
+                if session.is_stop() {
+                    log::debug!("exiting loop");
+                    break;
+                }
+                log::error!("{}", e.to_string());
+            }
+        }
+
+        // the number of sampled or deduplicated combinations is only known now
+        if self.combinations.is_estimate() && !session.is_stop() {
+            session.set_total(self.combinations.dispatched());
+        }
+
+        // workers exit once the queue is drained
+        session.close_credentials();
+        for worker in self.workers {
+            worker.await.map_err(|e| format!("worker failed: {}", e))?;
+        }
+
+        // release the statistics and scheduler tasks
+        session.set_stop();
+
+        Ok(())
+    }
+}
+
+pub(crate) async fn run(plugins: Plugins, session: Arc<Session>) -> Result<RunHandle, Error> {
+    let (_, plugin) = &plugins[0];
+    let single = plugin.payload_strategy() != PayloadStrategy::UsernamePassword;
+    let override_payload = if plugin.payload_strategy() == PayloadStrategy::None {
+        // just one attempt per target
+        Some(Expression::Constant {
+            value: String::new(),
+        })
+    } else {
+        plugin.override_payload()
+    };
+    let retry_policy = Arc::new(RetryPolicy::from_options(&session.options)?);
+
+    session.set_plugin_weights(
+        plugins
+            .iter()
+            .map(|(name, plugin)| (name.to_string(), plugin.weight().max(1))),
+    );
+    let mut combinations = session.combinations(override_payload, single)?;
+    combinations.set_requests_per_attempt(
+        plugins
+            .iter()
+            .map(|(_, plugin)| plugin.requests_per_attempt())
+            .max()
+            .unwrap_or(1),
+    );
+
+    // extra workers would just wait for the queue to be closed
+    let mut concurrency = concurrency(&plugins, &session.options);
+    if !combinations.is_sampling() {
+        let left = combinations
+            .search_space_size()
+            .saturating_sub(session.get_done())
+            .max(1);
+        if left < concurrency {
+            log::debug!("only {} combinations left, using {} workers", left, left);
+            concurrency = left;
+        }
+    }
+    session.set_concurrency(concurrency);
+    report::manifest(&session, &plugins).await;
+
+    if let Some(schedule) = &session.options.schedule {
+        let schedule =
+            utils::Schedule::parse(schedule, session.options.schedule_timezone.as_deref())?;
+        // workers must not start outside of the windows
+        session.set_paused(!schedule.is_open());
+        if session.is_paused() {
+            log::info!("outside of the scheduled time windows, pausing");
+        }
+        task::spawn(scheduler(session.clone(), schedule));
+    }
+
+    // spawn worker threads
+    let workers = (0..concurrency)
+        .map(|_| {
+            task::spawn(net::scope(
+                session.get_net_context(),
+                worker(plugins.clone(), session.clone(), retry_policy.clone()),
+            ))
+        })
+        .collect();
+
     if !session.options.quiet {
-        // start statistics reporting
-        let stat_sess = session.clone();
-        std::thread::spawn(move || {
-            report::statistics(stat_sess);
-        });
+        // start statistics reporting, it stops with the session
+        task::spawn(report::statistics(session.clone()));
     }
 
-    // loop credentials for this session
-    for creds in combinations {
-        // exit on ctrl-c if we have to, otherwise send the new credentials to the workers
-        if session.is_stop() {
-            log::debug!("exiting loop");
-            return Ok(());
-        } else if let Err(e) = session.send_credentials(creds).await {
-            log::error!("{}", e.to_string());
+    Ok(RunHandle {
+        session,
+        combinations,
+        workers,
+    })
+}
+
+// get the number of workers, capped by the plugins limits unless explicitly set by the user
+fn concurrency(plugins: &Plugins, options: &Options) -> usize {
+    if let Some(concurrency) = options.concurrency {
+        return concurrency;
+    }
+
+    let default = options.concurrency();
+    match plugins
+        .iter()
+        .filter_map(|(_, plugin)| plugin.max_concurrency())
+        .min()
+    {
+        Some(max) if max < default => {
+            log::info!("concurrency limited to {} by the plugin", max);
+            max
         }
+        _ => default,
     }
+}
 
-    Ok(())
+// decides which errors trigger a retry, by default all of them do
+#[derive(Default)]
+struct RetryPolicy {
+    retry_on: Option<Regex>,
+    no_retry_on: Option<Regex>,
 }
 
-async fn worker(
+impl RetryPolicy {
+    fn compile(expr: Option<&String>, name: &str) -> Result<Option<Regex>, Error> {
+        match expr {
+            Some(expr) => Regex::new(expr)
+                .map(Some)
+                .map_err(|e| format!("invalid --{} expression: {}", name, e).into()),
+            None => Ok(None),
+        }
+    }
+
+    fn from_options(options: &Options) -> Result<Self, Error> {
+        Ok(Self {
+            retry_on: Self::compile(options.retry_on.as_ref(), "retry-on")?,
+            no_retry_on: Self::compile(options.no_retry_on.as_ref(), "no-retry-on")?,
+        })
+    }
+
+    fn should_retry(&self, error: &str) -> bool {
+        if let Some(no_retry_on) = &self.no_retry_on {
+            if no_retry_on.is_match(error) {
+                return false;
+            }
+        }
+
+        if let Some(retry_on) = &self.retry_on {
+            return retry_on.is_match(error);
+        }
+
+        true
+    }
+}
+
+// get the jitter range for a target, with adaptive jitter the range doubles for every
+// consecutive error from the target
+fn jitter_range(session: &Session, target: &str) -> (u64, u64) {
+    let (min, max) = (session.options.jitter_min, session.options.jitter_max);
+    if session.options.jitter_adaptive {
+        let errors = session.get_target_errors(target);
+        if errors > 0 {
+            let factor = 1 << errors.min(MAX_ADAPTIVE_JITTER_SHIFT);
+            let max = if max > 0 {
+                max
+            } else {
+                DEFAULT_ADAPTIVE_JITTER
+            };
+            return (min * factor, max * factor);
+        }
+    }
+
+    (min, max)
+}
+
+// the loot keys not declared in the schema, a trailing '*' matches any key with that prefix
+fn undeclared_loot_keys<'a>(schema: &[&str], loot: &'a Loot) -> Vec<&'a str> {
+    loot.keys()
+        .filter(|key| {
+            !schema
+                .iter()
+                .any(|declared| match declared.strip_suffix('*') {
+                    Some(prefix) => key.starts_with(prefix),
+                    None => key == declared,
+                })
+        })
+        .collect()
+}
+
+// add the wordlist lines the credentials come from to the loot
+fn with_provenance(mut loot: Loot, provenance: &Provenance) -> Loot {
+    if let Some(line) = provenance.username_line {
+        loot = loot.with_data("username_line", line.to_string());
+    }
+    if let Some(line) = provenance.password_line {
+        loot = loot.with_data("password_line", line.to_string());
+    }
+    if let Some(username) = &provenance.raw_username {
+        loot = loot.with_data("raw_username", username.to_owned());
+    }
+    loot
+}
+
+// add the summary of the TLS certificate of the target to the loot, if captured
+fn with_certificate(mut loot: Loot, session: &Session) -> Loot {
+    if let Some(certificate) = session
+        .get_net_context()
+        .certificates
+        .get(loot.get_target())
+    {
+        for (key, value) in certificate {
+            loot = loot.with_data(&key, value);
+        }
+    }
+    loot
+}
+
+// perform the attempt for a single set of credentials or for a batch of them, returns at least
+// one result and no more than the credentials given
+async fn plugin_attempt(
     plugin: &dyn Plugin,
-    unreachables: Arc<RwLock<HashSet<String>>>,
-    session: Arc<Session>,
-) {
-    log::debug!("worker started");
+    batch: &[Credentials],
+    timeout: time::Duration,
+) -> Vec<Result<Option<Vec<Loot>>, Error>> {
+    if batch.len() == 1 {
+        return vec![plugin.attempt(&batch[0], timeout).await];
+    }
 
-    let timeout = time::Duration::from_millis(session.options.timeout);
+    let mut results = plugin.attempt_batch(batch, timeout).await;
+    results.truncate(batch.len());
+    if results.is_empty() {
+        results.push(Err(Error::Other(
+            "no result returned for the batch".to_owned(),
+        )));
+    }
+    results
+}
+
+// attempt the credentials once more to weed out spurious successes, any error or failure means
+// the first success can't be trusted; it counts as an attempt to the target and is paced as such
+async fn verify_success(
+    plugin: &dyn Plugin,
+    creds: &Credentials,
+    session: &Session,
+    timeout: time::Duration,
+) -> bool {
+    pace(session, &creds.target, 1).await;
+
+    let reason = match plugin.attempt(creds, timeout).await {
+        Ok(Some(loot)) if !loot.is_empty() => return true,
+        Ok(_) => "the second attempt failed".to_owned(),
+        Err(e) => e.to_string(),
+    };
+
+    log::warn!(
+        "[{}] discarding unverified success for {}: {}",
+        &creds.target,
+        &creds.username,
+        reason
+    );
+    false
+}
+
+// wait for the cooldown of the target, then for the jitter and the target delay of each of the
+// credentials about to be attempted
+async fn pace(session: &Session, target: &str, count: usize) {
+    // the target asked us to slow down
+    if let Some(wait) = session.get_target_cooldown(target) {
+        log::debug!("[{}] cooling down for {:?}", target, wait);
+        tokio::time::sleep(wait).await;
+    }
+
+    // perform random jitter if needed
+    let (jitter_min, jitter_max) = jitter_range(session, target);
+    if jitter_max > 0 {
+        let ms: u64 = (0..count)
+            .map(|_| rand::thread_rng().gen_range(jitter_min..=jitter_max))
+            .sum();
+        if ms > 0 {
+            log::debug!("jitter of {} ms", ms);
+            tokio::time::sleep(time::Duration::from_millis(ms)).await;
+        }
+    }
+
+    // keep the minimum gap between attempts to this target
+    if session.options.target_delay > 0 {
+        let delay = time::Duration::from_millis(session.options.target_delay);
+        let mut wait = time::Duration::ZERO;
+        for _ in 0..count {
+            wait = session.reserve_target_slot(target, delay);
+        }
+        if !wait.is_zero() {
+            log::debug!("[{}] target delay of {:?}", target, wait);
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+// perform an attempt with retries for each of the credentials of a batch for the same target,
+// returns which ones failed
+async fn attempt(
+    plugin_name: &str,
+    plugin: &dyn Plugin,
+    batch: &[Credentials],
+    provenance: &[Provenance],
+    session: &Session,
+    retry_policy: &RetryPolicy,
+) -> Vec<bool> {
+    let target = &batch[0].target;
+    let timeout = time::Duration::from_millis(
+        session
+            .options
+            .read_timeout
+            .unwrap_or(session.options.timeout),
+    );
     let retry_time: time::Duration = time::Duration::from_millis(session.options.retry_time);
+    let batch_size = plugin.batch_size().max(1);
+    // the same host might be unreachable for a plugin but not for another
+    let unreachable_key = format!("{}://{}", plugin_name, target);
 
-    while let Ok(creds) = session.recv_credentials().await {
-        if session.is_stop() {
-            log::debug!("exiting worker");
+    // which credentials of the batch produced loot and which ones failed
+    let mut found = vec![false; batch.len()];
+    let mut failed = vec![false; batch.len()];
+    let mut last_errors: Vec<Option<String>> = vec![None; batch.len()];
+
+    // the retries are counted for the first credentials not attempted yet, the ones following
+    // them are sent along if the plugin can batch them and attempted again if left untried
+    let mut next = 0;
+    let mut errors = 0;
+    let mut attempt = 0;
+    let mut rate_limited = 0;
+
+    while next < batch.len() && attempt < session.options.retries && !session.is_stop() {
+        // skip attempt if we had enough failures from this specific target
+        if session.is_unreachable(&unreachable_key) {
             break;
         }
 
-        let mut errors = 0;
-        let mut attempt = 0;
+        let pending = &batch[next..batch.len().min(next + batch_size)];
+        pace(
+            session,
+            target,
+            pending.len() * plugin.requests_per_attempt().max(1),
+        )
+        .await;
 
-        while attempt < session.options.retries && !session.is_stop() {
-            // perform random jitter if needed
-            if session.options.jitter_max > 0 {
-                let ms = rand::thread_rng()
-                    .gen_range(session.options.jitter_min..=session.options.jitter_max);
-                if ms > 0 {
-                    log::debug!("jitter of {} ms", ms);
-                    tokio::time::sleep(time::Duration::from_millis(ms)).await;
-                }
-            }
+        attempt += 1;
+
+        let started = time::Instant::now();
+        let results = plugin_attempt(plugin, pending, timeout).await;
+        session.record_timing(plugin_name, started.elapsed());
 
-            attempt += 1;
-
-            // skip attempt if we had enough failures from this specific target
-            if !unreachables.read().unwrap().contains(&creds.target) {
-                match plugin.attempt(&creds, timeout).await {
-                    Err(err) => {
-                        errors += 1;
-                        if attempt < session.options.retries {
-                            log::debug!(
-                                "[{}] attempt {}/{}: {}",
-                                &creds.target,
-                                attempt,
-                                session.options.retries,
-                                err
-                            );
-                            tokio::time::sleep(retry_time).await;
-                            continue;
-                        } else {
-                            // add this target to the list of unreachable in order to avoi
-                            // pointless attempts
-                            unreachables.write().unwrap().insert(creds.target.clone());
-
-                            log::error!(
-                                "[{}] attempt {}/{}: {}",
-                                &creds.target,
-                                attempt,
-                                session.options.retries,
-                                err
-                            );
+        for result in results {
+            let creds = &batch[next];
+            session.notify_attempt(creds, &result);
+
+            let loot = match result {
+                Ok(loot) => loot,
+                Err(err) => {
+                    // whether to move on to the next credentials or to retry these ones
+                    let give_up = match err {
+                        Error::RateLimited(wait) => {
+                            log::warn!("[{}] rate limited, pausing for {:?}", target, wait);
+                            session.set_target_cooldown(target, wait);
+                            if session.options.jitter_adaptive {
+                                session.inc_target_errors(target);
+                            }
+                            rate_limited += 1;
+                            if rate_limited <= MAX_RATE_LIMITED_RETRIES {
+                                // this attempt doesn't count, try again after the cooldown
+                                attempt -= 1;
+                                false
+                            } else {
+                                // the target keeps refusing, give up on this credential
+                                log::error!(
+                                    "[{}] still rate limited after {} retries",
+                                    target,
+                                    MAX_RATE_LIMITED_RETRIES
+                                );
+                                last_errors[next] = Some(Error::RateLimited(wait).to_string());
+                                true
+                            }
+                        }
+                        Error::ProtocolMismatch(err) => {
+                            // no point in sending the rest of the wordlist to the wrong service
+                            if !session.is_unreachable(&unreachable_key) {
+                                session.add_unreachable(&unreachable_key);
+                                log::error!("[{}] {}, skipping target", target, err);
+                            }
+                            last_errors[next] = Some(err);
+                            true
                         }
+                        Error::AccountLocked(reason) => {
+                            // more attempts would only extend the lockout
+                            match session.options.on_lockout {
+                                LockoutPolicy::Skip => {
+                                    if session.add_locked(&creds.username) {
+                                        log::warn!(
+                                            "[{}] {} is locked out ({}), skipping its other passwords",
+                                            target,
+                                            &creds.username,
+                                            reason
+                                        );
+                                    }
+                                }
+                                LockoutPolicy::Abort => {
+                                    if !session.is_stop() {
+                                        log::error!(
+                                            "[{}] {} is locked out ({}), stopping before locking out more accounts",
+                                            target,
+                                            &creds.username,
+                                            reason
+                                        );
+                                        session.set_stop();
+                                    }
+                                }
+                                LockoutPolicy::Continue => {
+                                    log::warn!(
+                                        "[{}] {} is locked out ({})",
+                                        target,
+                                        &creds.username,
+                                        reason
+                                    );
+                                }
+                            }
+                            last_errors[next] = Some(reason);
+                            true
+                        }
+                        err @ (Error::Proxy(_) | Error::ProxyAuth) => {
+                            // the target was never reached, it must not be flagged as unreachable
+                            errors += 1;
+                            if attempt < session.options.retries {
+                                log::debug!(
+                                    "[{}] attempt {}/{}: {}",
+                                    target,
+                                    attempt,
+                                    session.options.retries,
+                                    err
+                                );
+                                tokio::time::sleep(retry_time).await;
+                                false
+                            } else {
+                                log::error!("[{}] {}", target, err);
+                                // no attempt is going to get through the proxy without the right
+                                // credentials
+                                if err == Error::ProxyAuth {
+                                    log::error!("check --proxy-auth, stopping ...");
+                                    session.set_stop();
+                                }
+                                last_errors[next] = Some(err.to_string());
+                                true
+                            }
+                        }
+                        err @ (Error::Protocol(_) | Error::Auth(_)) => {
+                            // the target is reachable, retrying or flagging it as unreachable
+                            // would only prevent other credentials from being tested against it
+                            // the same error is going to be returned for every other credential,
+                            // the messages can differ by credential so only the kind is compared
+                            if !session.add_reported(&format!(
+                                "{}: {}",
+                                &unreachable_key,
+                                err.kind()
+                            )) {
+                                log::debug!("[{}] {}", target, err);
+                            } else if matches!(err, Error::Protocol(_)) {
+                                log::warn!("[{}] protocol error: {}", target, err);
+                            } else {
+                                log::error!("[{}] {}", target, err);
+                            }
+                            last_errors[next] = Some(err.to_string());
+                            true
+                        }
+                        err if !retry_policy.should_retry(&err.to_string()) => {
+                            log::error!("[{}] {} (not retrying)", target, err);
+                            last_errors[next] = Some(err.to_string());
+                            true
+                        }
+                        err => {
+                            errors += 1;
+                            if err.is_transport() {
+                                session.inc_target_errors(target);
+                            }
+                            if attempt < session.options.retries {
+                                log::debug!(
+                                    "[{}] attempt {}/{}: {}",
+                                    target,
+                                    attempt,
+                                    session.options.retries,
+                                    err
+                                );
+                                tokio::time::sleep(retry_time).await;
+                                false
+                            } else {
+                                // add this target to the list of unreachable in order to avoid
+                                // pointless attempts, other errors only fail this credential
+                                if err.is_transport() {
+                                    session.add_unreachable(&unreachable_key);
+                                }
+
+                                log::error!(
+                                    "[{}] attempt {}/{}: {}",
+                                    target,
+                                    attempt,
+                                    session.options.retries,
+                                    err
+                                );
+                                last_errors[next] = Some(err.to_string());
+                                true
+                            }
+                        }
+                    };
+
+                    if give_up {
+                        log::debug!("retries={} errors={}", session.options.retries, errors);
+                        failed[next] = true;
+                        next += 1;
+                        (errors, attempt, rate_limited) = (0, 0, 0);
                     }
-                    Ok(loot) => {
-                        // do we have new loot?
-                        if let Some(loots) = loot {
-                            for loot in loots {
-                                session.add_loot(loot).await.unwrap();
+
+                    // the credentials following these ones have not been attempted
+                    break;
+                }
+            };
+
+            if session.options.jitter_adaptive {
+                session.reset_target_errors(target);
+            }
+
+            // do we have new loot?
+            if loot.as_ref().is_some_and(|loot| !loot.is_empty()) {
+                let verify = session.options.retries_on_success_verify;
+                if !verify || verify_success(plugin, creds, session, timeout).await {
+                    found[next] = true;
+                    for loot in loot.into_iter().flatten() {
+                        // catch plugins producing keys they don't declare, once per key
+                        if cfg!(debug_assertions) {
+                            let undeclared: Vec<&str> =
+                                undeclared_loot_keys(plugin.loot_schema(), &loot)
+                                    .into_iter()
+                                    .filter(|key| {
+                                        session.add_reported(&format!(
+                                            "undeclared loot key {}: {}",
+                                            plugin_name, key
+                                        ))
+                                    })
+                                    .collect();
+                            if !undeclared.is_empty() {
+                                log::warn!(
+                                    "{} produced loot keys missing from its schema: {}",
+                                    plugin_name,
+                                    undeclared.join(", ")
+                                );
                             }
                         }
+
+                        let mut loot = with_provenance(loot, &provenance[next]);
+                        if verify {
+                            loot = loot.with_data("verified", "true".to_owned());
+                        }
+                        if session.options.capture_cert {
+                            loot = with_certificate(loot, session);
+                        }
+                        session.add_loot(loot).await.unwrap();
                     }
-                };
+                }
             }
 
+            // these credentials are done, the next ones start from scratch
+            next += 1;
+            (errors, attempt, rate_limited) = (0, 0, 0);
+        }
+    }
+
+    session.add_plugin_stats(
+        plugin_name,
+        batch.len(),
+        found.iter().filter(|found| **found).count(),
+        failed.iter().filter(|failed| **failed).count(),
+    );
+
+    for (i, creds) in batch.iter().enumerate() {
+        if failed[i] {
+            session.events().emit(
+                "error",
+                serde_json::json!({
+                    "plugin": plugin_name,
+                    "target": &creds.target,
+                    "error": last_errors[i],
+                }),
+            );
+        }
+
+        if session.events().has_consumers() {
+            session.events().emit(
+                "attempt",
+                serde_json::json!({
+                    "plugin": plugin_name,
+                    "target": &creds.target,
+                    "username": &creds.username,
+                    "password": session
+                        .options
+                        .event_passwords
+                        .then_some(&creds.password),
+                    "success": found[i],
+                    "error": failed[i],
+                }),
+            );
+        }
+    }
+
+    failed
+}
+
+// the next credentials to attempt, those of targets cooling down are put aside so that the other
+// targets are attempted meanwhile, and taken back once their cooldown is over or once there's
+// nothing else left
+async fn next_credentials(session: &Session) -> Option<(Credentials, Provenance)> {
+    loop {
+        if let Some(ready) = session.take_deferred(false) {
+            return Some(ready);
+        }
+
+        let received = match session.next_deferred_wait() {
+            // don't keep waiting for new credentials past the end of a cooldown
+            Some(wait) => tokio::select! {
+                received = session.recv_credentials() => received,
+                _ = tokio::time::sleep(wait) => continue,
+            },
+            None => session.recv_credentials().await,
+        };
+
+        match received {
+            Ok(creds) => {
+                if let Some(creds) = session.defer_credentials(creds) {
+                    return Some(creds);
+                }
+            }
+            // the queue is drained, the deferred credentials wait for their cooldown in attempt()
+            Err(_) => return session.take_deferred(true),
+        }
+    }
+}
+
+async fn worker(plugins: Plugins, session: Arc<Session>, retry_policy: Arc<RetryPolicy>) {
+    log::debug!("worker started");
+
+    // plugins that can't batch will just loop the credentials
+    let batch_size = plugins
+        .iter()
+        .map(|(_, plugin)| plugin.batch_size())
+        .max()
+        .unwrap_or(1);
+
+    while let Some(first) = next_credentials(&session).await {
+        // queued credentials wait for the schedule too
+        session.wait_resume().await;
+
+        if session.is_stop() {
+            // saved with the session rather than skipped once restored
+            session.put_back_credentials(first);
+            log::debug!("exiting worker");
             break;
         }
 
-        session.inc_done();
-        if errors == session.options.retries {
-            session.inc_errors();
-            log::debug!("retries={} errors={}", session.options.retries, errors);
+        // take whatever is already queued to fill the batch without waiting for more
+        let mut queued = vec![first];
+        while queued.len() < batch_size {
+            match session.try_recv_credentials() {
+                Some(next) => {
+                    if let Some(next) = session.defer_credentials(next) {
+                        queued.push(next);
+                    }
+                }
+                None => break,
+            }
+        }
+
+        // don't keep trying the passwords of locked out accounts
+        let queued_len = queued.len();
+        queued.retain(|(creds, _)| !session.is_locked(&creds.username));
+        for _ in queued.len()..queued_len {
+            session.inc_done();
+        }
+
+        let (batch, provenance): (Vec<Credentials>, Vec<Provenance>) = queued.into_iter().unzip();
+        let mut offset = 0;
+
+        for batch in batch.chunk_by(|a, b| a.target == b.target) {
+            let provenance = &provenance[offset..offset + batch.len()];
+            offset += batch.len();
+
+            // the same credentials are tested against every selected plugin
+            let mut failed = vec![false; batch.len()];
+            session.inc_in_flight();
+            for (plugin_name, plugin) in &plugins {
+                let plugin_failed = attempt(
+                    plugin_name,
+                    plugin.as_ref(),
+                    batch,
+                    provenance,
+                    &session,
+                    &retry_policy,
+                )
+                .await;
+                for (failed, plugin_failed) in failed.iter_mut().zip(plugin_failed) {
+                    *failed |= plugin_failed;
+                }
+            }
+            session.dec_in_flight();
+
+            for failed in failed {
+                session.inc_done();
+                if failed {
+                    session.inc_errors();
+                }
+            }
         }
     }
 
     log::debug!("worker exit");
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use super::{
+        attempt, concurrency, loot_keys, missing_options, next_credentials, run, setup, sweep,
+        undeclared_loot_keys, worker, Plugins, RetryPolicy, INVENTORY,
+    };
+    use crate::creds::{Credentials, Provenance};
+    use crate::session::{Error, LockoutPolicy, Loot, Session};
+    use crate::{Options, Plugin};
+
+    #[derive(Clone)]
+    struct Limited(Option<usize>);
+
+    #[derive(Clone)]
+    struct Port(u16);
+
+    #[async_trait]
+    impl Plugin for Port {
+        fn description(&self) -> &'static str {
+            "test"
+        }
+
+        fn default_port(&self) -> Option<u16> {
+            Some(self.0)
+        }
+
+        fn setup(&mut self, _opts: &Options) -> Result<(), Error> {
+            Ok(())
+        }
+
+        async fn attempt(
+            &self,
+            _creds: &Credentials,
+            _timeout: Duration,
+        ) -> Result<Option<Vec<Loot>>, Error> {
+            Ok(None)
+        }
+    }
+
+    #[tokio::test]
+    async fn default_check_does_not_connect() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        drop(listener);
+
+        // the port is closed, but only the plugins opting in are checked
+        assert!(Port(port)
+            .check("127.0.0.1", Duration::from_secs(5))
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn sweep_flags_closed_ports() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let open = listener.local_addr().unwrap().port();
+        let closed = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        let mut opts = Options::default();
+        opts.target = Some(format!("127.0.0.1:{}, 127.0.0.1", closed));
+        let session = Session::from_options(opts).unwrap();
+
+        // targets without a port are swept on the plugin one
+        let plugin: Arc<dyn Plugin> = Arc::new(Port(open));
+        let plugins: Plugins = vec![("port", plugin)];
+        sweep(&plugins, session.clone()).await;
+
+        assert!(session.is_unreachable(&format!("port://127.0.0.1:{}", closed)));
+        assert!(!session.is_unreachable("port://127.0.0.1"));
+    }
+
+    #[async_trait]
+    impl Plugin for Limited {
+        fn description(&self) -> &'static str {
+            "test"
+        }
+
+        fn max_concurrency(&self) -> Option<usize> {
+            self.0
+        }
+
+        fn setup(&mut self, _opts: &Options) -> Result<(), Error> {
+            Ok(())
+        }
+
+        async fn attempt(
+            &self,
+            _creds: &Credentials,
+            _timeout: Duration,
+        ) -> Result<Option<Vec<Loot>>, Error> {
+            Ok(None)
+        }
+    }
+
+    #[test]
+    fn plugins_can_limit_concurrency() {
+        let plugins: Plugins = vec![
+            ("a", Arc::new(Limited(None))),
+            ("b", Arc::new(Limited(Some(1)))),
+        ];
+        let mut opts = Options::default();
+
+        assert_eq!(concurrency(&plugins, &opts), 1);
+
+        // unless explicitly set
+        opts.concurrency = Some(16);
+        assert_eq!(concurrency(&plugins, &opts), 16);
+
+        let plugins: Plugins = vec![("a", Arc::new(Limited(None)))];
+        opts.concurrency = None;
+        assert_eq!(concurrency(&plugins, &opts), num_cpus::get());
+    }
+
+    #[test]
+    fn setup_leaves_inventory_intact() {
+        let mut opts = Options::default();
+        opts.plugin = Some("cmd".to_owned());
+        opts.retries = 5;
+        opts.cmd.cmd_binary = "/bin/true".to_owned();
+
+        let before = INVENTORY.lock().unwrap().len();
+
+        assert_eq!(setup(&opts).unwrap().len(), 1);
+        assert_eq!(setup(&opts).unwrap().len(), 1);
+
+        let inventory = INVENTORY.lock().unwrap();
+        assert_eq!(inventory.len(), before);
+        assert!(inventory.contains_key("cmd"));
+    }
+
+    #[test]
+    fn reports_missing_required_options() {
+        let plugin = crate::plugins::cmd::Command::new();
+        let mut opts = Options::default();
+
+        assert_eq!(missing_options(&plugin, &opts), vec!["cmd-binary"]);
+
+        opts.cmd.cmd_binary = "/bin/true".to_owned();
+        assert!(missing_options(&plugin, &opts).is_empty());
+
+        assert!(missing_options(&Limited(None), &opts).is_empty());
+    }
+
+    #[derive(Clone)]
+    struct Locked;
+
+    #[async_trait]
+    impl Plugin for Locked {
+        fn description(&self) -> &'static str {
+            "test"
+        }
+
+        fn setup(&mut self, _opts: &Options) -> Result<(), Error> {
+            Ok(())
+        }
+
+        async fn attempt(
+            &self,
+            _creds: &Credentials,
+            _timeout: Duration,
+        ) -> Result<Option<Vec<Loot>>, Error> {
+            Err(Error::AccountLocked("STATUS_ACCOUNT_LOCKED_OUT".to_owned()))
+        }
+    }
+
+    #[tokio::test]
+    async fn handles_locked_out_accounts() {
+        let creds = Credentials {
+            target: "127.0.0.1:445".to_owned(),
+            username: "admin".to_owned(),
+            password: "hunter2".to_owned(),
+        };
+        let provenance = [Provenance::default()];
+        let policy = RetryPolicy::default();
+
+        let mut opts = crate::Options::default();
+        opts.target = Some(creds.target.clone());
+        opts.retries = 3;
+
+        let session = Session::from_options(opts.clone()).unwrap();
+        let failed = attempt(
+            "test",
+            &Locked,
+            std::slice::from_ref(&creds),
+            &provenance,
+            &session,
+            &policy,
+        )
+        .await;
+        assert_eq!(failed, vec![true]);
+        assert!(session.is_locked("admin"));
+        assert!(!session.is_stop());
+
+        opts.on_lockout = LockoutPolicy::Abort;
+        let session = Session::from_options(opts.clone()).unwrap();
+        attempt(
+            "test",
+            &Locked,
+            std::slice::from_ref(&creds),
+            &provenance,
+            &session,
+            &policy,
+        )
+        .await;
+        assert!(session.is_stop());
+
+        opts.on_lockout = LockoutPolicy::Continue;
+        let session = Session::from_options(opts).unwrap();
+        attempt("test", &Locked, &[creds], &provenance, &session, &policy).await;
+        assert!(!session.is_locked("admin"));
+        assert!(!session.is_stop());
+    }
+
+    #[derive(Clone)]
+    struct Failing(Error);
+
+    #[async_trait]
+    impl Plugin for Failing {
+        fn description(&self) -> &'static str {
+            "test"
+        }
+
+        fn setup(&mut self, _opts: &Options) -> Result<(), Error> {
+            Ok(())
+        }
+
+        async fn attempt(
+            &self,
+            _creds: &Credentials,
+            _timeout: Duration,
+        ) -> Result<Option<Vec<Loot>>, Error> {
+            Err(self.0.clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn only_transport_errors_flag_unreachable_targets() {
+        let creds = Credentials {
+            target: "127.0.0.1:8080".to_owned(),
+            username: "admin".to_owned(),
+            password: "hunter2".to_owned(),
+        };
+        let provenance = [Provenance::default()];
+        let policy = RetryPolicy::default();
+
+        let mut opts = crate::Options::default();
+        opts.target = Some(creds.target.clone());
+        opts.retries = 2;
+
+        for (err, unreachable) in [
+            (Error::Connection("connection refused".to_owned()), true),
+            (Error::Timeout, true),
+            (Error::Other("unexpected status code".to_owned()), false),
+            (Error::Protocol("malformed reply".to_owned()), false),
+            (Error::Auth("server requires TLS".to_owned()), false),
+            (Error::Proxy("bad gateway".to_owned()), false),
+            (Error::ProxyAuth, false),
+        ] {
+            let session = Session::from_options(opts.clone()).unwrap();
+            let plugin = Failing(err.clone());
+            let failed = attempt(
+                "test",
+                &plugin,
+                std::slice::from_ref(&creds),
+                &provenance,
+                &session,
+                &policy,
+            )
+            .await;
+            assert_eq!(failed, vec![true]);
+            assert_eq!(
+                session.is_unreachable("test://127.0.0.1:8080"),
+                unreachable,
+                "{:?}",
+                err
+            );
+            // nothing gets through a proxy refusing the credentials
+            assert_eq!(session.is_stop(), err == Error::ProxyAuth, "{:?}", err);
+        }
+    }
+
+    #[tokio::test]
+    async fn gives_up_on_rate_limited_credentials() {
+        let creds = Credentials {
+            target: "127.0.0.1:8080".to_owned(),
+            username: "admin".to_owned(),
+            password: "hunter2".to_owned(),
+        };
+
+        let mut opts = crate::Options::default();
+        opts.target = Some(creds.target.clone());
+        opts.retries = 1;
+
+        let session = Session::from_options(opts).unwrap();
+        let plugin = Failing(Error::RateLimited(Duration::ZERO));
+        let failed = tokio::time::timeout(
+            Duration::from_secs(5),
+            attempt(
+                "test",
+                &plugin,
+                &[creds],
+                &[Provenance::default()],
+                &session,
+                &RetryPolicy::default(),
+            ),
+        )
+        .await
+        .unwrap();
+        assert_eq!(failed, vec![true]);
+    }
+
+    // succeeds for the first given number of attempts, then fails
+    #[derive(Clone)]
+    struct Flaky {
+        successes: usize,
+        attempts: Arc<AtomicUsize>,
+    }
+
+    impl Flaky {
+        fn new(successes: usize) -> Self {
+            Self {
+                successes,
+                attempts: Arc::new(AtomicUsize::new(0)),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl Plugin for Flaky {
+        fn description(&self) -> &'static str {
+            "test"
+        }
+
+        fn setup(&mut self, _opts: &Options) -> Result<(), Error> {
+            Ok(())
+        }
+
+        async fn attempt(
+            &self,
+            creds: &Credentials,
+            _timeout: Duration,
+        ) -> Result<Option<Vec<Loot>>, Error> {
+            Ok(
+                if self.attempts.fetch_add(1, Ordering::Relaxed) < self.successes {
+                    Some(vec![Loot::new(
+                        "test",
+                        &creds.target,
+                        [("username".to_owned(), creds.username.to_owned())],
+                    )])
+                } else {
+                    None
+                },
+            )
+        }
+    }
+
+    #[tokio::test]
+    async fn can_verify_successes() {
+        let creds = Credentials {
+            target: "127.0.0.1:22".to_owned(),
+            username: "admin".to_owned(),
+            password: "hunter2".to_owned(),
+        };
+        let provenance = [Provenance::default()];
+        let policy = RetryPolicy::default();
+
+        let mut opts = crate::Options::default();
+        opts.target = Some(creds.target.clone());
+        opts.retries = 3;
+        opts.retries_on_success_verify = true;
+
+        // the spurious success is discarded
+        let session = Session::from_options(opts.clone()).unwrap();
+        let plugin = Flaky::new(1);
+        attempt(
+            "test",
+            &plugin,
+            std::slice::from_ref(&creds),
+            &provenance,
+            &session,
+            &policy,
+        )
+        .await;
+        assert!(session.results.lock().unwrap().is_empty());
+        assert_eq!(plugin.attempts.load(Ordering::Relaxed), 2);
+
+        // a confirmed one is recorded as verified
+        let session = Session::from_options(opts).unwrap();
+        attempt(
+            "test",
+            &Flaky::new(2),
+            &[creds],
+            &provenance,
+            &session,
+            &policy,
+        )
+        .await;
+        let results = session.results.lock().unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].format_template("{verified}"), "true");
+    }
+
+    #[tokio::test]
+    async fn verification_waits_the_target_delay() {
+        let creds = Credentials {
+            target: "127.0.0.1:22".to_owned(),
+            username: "admin".to_owned(),
+            password: "hunter2".to_owned(),
+        };
+
+        let mut opts = crate::Options::default();
+        opts.target = Some(creds.target.clone());
+        opts.retries = 1;
+        opts.retries_on_success_verify = true;
+        opts.target_delay = 200;
+
+        let session = Session::from_options(opts).unwrap();
+        let started = std::time::Instant::now();
+        attempt(
+            "test",
+            &Flaky::new(2),
+            &[creds],
+            &[Provenance::default()],
+            &session,
+            &RetryPolicy::default(),
+        )
+        .await;
+        // the first slot is free, the verification takes the next one
+        assert!(started.elapsed() >= Duration::from_millis(200));
+        assert_eq!(session.results.lock().unwrap().len(), 1);
+    }
+
+    // tests up to three credentials at once, the first attempt of "flaky" fails
+    #[derive(Clone, Default)]
+    struct Batching {
+        attempted: Arc<std::sync::Mutex<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl Plugin for Batching {
+        fn description(&self) -> &'static str {
+            "test"
+        }
+
+        fn batch_size(&self) -> usize {
+            3
+        }
+
+        fn setup(&mut self, _opts: &Options) -> Result<(), Error> {
+            Ok(())
+        }
+
+        async fn attempt(
+            &self,
+            creds: &Credentials,
+            _timeout: Duration,
+        ) -> Result<Option<Vec<Loot>>, Error> {
+            let mut attempted = self.attempted.lock().unwrap();
+            attempted.push(creds.password.to_owned());
+            match creds.password.as_str() {
+                "ok" => Ok(Some(vec![Loot::new(
+                    "test",
+                    &creds.target,
+                    [("password".to_owned(), creds.password.to_owned())],
+                )])),
+                "flaky" if attempted.iter().filter(|p| *p == "flaky").count() == 1 => {
+                    Err(Error::Connection("connection reset".to_owned()))
+                }
+                _ => Ok(None),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn batch_errors_only_retry_the_credentials_left() {
+        let batch: Vec<Credentials> = ["ok", "flaky", "other"]
+            .iter()
+            .map(|password| Credentials {
+                target: "127.0.0.1:6379".to_owned(),
+                username: "default".to_owned(),
+                password: password.to_string(),
+            })
+            .collect();
+        let provenance = vec![Provenance::default(); batch.len()];
+
+        let mut opts = crate::Options::default();
+        opts.target = Some(batch[0].target.clone());
+        opts.retries = 2;
+
+        let session = Session::from_options(opts).unwrap();
+        let plugin = Batching::default();
+        let failed = attempt(
+            "test",
+            &plugin,
+            &batch,
+            &provenance,
+            &session,
+            &RetryPolicy::default(),
+        )
+        .await;
+
+        assert_eq!(failed, vec![false, false, false]);
+        // the success preceding the error is kept and the untried credentials are attempted
+        assert_eq!(session.results.lock().unwrap().len(), 1);
+        assert_eq!(
+            *plugin.attempted.lock().unwrap(),
+            vec!["ok", "flaky", "flaky", "other"]
+        );
+    }
+
+    #[tokio::test]
+    async fn attempt_events_leave_out_the_passwords() {
+        let creds = vec![Credentials {
+            target: "127.0.0.1:6379".to_owned(),
+            username: "default".to_owned(),
+            password: "secret".to_owned(),
+        }];
+        let provenance = vec![Provenance::default()];
+
+        for event_passwords in [false, true] {
+            let mut opts = crate::Options::default();
+            opts.target = Some(creds[0].target.clone());
+            opts.event_passwords = event_passwords;
+
+            let session = Session::from_options(opts).unwrap();
+            let mut events = session.events().subscribe();
+            attempt(
+                "test",
+                &Batching::default(),
+                &creds,
+                &provenance,
+                &session,
+                &RetryPolicy::default(),
+            )
+            .await;
+
+            let line = events.recv().await.unwrap();
+            let event: serde_json::Value = serde_json::from_str(&line).unwrap();
+            assert_eq!(event["event"], "attempt");
+            assert_eq!(event["data"]["username"], "default");
+            assert_eq!(
+                event["data"]["password"].as_str(),
+                event_passwords.then_some("secret")
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn batches_wait_the_target_delay_of_each_credential() {
+        let batch: Vec<Credentials> = ["a", "b", "c"]
+            .iter()
+            .map(|password| Credentials {
+                target: "127.0.0.1:6379".to_owned(),
+                username: "default".to_owned(),
+                password: password.to_string(),
+            })
+            .collect();
+        let provenance = vec![Provenance::default(); batch.len()];
+
+        let mut opts = crate::Options::default();
+        opts.target = Some(batch[0].target.clone());
+        opts.retries = 1;
+        opts.target_delay = 100;
+
+        let session = Session::from_options(opts).unwrap();
+        let started = std::time::Instant::now();
+        attempt(
+            "test",
+            &Batching::default(),
+            &batch,
+            &provenance,
+            &session,
+            &RetryPolicy::default(),
+        )
+        .await;
+        // the first slot is free, the other two are one delay apart
+        assert!(started.elapsed() >= Duration::from_millis(200));
+    }
+
+    #[tokio::test]
+    async fn reports_attempts_to_callback() {
+        let creds = Credentials {
+            target: "127.0.0.1:22".to_owned(),
+            username: "admin".to_owned(),
+            password: "hunter2".to_owned(),
+        };
+        let provenance = [Provenance::default()];
+        let policy = RetryPolicy::default();
+
+        let mut opts = crate::Options::default();
+        opts.target = Some(creds.target.clone());
+        opts.retries = 3;
+
+        let session = Session::from_options(opts).unwrap();
+        let seen = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let le_seen = seen.clone();
+        session.set_on_attempt(move |creds, result| {
+            le_seen.lock().unwrap().push((
+                creds.password.to_owned(),
+                matches!(result, Ok(Some(loot)) if !loot.is_empty()),
+            ));
+        });
+
+        let plugin = Flaky::new(1);
+        for _ in 0..2 {
+            attempt(
+                "test",
+                &plugin,
+                std::slice::from_ref(&creds),
+                &provenance,
+                &session,
+                &policy,
+            )
+            .await;
+        }
+
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![("hunter2".to_owned(), true), ("hunter2".to_owned(), false)]
+        );
+    }
+
+    // takes its time to fail
+    #[derive(Clone)]
+    struct Slow;
+
+    #[async_trait]
+    impl Plugin for Slow {
+        fn description(&self) -> &'static str {
+            "test"
+        }
+
+        fn setup(&mut self, _opts: &Options) -> Result<(), Error> {
+            Ok(())
+        }
+
+        async fn attempt(
+            &self,
+            _creds: &Credentials,
+            _timeout: Duration,
+        ) -> Result<Option<Vec<Loot>>, Error> {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            Ok(None)
+        }
+    }
+
+    fn run_options(password: &str) -> Options {
+        let mut opts = crate::Options::default();
+        opts.target = Some("127.0.0.1:22".to_owned());
+        opts.username = Some("admin".to_owned());
+        opts.password = Some(password.to_owned());
+        opts.concurrency = Some(4);
+        opts.retries = 1;
+        opts.quiet = true;
+        opts
+    }
+
+    #[tokio::test]
+    async fn run_waits_for_every_attempt() {
+        let session = Session::from_options(run_options("#1-1")).unwrap();
+        let plugin: Arc<dyn Plugin> = Arc::new(Flaky::new(0));
+
+        let handle = run(vec![("test", plugin)], session.clone()).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(5), handle.wait())
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(session.get_done(), session.get_total());
+        assert!(session.get_total() > 0);
+    }
+
+    #[tokio::test]
+    async fn run_ends_with_more_workers_than_combinations() {
+        let mut opts = run_options("[1-3]");
+        opts.concurrency = Some(100);
+        let session = Session::from_options(opts).unwrap();
+        let plugin: Arc<dyn Plugin> = Arc::new(Flaky::new(0));
+
+        let handle = run(vec![("test", plugin)], session.clone()).await.unwrap();
+        assert_eq!(handle.workers.len(), 3);
+        tokio::time::timeout(Duration::from_secs(5), handle.wait())
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(session.get_total(), 3);
+        assert_eq!(session.get_done(), 3);
+        assert!(session.is_stop());
+
+        // when sampling the workers aren't capped, the idle ones must still exit
+        let mut opts = run_options("[1-3]");
+        opts.concurrency = Some(100);
+        opts.sample = Some(100.0);
+        let session = Session::from_options(opts).unwrap();
+        let plugin: Arc<dyn Plugin> = Arc::new(Flaky::new(0));
+
+        let handle = run(vec![("test", plugin)], session.clone()).await.unwrap();
+        assert_eq!(handle.workers.len(), 100);
+        tokio::time::timeout(Duration::from_secs(5), handle.wait())
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(session.get_done(), 3);
+        assert!(session.is_stop());
+    }
+
+    #[tokio::test]
+    async fn stopped_workers_keep_the_deferred_credentials() {
+        let session = Session::from_options(run_options("#1-1")).unwrap();
+        let creds = Credentials {
+            target: "127.0.0.1:22".to_owned(),
+            username: "admin".to_owned(),
+            password: "1".to_owned(),
+        };
+        session.put_back_credentials((creds.clone(), Provenance::default()));
+        session.set_stop();
+
+        let plugin: Arc<dyn Plugin> = Arc::new(Flaky::new(0));
+        worker(
+            vec![("test", plugin)],
+            session.clone(),
+            Arc::new(RetryPolicy::from_options(&session.options).unwrap()),
+        )
+        .await;
+
+        // nothing attempted, the credentials are still there to be saved
+        assert_eq!(session.get_done(), 0);
+        assert_eq!(
+            session.take_deferred(true),
+            Some((creds, Provenance::default()))
+        );
+    }
+
+    #[tokio::test]
+    async fn cooling_targets_are_put_aside() {
+        let mut opts = run_options("#1-1");
+        opts.queue_size = Some(4);
+        let session = Session::from_options(opts).unwrap();
+
+        for (target, password) in [("a", "1"), ("a", "2"), ("b", "1")] {
+            let creds = Credentials {
+                target: target.to_owned(),
+                username: "admin".to_owned(),
+                password: password.to_owned(),
+            };
+            session
+                .send_credentials(creds, Provenance::default())
+                .await
+                .unwrap();
+        }
+        session.set_target_cooldown("a", Duration::from_secs(60));
+        session.close_credentials();
+
+        let mut order = vec![];
+        while let Some((creds, _)) = next_credentials(&session).await {
+            order.push(format!("{}:{}", creds.target, creds.password));
+        }
+
+        // the cooling target is still attempted once there's nothing else left
+        assert_eq!(order, vec!["b:1", "a:1", "a:2"]);
+    }
+
+    #[tokio::test]
+    async fn run_can_be_cancelled() {
+        let session = Session::from_options(run_options("#4-4")).unwrap();
+        let plugin: Arc<dyn Plugin> = Arc::new(Slow);
+
+        let handle = run(vec![("test", plugin)], session.clone()).await.unwrap();
+        let canceller = handle.canceller();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            canceller.cancel();
+        });
+
+        tokio::time::timeout(Duration::from_secs(5), handle.wait())
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert!(session.is_stop());
+        assert_eq!(session.get_in_flight(), 0);
+        assert!(session.get_done() < session.get_total());
+    }
+
+    #[test]
+    fn retries_everything_by_default() {
+        let policy = RetryPolicy::default();
+        assert!(policy.should_retry("connection reset by peer"));
+        assert!(policy.should_retry("unsupported auth mechanism"));
+    }
+
+    #[test]
+    fn can_filter_retries() {
+        let mut opts = crate::Options::default();
+        opts.retry_on = Some("(?i)connection|timed out".to_owned());
+        opts.no_retry_on = Some("refused".to_owned());
+
+        let policy = RetryPolicy::from_options(&opts).unwrap();
+        assert!(policy.should_retry("connection reset by peer"));
+        assert!(!policy.should_retry("connection refused"));
+        assert!(!policy.should_retry("unsupported auth mechanism"));
+    }
+
+    #[test]
+    fn rejects_invalid_patterns() {
+        let mut opts = crate::Options::default();
+        opts.retry_on = Some("(".to_owned());
+
+        assert!(RetryPolicy::from_options(&opts).is_err());
+    }
+
+    #[test]
+    fn finds_undeclared_loot_keys() {
+        let loot = Loot::new(
+            "test",
+            "127.0.0.1:80",
+            [
+                ("username".to_owned(), "admin".to_owned()),
+                ("banner.server".to_owned(), "nginx".to_owned()),
+                ("token".to_owned(), "abcd".to_owned()),
+            ],
+        );
+
+        assert_eq!(
+            undeclared_loot_keys(&["username", "password", "banner.*"], &loot),
+            vec!["token"]
+        );
+        assert!(undeclared_loot_keys(&["username", "banner.*", "token"], &loot).is_empty());
+    }
+
+    #[test]
+    fn loot_keys_are_listed_once() {
+        assert_eq!(
+            loot_keys(&["banner", "certificate.*"]),
+            vec![
+                "banner",
+                "certificate.*",
+                "username_line",
+                "password_line",
+                "raw_username",
+                "verified"
+            ]
+        );
+    }
+}
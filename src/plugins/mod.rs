@@ -28,14 +28,22 @@ plug! {
 
     #[cfg(feature = "amqp")]
     pub(crate) amqp;
+    #[cfg(feature = "bench")]
+    pub(crate) bench;
+    #[cfg(feature = "couchdb")]
+    pub(crate) couchdb;
     #[cfg(feature = "dns")]
     pub(crate) dns;
+    #[cfg(feature = "etcd")]
+    pub(crate) etcd;
     #[cfg(feature = "ftp")]
-    ftp;
+    pub(crate) ftp;
+    #[cfg(feature = "git")]
+    git;
     #[cfg(feature = "http")]
     pub(crate) http;
     #[cfg(feature = "imap")]
-    imap;
+    pub(crate) imap;
     #[cfg(feature = "kerberos")]
     pub(crate) kerberos;
     #[cfg(feature = "ldap")]
@@ -46,6 +54,8 @@ plug! {
     pub(crate) mqtt;
     #[cfg(feature = "mssql")]
     mssql;
+    #[cfg(feature = "nntp")]
+    pub(crate) nntp;
     #[cfg(feature = "oracle")]
     pub(crate) oracle; // optional as it requires libclntsh that's a pain to install and configure
     #[cfg(feature = "pop3")]
@@ -65,13 +75,19 @@ plug! {
     #[cfg(feature = "socks5")]
     pub(crate) socks5;
     #[cfg(feature = "sql")]
-    sql;
+    pub(crate) sql;
     #[cfg(feature = "ssh")]
     pub(crate) ssh;
     #[cfg(feature = "stomp")]
     pub(crate) stomp;
     #[cfg(feature = "telnet")]
     pub(crate) telnet;
+    #[cfg(feature = "tftp")]
+    tftp;
     #[cfg(feature = "vnc")]
     pub(crate) vnc;
+    #[cfg(feature = "wordpress")]
+    pub(crate) wordpress;
+    #[cfg(feature = "xmpp")]
+    pub(crate) xmpp;
 }
@@ -13,6 +13,8 @@ super::manager::register_plugin! {
     "mongodb" => MongoDB::new()
 }
 
+const DEFAULT_PORT: u16 = 27017;
+
 #[derive(Clone)]
 pub(crate) struct MongoDB {}
 
@@ -28,6 +30,14 @@ impl Plugin for MongoDB {
         "MongoDB password authentication."
     }
 
+    fn loot_schema(&self) -> &[&str] {
+        &["username", "password", "databases"]
+    }
+
+    fn default_port(&self) -> Option<u16> {
+        Some(DEFAULT_PORT)
+    }
+
     fn setup(&mut self, _opts: &Options) -> Result<(), Error> {
         Ok(())
     }
@@ -37,7 +47,7 @@ impl Plugin for MongoDB {
         creds: &Credentials,
         timeout: Duration,
     ) -> Result<Option<Vec<Loot>>, Error> {
-        let (host, port) = utils::parse_target(&creds.target, 27017)?;
+        let (host, port) = utils::parse_target(&creds.target, DEFAULT_PORT)?;
 
         let mut opts = mongodb::options::ClientOptions::default();
         let mut cred = Credential::default();
@@ -16,6 +16,8 @@ super::manager::register_plugin! {
     "mqtt" => Mqtt::new()
 }
 
+const DEFAULT_PORT: u16 = 1883;
+
 #[derive(Clone)]
 pub(crate) struct Mqtt {
     client_id: String,
@@ -37,6 +39,10 @@ impl Plugin for Mqtt {
         "MQTT password authentication."
     }
 
+    fn default_port(&self) -> Option<u16> {
+        Some(DEFAULT_PORT)
+    }
+
     fn setup(&mut self, opts: &Options) -> Result<(), Error> {
         self.client_id = opts.mqtt.mqtt_client_id.clone();
         self.use_v5 = opts.mqtt.mqtt_v5;
@@ -48,7 +54,7 @@ impl Plugin for Mqtt {
         creds: &Credentials,
         timeout: Duration,
     ) -> Result<Option<Vec<Loot>>, Error> {
-        let address = utils::parse_target_address(&creds.target, 1883)?;
+        let address = utils::parse_target_address(&creds.target, DEFAULT_PORT)?;
         let uri = format!("mqtt://{}", address);
 
         let create_opts = mqtt::CreateOptionsBuilder::new()
@@ -74,7 +80,7 @@ impl Plugin for Mqtt {
                     // Timeouts and failed connections are reported with n=-1, in which case we return the error
                     // as we want to retry --retry times.
                     if n == -1 {
-                        Err(err.to_string())
+                        Err(err.to_string().into())
                     } else {
                         // Failed logings and other protocol errors are reported with other integer codes, in which
                         // case we return Ok(None) to move to the next set of credentials.
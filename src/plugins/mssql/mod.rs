@@ -61,6 +61,8 @@ super::manager::register_plugin! {
     "mssql" => MSSQL::new()
 }
 
+const DEFAULT_PORT: u16 = 1433;
+
 #[derive(Clone)]
 pub(crate) struct MSSQL {}
 
@@ -76,6 +78,10 @@ impl Plugin for MSSQL {
         "Microsoft SQL Server password authentication."
     }
 
+    fn default_port(&self) -> Option<u16> {
+        Some(DEFAULT_PORT)
+    }
+
     fn setup(&mut self, _opts: &Options) -> Result<(), Error> {
         Ok(())
     }
@@ -85,7 +91,7 @@ impl Plugin for MSSQL {
         creds: &Credentials,
         timeout: Duration,
     ) -> Result<Option<Vec<Loot>>, Error> {
-        let address = utils::parse_target_address(&creds.target, 1433)?;
+        let address = utils::parse_target_address(&creds.target, DEFAULT_PORT)?;
 
         let mut stream = crate::utils::net::async_tcp_stream(&address, timeout, false).await?;
 
@@ -127,22 +133,24 @@ impl Plugin for MSSQL {
         .concat();
 
         tokio::time::timeout(timeout, stream.write_all(&data))
-            .await
-            .map_err(|e| e.to_string())?
+            .await?
             .map_err(|e| e.to_string())?;
 
         tokio::time::timeout(timeout, stream.write_all(MS_PACKET_LANGP))
-            .await
-            .map_err(|e| e.to_string())?
+            .await?
             .map_err(|e| e.to_string())?;
 
         let mut resp = [0; 1024];
 
-        tokio::time::timeout(timeout, stream.read(&mut resp))
-            .await
-            .map_err(|e| e.to_string())?
+        let size = tokio::time::timeout(timeout, stream.read(&mut resp))
+            .await?
             .map_err(|e| e.to_string())?;
 
+        // the server replies with a TDS tabular result packet
+        if size > 0 && resp[0] != 0x04 {
+            return Err(Error::protocol_mismatch("mssql", &resp[..size]));
+        }
+
         if resp.len() > 10 && resp[8] == 0xe3 {
             Ok(Some(vec![Loot::new(
                 "mssql",
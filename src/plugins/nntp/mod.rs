@@ -0,0 +1,308 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use crate::session::{Error, Loot};
+use crate::utils::net::{self, StreamLike};
+use crate::Options;
+use crate::Plugin;
+
+use crate::creds::Credentials;
+use crate::utils;
+
+pub(crate) mod options;
+
+super::manager::register_plugin! {
+    "nntp" => NNTP::new()
+}
+
+const DEFAULT_PORT: u16 = 119;
+
+// the status code of a reply, if any
+fn status(reply: &str) -> Option<u16> {
+    reply.get(..3).and_then(|code| code.parse().ok())
+}
+
+// get the list of capabilities, servers not supporting the command just return an empty list
+async fn capabilities(stream: &mut Box<dyn StreamLike>) -> Result<Vec<String>, Error> {
+    net::write_line(stream, "CAPABILITIES").await?;
+    let reply = net::read_line(stream, net::MAX_LINE_SIZE).await?;
+    if status(&reply) != Some(101) {
+        return Ok(vec![]);
+    }
+
+    let mut lines = net::read_lines_until(stream, |line| line == ".").await?;
+    lines.pop();
+
+    Ok(lines)
+}
+
+fn has_capability(capabilities: &[String], capability: &str) -> bool {
+    capabilities.iter().any(|line| {
+        line.split_whitespace()
+            .next()
+            .is_some_and(|name| name.eq_ignore_ascii_case(capability))
+    })
+}
+
+// AUTHINFO USER is only advertised when it can be used on the current connection
+fn allows_authinfo_user(capabilities: &[String]) -> bool {
+    capabilities.iter().any(|line| {
+        let mut parts = line.split_whitespace();
+        parts
+            .next()
+            .is_some_and(|name| name.eq_ignore_ascii_case("AUTHINFO"))
+            && parts.any(|arg| arg.eq_ignore_ascii_case("USER"))
+    })
+}
+
+// returns Some(true) if the credentials are valid, Some(false) if they're not and None if the
+// server doesn't accept them on this connection
+async fn login(
+    stream: &mut Box<dyn StreamLike>,
+    creds: &Credentials,
+) -> Result<Option<bool>, Error> {
+    net::write_line(stream, &format!("AUTHINFO USER {}", &creds.username)).await?;
+    let reply = net::read_line(stream, net::MAX_LINE_SIZE).await?;
+    match status(&reply) {
+        // no password required
+        Some(281) => return Ok(Some(true)),
+        Some(381) => {}
+        Some(481) => return Ok(Some(false)),
+        // 483 encryption required, 502 command unavailable
+        Some(483 | 502) => return Ok(None),
+        _ => return Err(Error::Protocol(format!("AUTHINFO USER failed: {}", reply))),
+    }
+
+    net::write_line(stream, &format!("AUTHINFO PASS {}", &creds.password)).await?;
+    let reply = net::read_line(stream, net::MAX_LINE_SIZE).await?;
+    match status(&reply) {
+        Some(281) => Ok(Some(true)),
+        Some(481 | 482) => Ok(Some(false)),
+        _ => Err(Error::Protocol(format!("AUTHINFO PASS failed: {}", reply))),
+    }
+}
+
+// what we learned about the server before authenticating
+struct Server {
+    greeting: String,
+    capabilities: Vec<String>,
+    tls: bool,
+}
+
+#[derive(Clone)]
+pub(crate) struct NNTP {
+    ssl: bool,
+    starttls: bool,
+}
+
+impl NNTP {
+    pub fn new() -> Self {
+        NNTP {
+            ssl: false,
+            starttls: true,
+        }
+    }
+
+    async fn greeting(stream: &mut Box<dyn StreamLike>) -> Result<String, Error> {
+        let greeting = net::read_line(stream, net::MAX_LINE_SIZE).await?;
+        match status(&greeting) {
+            // posting allowed or not
+            Some(200 | 201) => Ok(greeting),
+            Some(400 | 502) => Err(Error::Connection(format!(
+                "service unavailable: {}",
+                greeting
+            ))),
+            _ => Err(Error::protocol_mismatch("nntp", greeting.as_bytes())),
+        }
+    }
+
+    async fn session(
+        &self,
+        mut stream: Box<dyn StreamLike>,
+        address: &str,
+        creds: &Credentials,
+        timeout: Duration,
+    ) -> Result<Option<Server>, Error> {
+        let greeting = Self::greeting(&mut stream).await?;
+        let mut caps = capabilities(&mut stream).await?;
+        let mut tls = self.ssl;
+
+        if !tls
+            && self.starttls
+            && !allows_authinfo_user(&caps)
+            && has_capability(&caps, "STARTTLS")
+        {
+            net::write_line(&mut stream, "STARTTLS").await?;
+            let reply = net::read_line(&mut stream, net::MAX_LINE_SIZE).await?;
+            if status(&reply) != Some(382) {
+                return Err(Error::Protocol(format!("STARTTLS failed: {}", reply)));
+            }
+
+            stream = net::upgrade_tcp_stream_to_ssl(stream, address, timeout).await?;
+            tls = true;
+            // capabilities can change once the connection is encrypted
+            caps = capabilities(&mut stream).await?;
+        }
+
+        match login(&mut stream, creds).await? {
+            Some(true) => {
+                let _ = net::write_line(&mut stream, "QUIT").await;
+                Ok(Some(Server {
+                    greeting,
+                    capabilities: caps,
+                    tls,
+                }))
+            }
+            Some(false) => Ok(None),
+            None => Err(Error::Auth(
+                "the server doesn't accept AUTHINFO USER on this connection".to_owned(),
+            )),
+        }
+    }
+}
+
+#[async_trait]
+impl Plugin for NNTP {
+    fn description(&self) -> &'static str {
+        "NNTP password authentication."
+    }
+
+    fn loot_schema(&self) -> &[&str] {
+        &["username", "password", "tls", "greeting", "capabilities"]
+    }
+
+    fn default_port(&self) -> Option<u16> {
+        Some(DEFAULT_PORT)
+    }
+
+    fn setup(&mut self, opts: &Options) -> Result<(), Error> {
+        self.ssl = opts.nntp.nntp_ssl;
+        self.starttls = !opts.no_starttls;
+        Ok(())
+    }
+
+    async fn check(&self, target: &str, timeout: Duration) -> Result<(), Error> {
+        let address = utils::parse_target_address(target, DEFAULT_PORT)?;
+        let mut stream = net::async_tcp_stream(&address, timeout, self.ssl).await?;
+
+        tokio::time::timeout(timeout, Self::greeting(&mut stream))
+            .await?
+            .map(|_| ())
+    }
+
+    async fn attempt(
+        &self,
+        creds: &Credentials,
+        timeout: Duration,
+    ) -> Result<Option<Vec<Loot>>, Error> {
+        let address = utils::parse_target_address(&creds.target, DEFAULT_PORT)?;
+        let stream = net::async_tcp_stream(&address, timeout, self.ssl).await?;
+
+        let server =
+            tokio::time::timeout(timeout, self.session(stream, &address, creds, timeout)).await??;
+
+        Ok(server.map(|server| {
+            vec![Loot::new(
+                "nntp",
+                &address,
+                [
+                    ("username".to_owned(), creds.username.to_owned()),
+                    ("password".to_owned(), creds.password.to_owned()),
+                    ("tls".to_owned(), server.tls.to_string()),
+                    ("greeting".to_owned(), server.greeting),
+                    ("capabilities".to_owned(), server.capabilities.join(", ")),
+                ],
+            )]
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    use super::{allows_authinfo_user, status, NNTP};
+    use crate::creds::Credentials;
+    use crate::Plugin;
+
+    // fake NNTP server allowing plaintext authentication with the given password
+    async fn server(password: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+
+            stream
+                .write_all(b"200 news.example.com ready\r\n")
+                .await
+                .unwrap();
+            let mut line = String::new();
+            while stream.read_line(&mut line).await.unwrap() > 0 {
+                let reply = match line.trim_end() {
+                    "CAPABILITIES" => {
+                        "101 Capability list:\r\nVERSION 2\r\nREADER\r\nAUTHINFO USER\r\n.\r\n"
+                            .to_owned()
+                    }
+                    cmd if cmd.starts_with("AUTHINFO USER ") => {
+                        "381 password required\r\n".to_owned()
+                    }
+                    cmd if cmd == format!("AUTHINFO PASS {}", password) => {
+                        "281 authentication accepted\r\n".to_owned()
+                    }
+                    cmd if cmd.starts_with("AUTHINFO PASS ") => {
+                        "481 authentication failed\r\n".to_owned()
+                    }
+                    _ => "500 unknown command\r\n".to_owned(),
+                };
+                stream.write_all(reply.as_bytes()).await.unwrap();
+                line.clear();
+            }
+        });
+
+        address
+    }
+
+    #[test]
+    fn can_parse_replies() {
+        assert_eq!(status("281 Ok"), Some(281));
+        assert_eq!(status("+OK"), None);
+        assert!(allows_authinfo_user(&["AUTHINFO USER SASL".to_owned()]));
+        assert!(!allows_authinfo_user(&["AUTHINFO SASL".to_owned()]));
+    }
+
+    #[tokio::test]
+    async fn can_login() {
+        let creds = Credentials {
+            target: server("secret").await,
+            username: "reader".to_owned(),
+            password: "secret".to_owned(),
+        };
+
+        let loot = NNTP::new()
+            .attempt(&creds, Duration::from_secs(5))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(loot[0].format_template("{tls}"), "false");
+        assert_eq!(
+            loot[0].format_template("{capabilities}"),
+            "VERSION 2, READER, AUTHINFO USER"
+        );
+
+        let creds = Credentials {
+            target: server("secret").await,
+            username: "reader".to_owned(),
+            password: "wrong".to_owned(),
+        };
+        assert_eq!(
+            NNTP::new().attempt(&creds, Duration::from_secs(5)).await,
+            Ok(None)
+        );
+    }
+}
@@ -0,0 +1,10 @@
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+
+#[derive(Parser, Debug, Serialize, Deserialize, Clone, Default)]
+#[group(skip)]
+pub(crate) struct Options {
+    #[clap(long, default_value_t = false)]
+    /// Enable implicit TLS for NNTP (usually on port 563), otherwise STARTTLS is used if the server doesn't allow plaintext authentication.
+    pub nntp_ssl: bool,
+}
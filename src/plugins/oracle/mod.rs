@@ -15,6 +15,8 @@ super::manager::register_plugin! {
     "oracle" => Oracle::new()
 }
 
+const DEFAULT_PORT: u16 = 1521;
+
 #[derive(Clone)]
 pub(crate) struct Oracle {
     database: String,
@@ -34,6 +36,15 @@ impl Plugin for Oracle {
         "Oracle DB authentication."
     }
 
+    fn default_port(&self) -> Option<u16> {
+        Some(DEFAULT_PORT)
+    }
+
+    fn max_concurrency(&self) -> Option<usize> {
+        // the listener refuses connections when flooded with logon attempts
+        Some(4)
+    }
+
     fn setup(&mut self, opts: &Options) -> Result<(), Error> {
         self.database = opts.oracle.oracle_database.clone();
         Ok(())
@@ -44,7 +55,7 @@ impl Plugin for Oracle {
         creds: &Credentials,
         timeout: Duration,
     ) -> Result<Option<Vec<Loot>>, Error> {
-        let address = utils::parse_target_address(&creds.target, 1521)?;
+        let address = utils::parse_target_address(&creds.target, DEFAULT_PORT)?;
         let oracle = oracle::env().map_err(|e| e.to_string())?;
 
         let op = tokio::time::timeout(
@@ -55,7 +66,7 @@ impl Plugin for Oracle {
 
         if op.is_err() {
             // timeout
-            Err("timed out".to_owned())
+            Err(Error::Timeout)
         } else if let Ok(_) = op.unwrap() {
             Ok(Some(vec![Loot::from(
                 "oracle",
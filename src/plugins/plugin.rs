@@ -7,11 +7,14 @@ use crate::session::{Error, Loot};
 use crate::Options;
 
 /// What type of payload is consumed by a plugin.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub(crate) enum PayloadStrategy {
     /// Single payload like for dns, tcp.port, etc
     Single,
     /// Standard double payload.
     UsernamePassword,
+    /// No payload, the plugin is executed once per target.
+    None,
 }
 
 impl std::fmt::Display for PayloadStrategy {
@@ -22,13 +25,25 @@ impl std::fmt::Display for PayloadStrategy {
             match self {
                 PayloadStrategy::Single => "single",
                 PayloadStrategy::UsernamePassword => "username_and_password",
+                PayloadStrategy::None => "none",
             }
         )
     }
 }
 
+// lets the manager hand out copies of the registered plugins, every plugin is Clone
+pub(crate) trait PluginClone {
+    fn clone_box(&self) -> Box<dyn Plugin>;
+}
+
+impl<T: Plugin + Clone + 'static> PluginClone for T {
+    fn clone_box(&self) -> Box<dyn Plugin> {
+        Box::new(self.clone())
+    }
+}
+
 #[async_trait]
-pub(crate) trait Plugin: Sync + Send {
+pub(crate) trait Plugin: PluginClone + Sync + Send {
     // return the description for this plugin
     fn description(&self) -> &'static str;
 
@@ -37,7 +52,41 @@ pub(crate) trait Plugin: Sync + Send {
         PayloadStrategy::UsernamePassword
     }
 
-    // single credential plugins can override this method to return their own payload expression
+    // default port used when the target doesn't specify one, if any
+    fn default_port(&self) -> Option<u16> {
+        None
+    }
+
+    // SRV record prefix used by --srv to find the servers of a domain, like _ldap._tcp
+    fn srv_service(&self) -> Option<&'static str> {
+        None
+    }
+
+    // whether the service is reached over TCP, the --only-open connect sweep skips the others
+    fn uses_tcp(&self) -> bool {
+        true
+    }
+
+    // maximum number of concurrent workers this protocol tolerates, applied unless --concurrency
+    // is explicitly set
+    fn max_concurrency(&self) -> Option<usize> {
+        None
+    }
+
+    // options this plugin can't work without, by their command line name without the leading
+    // dashes (like "kerberos-realm")
+    fn required_options(&self) -> &[&str] {
+        &[]
+    }
+
+    // keys of the loot data this plugin can produce, a trailing '*' stands for any key with that
+    // prefix, the ones added by the manager to every loot are not listed
+    fn loot_schema(&self) -> &[&str] {
+        &["username", "password"]
+    }
+
+    // single credential plugins can override this method to return their own payload expression,
+    // for username and password plugins it replaces the password expression
     fn override_payload(&self) -> Option<Expression> {
         None
     }
@@ -45,10 +94,65 @@ pub(crate) trait Plugin: Sync + Send {
     // configure the plugin initial state
     fn setup(&mut self, options: &Options) -> Result<(), Error>;
 
+    // reject targets this plugin can't handle, called once per target before any attempt
+    fn validate_target(&self, _target: &str) -> Result<(), Error> {
+        Ok(())
+    }
+
+    // connect to what the plugin needs before reaching any target, like a jump host, called once
+    // before the attempts; an error stops the run
+    async fn prepare(&self, _timeout: Duration) -> Result<(), Error> {
+        Ok(())
+    }
+
+    // check that the target is reachable and speaks the expected protocol without authenticating,
+    // plugins that can do it without side effects opt in by overriding this method
+    async fn check(&self, _target: &str, _timeout: Duration) -> Result<(), Error> {
+        Ok(())
+    }
+
     // perform a plugin step with the given credentials and timeout
     async fn attempt(
         &self,
         creds: &Credentials,
         timeout: Duration,
     ) -> Result<Option<Vec<Loot>>, Error>;
+
+    // relative cost of an attempt compared to the other plugins, used to weight the progress and
+    // the ETA when running plugins with different costs
+    fn weight(&self) -> usize {
+        1
+    }
+
+    // number of requests sent to the target by each attempt, paced and rate limited like as many
+    // attempts
+    fn requests_per_attempt(&self) -> usize {
+        1
+    }
+
+    // maximum number of credentials passed to attempt_batch
+    fn batch_size(&self) -> usize {
+        1
+    }
+
+    // perform a plugin step for each of the given credentials, all for the same target, plugins
+    // able to test more than one credential per connection can override it; results are in the
+    // same order as the credentials and can stop at the first error, the credentials left
+    // untried are attempted again
+    async fn attempt_batch(
+        &self,
+        creds: &[Credentials],
+        timeout: Duration,
+    ) -> Vec<Result<Option<Vec<Loot>>, Error>> {
+        let mut results = Vec::with_capacity(creds.len());
+        for creds in creds {
+            let result = self.attempt(creds, timeout).await;
+            let failed = result.is_err();
+            results.push(result);
+            if failed {
+                break;
+            }
+        }
+        results
+    }
 }
@@ -3,6 +3,7 @@ use std::time::Duration;
 use async_trait::async_trait;
 
 use crate::session::{Error, Loot};
+use crate::utils::net::{self, StreamLike};
 use crate::Options;
 use crate::Plugin;
 
@@ -15,14 +16,94 @@ super::manager::register_plugin! {
     "pop3" => POP3::new()
 }
 
+const DEFAULT_PORT: u16 = 110;
+
+// check the server capabilities and upgrade the connection to TLS if USER is not available in
+// plaintext, returns the stream and whether it has been upgraded
+async fn starttls(
+    mut stream: Box<dyn StreamLike>,
+    address: &str,
+    timeout: Duration,
+) -> Result<(Box<dyn StreamLike>, bool), Error> {
+    net::write_line(&mut stream, "CAPA").await?;
+    let capabilities = net::read_lines_until(&mut stream, |line| {
+        line == "." || line.starts_with("-ERR")
+    })
+    .await?;
+
+    let has = |capability: &str| {
+        capabilities
+            .iter()
+            .any(|line| line.eq_ignore_ascii_case(capability))
+    };
+    if has("USER") || !has("STLS") {
+        return Ok((stream, false));
+    }
+
+    net::write_line(&mut stream, "STLS").await?;
+    let reply = net::read_line(&mut stream, net::MAX_LINE_SIZE).await?;
+    if !reply.starts_with("+OK") {
+        return Err(Error::Protocol(format!("STLS failed: {}", reply)));
+    }
+
+    Ok((
+        net::upgrade_tcp_stream_to_ssl(stream, address, timeout).await?,
+        true,
+    ))
+}
+
+async fn login(stream: &mut Box<dyn StreamLike>, creds: &Credentials) -> Result<bool, Error> {
+    net::write_line(stream, &format!("USER {}", &creds.username)).await?;
+    if !net::read_line(stream, net::MAX_LINE_SIZE)
+        .await?
+        .starts_with("+OK")
+    {
+        return Ok(false);
+    }
+
+    net::write_line(stream, &format!("PASS {}", &creds.password)).await?;
+    Ok(net::read_line(stream, net::MAX_LINE_SIZE)
+        .await?
+        .starts_with("+OK"))
+}
+
 #[derive(Clone)]
 pub(crate) struct POP3 {
     ssl: bool,
+    starttls: bool,
 }
 
 impl POP3 {
     pub fn new() -> Self {
-        POP3 { ssl: false }
+        POP3 {
+            ssl: false,
+            starttls: true,
+        }
+    }
+
+    async fn session(
+        &self,
+        mut stream: Box<dyn StreamLike>,
+        address: &str,
+        creds: &Credentials,
+        timeout: Duration,
+    ) -> Result<Option<bool>, Error> {
+        let greeting = net::read_line(&mut stream, net::MAX_LINE_SIZE).await?;
+        if !greeting.starts_with("+OK") {
+            return Err(Error::protocol_mismatch("pop3", greeting.as_bytes()));
+        }
+
+        let mut tls = self.ssl;
+        if !self.ssl && self.starttls {
+            (stream, tls) = starttls(stream, address, timeout).await?;
+        }
+
+        Ok(if login(&mut stream, creds).await? {
+            let _ = net::write_line(&mut stream, "QUIT").await;
+            Some(tls)
+        } else {
+            None
+        })
     }
 }
 
@@ -32,57 +113,118 @@ impl Plugin for POP3 {
         "POP3 password authentication."
     }
 
+    fn loot_schema(&self) -> &[&str] {
+        &["username", "password", "tls"]
+    }
+
+    fn default_port(&self) -> Option<u16> {
+        Some(DEFAULT_PORT)
+    }
+
     fn setup(&mut self, opts: &Options) -> Result<(), Error> {
         self.ssl = opts.pop3.pop3_ssl;
+        self.starttls = !opts.no_starttls;
         Ok(())
     }
 
+    async fn check(&self, target: &str, timeout: Duration) -> Result<(), Error> {
+        let address = utils::parse_target_address(target, DEFAULT_PORT)?;
+        let mut stream = net::async_tcp_stream(&address, timeout, self.ssl).await?;
+
+        let greeting = tokio::time::timeout(timeout, net::read_line(&mut stream, net::MAX_LINE_SIZE))
+            .await??;
+        if greeting.starts_with("+OK") {
+            Ok(())
+        } else {
+            Err(Error::protocol_mismatch("pop3", greeting.as_bytes()))
+        }
+    }
+
     async fn attempt(
         &self,
         creds: &Credentials,
         timeout: Duration,
     ) -> Result<Option<Vec<Loot>>, Error> {
-        let (host, port) = utils::parse_target(&creds.target, 110)?;
-        let address = (host, port);
-
-        if self.ssl {
-            let tls = async_native_tls::TlsConnector::new()
-                .danger_accept_invalid_certs(true)
-                .danger_accept_invalid_hostnames(true);
-
-            let mut client = tokio::time::timeout(timeout, async_pop::connect(&address, "", &tls))
-                .await
-                .map_err(|e| e.to_string())?
-                .map_err(|e| e.to_string())?;
-
-            if client.login(&creds.username, &creds.password).await.is_ok() {
-                return Ok(Some(vec![Loot::new(
-                    "pop3",
-                    &address.0,
-                    [
-                        ("username".to_owned(), creds.username.to_owned()),
-                        ("password".to_owned(), creds.password.to_owned()),
-                    ],
-                )]));
-            }
-        } else {
-            let mut client = tokio::time::timeout(timeout, async_pop::connect_plain(&address))
-                .await
-                .map_err(|e| e.to_string())?
-                .map_err(|e| e.to_string())?;
-
-            if client.login(&creds.username, &creds.password).await.is_ok() {
-                return Ok(Some(vec![Loot::new(
-                    "pop3",
-                    &address.0,
-                    [
-                        ("username".to_owned(), creds.username.to_owned()),
-                        ("password".to_owned(), creds.password.to_owned()),
-                    ],
-                )]));
+        let address = utils::parse_target_address(&creds.target, DEFAULT_PORT)?;
+        let stream = net::async_tcp_stream(&address, timeout, self.ssl).await?;
+
+        let tls =
+            tokio::time::timeout(timeout, self.session(stream, &address, creds, timeout)).await??;
+
+        Ok(tls.map(|tls| {
+            vec![Loot::new(
+                "pop3",
+                &address,
+                [
+                    ("username".to_owned(), creds.username.to_owned()),
+                    ("password".to_owned(), creds.password.to_owned()),
+                    ("tls".to_owned(), tls.to_string()),
+                ],
+            )]
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    use super::POP3;
+    use crate::creds::Credentials;
+    use crate::Plugin;
+
+    // fake POP3 server allowing plaintext authentication with the given password
+    async fn server(password: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+
+            stream.write_all(b"+OK ready\r\n").await.unwrap();
+            let mut line = String::new();
+            while stream.read_line(&mut line).await.unwrap() > 0 {
+                let reply = match line.trim_end() {
+                    "CAPA" => "+OK\r\nUSER\r\nSTLS\r\n.\r\n".to_owned(),
+                    cmd if cmd.starts_with("USER ") => "+OK\r\n".to_owned(),
+                    cmd if cmd == format!("PASS {}", password) => "+OK logged in\r\n".to_owned(),
+                    _ => "-ERR\r\n".to_owned(),
+                };
+                stream.write_all(reply.as_bytes()).await.unwrap();
+                line.clear();
             }
-        }
+        });
+
+        address
+    }
+
+    #[tokio::test]
+    async fn can_login_without_starttls() {
+        let creds = Credentials {
+            target: server("secret").await,
+            username: "admin".to_owned(),
+            password: "secret".to_owned(),
+        };
+
+        let loot = POP3::new()
+            .attempt(&creds, Duration::from_secs(5))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(loot[0].format_template("{tls}"), "false");
 
-        Ok(None)
+        let creds = Credentials {
+            target: server("secret").await,
+            username: "admin".to_owned(),
+            password: "wrong".to_owned(),
+        };
+        assert_eq!(
+            POP3::new().attempt(&creds, Duration::from_secs(5)).await,
+            Ok(None)
+        );
     }
 }
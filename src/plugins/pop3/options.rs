@@ -5,6 +5,6 @@ use serde::{Deserialize, Serialize};
 #[group(skip)]
 pub(crate) struct Options {
     #[clap(long, default_value_t = false)]
-    /// Enable SSL for POP3.
+    /// Enable SSL for POP3, otherwise STARTTLS is used if the server doesn't allow plaintext authentication.
     pub pop3_ssl: bool,
 }
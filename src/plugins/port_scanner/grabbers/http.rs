@@ -74,7 +74,9 @@ pub(crate) async fn http_grabber(
 
     // if ssl, upgrade stream to get certificate information
     if ssl {
-        if let Ok(tls) = upgrade_tcp_stream_to_tls(stream, timeout).await {
+        if let Ok(tls) =
+            upgrade_tcp_stream_to_tls(stream, &format!("{}:{}", address, port), timeout).await
+        {
             if let Ok(Some(cert)) = tls.peer_certificate() {
                 if let Ok(der) = cert.to_der() {
                     if let Ok((_, cert)) = X509Certificate::from_der(&der) {
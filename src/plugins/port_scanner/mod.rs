@@ -93,7 +93,7 @@ impl PortScanner {
         }
 
         if addresses.is_empty() {
-            Err(format!("can't get socket address for {target}"))
+            Err(format!("can't get socket address for {target}").into())
         } else {
             Ok(addresses[0])
         }
@@ -121,16 +121,14 @@ impl PortScanner {
             let mut buf = [0u8; 1024];
 
             tokio::time::timeout(timeout, udp_socket.connect(socket))
-                .await
-                .map_err(|e| e.to_string())?
+                .await?
                 .map_err(|e| e.to_string())?;
 
             tokio::time::timeout(
                 timeout,
                 udp_socket.send(grabbers::dns::CHAOS_BIND_VERSION_QUERY),
             )
-            .await
-            .map_err(|e| e.to_string())?
+            .await?
             .map_err(|e| e.to_string())?;
 
             if let Ok(Ok(size)) = tokio::time::timeout(timeout, udp_socket.recv(&mut buf)).await {
@@ -158,6 +156,20 @@ impl Plugin for PortScanner {
         "TCP and UDP ports scanner."
     }
 
+    fn loot_schema(&self) -> &[&str] {
+        // banners are grabbed by protocol, the udp ones are not prefixed
+        &[
+            "transport",
+            "port",
+            "time",
+            "protocol",
+            "banner",
+            "banner.*",
+            "dns.*",
+            "certificate.*",
+        ]
+    }
+
     fn payload_strategy(&self) -> PayloadStrategy {
         PayloadStrategy::Single
     }
@@ -191,13 +203,13 @@ impl Plugin for PortScanner {
             return Err(format!(
                 "'{:?}' is not a valid port range expression",
                 &self.ports
-            ));
+            ).into());
         }
 
         self.opts = opts.port_scanner.clone();
 
         if self.opts.port_scanner_no_tcp && self.opts.port_scanner_no_udp {
-            Err("both TCP and UDP port scanning are disabled".to_string())
+            Err("both TCP and UDP port scanning are disabled".into())
         } else {
             Ok(())
         }
@@ -18,6 +18,8 @@ super::manager::register_plugin! {
     "rdp" => RDP::new()
 }
 
+const DEFAULT_PORT: u16 = 3389;
+
 #[derive(Clone)]
 pub(crate) struct RDP {
     options: options::Options,
@@ -37,6 +39,15 @@ impl Plugin for RDP {
         "Microsoft Remote Desktop password authentication."
     }
 
+    fn default_port(&self) -> Option<u16> {
+        Some(DEFAULT_PORT)
+    }
+
+    fn max_concurrency(&self) -> Option<usize> {
+        // too many concurrent CredSSP handshakes get dropped by the server
+        Some(4)
+    }
+
     fn setup(&mut self, opts: &Options) -> Result<(), Error> {
         self.options = opts.rdp.clone();
         Ok(())
@@ -47,7 +58,7 @@ impl Plugin for RDP {
         creds: &Credentials,
         timeout: Duration,
     ) -> Result<Option<Vec<Loot>>, Error> {
-        let (host, port) = utils::parse_target(&creds.target, 3389)?;
+        let (host, port) = utils::parse_target(&creds.target, DEFAULT_PORT)?;
         let address = format!("{}:{}", &host, port)
             .parse::<SocketAddr>()
             .map_err(|e| e.to_string())?;
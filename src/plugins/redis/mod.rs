@@ -1,28 +1,159 @@
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use async_trait::async_trait;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
 use crate::session::{Error, Loot};
+use crate::utils::net::{self, StreamLike};
 use crate::Plugin;
 use crate::{utils, Options};
 
 use crate::creds::Credentials;
 
 pub(crate) mod options;
+mod resp;
+
+// number of credentials tested on the same connection
+const BATCH_SIZE: usize = 16;
 
 super::manager::register_plugin! {
     "redis" => Redis::new()
 }
 
+const DEFAULT_PORT: u16 = 6379;
+
 #[derive(Clone)]
 pub(crate) struct Redis {
     ssl: bool,
+    // targets that closed the connection after AUTH, reported once
+    auth_unavailable: Arc<Mutex<HashSet<String>>>,
 }
 
 impl Redis {
     pub fn new() -> Self {
-        Redis { ssl: false }
+        Redis {
+            ssl: false,
+            auth_unavailable: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    fn loot(address: &str, creds: &Credentials, info: Vec<(String, String)>) -> Loot {
+        let mut data = vec![
+            ("username".to_owned(), creds.username.to_owned()),
+            ("password".to_owned(), creds.password.to_owned()),
+        ];
+        data.extend(info);
+
+        Loot::new("redis", address, data)
+    }
+
+    // server version and replication role, read with INFO once authenticated. An error means the
+    // connection can't be used anymore, a denied INFO just gives nothing.
+    async fn info(
+        stream: &mut Box<dyn StreamLike>,
+        timeout: Duration,
+    ) -> Result<Vec<(String, String)>, Error> {
+        net::write_line(stream, "INFO").await?;
+
+        loop {
+            let line = tokio::time::timeout(timeout, resp::read_line(stream, net::MAX_LINE_SIZE))
+                .await?
+                .map_err(Self::reply_error)?;
+            let reply =
+                tokio::time::timeout(timeout, resp::parse(&line, stream, net::MAX_LINE_SIZE))
+                    .await?
+                    .map_err(Self::reply_error)?;
+
+            match reply {
+                resp::Reply::OutOfBand => continue,
+                resp::Reply::Value(info) => return Ok(parse_info(&info)),
+                _ => return Ok(vec![]),
+            }
+        }
+    }
+
+    // returns whether the credentials are valid, or None if the server closed the connection
+    async fn auth(
+        &self,
+        stream: &mut Box<dyn StreamLike>,
+        address: &str,
+        creds: &Credentials,
+    ) -> Result<Option<bool>, Error> {
+        net::write_line(
+            stream,
+            &format!("AUTH {} {}", &creds.username, &creds.password),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+        loop {
+            let line = match resp::read_line(stream, net::MAX_LINE_SIZE).await {
+                Ok(line) => line,
+                // a server closing the connection right after AUTH is not going to change its mind
+                // on retry, treat it as a definitive failure and only report it once
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        std::io::ErrorKind::UnexpectedEof
+                            | std::io::ErrorKind::ConnectionReset
+                            | std::io::ErrorKind::ConnectionAborted
+                            | std::io::ErrorKind::BrokenPipe
+                    ) =>
+                {
+                    if self
+                        .auth_unavailable
+                        .lock()
+                        .unwrap()
+                        .insert(address.to_owned())
+                    {
+                        log::warn!(
+                            "[{}] connection closed after AUTH, authentication is likely unavailable: {}",
+                            address,
+                            e
+                        );
+                    }
+                    return Ok(None);
+                }
+                Err(e) => return Err(Self::reply_error(e)),
+            };
+
+            // every RESP reply starts with its type
+            if !line.starts_with(|c: char| c.is_ascii() && resp::TYPES.contains(&(c as u8))) {
+                return Err(Error::protocol_mismatch("redis", line.as_bytes()));
+            }
+
+            match resp::parse(&line, stream, net::MAX_LINE_SIZE)
+                .await
+                .map_err(Self::reply_error)?
+            {
+                // the actual reply follows
+                resp::Reply::OutOfBand => continue,
+                resp::Reply::Status(status) => return Ok(Some(status == "OK")),
+                resp::Reply::Error(error) => {
+                    log::debug!("[{}] {}", address, error);
+                    return Ok(Some(false));
+                }
+                reply => {
+                    return Err(Error::Protocol(format!(
+                        "unexpected reply to AUTH: {:?}",
+                        reply
+                    )))
+                }
+            }
+        }
+    }
+
+    // malformed, truncated and oversized replies are protocol errors
+    fn reply_error(e: std::io::Error) -> Error {
+        if matches!(
+            e.kind(),
+            std::io::ErrorKind::InvalidData | std::io::ErrorKind::UnexpectedEof
+        ) {
+            Error::Protocol(format!("invalid reply: {}", e))
+        } else {
+            e.to_string().into()
+        }
     }
 }
 
@@ -32,43 +163,225 @@ impl Plugin for Redis {
         "Redis legacy and ACL password authentication."
     }
 
+    fn loot_schema(&self) -> &[&str] {
+        &["username", "password", "version", "role"]
+    }
+
+    fn default_port(&self) -> Option<u16> {
+        Some(DEFAULT_PORT)
+    }
+
     fn setup(&mut self, opts: &Options) -> Result<(), Error> {
         self.ssl = opts.redis.redis_ssl;
         Ok(())
     }
 
+    async fn check(&self, target: &str, timeout: Duration) -> Result<(), Error> {
+        let address = utils::parse_target_address(target, DEFAULT_PORT)?;
+        let mut stream = net::async_tcp_stream(&address, timeout, self.ssl).await?;
+
+        net::write_line(&mut stream, "PING").await?;
+        // either +PONG or -NOAUTH
+        let reply = tokio::time::timeout(timeout, resp::read_line(&mut stream, net::MAX_LINE_SIZE))
+            .await?
+            .map_err(Self::reply_error)?;
+        if reply.starts_with(['+', '-']) {
+            Ok(())
+        } else {
+            Err(Error::protocol_mismatch("redis", reply.as_bytes()))
+        }
+    }
+
+    fn batch_size(&self) -> usize {
+        BATCH_SIZE
+    }
+
     async fn attempt(
         &self,
         creds: &Credentials,
         timeout: Duration,
     ) -> Result<Option<Vec<Loot>>, Error> {
-        let address = utils::parse_target_address(&creds.target, 6379)?;
-
-        let mut stream = crate::utils::net::async_tcp_stream(&address, timeout, self.ssl).await?;
-
-        stream
-            .write_all(format!("AUTH {} {}\n", &creds.username, &creds.password).as_bytes())
-            .await
-            .map_err(|e| e.to_string())?;
-
-        let mut buffer = [0_u8; 3];
-
-        stream
-            .read_exact(&mut buffer)
-            .await
-            .map_err(|e| e.to_string())?;
-
-        if buffer.starts_with(&[b'+', b'O', b'K']) {
-            Ok(Some(vec![Loot::new(
-                "redis",
-                &address,
-                [
-                    ("username".to_owned(), creds.username.to_owned()),
-                    ("password".to_owned(), creds.password.to_owned()),
-                ],
-            )]))
-        } else {
-            Ok(None)
+        let address = utils::parse_target_address(&creds.target, DEFAULT_PORT)?;
+        let mut stream = net::async_tcp_stream(&address, timeout, self.ssl).await?;
+
+        Ok(match self.auth(&mut stream, &address, creds).await? {
+            Some(true) => {
+                let info = Self::info(&mut stream, timeout).await.unwrap_or_else(|e| {
+                    log::debug!("[{}] INFO failed: {}", &address, e);
+                    vec![]
+                });
+                Some(vec![Self::loot(&address, creds, info)])
+            }
+            _ => None,
+        })
+    }
+
+    async fn attempt_batch(
+        &self,
+        creds: &[Credentials],
+        timeout: Duration,
+    ) -> Vec<Result<Option<Vec<Loot>>, Error>> {
+        let address = match utils::parse_target_address(&creds[0].target, DEFAULT_PORT) {
+            Ok(address) => address,
+            Err(e) => return vec![Err(e)],
+        };
+        let mut stream = match net::async_tcp_stream(&address, timeout, self.ssl).await {
+            Ok(stream) => stream,
+            Err(e) => return vec![Err(e)],
+        };
+        let total = creds.len();
+        let mut results = Vec::with_capacity(total);
+
+        // redis doesn't limit the AUTH attempts per connection
+        for creds in creds {
+            match self.auth(&mut stream, &address, creds).await {
+                Ok(Some(true)) => match Self::info(&mut stream, timeout).await {
+                    Ok(info) => results.push(Ok(Some(vec![Self::loot(&address, creds, info)]))),
+                    Err(e) => {
+                        log::debug!("[{}] INFO failed: {}", &address, e);
+                        results.push(Ok(Some(vec![Self::loot(&address, creds, vec![])])));
+                        // a partially read reply would be taken for the next AUTH one
+                        match net::async_tcp_stream(&address, timeout, self.ssl).await {
+                            Ok(new_stream) => stream = new_stream,
+                            // the credentials left are attempted again
+                            Err(_) => break,
+                        }
+                    }
+                },
+                Ok(Some(false)) => results.push(Ok(None)),
+                // authentication is unavailable, none of the credentials left can succeed
+                Ok(None) => {
+                    results.resize_with(total, || Ok(None));
+                    break;
+                }
+                Err(e) => {
+                    results.push(Err(e));
+                    break;
+                }
+            }
         }
+
+        results
+    }
+}
+
+// pick the interesting fields of an INFO reply
+fn parse_info(info: &str) -> Vec<(String, String)> {
+    info.lines()
+        .filter_map(|line| line.split_once(':'))
+        .filter_map(|(key, value)| match key {
+            "redis_version" => Some(("version".to_owned(), value.trim().to_owned())),
+            "role" => Some(("role".to_owned(), value.trim().to_owned())),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    use super::Redis;
+    use crate::creds::Credentials;
+    use crate::session::Error;
+    use crate::Plugin;
+
+    #[tokio::test]
+    async fn connection_closed_after_auth_is_not_an_error() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            // accept and immediately close every connection
+            while let Ok((stream, _)) = listener.accept().await {
+                drop(stream);
+            }
+        });
+
+        let redis = Redis::new();
+        let creds = Credentials {
+            target: address.clone(),
+            username: "default".to_owned(),
+            password: "foobar".to_owned(),
+        };
+
+        for _ in 0..2 {
+            let res = redis.attempt(&creds, Duration::from_secs(5)).await;
+            assert_eq!(res, Ok(None));
+        }
+
+        assert!(redis.auth_unavailable.lock().unwrap().contains(&address));
+    }
+
+    #[tokio::test]
+    async fn detects_protocol_mismatch() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            stream
+                .write_all(b"HTTP/1.1 400 Bad Request\r\n\r\n")
+                .await
+                .unwrap();
+        });
+
+        let creds = Credentials {
+            target: address,
+            username: "default".to_owned(),
+            password: "foobar".to_owned(),
+        };
+
+        let res = Redis::new().attempt(&creds, Duration::from_secs(5)).await;
+        assert!(matches!(res, Err(Error::ProtocolMismatch(_))));
+    }
+
+    #[tokio::test]
+    async fn can_batch_on_one_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            // a single connection is accepted
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+            let mut line = String::new();
+            while stream.read_line(&mut line).await.unwrap() > 0 {
+                let reply: &[u8] = if line.trim_end() == "AUTH default secret" {
+                    b"+OK\r\n"
+                } else if line.trim_end() == "INFO" {
+                    b"$60\r\n# Server\r\nredis_version:7.2.4\r\n\r\n# Replication\r\nrole:slave\r\n\r\n"
+                } else {
+                    b"-WRONGPASS invalid username-password pair\r\n"
+                };
+                stream.write_all(reply).await.unwrap();
+                line.clear();
+            }
+        });
+
+        let creds: Vec<Credentials> = ["foo", "secret", "bar"]
+            .iter()
+            .map(|password| Credentials {
+                target: address.clone(),
+                username: "default".to_owned(),
+                password: password.to_string(),
+            })
+            .collect();
+
+        let res = Redis::new()
+            .attempt_batch(&creds, Duration::from_secs(5))
+            .await;
+        assert_eq!(res.len(), 3);
+        assert_eq!(res[0], Ok(None));
+        assert_eq!(res[2], Ok(None));
+
+        let loot = &res[1].as_ref().unwrap().as_ref().unwrap()[0];
+        assert_eq!(
+            loot.format_template("{password} {version} {role}"),
+            "secret 7.2.4 slave"
+        );
     }
 }
@@ -0,0 +1,243 @@
+use std::io;
+
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+// the first byte of every RESP2 and RESP3 reply
+pub(crate) const TYPES: &[u8] = b"+-:$*_#,(!=%~>|";
+
+// bulk strings bigger than this are not a reply to anything we send
+const MAX_BULK_SIZE: usize = 64 * 1024;
+// maximum number of elements, nested ones included, of an aggregate reply
+const MAX_ELEMENTS: usize = 1024;
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum Reply {
+    Status(String),
+    // simple and blob errors
+    Error(String),
+    // integers, doubles, bulk strings and the other scalar types
+    Value(String),
+    Null,
+    // arrays, maps and sets, their elements are read and discarded
+    Aggregate,
+    // push messages and attributes, the actual reply follows them
+    OutOfBand,
+}
+
+fn invalid<E: Into<Box<dyn std::error::Error + Send + Sync>>>(error: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+// read a CRLF terminated line up to max bytes. Unlike net::read_line this doesn't truncate: lines
+// exceeding max and lines cut by the connection being closed are InvalidData errors, while
+// UnexpectedEof is only returned if the connection is closed before any data is read.
+pub(crate) async fn read_line<S: AsyncRead + Unpin + ?Sized>(
+    stream: &mut S,
+    max: usize,
+) -> io::Result<String> {
+    let mut line = vec![];
+    loop {
+        let byte = match stream.read_u8().await {
+            Ok(byte) => byte,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof && !line.is_empty() => {
+                return Err(invalid("truncated reply"));
+            }
+            Err(e) => return Err(e),
+        };
+
+        if byte == b'\n' && line.last() == Some(&b'\r') {
+            line.pop();
+            return Ok(String::from_utf8_lossy(&line).into_owned());
+        } else if line.len() > max {
+            return Err(invalid(format!("reply line exceeds {} bytes", max)));
+        }
+
+        line.push(byte);
+    }
+}
+
+fn split_type(line: &str) -> io::Result<(u8, &str)> {
+    match line.as_bytes().first() {
+        Some(kind) if TYPES.contains(kind) => Ok((*kind, &line[1..])),
+        _ => Err(invalid(format!("unexpected reply {:?}", line))),
+    }
+}
+
+// length of a blob or aggregate, None for the RESP2 null values
+fn length(data: &str, max: usize) -> io::Result<Option<usize>> {
+    match data.parse::<i64>() {
+        Ok(-1) => Ok(None),
+        Ok(len) if len >= 0 && len as usize <= max => Ok(Some(len as usize)),
+        Ok(len) => Err(invalid(format!("invalid reply length {}", len))),
+        Err(_) => Err(invalid(format!("invalid reply length {:?}", data))),
+    }
+}
+
+async fn read_blob<S: AsyncRead + Unpin + ?Sized>(
+    stream: &mut S,
+    data: &str,
+) -> io::Result<Option<String>> {
+    let Some(len) = length(data, MAX_BULK_SIZE)? else {
+        return Ok(None);
+    };
+
+    let mut blob = vec![0u8; len + 2];
+    stream
+        .read_exact(&mut blob)
+        .await
+        .map_err(|_| invalid("truncated reply"))?;
+    if !blob.ends_with(b"\r\n") {
+        return Err(invalid("bulk reply is not terminated by CRLF"));
+    }
+    blob.truncate(len);
+
+    Ok(Some(String::from_utf8_lossy(&blob).into_owned()))
+}
+
+// maps and attributes have a key and a value per entry
+fn elements(kind: u8, entries: usize) -> usize {
+    if matches!(kind, b'%' | b'|') {
+        entries * 2
+    } else {
+        entries
+    }
+}
+
+// read and discard the given number of elements of an aggregate, nested ones included
+async fn skip_elements<S: AsyncRead + Unpin + ?Sized>(
+    stream: &mut S,
+    mut pending: usize,
+    max_line: usize,
+) -> io::Result<()> {
+    let mut total = 0;
+    while pending > 0 {
+        pending -= 1;
+        total += 1;
+        if total > MAX_ELEMENTS {
+            return Err(invalid(format!(
+                "aggregate reply exceeds {} elements",
+                MAX_ELEMENTS
+            )));
+        }
+
+        let line = read_line(stream, max_line).await?;
+        match split_type(&line)? {
+            (b'$' | b'!' | b'=', data) => {
+                read_blob(stream, data).await?;
+            }
+            (kind @ (b'*' | b'%' | b'~' | b'>' | b'|'), data) => {
+                if let Some(entries) = length(data, MAX_ELEMENTS)? {
+                    pending += elements(kind, entries);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+// parse the reply starting with the given line, reading the rest of it from the stream
+pub(crate) async fn parse<S: AsyncRead + Unpin + ?Sized>(
+    line: &str,
+    stream: &mut S,
+    max_line: usize,
+) -> io::Result<Reply> {
+    let (kind, data) = split_type(line)?;
+    match kind {
+        b'+' => Ok(Reply::Status(data.to_owned())),
+        b'-' => Ok(Reply::Error(data.to_owned())),
+        b'_' => Ok(Reply::Null),
+        b'$' | b'!' | b'=' => Ok(match read_blob(stream, data).await? {
+            None => Reply::Null,
+            Some(blob) if kind == b'!' => Reply::Error(blob),
+            Some(blob) => Reply::Value(blob),
+        }),
+        b'*' | b'%' | b'~' | b'>' | b'|' => match length(data, MAX_ELEMENTS)? {
+            None => Ok(Reply::Null),
+            Some(entries) => {
+                skip_elements(stream, elements(kind, entries), max_line).await?;
+                Ok(if matches!(kind, b'>' | b'|') {
+                    Reply::OutOfBand
+                } else {
+                    Reply::Aggregate
+                })
+            }
+        },
+        _ => Ok(Reply::Value(data.to_owned())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+
+    use super::{parse, read_line, Reply};
+
+    async fn read(mut data: &[u8]) -> std::io::Result<Reply> {
+        let line = read_line(&mut data, 64).await?;
+        parse(&line, &mut data, 64).await
+    }
+
+    #[tokio::test]
+    async fn can_parse_replies() {
+        assert_eq!(
+            read(b"+OK\r\n").await.unwrap(),
+            Reply::Status("OK".to_owned())
+        );
+        assert_eq!(
+            read(b"-WRONGPASS invalid username-password pair\r\n")
+                .await
+                .unwrap(),
+            Reply::Error("WRONGPASS invalid username-password pair".to_owned())
+        );
+        assert_eq!(
+            read(b"!22\r\nSYNTAX invalid\r\nsyntax\r\n").await.unwrap(),
+            Reply::Error("SYNTAX invalid\r\nsyntax".to_owned())
+        );
+        assert_eq!(read(b"$-1\r\n").await.unwrap(), Reply::Null);
+        assert_eq!(
+            read(b">3\r\n$10\r\ninvalidate\r\n*1\r\n$3\r\nfoo\r\n:1\r\n")
+                .await
+                .unwrap(),
+            Reply::OutOfBand
+        );
+        assert_eq!(
+            read(b"%1\r\n+key\r\n*2\r\n:1\r\n:2\r\n").await.unwrap(),
+            Reply::Aggregate
+        );
+    }
+
+    #[tokio::test]
+    async fn oversized_reply_is_an_error() {
+        let mut reply = b"-ERR ".to_vec();
+        reply.extend(std::iter::repeat_n(b'A', 1024 * 1024));
+        reply.extend_from_slice(b"\r\n");
+
+        let err = read(&reply).await.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+        let err = read(b"$1073741824\r\nAAAA").await.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+        let err = read(b"*100000\r\n").await.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+
+    #[tokio::test]
+    async fn truncated_reply_is_an_error() {
+        assert_eq!(
+            read(b"-ERR wrong").await.unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+        assert_eq!(
+            read(b"$10\r\nabc").await.unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+        // nothing at all is a closed connection
+        assert_eq!(
+            read(b"").await.unwrap_err().kind(),
+            ErrorKind::UnexpectedEof
+        );
+    }
+}
@@ -21,6 +21,8 @@ super::manager::register_plugin! {
     "smb" => SMB::new()
 }
 
+const DEFAULT_PORT: u16 = 445;
+
 #[derive(Clone)]
 pub(crate) struct SMB {
     share: Option<String>,
@@ -54,7 +56,7 @@ impl SMB {
                 .no_auto_anonymous_login(false)
                 .one_share_per_server(true),
         )
-        .map_err(|e| format!("error creating client for {}: {}", share, e))
+        .map_err(|e| format!("error creating client for {}: {}", share, e).into())
     }
 
     async fn get_share_for(&self, target: &str) -> Result<String, Error> {
@@ -99,7 +101,7 @@ impl SMB {
         Err(format!(
             "could not find private share for {}, provide one with --smb-share",
             target
-        ))
+        ).into())
     }
 }
 
@@ -109,6 +111,10 @@ impl Plugin for SMB {
         "Samba password authentication."
     }
 
+    fn default_port(&self) -> Option<u16> {
+        Some(DEFAULT_PORT)
+    }
+
     fn setup(&mut self, opts: &Options) -> Result<(), Error> {
         self.share = opts.smb.smb_share.clone();
         self.workgroup = opts.smb.smb_workgroup.clone();
@@ -120,7 +126,7 @@ impl Plugin for SMB {
         creds: &Credentials,
         timeout: Duration,
     ) -> Result<Option<Vec<Loot>>, Error> {
-        let address = utils::parse_target_address(&creds.target, 445)?;
+        let address = utils::parse_target_address(&creds.target, DEFAULT_PORT)?;
         let server = format!("smb://{}", &address);
         let share = tokio::time::timeout(timeout, self.get_share_for(&address))
             .await
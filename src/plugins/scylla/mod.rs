@@ -13,6 +13,8 @@ super::manager::register_plugin! {
     "scylla" => Scylla::new()
 }
 
+const DEFAULT_PORT: u16 = 9042;
+
 #[derive(Clone)]
 pub(crate) struct Scylla {}
 
@@ -28,6 +30,10 @@ impl Plugin for Scylla {
         "ScyllaDB / Cassandra password authentication."
     }
 
+    fn default_port(&self) -> Option<u16> {
+        Some(DEFAULT_PORT)
+    }
+
     fn setup(&mut self, _opts: &Options) -> Result<(), Error> {
         Ok(())
     }
@@ -37,7 +43,7 @@ impl Plugin for Scylla {
         creds: &Credentials,
         timeout: Duration,
     ) -> Result<Option<Vec<Loot>>, Error> {
-        let address: String = utils::parse_target_address(&creds.target, 9042)?;
+        let address: String = utils::parse_target_address(&creds.target, DEFAULT_PORT)?;
         let session = scylla::SessionBuilder::new()
             .known_node(&address)
             .connection_timeout(timeout)
@@ -60,7 +66,7 @@ impl Plugin for Scylla {
             if err.contains("Authentication failed") {
                 Ok(None)
             } else {
-                Err(err)
+                Err(err.into())
             }
         }
     }
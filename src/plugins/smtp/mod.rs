@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use async_smtp::{authentication, SmtpClient, SmtpTransport};
@@ -5,27 +7,145 @@ use async_trait::async_trait;
 use tokio::io::BufStream;
 
 use crate::session::{Error, Loot};
+use crate::utils::net::{self, StreamLike};
 use crate::Options;
 use crate::Plugin;
 
 use crate::creds::Credentials;
 use crate::utils;
 
-pub(crate) mod options;
-
 super::manager::register_plugin! {
     "smtp" => SMTP::new()
 }
 
+const DEFAULT_PORT: u16 = 25;
+
+// the last line of a reply has a space instead of a dash after the code
+fn is_last_line(line: &str) -> bool {
+    line.as_bytes().get(3) != Some(&b'-')
+}
+
+fn parse_mechanism(name: &str) -> Result<authentication::Mechanism, Error> {
+    match name.to_uppercase().as_str() {
+        "PLAIN" => Ok(authentication::Mechanism::Plain),
+        "LOGIN" => Ok(authentication::Mechanism::Login),
+        "XOAUTH2" => Ok(authentication::Mechanism::Xoauth2),
+        _ => Err(format!(
+            "'{}' is not a valid authentication mechanism, only PLAIN, LOGIN or XOAUTH2 are accepted.",
+            name
+        )
+        .into()),
+    }
+}
+
+async fn extensions(stream: &mut Box<dyn StreamLike>) -> Result<Vec<String>, Error> {
+    net::write_line(stream, "EHLO localhost").await?;
+    Ok(net::read_lines_until(stream, is_last_line)
+        .await?
+        .iter()
+        // skip the code and separator
+        .map(|line| line.get(4..).unwrap_or_default().to_uppercase())
+        .collect())
+}
+
+// mechanisms from both the "AUTH PLAIN LOGIN" and the legacy "AUTH=PLAIN LOGIN" forms
+fn parse_auth(extensions: &[String]) -> Vec<String> {
+    let mut mechanisms: Vec<String> = vec![];
+    for ext in extensions {
+        if let Some(rest) = ext
+            .strip_prefix("AUTH")
+            .filter(|rest| rest.is_empty() || rest.starts_with(' ') || rest.starts_with('='))
+        {
+            for mechanism in rest.trim_start_matches('=').split_whitespace() {
+                if !mechanisms.iter().any(|m| m == mechanism) {
+                    mechanisms.push(mechanism.to_owned());
+                }
+            }
+        }
+    }
+    mechanisms
+}
+
+// the preferred mechanism if advertised, otherwise PLAIN or LOGIN; XOAUTH2 needs a token
+// instead of a password and it's never picked unless preferred
+fn select_mechanism(
+    advertised: &[String],
+    preferred: authentication::Mechanism,
+) -> Result<authentication::Mechanism, Error> {
+    let candidates = [
+        preferred,
+        authentication::Mechanism::Plain,
+        authentication::Mechanism::Login,
+    ];
+
+    if advertised.is_empty() {
+        return Err(Error::Auth(
+            "the server doesn't advertise AUTH on this connection".to_owned(),
+        ));
+    }
+
+    candidates
+        .into_iter()
+        .find(|m| advertised.iter().any(|a| *a == m.to_string()))
+        .ok_or_else(|| {
+            Error::Auth(format!(
+                "the advertised authentication mechanisms are not supported: {} (use --auth-mech to force one)",
+                advertised.join(", ")
+            ))
+        })
+}
+
+// read the greeting and check the server capabilities, upgrading the connection to TLS if AUTH
+// is only offered after STARTTLS, returns the stream, whether it has been upgraded and the
+// advertised authentication mechanisms
+async fn negotiate(
+    mut stream: Box<dyn StreamLike>,
+    address: &str,
+    timeout: Duration,
+    enabled: bool,
+) -> Result<(Box<dyn StreamLike>, bool, Vec<String>), Error> {
+    let greeting = net::read_lines_until(&mut stream, is_last_line).await?;
+    if !greeting[0].starts_with("220") {
+        return Err(Error::protocol_mismatch("smtp", greeting[0].as_bytes()));
+    }
+
+    let exts = extensions(&mut stream).await?;
+    let mechanisms = parse_auth(&exts);
+    let has_starttls = exts.iter().any(|ext| ext == "STARTTLS");
+    if !enabled || !mechanisms.is_empty() || !has_starttls {
+        return Ok((stream, false, mechanisms));
+    }
+
+    net::write_line(&mut stream, "STARTTLS").await?;
+    let reply = net::read_lines_until(&mut stream, is_last_line).await?;
+    if !reply[0].starts_with("220") {
+        return Err(Error::Protocol(format!("STARTTLS failed: {:?}", reply)));
+    }
+
+    let mut stream = net::upgrade_tcp_stream_to_ssl(stream, address, timeout).await?;
+    // the extensions must be requested again once the connection is encrypted
+    let mechanisms = parse_auth(&extensions(&mut stream).await?);
+
+    Ok((stream, true, mechanisms))
+}
+
 #[derive(Clone)]
 pub(crate) struct SMTP {
     mechanism: authentication::Mechanism,
+    // skip the negotiation if set by --auth-mech
+    forced: bool,
+    starttls: bool,
+    // targets without any supported mechanism, there's no point connecting to them again
+    unsupported: Arc<Mutex<HashMap<String, Error>>>,
 }
 
 impl SMTP {
     pub fn new() -> Self {
         SMTP {
             mechanism: authentication::Mechanism::Plain,
+            forced: false,
+            starttls: true,
+            unsupported: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 }
@@ -36,28 +156,65 @@ impl Plugin for SMTP {
         "SMTP password authentication."
     }
 
+    fn loot_schema(&self) -> &[&str] {
+        &["username", "password", "tls"]
+    }
+
+    fn default_port(&self) -> Option<u16> {
+        Some(DEFAULT_PORT)
+    }
+
     fn setup(&mut self, opts: &Options) -> Result<(), Error> {
-        self.mechanism = match opts.smtp.smtp_mechanism.as_ref() {
-            "PLAIN" => authentication::Mechanism::Plain,
-            "LOGIN" => authentication::Mechanism::Login,
-            "XOAUTH2" => authentication::Mechanism::Xoauth2,
-            _ => {
-                return Err(format!("'{}' is not a valid authentication mechanism, only PLAIN., LOGIN or XOAUTH2 are accepted.", &opts.smtp.smtp_mechanism));
-            }
-        };
+        // PLAIN is preferred when negotiating
+        self.forced = opts.auth_mech.is_some();
+        self.mechanism = parse_mechanism(opts.auth_mech.as_deref().unwrap_or("PLAIN"))?;
+        self.starttls = !opts.no_starttls;
 
         Ok(())
     }
 
+    async fn check(&self, target: &str, timeout: Duration) -> Result<(), Error> {
+        let address = utils::parse_target_address(target, DEFAULT_PORT)?;
+        let mut stream = net::async_tcp_stream(&address, timeout, false).await?;
+
+        let greeting =
+            tokio::time::timeout(timeout, net::read_lines_until(&mut stream, is_last_line))
+                .await??;
+        if greeting[0].starts_with("220") {
+            Ok(())
+        } else {
+            Err(Error::protocol_mismatch("smtp", greeting[0].as_bytes()))
+        }
+    }
+
     async fn attempt(
         &self,
         creds: &Credentials,
         timeout: Duration,
     ) -> Result<Option<Vec<Loot>>, Error> {
-        let address = utils::parse_target_address(&creds.target, 25)?;
-        let stream = crate::utils::net::async_tcp_stream(&address, timeout, false).await?;
+        let address = utils::parse_target_address(&creds.target, DEFAULT_PORT)?;
+        if let Some(err) = self.unsupported.lock().unwrap().get(&address) {
+            return Err(err.clone());
+        }
 
-        let client = SmtpClient::new();
+        let stream = net::async_tcp_stream(&address, timeout, false).await?;
+        let (stream, tls, advertised) =
+            tokio::time::timeout(timeout, negotiate(stream, &address, timeout, self.starttls))
+                .await??;
+
+        let mechanism = if self.forced {
+            self.mechanism
+        } else {
+            select_mechanism(&advertised, self.mechanism).inspect_err(|err| {
+                self.unsupported
+                    .lock()
+                    .unwrap()
+                    .insert(address.to_owned(), err.clone());
+            })?
+        };
+
+        // the greeting has already been consumed
+        let client = SmtpClient::new().without_greeting();
         let mut transport =
             tokio::time::timeout(timeout, SmtpTransport::new(client, BufStream::new(stream)))
                 .await
@@ -67,13 +224,14 @@ impl Plugin for SMTP {
         let credentials =
             authentication::Credentials::new(creds.username.clone(), creds.password.clone());
 
-        if transport.auth(self.mechanism, &credentials).await.is_ok() {
+        if transport.auth(mechanism, &credentials).await.is_ok() {
             Ok(Some(vec![Loot::new(
                 "smtp",
                 &address,
                 [
                     ("username".to_owned(), creds.username.to_owned()),
                     ("password".to_owned(), creds.password.to_owned()),
+                    ("tls".to_owned(), tls.to_string()),
                 ],
             )]))
         } else {
@@ -81,3 +239,108 @@ impl Plugin for SMTP {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use async_smtp::authentication::Mechanism;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::{parse_auth, select_mechanism, SMTP};
+    use crate::creds::Credentials;
+    use crate::session::Error;
+    use crate::Options;
+    use crate::Plugin;
+
+    fn exts(extensions: &[&str]) -> Vec<String> {
+        extensions.iter().map(|e| e.to_string()).collect()
+    }
+
+    #[test]
+    fn smtp_mechanism_is_an_alias_of_auth_mech() {
+        use clap::Parser;
+
+        let opts = Options::try_parse_from(["legba", "smtp", "--smtp-mechanism", "LOGIN"]).unwrap();
+        assert_eq!(opts.auth_mech, Some("LOGIN".to_owned()));
+
+        let mut smtp = SMTP::new();
+        assert_eq!(Ok(()), smtp.setup(&opts));
+        assert!(smtp.forced);
+        assert_eq!(smtp.mechanism, Mechanism::Login);
+    }
+
+    #[test]
+    fn can_parse_auth_extension() {
+        assert_eq!(
+            parse_auth(&exts(&[
+                "PIPELINING",
+                "AUTH LOGIN PLAIN",
+                "AUTH=LOGIN",
+                "AUTHX"
+            ])),
+            vec!["LOGIN".to_owned(), "PLAIN".to_owned()]
+        );
+        assert!(parse_auth(&exts(&["PIPELINING", "STARTTLS"])).is_empty());
+    }
+
+    #[test]
+    fn can_select_mechanism() {
+        let advertised = exts(&["LOGIN", "XOAUTH2"]);
+        assert_eq!(
+            select_mechanism(&advertised, Mechanism::Plain),
+            Ok(Mechanism::Login)
+        );
+        assert_eq!(
+            select_mechanism(&advertised, Mechanism::Xoauth2),
+            Ok(Mechanism::Xoauth2)
+        );
+        assert!(matches!(
+            select_mechanism(&exts(&["XOAUTH2", "GSSAPI"]), Mechanism::Plain),
+            Err(Error::Auth(msg)) if msg.contains("XOAUTH2, GSSAPI")
+        ));
+        assert!(matches!(
+            select_mechanism(&[], Mechanism::Plain),
+            Err(Error::Auth(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn reports_unsupported_mechanisms() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        // a single connection is accepted, the second attempt must not connect again
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            stream
+                .write_all(b"220 mail.example.com ESMTP\r\n")
+                .await
+                .unwrap();
+            let mut buf = [0u8; 128];
+            let _ = stream.read(&mut buf).await.unwrap();
+            stream
+                .write_all(
+                    b"250-mail.example.com\r\n250-AUTH XOAUTH2 OAUTHBEARER\r\n250 8BITMIME\r\n",
+                )
+                .await
+                .unwrap();
+            let _ = stream.read(&mut buf).await;
+        });
+
+        let opts = Options::default();
+        let mut smtp = SMTP::new();
+        assert_eq!(Ok(()), smtp.setup(&opts));
+
+        let creds = Credentials {
+            target: address,
+            username: "admin".to_owned(),
+            password: "admin".to_owned(),
+        };
+        let timeout = std::time::Duration::from_secs(5);
+        for _ in 0..2 {
+            assert!(matches!(
+                smtp.attempt(&creds, timeout).await,
+                Err(Error::Auth(msg)) if msg.contains("XOAUTH2, OAUTHBEARER")
+            ));
+        }
+    }
+}
@@ -15,6 +15,8 @@ super::manager::register_plugin! {
     "socks5" => Socks5::new()
 }
 
+const DEFAULT_PORT: u16 = 1080;
+
 #[derive(Clone)]
 pub(crate) struct Socks5 {
     remote_address: String,
@@ -36,6 +38,10 @@ impl Plugin for Socks5 {
         "SOCKS5 password authentication."
     }
 
+    fn default_port(&self) -> Option<u16> {
+        Some(DEFAULT_PORT)
+    }
+
     fn setup(&mut self, opts: &Options) -> Result<(), Error> {
         self.remote_address.clone_from(&opts.socks5.socks5_address);
         self.remote_port = opts.socks5.socks5_port;
@@ -48,7 +54,7 @@ impl Plugin for Socks5 {
         creds: &Credentials,
         timeout: Duration,
     ) -> Result<Option<Vec<Loot>>, Error> {
-        let address: String = utils::parse_target_address(&creds.target, 1080)?;
+        let address: String = utils::parse_target_address(&creds.target, DEFAULT_PORT)?;
         let res = tokio::time::timeout(
             timeout,
             fast_socks5::client::Socks5Stream::connect_with_password(
@@ -60,8 +66,7 @@ impl Plugin for Socks5 {
                 fast_socks5::client::Config::default(),
             ),
         )
-        .await
-        .map_err(|e| e.to_string())?;
+        .await?;
 
         return Ok(if res.is_ok() {
             Some(vec![Loot::new(
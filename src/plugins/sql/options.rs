@@ -0,0 +1,21 @@
+use clap::{Parser, ValueEnum};
+use serde::{Deserialize, Serialize};
+
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) enum SslMode {
+    /// Never use TLS.
+    Disable,
+    /// Use TLS if the server supports it.
+    #[default]
+    Prefer,
+    /// Fail if the server does not support TLS.
+    Require,
+}
+
+#[derive(Parser, Debug, Serialize, Deserialize, Clone, Default)]
+#[group(skip)]
+pub(crate) struct Options {
+    #[clap(long, value_enum, default_value_t = SslMode::Prefer)]
+    /// TLS negotiation mode for MySQL and PostgreSQL.
+    pub sql_ssl_mode: SslMode,
+}
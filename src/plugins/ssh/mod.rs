@@ -1,10 +1,14 @@
-use async_ssh2_tokio::client::{AuthMethod, Client, ServerCheckMethod};
-
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use async_trait::async_trait;
+use russh::client::{self, KeyboardInteractiveAuthResponse};
+use russh_keys::key;
+use tokio::sync::{watch, OwnedSemaphorePermit, Semaphore};
 
-use crate::creds::Credentials;
+use crate::creds::{Credentials, Expression};
 use crate::session::{Error, Loot};
 use crate::utils;
 use crate::Options;
@@ -17,10 +21,249 @@ super::manager::register_plugin! {
     "sftp" => SSH::new()
 }
 
+const DEFAULT_PORT: u16 = 22;
+
+// how the host key compares with the one in the known_hosts file
+#[derive(Clone, Debug, PartialEq)]
+enum HostKeyStatus {
+    Match,
+    Unknown,
+    Changed(usize),
+}
+
+impl std::fmt::Display for HostKeyStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HostKeyStatus::Match => write!(f, "match"),
+            HostKeyStatus::Unknown => write!(f, "unknown"),
+            HostKeyStatus::Changed(line) => write!(f, "changed (known_hosts line {})", line),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct HostKey {
+    kind: String,
+    fingerprint: String,
+    status: Option<HostKeyStatus>,
+}
+
+impl HostKey {
+    fn new(key: &key::PublicKey, known_hosts: Option<&str>, address: &str) -> Self {
+        let status = known_hosts.map(|path| {
+            let (host, port) = split_address(address);
+            match russh_keys::check_known_hosts_path(host, port, key, path) {
+                Ok(true) => HostKeyStatus::Match,
+                Ok(false) => HostKeyStatus::Unknown,
+                Err(russh_keys::Error::KeyChanged { line }) => HostKeyStatus::Changed(line),
+                Err(e) => {
+                    log::debug!("could not check {} in {}: {}", address, path, e);
+                    HostKeyStatus::Unknown
+                }
+            }
+        });
+
+        Self {
+            kind: key.name().to_owned(),
+            fingerprint: format!("SHA256:{}", key.fingerprint()),
+            status,
+        }
+    }
+
+    fn loot_data(&self) -> Vec<(String, String)> {
+        let mut data = vec![
+            ("host_key_type".to_owned(), self.kind.clone()),
+            ("host_key".to_owned(), self.fingerprint.clone()),
+        ];
+        if let Some(status) = &self.status {
+            data.push(("host_key_status".to_owned(), status.to_string()));
+        }
+        data
+    }
+}
+
+// known_hosts entries use the host and port separately, ipv6 addresses are in brackets
+fn split_address(address: &str) -> (&str, u16) {
+    match address.rsplit_once(':') {
+        Some((host, port)) => (
+            host.trim_start_matches('[').trim_end_matches(']'),
+            port.parse().unwrap_or(DEFAULT_PORT),
+        ),
+        None => (address, DEFAULT_PORT),
+    }
+}
+
+// channels opened on a single connection to the jump host, OpenSSH's default MaxSessions
+const JUMP_MAX_SESSIONS: usize = 10;
+
+// an SSH server the connections to the targets are tunneled through
+#[derive(Clone, Debug, PartialEq)]
+struct Jump {
+    username: String,
+    address: String,
+    password: Option<String>,
+    key: Option<String>,
+}
+
+impl Jump {
+    // parse a user@host:port specification, the port defaults to 22
+    fn parse(spec: &str) -> Result<Self, Error> {
+        let (username, host) = spec
+            .split_once('@')
+            .filter(|(username, host)| !username.is_empty() && !host.is_empty())
+            .ok_or_else(|| format!("invalid jump host '{}', expected user@host:port", spec))?;
+
+        Ok(Self {
+            username: username.to_owned(),
+            address: utils::parse_target_address(host, DEFAULT_PORT)?,
+            password: None,
+            key: None,
+        })
+    }
+}
+
+// a connection to the jump host and the channels that can still be opened on it
+struct Tunnel {
+    handle: Arc<client::Handle<ClientHandler>>,
+    sessions: Arc<Semaphore>,
+}
+
+// the reason sent by the server before closing the connection, if any
+type Disconnect = Option<(russh::Disconnect, String)>;
+
+// accept any host key, but keep track of it
+struct ClientHandler {
+    address: String,
+    known_hosts: Option<String>,
+    host_key: Arc<Mutex<Option<HostKey>>>,
+    disconnect: watch::Sender<Disconnect>,
+}
+
+#[async_trait]
+impl client::Handler for ClientHandler {
+    type Error = russh::Error;
+
+    async fn check_server_key(
+        &mut self,
+        server_public_key: &key::PublicKey,
+    ) -> Result<bool, Self::Error> {
+        *self.host_key.lock().unwrap() = Some(HostKey::new(
+            server_public_key,
+            self.known_hosts.as_deref(),
+            &self.address,
+        ));
+        Ok(true)
+    }
+
+    async fn disconnected(
+        &mut self,
+        reason: client::DisconnectReason<Self::Error>,
+    ) -> Result<(), Self::Error> {
+        match reason {
+            client::DisconnectReason::ReceivedDisconnect(info) => {
+                self.disconnect
+                    .send_replace(Some((info.reason_code, info.message)));
+                Ok(())
+            }
+            client::DisconnectReason::Error(e) => Err(e),
+        }
+    }
+}
+
+// answer every prompt with the password, PAM usually asks for it just once
+async fn keyboard_interactive(
+    handle: &mut client::Handle<ClientHandler>,
+    username: &str,
+    password: &str,
+) -> Result<bool, russh::Error> {
+    let mut response = handle
+        .authenticate_keyboard_interactive_start(username, None::<String>)
+        .await?;
+
+    loop {
+        match response {
+            KeyboardInteractiveAuthResponse::Success => return Ok(true),
+            KeyboardInteractiveAuthResponse::Failure => return Ok(false),
+            KeyboardInteractiveAuthResponse::InfoRequest { prompts, .. } => {
+                let answers = prompts.iter().map(|_| password.to_owned()).collect();
+                response = handle
+                    .authenticate_keyboard_interactive_respond(answers)
+                    .await?;
+            }
+        }
+    }
+}
+
+// OpenSSH drops the connection once MaxAuthTries is reached instead of rejecting the request,
+// the credentials have been tested already
+fn is_max_auth_tries(reason: &russh::Disconnect, message: &str) -> bool {
+    matches!(reason, russh::Disconnect::NoMoreAuthMethodsAvailable)
+        || message
+            .to_lowercase()
+            .contains("too many authentication failures")
+}
+
+// the reason is known once the session is over, nothing is received if the connection just dropped
+async fn disconnected_by_max_auth_tries(disconnect: &mut watch::Receiver<Disconnect>) -> bool {
+    match disconnect.wait_for(Option::is_some).await {
+        Ok(reason) => {
+            matches!(&*reason, Some((reason, message)) if is_max_auth_tries(reason, message))
+        }
+        Err(_) => false,
+    }
+}
+
+// get the private keys from either a directory or a file with one key path per line
+fn keys_expression(path: &str) -> Result<Expression, Error> {
+    if !Path::new(path).is_dir() {
+        return Ok(Expression::Wordlist {
+            filename: path.to_owned(),
+        });
+    }
+
+    let mut keys = vec![];
+    for entry in std::fs::read_dir(path).map_err(|e| format!("can't read {}: {}", path, e))? {
+        let path = entry?.path();
+        let is_key = path.is_file()
+            && !matches!(
+                path.file_name().and_then(|name| name.to_str()),
+                Some("known_hosts" | "authorized_keys" | "config")
+            )
+            && path.extension().and_then(|ext| ext.to_str()) != Some("pub");
+
+        if is_key {
+            keys.push(path.to_string_lossy().into_owned());
+        }
+    }
+
+    if keys.is_empty() {
+        return Err(format!("no private keys found in {}", path).into());
+    }
+
+    keys.sort();
+
+    Ok(Expression::Multiple {
+        expressions: keys
+            .into_iter()
+            .map(|value| Expression::Constant { value })
+            .collect(),
+    })
+}
+
 #[derive(Clone)]
 pub(crate) struct SSH {
     mode: options::Mode,
     passphrase: Option<String>,
+    keys: Option<Expression>,
+    known_hosts: Option<String>,
+    // targets already reported for a changed host key
+    changed: Arc<Mutex<HashSet<String>>>,
+    jump: Option<Jump>,
+    // connections to the jump host, shared by every attempt
+    tunnels: Arc<tokio::sync::Mutex<Vec<Tunnel>>>,
+    max_tries: usize,
+    // targets that disconnected after the first authentication request
+    single_try: Arc<Mutex<HashSet<String>>>,
 }
 
 impl SSH {
@@ -28,8 +271,244 @@ impl SSH {
         SSH {
             mode: options::Mode::default(),
             passphrase: None,
+            keys: None,
+            known_hosts: None,
+            changed: Arc::new(Mutex::new(HashSet::new())),
+            jump: None,
+            tunnels: Arc::new(tokio::sync::Mutex::new(vec![])),
+            max_tries: 2,
+            single_try: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    fn check_host_key(&self, address: &str, host_key: &HostKey) {
+        if let Some(HostKeyStatus::Changed(line)) = &host_key.status {
+            if self.changed.lock().unwrap().insert(address.to_owned()) {
+                log::warn!(
+                    "[{}] host key {} doesn't match line {} of {}, possible man in the middle or host change",
+                    address,
+                    &host_key.fingerprint,
+                    line,
+                    self.known_hosts.as_deref().unwrap_or_default()
+                );
+            }
+        }
+    }
+
+    // authentication requests that can be sent over a single connection to the target
+    fn tries_per_conn(&self, address: &str) -> usize {
+        if self.single_try.lock().unwrap().contains(address) {
+            1
+        } else if self.max_tries == 0 {
+            usize::MAX
+        } else {
+            self.max_tries
         }
     }
+
+    fn set_single_try(&self, address: &str) {
+        if self.single_try.lock().unwrap().insert(address.to_owned()) {
+            log::warn!(
+                "[{}] the server allows a single authentication request per connection (MaxAuthTries), the keyboard-interactive fallback is disabled",
+                address
+            );
+        }
+    }
+
+    fn handler(
+        &self,
+        address: &str,
+    ) -> (
+        ClientHandler,
+        Arc<Mutex<Option<HostKey>>>,
+        watch::Receiver<Disconnect>,
+    ) {
+        let host_key = Arc::new(Mutex::new(None));
+        let (disconnect, disconnected) = watch::channel(None);
+        let handler = ClientHandler {
+            address: address.to_owned(),
+            known_hosts: self.known_hosts.clone(),
+            host_key: host_key.clone(),
+            disconnect,
+        };
+        (handler, host_key, disconnected)
+    }
+
+    async fn connect_jump(&self, jump: &Jump) -> Result<client::Handle<ClientHandler>, Error> {
+        let (handler, host_key, _) = self.handler(&jump.address);
+        let config = Arc::new(client::Config::default());
+        let mut handle = client::connect(config, &jump.address, handler)
+            .await
+            .map_err(|e| Error::Connection(format!("jump host {}: {}", &jump.address, e)))?;
+
+        if let Some(host_key) = host_key.lock().unwrap().take() {
+            self.check_host_key(&jump.address, &host_key);
+        }
+
+        let authenticated = if let Some(key) = &jump.key {
+            let key = russh_keys::load_secret_key(key, self.passphrase.as_deref())
+                .map_err(|e| format!("could not load key {}: {}", key, e))?;
+            handle
+                .authenticate_publickey(&jump.username, Arc::new(key))
+                .await
+        } else {
+            handle
+                .authenticate_password(&jump.username, jump.password.as_deref().unwrap_or_default())
+                .await
+        }
+        .map_err(|e| Error::Connection(format!("jump host {}: {}", &jump.address, e)))?;
+
+        if !authenticated {
+            return Err(Error::Auth(format!(
+                "could not authenticate to the jump host {} as {}",
+                &jump.address, &jump.username
+            )));
+        }
+
+        Ok(handle)
+    }
+
+    // get a connection to the jump host with a free channel, another connection is established
+    // once they're all busy; the permit must be held as long as the channel is open
+    async fn tunnel(
+        &self,
+        jump: &Jump,
+    ) -> Result<(Arc<client::Handle<ClientHandler>>, OwnedSemaphorePermit), Error> {
+        let mut tunnels = self.tunnels.lock().await;
+        tunnels.retain(|tunnel| {
+            if tunnel.handle.is_closed() {
+                log::debug!("connection to the jump host {} closed", &jump.address);
+                return false;
+            }
+            true
+        });
+
+        for tunnel in tunnels.iter() {
+            if let Ok(permit) = tunnel.sessions.clone().try_acquire_owned() {
+                return Ok((tunnel.handle.clone(), permit));
+            }
+        }
+
+        let tunnel = Tunnel {
+            handle: Arc::new(self.connect_jump(jump).await?),
+            sessions: Arc::new(Semaphore::new(JUMP_MAX_SESSIONS)),
+        };
+        let permit = tunnel
+            .sessions
+            .clone()
+            .try_acquire_owned()
+            .map_err(|e| e.to_string())?;
+        let handle = tunnel.handle.clone();
+        tunnels.push(tunnel);
+
+        log::debug!(
+            "{} connection(s) to the jump host {}",
+            tunnels.len(),
+            &jump.address
+        );
+
+        Ok((handle, permit))
+    }
+
+    // returns the name of the authentication method that succeeded, if any, and the host key
+    async fn authenticate(
+        &self,
+        address: &str,
+        creds: &Credentials,
+    ) -> Result<(Option<&'static str>, Option<HostKey>), Error> {
+        let (handler, host_key, mut disconnected) = self.handler(address);
+        let config = Arc::new(client::Config::default());
+        // the channel through the jump host is released once the connection is dropped
+        let (mut handle, _channel) = match &self.jump {
+            None => (
+                client::connect(config, address, handler)
+                    .await
+                    .map_err(|e| Error::Connection(e.to_string()))?,
+                None,
+            ),
+            Some(jump) => {
+                let (host, port) = split_address(address);
+                let (tunnel, permit) = self.tunnel(jump).await?;
+                let channel = tunnel
+                    .channel_open_direct_tcpip(host, port as u32, "127.0.0.1", 0)
+                    .await
+                    .map_err(|e| {
+                        Error::Connection(format!(
+                            "could not reach {} through {}: {}",
+                            address, &jump.address, e
+                        ))
+                    })?;
+                (
+                    client::connect_stream(config, channel.into_stream(), handler)
+                        .await
+                        .map_err(|e| Error::Connection(e.to_string()))?,
+                    Some(permit),
+                )
+            }
+        };
+
+        let host_key = host_key.lock().unwrap().take();
+        if let Some(host_key) = &host_key {
+            self.check_host_key(address, host_key);
+        }
+
+        Ok((
+            self.login(&mut handle, &mut disconnected, address, creds)
+                .await?,
+            host_key,
+        ))
+    }
+
+    async fn login(
+        &self,
+        handle: &mut client::Handle<ClientHandler>,
+        disconnected: &mut watch::Receiver<Disconnect>,
+        address: &str,
+        creds: &Credentials,
+    ) -> Result<Option<&'static str>, Error> {
+        match self.mode {
+            options::Mode::Password => {
+                if handle
+                    .authenticate_password(&creds.username, &creds.password)
+                    .await
+                    .map_err(|e| e.to_string())?
+                {
+                    return Ok(Some("password"));
+                }
+
+                // servers with PasswordAuthentication disabled might still accept the password via PAM
+                if self.tries_per_conn(address) > 1 {
+                    match keyboard_interactive(handle, &creds.username, &creds.password).await {
+                        Ok(true) => return Ok(Some("keyboard-interactive")),
+                        Ok(false) => {}
+                        // the password has been rejected, this is not a connection error
+                        Err(e) => {
+                            if !handle.is_closed()
+                                || !disconnected_by_max_auth_tries(disconnected).await
+                            {
+                                return Err(e.to_string().into());
+                            }
+                            self.set_single_try(address);
+                        }
+                    }
+                }
+            }
+            options::Mode::Key => {
+                let key = russh_keys::load_secret_key(&creds.password, self.passphrase.as_deref())
+                    .map_err(|e| format!("could not load key {}: {}", &creds.password, e))?;
+
+                if handle
+                    .authenticate_publickey(&creds.username, Arc::new(key))
+                    .await
+                    .map_err(|e| e.to_string())?
+                {
+                    return Ok(Some("publickey"));
+                }
+            }
+        }
+
+        Ok(None)
+    }
 }
 
 #[async_trait]
@@ -38,9 +517,87 @@ impl Plugin for SSH {
         "SSH/SFTP password and private key authentication."
     }
 
+    fn loot_schema(&self) -> &[&str] {
+        match self.mode {
+            options::Mode::Password => &[
+                "username",
+                "password",
+                "method",
+                "host_key_type",
+                "host_key",
+                "host_key_status",
+            ],
+            options::Mode::Key => &[
+                "username",
+                "key",
+                "method",
+                "host_key_type",
+                "host_key",
+                "host_key_status",
+            ],
+        }
+    }
+
+    fn default_port(&self) -> Option<u16> {
+        Some(DEFAULT_PORT)
+    }
+
+    // key exchange and authentication take several round trips and some CPU on both ends
+    fn weight(&self) -> usize {
+        4
+    }
+
+    fn override_payload(&self) -> Option<Expression> {
+        self.keys.clone()
+    }
+
     fn setup(&mut self, opts: &Options) -> Result<(), Error> {
         self.mode = opts.ssh.ssh_auth_mode.clone();
         self.passphrase.clone_from(&opts.ssh.ssh_key_passphrase);
+        self.max_tries = opts.ssh.ssh_max_tries_per_conn;
+
+        if let Some(known_hosts) = &opts.ssh.ssh_known_hosts {
+            if !Path::new(known_hosts).is_file() {
+                return Err(format!("{} is not a known_hosts file", known_hosts).into());
+            }
+            self.known_hosts = Some(known_hosts.to_owned());
+        }
+
+        if let Some(jump) = &opts.ssh.ssh_jump {
+            let mut jump = Jump::parse(jump)?;
+            if opts.ssh.ssh_jump_password.is_none() && opts.ssh.ssh_jump_key.is_none() {
+                return Err("--ssh-jump requires --ssh-jump-password or --ssh-jump-key".into());
+            }
+            jump.password.clone_from(&opts.ssh.ssh_jump_password);
+            jump.key.clone_from(&opts.ssh.ssh_jump_key);
+            self.jump = Some(jump);
+        }
+
+        if let Some(keys) = &opts.ssh.ssh_keys {
+            if opts.password.is_some() || opts.password_env.is_some() {
+                return Err("--ssh-keys can't be used together with --password".into());
+            } else if opts.combinations.is_some() {
+                return Err("--ssh-keys can't be used together with --combinations".into());
+            }
+
+            self.mode = options::Mode::Key;
+            self.keys = Some(keys_expression(keys)?);
+        }
+
+        Ok(())
+    }
+
+    // make sure the jump host can be used before any attempt, the connection is kept for them
+    async fn prepare(&self, timeout: Duration) -> Result<(), Error> {
+        if let Some(jump) = &self.jump {
+            // the channel is released right away, the connection stays in the pool
+            let _tunnel = tokio::time::timeout(timeout, self.tunnel(jump))
+                .await
+                .map_err(|_| {
+                    Error::Connection(format!("jump host {}: timed out", &jump.address))
+                })??;
+        }
+
         Ok(())
     }
 
@@ -49,43 +606,152 @@ impl Plugin for SSH {
         creds: &Credentials,
         timeout: Duration,
     ) -> Result<Option<Vec<Loot>>, Error> {
-        let address = utils::parse_target_address(&creds.target, 22)?;
-        let (method, key_label) = match self.mode {
-            options::Mode::Password => (
-                AuthMethod::with_password(&creds.password),
-                "password".to_owned(),
-            ),
-            options::Mode::Key => (
-                AuthMethod::with_key_file(&creds.password, self.passphrase.as_deref()),
-                "key".to_owned(),
-            ),
+        let address = utils::parse_target_address(&creds.target, DEFAULT_PORT)?;
+        let key_label = match self.mode {
+            options::Mode::Password => "password",
+            options::Mode::Key => "key",
         };
 
-        let res = tokio::time::timeout(
-            timeout,
-            Client::connect(
-                address.clone(),
-                &creds.username,
-                method,
-                ServerCheckMethod::NoCheck,
-            ),
-        )
-        .await
-        .map_err(|e| e.to_string())?;
-
-        if res.is_ok() {
-            Ok(Some(vec![Loot::new(
-                "ssh",
-                &address,
-                [
-                    ("username".to_owned(), creds.username.to_owned()),
-                    (key_label, creds.password.to_owned()),
-                ],
-            )]))
-        } else if let Err(async_ssh2_tokio::Error::PasswordWrong) = res {
-            Ok(None)
-        } else {
-            Err(res.err().unwrap().to_string())
+        let (method, host_key) =
+            tokio::time::timeout(timeout, self.authenticate(&address, creds)).await??;
+
+        Ok(method.map(|method| {
+            let mut data = vec![
+                ("username".to_owned(), creds.username.to_owned()),
+                (key_label.to_owned(), creds.password.to_owned()),
+                ("method".to_owned(), method.to_owned()),
+            ];
+            if let Some(host_key) = host_key {
+                data.extend(host_key.loot_data());
+            }
+
+            vec![Loot::new("ssh", &address, data)]
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{
+        disconnected_by_max_auth_tries, is_max_auth_tries, keys_expression, split_address,
+        HostKeyStatus, Jump, SSH,
+    };
+    use crate::creds::Expression;
+    use tokio::sync::watch;
+
+    #[test]
+    fn can_load_keys_from_directory() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        for name in ["id_rsa", "id_rsa.pub", "id_ed25519", "known_hosts"] {
+            fs::write(tmpdir.path().join(name), "").unwrap();
         }
+
+        let key = |name: &str| Expression::Constant {
+            value: tmpdir.path().join(name).to_string_lossy().into_owned(),
+        };
+
+        assert_eq!(
+            keys_expression(tmpdir.path().to_str().unwrap()).unwrap(),
+            Expression::Multiple {
+                expressions: vec![key("id_ed25519"), key("id_rsa")]
+            }
+        );
+    }
+
+    #[test]
+    fn can_load_keys_from_list() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let list = tmpdir.path().join("keys.txt");
+        fs::write(&list, "/tmp/id_rsa\n/tmp/id_ed25519\n").unwrap();
+
+        assert_eq!(
+            keys_expression(list.to_str().unwrap()).unwrap(),
+            Expression::Wordlist {
+                filename: list.to_str().unwrap().to_owned()
+            }
+        );
+    }
+
+    #[test]
+    fn empty_directory_is_an_error() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        assert!(keys_expression(tmpdir.path().to_str().unwrap()).is_err());
+    }
+
+    #[test]
+    fn can_split_known_hosts_address() {
+        assert_eq!(split_address("10.0.0.1:22"), ("10.0.0.1", 22));
+        assert_eq!(split_address("[::1]:2222"), ("::1", 2222));
+        assert_eq!(split_address("example.com"), ("example.com", 22));
+    }
+
+    #[test]
+    fn can_parse_jump_host() {
+        let jump = Jump::parse("admin@bastion.example.com").unwrap();
+        assert_eq!(jump.username, "admin");
+        assert_eq!(jump.address, "bastion.example.com:22");
+        assert_eq!(
+            Jump::parse("admin@10.0.0.1:2222").unwrap().address,
+            "10.0.0.1:2222"
+        );
+        assert!(Jump::parse("bastion.example.com").is_err());
+        assert!(Jump::parse("@bastion.example.com").is_err());
+    }
+
+    #[test]
+    fn host_key_status_is_readable() {
+        assert_eq!(HostKeyStatus::Match.to_string(), "match");
+        assert_eq!(
+            HostKeyStatus::Changed(3).to_string(),
+            "changed (known_hosts line 3)"
+        );
+    }
+
+    #[test]
+    fn limits_tries_per_connection() {
+        let mut ssh = SSH::new();
+        assert_eq!(ssh.tries_per_conn("127.0.0.1:22"), 2);
+
+        ssh.set_single_try("127.0.0.1:22");
+        assert_eq!(ssh.tries_per_conn("127.0.0.1:22"), 1);
+        assert_eq!(ssh.tries_per_conn("127.0.0.1:2222"), 2);
+
+        ssh.max_tries = 0;
+        assert_eq!(ssh.tries_per_conn("127.0.0.1:2222"), usize::MAX);
+    }
+
+    #[test]
+    fn detects_max_auth_tries_disconnect() {
+        assert!(is_max_auth_tries(
+            &russh::Disconnect::NoMoreAuthMethodsAvailable,
+            ""
+        ));
+        assert!(is_max_auth_tries(
+            &russh::Disconnect::ProtocolError,
+            "Too many authentication failures"
+        ));
+        assert!(!is_max_auth_tries(&russh::Disconnect::ConnectionLost, ""));
+        assert!(!is_max_auth_tries(
+            &russh::Disconnect::TooManyConnections,
+            "too many connections"
+        ));
+    }
+
+    #[tokio::test]
+    async fn max_auth_tries_needs_the_disconnect_reason() {
+        let (disconnect, mut disconnected) = watch::channel(None);
+        disconnect.send_replace(Some((
+            russh::Disconnect::ProtocolError,
+            "Too many authentication failures for root".to_owned(),
+        )));
+        drop(disconnect);
+        assert!(disconnected_by_max_auth_tries(&mut disconnected).await);
+
+        // the connection dropped without a reason
+        let (disconnect, mut disconnected) = watch::channel(None);
+        drop(disconnect);
+        assert!(!disconnected_by_max_auth_tries(&mut disconnected).await);
     }
 }
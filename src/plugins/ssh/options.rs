@@ -17,4 +17,22 @@ pub(crate) struct Options {
     #[clap(long)]
     /// Optional private key passphrase for key based authentication.
     pub ssh_key_passphrase: Option<String>,
+    #[clap(long)]
+    /// Directory of private keys or file with one key path per line to try for every username, implies --ssh-auth-mode key.
+    pub ssh_keys: Option<String>,
+    #[clap(long)]
+    /// Check the server host keys against this known_hosts file and flag the ones that changed. Any host key is accepted regardless.
+    pub ssh_known_hosts: Option<String>,
+    #[clap(long)]
+    /// Tunnel the connections to the targets through this SSH jump host, as user@host:port, like OpenSSH ProxyJump. --proxy only applies to HTTP and is not used here.
+    pub ssh_jump: Option<String>,
+    #[clap(long)]
+    /// Password for the --ssh-jump host.
+    pub ssh_jump_password: Option<String>,
+    #[clap(long)]
+    /// Private key for the --ssh-jump host, decrypted with --ssh-key-passphrase if set.
+    pub ssh_jump_key: Option<String>,
+    #[clap(long, default_value_t = 2)]
+    /// Maximum number of authentication requests per connection, set it to 1 for servers with MaxAuthTries 1 to skip the keyboard-interactive fallback. Every attempt uses a new connection, 0 means no limit.
+    pub ssh_max_tries_per_conn: usize,
 }
@@ -16,6 +16,8 @@ super::manager::register_plugin! {
     "stomp" => STOMP::new()
 }
 
+const DEFAULT_PORT: u16 = 61613;
+
 #[derive(Clone)]
 pub(crate) struct STOMP {}
 
@@ -31,6 +33,10 @@ impl Plugin for STOMP {
         "STOMP password authentication (ActiveMQ, RabbitMQ, HornetQ and OpenMQ)."
     }
 
+    fn default_port(&self) -> Option<u16> {
+        Some(DEFAULT_PORT)
+    }
+
     fn setup(&mut self, _opts: &Options) -> Result<(), Error> {
         Ok(())
     }
@@ -40,7 +46,7 @@ impl Plugin for STOMP {
         creds: &Credentials,
         timeout: Duration,
     ) -> Result<Option<Vec<Loot>>, Error> {
-        let address = utils::parse_target_address(&creds.target, 61613)?;
+        let address = utils::parse_target_address(&creds.target, DEFAULT_PORT)?;
         let mut stream = crate::utils::net::async_tcp_stream(&address, timeout, false).await?;
 
         stream
@@ -56,9 +62,15 @@ impl Plugin for STOMP {
 
         let mut buffer = [0_u8; 1024];
 
-        stream.read(&mut buffer).await.map_err(|e| e.to_string())?;
+        let size = stream.read(&mut buffer).await.map_err(|e| e.to_string())?;
+        let reply = &buffer[..size];
+
+        if !reply.is_empty() && !reply.starts_with(CONNECTED_RESPONSE) && !reply.starts_with(b"ERROR")
+        {
+            return Err(Error::protocol_mismatch("stomp", reply));
+        }
 
-        if buffer.starts_with(CONNECTED_RESPONSE) {
+        if reply.starts_with(CONNECTED_RESPONSE) {
             Ok(Some(vec![Loot::new(
                 "stomp",
                 &address,
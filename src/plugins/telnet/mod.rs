@@ -14,6 +14,8 @@ super::manager::register_plugin! {
     "telnet" => Telnet::new()
 }
 
+const DEFAULT_PORT: u16 = 23;
+
 #[derive(Clone)]
 pub(crate) struct Telnet {
     user_prompt: String,
@@ -37,6 +39,10 @@ impl Plugin for Telnet {
         "Telnet password authentication."
     }
 
+    fn default_port(&self) -> Option<u16> {
+        Some(DEFAULT_PORT)
+    }
+
     fn setup(&mut self, opts: &Options) -> Result<(), Error> {
         self.user_prompt.clone_from(&opts.telnet.telnet_user_prompt);
         self.pass_prompt.clone_from(&opts.telnet.telnet_pass_prompt);
@@ -49,7 +55,7 @@ impl Plugin for Telnet {
         creds: &Credentials,
         timeout: Duration,
     ) -> Result<Option<Vec<Loot>>, Error> {
-        let address = utils::parse_target_address(&creds.target, 23)?;
+        let address = utils::parse_target_address(&creds.target, DEFAULT_PORT)?;
         let mut client = mini_telnet::Telnet::builder()
             .connect_timeout(Duration::from_secs(10))
             .login_prompt(&self.user_prompt, &self.pass_prompt)
@@ -0,0 +1,192 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use crate::session::{Error, Loot};
+use crate::utils::net::UdpSocket;
+use crate::Plugin;
+use crate::{utils, Options};
+
+use crate::creds::Credentials;
+
+use super::plugin::PayloadStrategy;
+
+super::manager::register_plugin! {
+    "tftp" => TFTP::new()
+}
+
+const DEFAULT_PORT: u16 = 69;
+
+const OPCODE_RRQ: u16 = 1;
+const OPCODE_DATA: u16 = 3;
+const OPCODE_ERROR: u16 = 5;
+
+const ERROR_FILE_NOT_FOUND: u16 = 1;
+const ERROR_ACCESS_VIOLATION: u16 = 2;
+
+// 4 bytes header plus the default 512 bytes block
+const MAX_PACKET_SIZE: usize = 516;
+
+#[derive(Debug, PartialEq)]
+enum Response {
+    Data { block: u16, size: usize },
+    Error { code: u16, message: String },
+}
+
+// read request for the file in binary mode
+fn read_request(filename: &str) -> Vec<u8> {
+    let mut packet = OPCODE_RRQ.to_be_bytes().to_vec();
+    packet.extend_from_slice(filename.as_bytes());
+    packet.push(0);
+    packet.extend_from_slice(b"octet");
+    packet.push(0);
+    packet
+}
+
+// tell the server we're not going to read the rest of the file
+fn abort_transfer() -> Vec<u8> {
+    let mut packet = OPCODE_ERROR.to_be_bytes().to_vec();
+    packet.extend_from_slice(&0u16.to_be_bytes());
+    packet.extend_from_slice(b"transfer aborted");
+    packet.push(0);
+    packet
+}
+
+fn parse_response(raw: &[u8]) -> Result<Response, Error> {
+    if raw.len() < 4 {
+        return Err(Error::protocol_mismatch("tftp", raw));
+    }
+
+    let opcode = u16::from_be_bytes([raw[0], raw[1]]);
+    let arg = u16::from_be_bytes([raw[2], raw[3]]);
+    match opcode {
+        OPCODE_DATA => Ok(Response::Data {
+            block: arg,
+            size: raw.len() - 4,
+        }),
+        OPCODE_ERROR => Ok(Response::Error {
+            code: arg,
+            message: String::from_utf8_lossy(&raw[4..])
+                .trim_end_matches('\0')
+                .to_owned(),
+        }),
+        _ => Err(Error::protocol_mismatch("tftp", raw)),
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct TFTP {}
+
+impl TFTP {
+    pub fn new() -> Self {
+        TFTP {}
+    }
+
+    // request the file and return the first answer of the server
+    async fn request(&self, address: &str, filename: &str, timeout: Duration) -> Result<(UdpSocket, Response), Error> {
+        let mut socket = utils::net::async_udp_socket(address, timeout).await?;
+        socket.send(&read_request(filename)).await?;
+
+        let mut buf = [0u8; MAX_PACKET_SIZE];
+        let (size, from) = socket.recv_from(&mut buf).await?;
+        // the transfer continues on the port the server answered from
+        socket.set_peer(from);
+
+        Ok((socket, parse_response(&buf[..size])?))
+    }
+}
+
+#[async_trait]
+impl Plugin for TFTP {
+    fn description(&self) -> &'static str {
+        "TFTP files read access."
+    }
+
+    fn loot_schema(&self) -> &[&str] {
+        &["file", "first_block_size"]
+    }
+
+    fn payload_strategy(&self) -> PayloadStrategy {
+        PayloadStrategy::Single
+    }
+
+    fn default_port(&self) -> Option<u16> {
+        Some(DEFAULT_PORT)
+    }
+
+    fn uses_tcp(&self) -> bool {
+        false
+    }
+
+    fn setup(&mut self, _opts: &Options) -> Result<(), Error> {
+        Ok(())
+    }
+
+    // there's no connection to check, any answer to a request means the service is there
+    async fn check(&self, target: &str, timeout: Duration) -> Result<(), Error> {
+        let address = utils::parse_target_address(target, DEFAULT_PORT)?;
+        self.request(&address, "legba-check", timeout)
+            .await
+            .map(|_| ())
+    }
+
+    async fn attempt(
+        &self,
+        creds: &Credentials,
+        timeout: Duration,
+    ) -> Result<Option<Vec<Loot>>, Error> {
+        let address = utils::parse_target_address(&creds.target, DEFAULT_PORT)?;
+        let filename = creds.single();
+
+        match self.request(&address, filename, timeout).await? {
+            (socket, Response::Data { block: 1, size }) => {
+                let _ = socket.send(&abort_transfer()).await;
+                Ok(Some(vec![Loot::new(
+                    "tftp",
+                    &address,
+                    [
+                        ("file".to_owned(), filename.to_owned()),
+                        // only the first block is read, the whole file might be bigger
+                        ("first_block_size".to_owned(), size.to_string()),
+                    ],
+                )]))
+            }
+            (_, Response::Data { block, .. }) => {
+                Err(Error::Protocol(format!("unexpected data block {}", block)))
+            }
+            (_, Response::Error { code, message }) => {
+                if code != ERROR_FILE_NOT_FOUND && code != ERROR_ACCESS_VIOLATION {
+                    log::debug!("[{}] {}: error {} {}", &address, filename, code, message);
+                }
+                Ok(None)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_response, read_request, Response};
+
+    #[test]
+    fn builds_read_request() {
+        assert_eq!(read_request("boot.cfg"), b"\x00\x01boot.cfg\x00octet\x00");
+    }
+
+    #[test]
+    fn can_parse_responses() {
+        assert_eq!(
+            parse_response(b"\x00\x03\x00\x01hello").unwrap(),
+            Response::Data { block: 1, size: 5 }
+        );
+        assert_eq!(
+            parse_response(b"\x00\x05\x00\x01File not found\x00").unwrap(),
+            Response::Error {
+                code: 1,
+                message: "File not found".to_owned()
+            }
+        );
+        assert!(parse_response(b"SSH-2.0-OpenSSH\r\n").is_err());
+        assert!(parse_response(b"\x00").is_err());
+    }
+}
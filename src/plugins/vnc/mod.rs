@@ -1,9 +1,9 @@
 use std::time::Duration;
 
 use async_trait::async_trait;
-use vnc::{PixelFormat, VncConnector};
 
 use crate::session::{Error, Loot};
+use crate::utils::net::StreamLike;
 use crate::Plugin;
 use crate::{utils, Options};
 
@@ -11,70 +11,117 @@ use crate::creds::Credentials;
 
 use super::plugin::PayloadStrategy;
 
+mod rfb;
+
 super::manager::register_plugin! {
-    "vnc" => VNC::new()
+    "vnc" => VNC::new(false),
+    "vnc.noauth" => VNC::new(true)
 }
 
+const DEFAULT_PORT: u16 = 5900;
+
 #[derive(Clone)]
-pub(crate) struct VNC {}
+pub(crate) struct VNC {
+    // only check for servers not requiring authentication
+    no_auth: bool,
+}
 
 impl VNC {
-    pub fn new() -> Self {
-        VNC {}
+    pub fn new(no_auth: bool) -> Self {
+        VNC { no_auth }
+    }
+
+    async fn rfb_attempt(
+        &self,
+        stream: Box<dyn StreamLike>,
+        address: &str,
+        creds: &Credentials,
+    ) -> Result<Option<Vec<Loot>>, Error> {
+        let handshake = rfb::Handshake::start(stream).await?;
+
+        if handshake.is_no_auth() {
+            return Ok(Some(vec![Loot::new(
+                "vnc",
+                address,
+                [
+                    ("kind".to_owned(), "no_auth".to_owned()),
+                    ("version".to_owned(), handshake.version),
+                ],
+            )]));
+        } else if self.no_auth {
+            return Ok(None);
+        }
+
+        let version = handshake.version.clone();
+        // being this plugin single credentials, this is going to be the password
+        if handshake.vnc_auth(creds.single()).await? {
+            return Ok(Some(vec![Loot::new(
+                "vnc",
+                address,
+                [
+                    ("username".to_owned(), creds.username.to_owned()),
+                    ("password".to_owned(), creds.password.to_owned()),
+                    ("version".to_owned(), version),
+                ],
+            )]));
+        }
+
+        Ok(None)
     }
 }
 
 #[async_trait]
 impl Plugin for VNC {
     fn description(&self) -> &'static str {
-        "VNC password authentication."
+        if self.no_auth {
+            "VNC unauthenticated access detection."
+        } else {
+            "VNC password authentication."
+        }
+    }
+
+    fn loot_schema(&self) -> &[&str] {
+        if self.no_auth {
+            &["kind", "version"]
+        } else {
+            // servers not requiring authentication are reported either way
+            &["username", "password", "kind", "version"]
+        }
     }
 
     fn payload_strategy(&self) -> PayloadStrategy {
-        PayloadStrategy::Single
+        if self.no_auth {
+            PayloadStrategy::None
+        } else {
+            PayloadStrategy::Single
+        }
+    }
+
+    fn default_port(&self) -> Option<u16> {
+        Some(DEFAULT_PORT)
     }
 
     fn setup(&mut self, _opts: &Options) -> Result<(), Error> {
         Ok(())
     }
 
+    async fn check(&self, target: &str, timeout: Duration) -> Result<(), Error> {
+        let address = utils::parse_target_address(target, DEFAULT_PORT)?;
+        let stream = utils::net::async_tcp_stream(&address, timeout, false).await?;
+
+        tokio::time::timeout(timeout, rfb::Handshake::start(stream))
+            .await?
+            .map(|_| ())
+    }
+
     async fn attempt(
         &self,
         creds: &Credentials,
         timeout: Duration,
     ) -> Result<Option<Vec<Loot>>, Error> {
-        let address = utils::parse_target_address(&creds.target, 5900)?;
-        let stream = crate::utils::net::async_tcp_stream(&address, timeout, false).await?;
-        // being this plugin single credentials, this is going to be the password
-        let password = creds.single().to_owned();
-        let vnc = tokio::time::timeout(
-            timeout,
-            VncConnector::new(stream)
-                .set_auth_method(async move { Ok(password) })
-                .add_encoding(vnc::VncEncoding::Tight)
-                .add_encoding(vnc::VncEncoding::Zrle)
-                .add_encoding(vnc::VncEncoding::CopyRect)
-                .add_encoding(vnc::VncEncoding::Raw)
-                .allow_shared(false)
-                .set_pixel_format(PixelFormat::bgra())
-                .build()
-                .map_err(|e| e.to_string())?
-                .try_start(),
-        )
-        .await
-        .map_err(|e| e.to_string())?;
-
-        if vnc.is_ok() && vnc.unwrap().finish().is_ok() {
-            return Ok(Some(vec![Loot::new(
-                "vnc",
-                &address,
-                [
-                    ("username".to_owned(), creds.username.to_owned()),
-                    ("password".to_owned(), creds.password.to_owned()),
-                ],
-            )]));
-        }
+        let address = utils::parse_target_address(&creds.target, DEFAULT_PORT)?;
+        let stream = utils::net::async_tcp_stream(&address, timeout, false).await?;
 
-        Ok(None)
+        tokio::time::timeout(timeout, self.rfb_attempt(stream, &address, creds)).await?
     }
 }
@@ -0,0 +1,248 @@
+use des::cipher::{generic_array::GenericArray, BlockEncrypt, KeyInit};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::session::Error;
+use crate::utils::net::StreamLike;
+
+pub(crate) const SECURITY_NONE: u8 = 1;
+pub(crate) const SECURITY_VNC_AUTH: u8 = 2;
+
+// maximum length of a failure reason we're going to read
+const MAX_REASON_SIZE: usize = 4096;
+
+fn parse_version(raw: &[u8]) -> Result<(u16, u16), Error> {
+    let invalid = || Error::protocol_mismatch("vnc", raw);
+
+    let version = std::str::from_utf8(raw).map_err(|_| invalid())?;
+    if version.len() != 12 || !version.starts_with("RFB ") || !version.ends_with('\n') {
+        return Err(invalid());
+    }
+
+    let major = version[4..7].parse::<u16>().map_err(|_| invalid())?;
+    let minor = version[8..11].parse::<u16>().map_err(|_| invalid())?;
+
+    Ok((major, minor))
+}
+
+async fn read_reason(stream: &mut Box<dyn StreamLike>) -> Result<String, Error> {
+    let size = stream.read_u32().await? as usize;
+    let mut reason = vec![0u8; size.min(MAX_REASON_SIZE)];
+    stream.read_exact(&mut reason).await?;
+
+    Ok(String::from_utf8_lossy(&reason).into_owned())
+}
+
+// the VNC authentication response is the challenge encrypted with DES, using the first 8 bytes
+// of the password as the key, with the bits of each byte in reverse order
+pub(crate) fn challenge_response(password: &str, challenge: &[u8; 16]) -> [u8; 16] {
+    let mut key = [0u8; 8];
+    for (k, b) in key.iter_mut().zip(password.as_bytes()) {
+        *k = b.reverse_bits();
+    }
+
+    let cipher = des::Des::new(&GenericArray::from(key));
+    let mut response = *challenge;
+    for block in response.chunks_exact_mut(8) {
+        cipher.encrypt_block(GenericArray::from_mut_slice(block));
+    }
+
+    response
+}
+
+pub(crate) struct Handshake {
+    pub version: String,
+    pub security_types: Vec<u8>,
+    minor: u16,
+    stream: Box<dyn StreamLike>,
+}
+
+impl Handshake {
+    // exchange protocol versions and get the security types supported by the server
+    pub async fn start(mut stream: Box<dyn StreamLike>) -> Result<Self, Error> {
+        let mut raw = [0u8; 12];
+        stream.read_exact(&mut raw).await?;
+
+        let (major, minor) = parse_version(&raw)?;
+        let version = String::from_utf8_lossy(&raw).trim_end().to_owned();
+
+        // we only speak 3.3, 3.7 and 3.8, anything newer gets 3.8
+        let minor = if major > 3 || minor >= 8 {
+            8
+        } else if minor == 7 {
+            7
+        } else {
+            3
+        };
+
+        stream
+            .write_all(format!("RFB 003.{:03}\n", minor).as_bytes())
+            .await?;
+
+        let security_types = if minor == 3 {
+            // the server decides the security type
+            match stream.read_u32().await? {
+                0 => return Err(read_reason(&mut stream).await?.into()),
+                security => vec![u8::try_from(security).map_err(|_| {
+                    Error::Protocol(format!("unexpected security type {}", security))
+                })?],
+            }
+        } else {
+            let num = stream.read_u8().await? as usize;
+            if num == 0 {
+                return Err(read_reason(&mut stream).await?.into());
+            }
+
+            let mut types = vec![0u8; num];
+            stream.read_exact(&mut types).await?;
+            types
+        };
+
+        log::debug!("{} security_types={:?}", &version, &security_types);
+
+        Ok(Self {
+            version,
+            security_types,
+            minor,
+            stream,
+        })
+    }
+
+    pub fn is_no_auth(&self) -> bool {
+        self.security_types.contains(&SECURITY_NONE)
+    }
+
+    // perform the VNC challenge-response authentication, returns true if the password is valid
+    pub async fn vnc_auth(mut self, password: &str) -> Result<bool, Error> {
+        if !self.security_types.contains(&SECURITY_VNC_AUTH) {
+            return Err(Error::Protocol(format!(
+                "server does not support VNC authentication (security types {:?})",
+                self.security_types
+            )));
+        }
+
+        if self.minor >= 7 {
+            self.stream.write_u8(SECURITY_VNC_AUTH).await?;
+        }
+
+        let mut challenge = [0u8; 16];
+        self.stream.read_exact(&mut challenge).await?;
+
+        self.stream
+            .write_all(&challenge_response(password, &challenge))
+            .await?;
+
+        // 0 is ok, 1 is failed
+        Ok(self.stream.read_u32().await? == 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use super::{challenge_response, parse_version, Handshake};
+    use crate::utils::net::async_tcp_stream;
+
+    const CHALLENGE: [u8; 16] = *b"0123456789abcdef";
+
+    // fake RFB 3.8 server offering the given security types and accepting the given password
+    async fn server(security_types: &'static [u8], password: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut version = [0u8; 12];
+
+            stream.write_all(b"RFB 003.008\n").await.unwrap();
+            stream.read_exact(&mut version).await.unwrap();
+            assert_eq!(&version, b"RFB 003.008\n");
+
+            stream.write_u8(security_types.len() as u8).await.unwrap();
+            stream.write_all(security_types).await.unwrap();
+
+            if matches!(stream.read_u8().await, Ok(super::SECURITY_VNC_AUTH)) {
+                let mut response = [0u8; 16];
+                stream.write_all(&CHALLENGE).await.unwrap();
+                stream.read_exact(&mut response).await.unwrap();
+
+                let ok = response == challenge_response(password, &CHALLENGE);
+                stream.write_u32(if ok { 0 } else { 1 }).await.unwrap();
+            }
+        });
+
+        address
+    }
+
+    #[test]
+    fn can_parse_version() {
+        assert_eq!(parse_version(b"RFB 003.008\n").unwrap(), (3, 8));
+        assert_eq!(parse_version(b"RFB 003.889\n").unwrap(), (3, 889));
+        assert!(parse_version(b"SSH-2.0-foo\n").is_err());
+    }
+
+    #[test]
+    fn challenge_response_depends_on_password() {
+        let a = challenge_response("secret", &CHALLENGE);
+        assert_ne!(a, CHALLENGE);
+        assert_ne!(a, challenge_response("s3cr3t", &CHALLENGE));
+        // only the first 8 bytes of the password are used
+        assert_eq!(
+            challenge_response("password", &CHALLENGE),
+            challenge_response("password123", &CHALLENGE)
+        );
+    }
+
+    #[test]
+    fn challenge_response_known_answers() {
+        // computed with openssl enc -des-ecb -nopad, using the bit reversed password as the key
+        assert_eq!(
+            challenge_response("password", &CHALLENGE),
+            [
+                0x56, 0x45, 0xab, 0xeb, 0x5f, 0x1e, 0x64, 0x75, 0xe8, 0xfe, 0xb1, 0x1b, 0xeb, 0x66,
+                0xea, 0x19
+            ]
+        );
+        // shorter passwords are padded with zeros
+        assert_eq!(
+            challenge_response("secret", &CHALLENGE),
+            [
+                0x75, 0x24, 0x40, 0xee, 0x2b, 0xfc, 0xc2, 0xa0, 0xd9, 0x01, 0x3f, 0xd2, 0x03, 0x71,
+                0xe2, 0x3b
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn detects_no_auth() {
+        let address = server(&[1], "").await;
+        let stream = async_tcp_stream(&address, Duration::from_secs(5), false)
+            .await
+            .unwrap();
+        let handshake = Handshake::start(stream).await.unwrap();
+
+        assert_eq!(handshake.version, "RFB 003.008");
+        assert!(handshake.is_no_auth());
+    }
+
+    #[tokio::test]
+    async fn can_authenticate() {
+        let address = server(&[2], "secret").await;
+        let stream = async_tcp_stream(&address, Duration::from_secs(5), false)
+            .await
+            .unwrap();
+        let handshake = Handshake::start(stream).await.unwrap();
+        assert!(!handshake.is_no_auth());
+        assert!(handshake.vnc_auth("secret").await.unwrap());
+
+        let address = server(&[2], "secret").await;
+        let stream = async_tcp_stream(&address, Duration::from_secs(5), false)
+            .await
+            .unwrap();
+        let handshake = Handshake::start(stream).await.unwrap();
+        assert!(!handshake.vnc_auth("wrong").await.unwrap());
+    }
+}
@@ -0,0 +1,300 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use reqwest::{
+    header::{CONTENT_TYPE, COOKIE, LOCATION, SET_COOKIE},
+    redirect, Client, Url,
+};
+
+use crate::creds::Credentials;
+use crate::session::{Error, Loot};
+use crate::utils;
+use crate::Options;
+use crate::Plugin;
+
+pub(crate) mod options;
+
+super::manager::register_plugin! {
+    "wordpress" => WordPress::new()
+}
+
+const DEFAULT_PORT: u16 = 80;
+
+// wp-login.php refuses to authenticate if this cookie is not set
+const TEST_COOKIE: &str = "wordpress_test_cookie=WP%20Cookie%20check";
+
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn xmlrpc_payload(creds: &Credentials) -> String {
+    format!(
+        "<?xml version=\"1.0\"?><methodCall><methodName>wp.getUsersBlogs</methodName><params>\
+        <param><value><string>{}</string></value></param>\
+        <param><value><string>{}</string></value></param>\
+        </params></methodCall>",
+        xml_escape(&creds.username),
+        xml_escape(&creds.password)
+    )
+}
+
+fn is_login_success(url: &str, status: u16, location: &str, cookies: &[String]) -> bool {
+    // a valid login sets the logged in cookie and redirects to the admin panel, anything else
+    // (the login form again, an interstitial page, a redirect to wp-login.php) is a failure
+    if cookies
+        .iter()
+        .any(|c| c.starts_with("wordpress_logged_in_"))
+    {
+        return true;
+    }
+
+    // the location can be relative to the login page, the path ends with /wp-admin/ also when
+    // wordpress is installed in a subdirectory
+    (300..400).contains(&status)
+        && Url::parse(url)
+            .and_then(|url| url.join(location))
+            .map(|target| target.path().ends_with("/wp-admin/"))
+            .unwrap_or(false)
+}
+
+fn is_xmlrpc_success(status: u16, body: &str) -> bool {
+    status == 200 && !body.contains("<name>faultCode</name>") && body.contains("isAdmin")
+}
+
+#[derive(Clone)]
+pub(crate) struct WordPress {
+    client: Client,
+    opts: options::Options,
+}
+
+impl WordPress {
+    pub fn new() -> Self {
+        WordPress {
+            client: Client::new(),
+            opts: options::Options::default(),
+        }
+    }
+
+    fn get_url(target: &str, path: &str) -> String {
+        // add default schema if not present
+        let base = if !target.contains("://") {
+            format!("http://{}", target)
+        } else {
+            target.to_owned()
+        };
+
+        format!(
+            "{}/{}",
+            base.trim_end_matches('/'),
+            path.trim_start_matches('/')
+        )
+    }
+
+    async fn login_attempt(
+        &self,
+        creds: &Credentials,
+        timeout: Duration,
+    ) -> Result<Option<Vec<Loot>>, Error> {
+        let url = Self::get_url(&creds.target, &self.opts.wordpress_login_path);
+        let redirect_to = Self::get_url(&creds.target, "/wp-admin/");
+
+        let res = self
+            .client
+            .post(&url)
+            .header(COOKIE, TEST_COOKIE)
+            .form(&[
+                ("log", creds.username.as_str()),
+                ("pwd", creds.password.as_str()),
+                ("wp-submit", "Log In"),
+                ("redirect_to", redirect_to.as_str()),
+                ("testcookie", "1"),
+            ])
+            .timeout(timeout)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        utils::http::check_rate_limit(&res)?;
+
+        let status = res.status().as_u16();
+        let location = res
+            .headers()
+            .get(LOCATION)
+            .map(|v| v.to_str().unwrap_or("").to_owned())
+            .unwrap_or_default();
+        let cookies: Vec<String> = res
+            .headers()
+            .get_all(SET_COOKIE)
+            .iter()
+            .map(|v| v.to_str().unwrap_or("").to_owned())
+            .collect();
+
+        log::debug!("status={} location={}", status, &location);
+
+        Ok(if is_login_success(&url, status, &location, &cookies) {
+            Some(vec![Loot::new(
+                "wordpress",
+                &url,
+                [
+                    ("username".to_owned(), creds.username.to_owned()),
+                    ("password".to_owned(), creds.password.to_owned()),
+                ],
+            )])
+        } else {
+            None
+        })
+    }
+
+    async fn xmlrpc_attempt(
+        &self,
+        creds: &Credentials,
+        timeout: Duration,
+    ) -> Result<Option<Vec<Loot>>, Error> {
+        let url = Self::get_url(&creds.target, &self.opts.wordpress_xmlrpc_path);
+
+        let res = self
+            .client
+            .post(&url)
+            .header(CONTENT_TYPE, "text/xml")
+            .body(xmlrpc_payload(creds))
+            .timeout(timeout)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        utils::http::check_rate_limit(&res)?;
+
+        let status = res.status().as_u16();
+        let body = res.text().await.unwrap_or_default();
+
+        log::debug!("status={}", status);
+
+        Ok(if is_xmlrpc_success(status, &body) {
+            Some(vec![Loot::new(
+                "wordpress",
+                &url,
+                [
+                    ("username".to_owned(), creds.username.to_owned()),
+                    ("password".to_owned(), creds.password.to_owned()),
+                    ("mode".to_owned(), "xmlrpc".to_owned()),
+                ],
+            )])
+        } else {
+            None
+        })
+    }
+}
+
+#[async_trait]
+impl Plugin for WordPress {
+    fn description(&self) -> &'static str {
+        "WordPress wp-login.php and XML-RPC password authentication."
+    }
+
+    fn loot_schema(&self) -> &[&str] {
+        &["username", "password", "mode"]
+    }
+
+    fn default_port(&self) -> Option<u16> {
+        Some(DEFAULT_PORT)
+    }
+
+    fn setup(&mut self, opts: &Options) -> Result<(), Error> {
+        self.opts = opts.wordpress.clone();
+        self.client = Client::builder()
+            .no_proxy() // used to set auto_sys_proxy to false, see https://github.com/evilsocket/legba/issues/8
+            .danger_accept_invalid_certs(true)
+            // we need to see the redirect to wp-admin and the cookies set along with it
+            .redirect(redirect::Policy::none())
+            .build()
+            .map_err(|e| e.to_string())?;
+
+        Ok(())
+    }
+
+    async fn attempt(
+        &self,
+        creds: &Credentials,
+        timeout: Duration,
+    ) -> Result<Option<Vec<Loot>>, Error> {
+        if self.opts.wordpress_xmlrpc {
+            self.xmlrpc_attempt(creds, timeout).await
+        } else {
+            self.login_attempt(creds, timeout).await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_login_success, is_xmlrpc_success, xmlrpc_payload, WordPress};
+    use crate::creds::Credentials;
+
+    #[test]
+    fn can_build_urls() {
+        assert_eq!(
+            WordPress::get_url("localhost:8080", "/wp-login.php"),
+            "http://localhost:8080/wp-login.php"
+        );
+        assert_eq!(
+            WordPress::get_url("https://example.com/blog/", "/xmlrpc.php"),
+            "https://example.com/blog/xmlrpc.php"
+        );
+    }
+
+    #[test]
+    fn xmlrpc_payload_is_escaped() {
+        let creds = Credentials {
+            target: String::new(),
+            username: "admin".to_owned(),
+            password: "<p&ss>".to_owned(),
+        };
+        let payload = xmlrpc_payload(&creds);
+        assert!(payload.contains("<methodName>wp.getUsersBlogs</methodName>"));
+        assert!(payload.contains("<string>admin</string>"));
+        assert!(payload.contains("<string>&lt;p&amp;ss&gt;</string>"));
+    }
+
+    #[test]
+    fn detects_login_success() {
+        let url = "http://localhost/blog/wp-login.php";
+        let cookies = vec!["wordpress_logged_in_abcdef=admin%7C123; path=/".to_owned()];
+        assert!(is_login_success(url, 200, "", &cookies));
+        assert!(is_login_success(
+            url,
+            302,
+            "http://localhost/blog/wp-admin/",
+            &[]
+        ));
+        assert!(is_login_success(url, 302, "/blog/wp-admin/", &[]));
+        // the login form with an error
+        assert!(!is_login_success(url, 200, "", &[]));
+        // back to the login page, with the admin panel as the redirect_to parameter
+        assert!(!is_login_success(
+            url,
+            302,
+            "http://localhost/blog/wp-login.php?redirect_to=%2Fblog%2Fwp-admin%2F",
+            &[]
+        ));
+        // not the logged in cookie
+        let cookies = vec!["wordpress_test_cookie=WP%20Cookie%20check".to_owned()];
+        assert!(!is_login_success(url, 200, "", &cookies));
+    }
+
+    #[test]
+    fn detects_xmlrpc_success() {
+        assert!(is_xmlrpc_success(
+            200,
+            "<member><name>isAdmin</name><value><boolean>1</boolean></value></member>"
+        ));
+        assert!(!is_xmlrpc_success(
+            200,
+            "<member><name>faultCode</name><value><int>403</int></value></member>"
+        ));
+    }
+}
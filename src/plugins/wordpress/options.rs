@@ -0,0 +1,16 @@
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+
+#[derive(Parser, Debug, Serialize, Deserialize, Clone, Default)]
+#[group(skip)]
+pub(crate) struct Options {
+    #[clap(long, default_value = "/wp-login.php")]
+    /// WordPress login page path.
+    pub wordpress_login_path: String,
+    #[clap(long, default_value_t = false)]
+    /// Authenticate via XML-RPC wp.getUsersBlogs instead of the login page.
+    pub wordpress_xmlrpc: bool,
+    #[clap(long, default_value = "/xmlrpc.php")]
+    /// WordPress XML-RPC endpoint path.
+    pub wordpress_xmlrpc_path: String,
+}
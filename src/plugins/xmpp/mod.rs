@@ -0,0 +1,529 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use base64::prelude::{Engine, BASE64_STANDARD};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::session::{Error, Loot};
+use crate::utils::net::{self, StreamLike};
+use crate::Options;
+use crate::Plugin;
+
+use crate::creds::Credentials;
+use crate::utils;
+
+pub(crate) mod options;
+mod scram;
+
+super::manager::register_plugin! {
+    "xmpp" => XMPP::new()
+}
+
+const DEFAULT_PORT: u16 = 5222;
+
+const NS_TLS: &str = "urn:ietf:params:xml:ns:xmpp-tls";
+const NS_SASL: &str = "urn:ietf:params:xml:ns:xmpp-sasl";
+
+// maximum amount of data we're going to buffer while waiting for an element
+const MAX_BUFFER_SIZE: usize = 64 * 1024;
+
+// mechanisms we support, in order of preference
+const MECHANISMS: &[&str] = &["SCRAM-SHA-1", "PLAIN"];
+
+// find the first complete element with the given name in the buffer, returns its range
+fn find_element(buffer: &str, name: &str) -> Option<(usize, usize)> {
+    let open = format!("<{}", name);
+    let mut offset = 0;
+
+    while let Some(pos) = buffer[offset..].find(&open) {
+        let start = offset + pos;
+        let after = start + open.len();
+        offset = after;
+
+        // make sure we didn't match a longer name
+        if !matches!(
+            buffer[after..].chars().next(),
+            Some(' ' | '>' | '/' | '\t' | '\r' | '\n')
+        ) {
+            continue;
+        }
+
+        let tag_end = after + buffer[after..].find('>')?;
+        if buffer[..tag_end].ends_with('/') {
+            return Some((start, tag_end + 1));
+        }
+
+        let close = format!("</{}>", name);
+        let end = tag_end + buffer[tag_end..].find(&close)? + close.len();
+        return Some((start, end));
+    }
+
+    None
+}
+
+// text content of an element, empty for self closing ones
+fn element_text(element: &str) -> &str {
+    match (element.find('>'), element.rfind("</")) {
+        (Some(start), Some(end)) if start < end => element[start + 1..end].trim(),
+        _ => "",
+    }
+}
+
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+// the buffered data up to the last complete character, a character can be split across reads
+fn buffered_text(buffer: &[u8]) -> Result<&str, Error> {
+    match std::str::from_utf8(buffer) {
+        Ok(text) => Ok(text),
+        Err(e) if e.error_len().is_none() => {
+            Ok(std::str::from_utf8(&buffer[..e.valid_up_to()]).unwrap_or_default())
+        }
+        // XMPP streams are always UTF-8
+        Err(_) => Err(Error::protocol_mismatch("xmpp", buffer)),
+    }
+}
+
+fn decode(text: &str) -> Result<String, Error> {
+    let raw = BASE64_STANDARD
+        .decode(text)
+        .map_err(|e| Error::Protocol(format!("invalid base64 payload: {}", e)))?;
+    Ok(String::from_utf8_lossy(&raw).into_owned())
+}
+
+// SASL mechanisms advertised in the stream features
+fn parse_mechanisms(features: &str) -> Vec<String> {
+    let mut mechanisms = vec![];
+    let mut rest = features;
+    while let Some((start, end)) = find_element(rest, "mechanism") {
+        mechanisms.push(element_text(&rest[start..end]).to_owned());
+        rest = &rest[end..];
+    }
+    mechanisms
+}
+
+// a very small incremental reader of the XML stream, good enough for the few elements
+// exchanged before authentication
+struct Stream {
+    inner: Box<dyn StreamLike>,
+    buffer: Vec<u8>,
+}
+
+impl Stream {
+    fn new(inner: Box<dyn StreamLike>) -> Self {
+        Self {
+            inner,
+            buffer: vec![],
+        }
+    }
+
+    async fn send(&mut self, data: &str) -> Result<(), Error> {
+        self.inner.write_all(data.as_bytes()).await?;
+        self.inner.flush().await?;
+        Ok(())
+    }
+
+    async fn open(&mut self, domain: &str) -> Result<(), Error> {
+        self.buffer.clear();
+        self.send(&format!(
+            "<?xml version='1.0'?><stream:stream to='{}' version='1.0' xmlns='jabber:client' \
+            xmlns:stream='http://etherx.jabber.org/streams'>",
+            xml_escape(domain)
+        ))
+        .await
+    }
+
+    // wait for the first of the given elements, returns its name and content
+    async fn read(&mut self, names: &[&'static str]) -> Result<(&'static str, String), Error> {
+        let mut chunk = [0u8; 1024];
+        loop {
+            let text = buffered_text(&self.buffer)?;
+            if let Some((start, end)) = find_element(text, "stream:error") {
+                return Err(Error::Protocol(text[start..end].to_owned()));
+            }
+
+            let found = names
+                .iter()
+                .filter_map(|name| find_element(text, name).map(|range| (*name, range)))
+                .min_by_key(|(_, (start, _))| *start);
+            if let Some((name, (start, end))) = found {
+                let element = text[start..end].to_owned();
+                self.buffer.drain(..end);
+                return Ok((name, element));
+            }
+
+            if self.buffer.len() > MAX_BUFFER_SIZE {
+                return Err(Error::Protocol("XML stream buffer exceeded".to_owned()));
+            }
+
+            let size = self.inner.read(&mut chunk).await?;
+            if size == 0 {
+                return Err(Error::protocol_mismatch("xmpp", &self.buffer));
+            }
+            self.buffer.extend_from_slice(&chunk[..size]);
+        }
+    }
+
+    async fn features(&mut self) -> Result<String, Error> {
+        Ok(self.read(&["stream:features"]).await?.1)
+    }
+
+    async fn sasl(&mut self) -> Result<(&'static str, String), Error> {
+        self.read(&["challenge", "success", "failure"]).await
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct XMPP {
+    domain: Option<String>,
+    starttls: bool,
+}
+
+impl XMPP {
+    pub fn new() -> Self {
+        XMPP {
+            domain: None,
+            starttls: true,
+        }
+    }
+
+    fn domain(&self, target: &str) -> Result<String, Error> {
+        match &self.domain {
+            Some(domain) => Ok(domain.to_owned()),
+            None => Ok(utils::parse_target(target, DEFAULT_PORT)?.0),
+        }
+    }
+
+    // open the stream, upgrading it to TLS if the server offers it, returns the stream features
+    async fn connect(
+        &self,
+        stream: Box<dyn StreamLike>,
+        address: &str,
+        domain: &str,
+        timeout: Duration,
+    ) -> Result<(Stream, String, bool), Error> {
+        let mut stream = Stream::new(stream);
+        stream.open(domain).await?;
+        let features = stream.features().await?;
+
+        if !self.starttls || find_element(&features, "starttls").is_none() {
+            return Ok((stream, features, false));
+        }
+
+        stream
+            .send(&format!("<starttls xmlns='{}'/>", NS_TLS))
+            .await?;
+        let (name, element) = stream.read(&["proceed", "failure"]).await?;
+        if name != "proceed" {
+            return Err(Error::Protocol(format!("STARTTLS failed: {}", element)));
+        }
+
+        let mut stream =
+            Stream::new(net::upgrade_tcp_stream_to_ssl(stream.inner, address, timeout).await?);
+        stream.open(domain).await?;
+        let features = stream.features().await?;
+
+        Ok((stream, features, true))
+    }
+
+    async fn plain(stream: &mut Stream, creds: &Credentials) -> Result<bool, Error> {
+        let payload = format!("\0{}\0{}", &creds.username, &creds.password);
+        stream
+            .send(&format!(
+                "<auth xmlns='{}' mechanism='PLAIN'>{}</auth>",
+                NS_SASL,
+                BASE64_STANDARD.encode(payload)
+            ))
+            .await?;
+
+        match stream.sasl().await? {
+            ("success", _) => Ok(true),
+            ("failure", element) => Self::failure(element),
+            (_, element) => Err(Error::Protocol(format!(
+                "unexpected challenge: {}",
+                element
+            ))),
+        }
+    }
+
+    async fn scram(stream: &mut Stream, creds: &Credentials) -> Result<bool, Error> {
+        let mut scram = scram::Scram::new(&creds.username);
+        stream
+            .send(&format!(
+                "<auth xmlns='{}' mechanism='SCRAM-SHA-1'>{}</auth>",
+                NS_SASL,
+                BASE64_STANDARD.encode(scram.client_first())
+            ))
+            .await?;
+
+        let server_first = match stream.sasl().await? {
+            ("challenge", element) => decode(element_text(&element))?,
+            ("failure", element) => return Self::failure(element),
+            (_, element) => return Err(Error::Protocol(format!("unexpected reply: {}", element))),
+        };
+
+        let client_final = scram.client_final(&server_first, &creds.password).await?;
+        stream
+            .send(&format!(
+                "<response xmlns='{}'>{}</response>",
+                NS_SASL,
+                BASE64_STANDARD.encode(client_final)
+            ))
+            .await?;
+
+        // the server signature comes either with the success or as an additional challenge
+        let server_final = match stream.sasl().await? {
+            ("success", element) => decode(element_text(&element))?,
+            ("challenge", element) => {
+                let server_final = decode(element_text(&element))?;
+                stream
+                    .send(&format!("<response xmlns='{}'/>", NS_SASL))
+                    .await?;
+                match stream.sasl().await? {
+                    ("success", _) => server_final,
+                    ("failure", element) => return Self::failure(element),
+                    (_, element) => {
+                        return Err(Error::Protocol(format!("unexpected reply: {}", element)))
+                    }
+                }
+            }
+            (_, element) => return Self::failure(element),
+        };
+
+        if !scram.verify(&server_final) {
+            return Err(Error::Protocol(
+                "the server signature doesn't match, the server might not be legit".to_owned(),
+            ));
+        }
+
+        Ok(true)
+    }
+
+    // not-authorized is a bad credential, anything else is reported as an error
+    fn failure(element: String) -> Result<bool, Error> {
+        if find_element(&element, "not-authorized").is_some() {
+            Ok(false)
+        } else if find_element(&element, "account-disabled").is_some() {
+            Err(Error::AccountLocked(element))
+        } else {
+            Err(Error::Auth(element))
+        }
+    }
+
+    async fn session(
+        &self,
+        stream: Box<dyn StreamLike>,
+        address: &str,
+        domain: &str,
+        creds: &Credentials,
+        timeout: Duration,
+    ) -> Result<Option<(&'static str, bool)>, Error> {
+        let (mut stream, features, tls) = self.connect(stream, address, domain, timeout).await?;
+
+        let offered = parse_mechanisms(&features);
+        let mechanism = MECHANISMS
+            .iter()
+            .find(|m| offered.iter().any(|o| o.eq_ignore_ascii_case(m)))
+            .ok_or_else(|| Error::Auth(format!("no supported SASL mechanism in {:?}", &offered)))?;
+
+        let ok = if *mechanism == "PLAIN" {
+            Self::plain(&mut stream, creds).await?
+        } else {
+            Self::scram(&mut stream, creds).await?
+        };
+
+        if ok {
+            let _ = stream.send("</stream:stream>").await;
+            Ok(Some((mechanism, tls)))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+#[async_trait]
+impl Plugin for XMPP {
+    fn description(&self) -> &'static str {
+        "XMPP SASL authentication."
+    }
+
+    fn loot_schema(&self) -> &[&str] {
+        &["username", "password", "domain", "mechanism", "tls"]
+    }
+
+    fn default_port(&self) -> Option<u16> {
+        Some(DEFAULT_PORT)
+    }
+
+    fn srv_service(&self) -> Option<&'static str> {
+        Some("_xmpp-client._tcp")
+    }
+
+    fn setup(&mut self, opts: &Options) -> Result<(), Error> {
+        self.domain = opts.xmpp.xmpp_domain.clone();
+        self.starttls = !opts.no_starttls;
+        Ok(())
+    }
+
+    async fn check(&self, target: &str, timeout: Duration) -> Result<(), Error> {
+        let address = utils::parse_target_address(target, DEFAULT_PORT)?;
+        let domain = self.domain(target)?;
+        let stream = net::async_tcp_stream(&address, timeout, false).await?;
+
+        tokio::time::timeout(timeout, async move {
+            let mut stream = Stream::new(stream);
+            stream.open(&domain).await?;
+            stream.features().await.map(|_| ())
+        })
+        .await?
+    }
+
+    async fn attempt(
+        &self,
+        creds: &Credentials,
+        timeout: Duration,
+    ) -> Result<Option<Vec<Loot>>, Error> {
+        let address = utils::parse_target_address(&creds.target, DEFAULT_PORT)?;
+        let domain = self.domain(&creds.target)?;
+        let stream = net::async_tcp_stream(&address, timeout, false).await?;
+
+        let result = tokio::time::timeout(
+            timeout,
+            self.session(stream, &address, &domain, creds, timeout),
+        )
+        .await??;
+
+        Ok(result.map(|(mechanism, tls)| {
+            vec![Loot::new(
+                "xmpp",
+                &address,
+                [
+                    ("username".to_owned(), creds.username.to_owned()),
+                    ("password".to_owned(), creds.password.to_owned()),
+                    ("domain".to_owned(), domain),
+                    ("mechanism".to_owned(), mechanism.to_owned()),
+                    ("tls".to_owned(), tls.to_string()),
+                ],
+            )]
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use base64::prelude::{Engine, BASE64_STANDARD};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use super::{buffered_text, element_text, find_element, parse_mechanisms, xml_escape, XMPP};
+    use crate::creds::Credentials;
+    use crate::Plugin;
+
+    const FEATURES: &str = "<stream:features><mechanisms xmlns='urn:ietf:params:xml:ns:xmpp-sasl'>\
+        <mechanism>PLAIN</mechanism></mechanisms></stream:features>";
+
+    // fake XMPP server offering PLAIN authentication and accepting the given password
+    async fn server(password: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buffer = String::new();
+            let mut chunk = [0u8; 1024];
+            let mut opened = false;
+
+            while find_element(&buffer, "auth").is_none() {
+                let size = stream.read(&mut chunk).await.unwrap();
+                assert!(size > 0);
+                buffer.push_str(&String::from_utf8_lossy(&chunk[..size]));
+
+                if !opened && buffer.contains("<stream:stream") {
+                    let reply = format!(
+                        "<?xml version='1.0'?><stream:stream from='localhost' id='1' \
+                        version='1.0' xmlns='jabber:client' \
+                        xmlns:stream='http://etherx.jabber.org/streams'>{}",
+                        FEATURES
+                    );
+                    stream.write_all(reply.as_bytes()).await.unwrap();
+                    opened = true;
+                }
+            }
+
+            let (start, end) = find_element(&buffer, "auth").unwrap();
+            let payload = BASE64_STANDARD
+                .decode(element_text(&buffer[start..end]))
+                .unwrap();
+            let reply = if payload == format!("\0admin\0{}", password).as_bytes() {
+                "<success xmlns='urn:ietf:params:xml:ns:xmpp-sasl'/>"
+            } else {
+                "<failure xmlns='urn:ietf:params:xml:ns:xmpp-sasl'><not-authorized/></failure>"
+            };
+            stream.write_all(reply.as_bytes()).await.unwrap();
+        });
+
+        address
+    }
+
+    #[test]
+    fn can_find_elements() {
+        let data = "<stream:stream><starttls xmlns='x'><required/></starttls><success/>";
+        let (start, end) = find_element(data, "starttls").unwrap();
+        assert_eq!(
+            &data[start..end],
+            "<starttls xmlns='x'><required/></starttls>"
+        );
+        assert!(find_element(data, "success").is_some());
+        assert!(find_element(data, "stream").is_none());
+        assert!(find_element("<failure xmlns='x'>", "failure").is_none());
+        assert_eq!(parse_mechanisms(FEATURES), vec!["PLAIN".to_owned()]);
+    }
+
+    #[test]
+    fn decodes_characters_split_across_reads() {
+        let data = "<mechanism>é</mechanism>".as_bytes();
+        assert_eq!(buffered_text(&data[..12]).unwrap(), "<mechanism>");
+        assert_eq!(buffered_text(data).unwrap(), "<mechanism>é</mechanism>");
+        assert!(buffered_text(b"<mechanism>\xff</mechanism>").is_err());
+    }
+
+    #[test]
+    fn escapes_the_domain() {
+        assert_eq!(xml_escape("a'b<c>&"), "a&apos;b&lt;c&gt;&amp;");
+    }
+
+    #[tokio::test]
+    async fn can_login() {
+        let creds = Credentials {
+            target: server("secret").await,
+            username: "admin".to_owned(),
+            password: "secret".to_owned(),
+        };
+
+        let loot = XMPP::new()
+            .attempt(&creds, Duration::from_secs(5))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(loot[0].format_template("{mechanism}"), "PLAIN");
+        assert_eq!(loot[0].format_template("{domain}"), "127.0.0.1");
+
+        let creds = Credentials {
+            target: server("secret").await,
+            username: "admin".to_owned(),
+            password: "wrong".to_owned(),
+        };
+        assert_eq!(
+            XMPP::new().attempt(&creds, Duration::from_secs(5)).await,
+            Ok(None)
+        );
+    }
+}
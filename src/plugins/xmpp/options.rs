@@ -0,0 +1,10 @@
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+
+#[derive(Parser, Debug, Serialize, Deserialize, Clone, Default)]
+#[group(skip)]
+pub(crate) struct Options {
+    #[clap(long)]
+    /// XMPP domain to authenticate against, defaults to the target host.
+    pub xmpp_domain: Option<String>,
+}
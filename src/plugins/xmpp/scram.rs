@@ -0,0 +1,182 @@
+use base64::prelude::{Engine, BASE64_STANDARD};
+use hmac::{Hmac, Mac};
+use rand::{distributions::Alphanumeric, Rng};
+use sha1::{Digest, Sha1};
+
+use crate::session::Error;
+
+// gs2 header without channel binding nor authorization identity
+const GS2_HEADER: &str = "n,,";
+// the iteration count comes from the server, past this the key derivation would keep a thread
+// busy for too long
+const MAX_ITERATIONS: u32 = 1_000_000;
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).unwrap();
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+// '=' and ',' are the only characters that need escaping in a SCRAM username
+fn escape(username: &str) -> String {
+    username.replace('=', "=3D").replace(',', "=2C")
+}
+
+fn attribute(message: &str, name: char) -> Option<&str> {
+    message.split(',').find_map(|attr| {
+        attr.strip_prefix(name)
+            .and_then(|attr| attr.strip_prefix('='))
+    })
+}
+
+// client side of a SCRAM-SHA-1 exchange (RFC 5802)
+pub(crate) struct Scram {
+    client_first_bare: String,
+    nonce: String,
+    server_signature: Vec<u8>,
+}
+
+impl Scram {
+    pub fn new(username: &str) -> Self {
+        let nonce: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(24)
+            .map(char::from)
+            .collect();
+        Self::with_nonce(username, &nonce)
+    }
+
+    fn with_nonce(username: &str, nonce: &str) -> Self {
+        Self {
+            client_first_bare: format!("n={},r={}", escape(username), nonce),
+            nonce: nonce.to_owned(),
+            server_signature: vec![],
+        }
+    }
+
+    pub fn client_first(&self) -> String {
+        format!("{}{}", GS2_HEADER, &self.client_first_bare)
+    }
+
+    // compute the client proof for the server challenge
+    pub async fn client_final(
+        &mut self,
+        server_first: &str,
+        password: &str,
+    ) -> Result<String, Error> {
+        let invalid = || Error::Protocol(format!("invalid SCRAM challenge: {}", server_first));
+
+        let nonce = attribute(server_first, 'r').ok_or_else(invalid)?;
+        if !nonce.starts_with(&self.nonce) {
+            return Err(invalid());
+        }
+        let salt = attribute(server_first, 's')
+            .and_then(|salt| BASE64_STANDARD.decode(salt).ok())
+            .ok_or_else(invalid)?;
+        let iterations: u32 = attribute(server_first, 'i')
+            .and_then(|i| i.parse().ok())
+            .filter(|i| *i > 0)
+            .ok_or_else(invalid)?;
+        if iterations > MAX_ITERATIONS {
+            return Err(Error::Protocol(format!(
+                "SCRAM iteration count {} is above the limit of {}",
+                iterations, MAX_ITERATIONS
+            )));
+        }
+
+        // CPU bound, off the async workers
+        let password = password.to_owned();
+        let salted = tokio::task::spawn_blocking(move || {
+            let mut salted = [0u8; 20];
+            pbkdf2::pbkdf2_hmac::<Sha1>(password.as_bytes(), &salt, iterations, &mut salted);
+            salted
+        })
+        .await
+        .map_err(|e| e.to_string())?;
+
+        let without_proof = format!("c={},r={}", BASE64_STANDARD.encode(GS2_HEADER), nonce);
+        let auth_message = format!(
+            "{},{},{}",
+            &self.client_first_bare, server_first, &without_proof
+        );
+
+        let client_key = hmac(&salted, b"Client Key");
+        let stored_key = Sha1::digest(&client_key);
+        let client_signature = hmac(&stored_key, auth_message.as_bytes());
+        let proof: Vec<u8> = client_key
+            .iter()
+            .zip(client_signature)
+            .map(|(k, s)| k ^ s)
+            .collect();
+
+        let server_key = hmac(&salted, b"Server Key");
+        self.server_signature = hmac(&server_key, auth_message.as_bytes());
+
+        Ok(format!(
+            "{},p={}",
+            without_proof,
+            BASE64_STANDARD.encode(proof)
+        ))
+    }
+
+    // check that the server knows the password too
+    pub fn verify(&self, server_final: &str) -> bool {
+        attribute(server_final, 'v')
+            .and_then(|v| BASE64_STANDARD.decode(v).ok())
+            .is_some_and(|v| !self.server_signature.is_empty() && v == self.server_signature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{escape, Scram};
+    use crate::session::Error;
+
+    #[test]
+    fn can_escape_username() {
+        assert_eq!(escape("a=b,c"), "a=3Db=2Cc");
+    }
+
+    // example exchange from RFC 5802
+    #[tokio::test]
+    async fn can_compute_rfc_example() {
+        let mut scram = Scram::with_nonce("user", "fyko+d2lbbFgONRv9qkxdawL");
+        assert_eq!(scram.client_first(), "n,,n=user,r=fyko+d2lbbFgONRv9qkxdawL");
+
+        let client_final = scram
+            .client_final(
+                "r=fyko+d2lbbFgONRv9qkxdawL3rfcNHYJY1ZVvWVs7j,s=QSXCR+Q6sek8bf92,i=4096",
+                "pencil",
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            client_final,
+            "c=biws,r=fyko+d2lbbFgONRv9qkxdawL3rfcNHYJY1ZVvWVs7j,p=v0X8v3Bz2T0CJGbJQyF0X+HI4Ts="
+        );
+        assert!(scram.verify("v=rmF9pqV8S7suAoZWja4dJRkFsKQ="));
+        assert!(!scram.verify("v=AAAAAAAAAAAAAAAAAAAAAAAAAAA="));
+    }
+
+    #[tokio::test]
+    async fn rejects_foreign_nonce() {
+        let mut scram = Scram::with_nonce("user", "abc");
+        assert!(scram
+            .client_final("r=xyz123,s=QSXCR+Q6sek8bf92,i=4096", "pencil")
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_excessive_iterations() {
+        let mut scram = Scram::with_nonce("user", "abc");
+        let res = scram
+            .client_final("r=abc123,s=QSXCR+Q6sek8bf92,i=4294967295", "pencil")
+            .await;
+        assert!(matches!(res, Err(Error::Protocol(_))), "{:?}", res);
+        assert!(scram
+            .client_final("r=abc123,s=QSXCR+Q6sek8bf92,i=0", "pencil")
+            .await
+            .is_err());
+    }
+}
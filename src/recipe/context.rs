@@ -27,7 +27,7 @@ impl Context {
         }
 
         if !expr.is_empty() && context.data.is_empty() {
-            Err(CONTEXT_EXPRESSION_ERROR.to_owned())
+            Err(CONTEXT_EXPRESSION_ERROR.into())
         } else {
             Ok(context)
         }
@@ -58,13 +58,13 @@ mod tests {
     #[test]
     fn wont_parse_without_value() {
         let ctx = Context::parse("foo=");
-        assert_eq!(ctx.err(), Some(CONTEXT_EXPRESSION_ERROR.to_owned()));
+        assert_eq!(ctx.err(), Some(CONTEXT_EXPRESSION_ERROR.into()));
     }
 
     #[test]
     fn wont_parse_without_key() {
         let ctx = Context::parse("=bar");
-        assert_eq!(ctx.err(), Some(CONTEXT_EXPRESSION_ERROR.to_owned()));
+        assert_eq!(ctx.err(), Some(CONTEXT_EXPRESSION_ERROR.into()));
     }
 
     #[test]
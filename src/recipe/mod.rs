@@ -81,7 +81,7 @@ impl Recipe {
                 // get variable from default if provided
                 def.to_owned()
             } else {
-                return Err(format!("no '{}' variable specified for recipe", var_name));
+                return Err(format!("no '{}' variable specified for recipe", var_name).into());
             };
 
             // cache value in context
@@ -0,0 +1,259 @@
+use std::fmt;
+use std::time::Duration;
+
+use serde::{Serialize, Serializer};
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Error {
+    /// Transport level error (connection refused, reset, etc).
+    Connection(String),
+    /// The operation timed out.
+    Timeout,
+    /// Unexpected or malformed data from the target.
+    Protocol(String),
+    /// The target is running a different service than the one expected by the plugin.
+    ProtocolMismatch(String),
+    /// Authentication can't be performed against the target.
+    Auth(String),
+    /// The account is locked out, any other password for the same user is going to fail.
+    AccountLocked(String),
+    /// The target asked us to slow down for the given amount of time.
+    RateLimited(Duration),
+    /// The proxy refused or failed to forward the connection, the target was never reached.
+    Proxy(String),
+    /// The proxy requires credentials that are missing or wrong, nothing can go through it.
+    ProxyAuth,
+    /// Any other error.
+    Other(String),
+}
+
+// how long to wait when the target is rate limiting us without saying for how long
+const DEFAULT_RATE_LIMIT_WAIT: Duration = Duration::from_secs(5);
+
+// first bytes sent by common services, used to tell the user what is actually listening
+const KNOWN_BANNERS: &[(&[u8], &str)] = &[
+    (b"HTTP/", "HTTP"),
+    (b"SSH-", "SSH"),
+    (b"RFB ", "VNC"),
+    (b"AMQP", "AMQP"),
+    (b"* OK", "IMAP"),
+    (b"+OK", "POP3"),
+    (b"220", "FTP or SMTP"),
+    (b"\x16\x03", "TLS"),
+];
+
+impl Error {
+    /// Create a rate limit error from the value of a Retry-After header, either a number of
+    /// seconds or an HTTP date.
+    pub fn rate_limited(retry_after: Option<&str>) -> Self {
+        let wait = retry_after.map(|v| v.trim()).and_then(|value| {
+            if let Ok(secs) = value.parse::<u64>() {
+                Some(Duration::from_secs(secs))
+            } else if let Ok(date) = chrono::DateTime::parse_from_rfc2822(value) {
+                // a date in the past means no wait at all
+                Some(
+                    (date.with_timezone(&chrono::Utc) - chrono::Utc::now())
+                        .to_std()
+                        .unwrap_or(Duration::ZERO),
+                )
+            } else {
+                None
+            }
+        });
+
+        Error::RateLimited(wait.unwrap_or(DEFAULT_RATE_LIMIT_WAIT))
+    }
+
+    /// Create an error for a target that replied with data not belonging to the expected protocol.
+    pub fn protocol_mismatch(expected: &str, data: &[u8]) -> Self {
+        let found = KNOWN_BANNERS
+            .iter()
+            .find(|(banner, _)| data.starts_with(banner))
+            .map(|(_, service)| format!("looks like {}", service))
+            .unwrap_or_else(|| {
+                let data = &data[..data.len().min(32)];
+                format!("unexpected data {:?}", String::from_utf8_lossy(data))
+            });
+
+        Error::ProtocolMismatch(format!("not a {} service, {}", expected, found))
+    }
+
+    /// Name of the kind of error, the same for every message.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::Connection(_) => "connection",
+            Error::Timeout => "timeout",
+            Error::Protocol(_) => "protocol",
+            Error::ProtocolMismatch(_) => "protocol mismatch",
+            Error::Auth(_) => "auth",
+            Error::AccountLocked(_) => "account locked",
+            Error::RateLimited(_) => "rate limited",
+            Error::Proxy(_) => "proxy",
+            Error::ProxyAuth => "proxy auth",
+            Error::Other(_) => "other",
+        }
+    }
+
+    /// Returns true if the target could not be reached or stopped responding, only these errors
+    /// count toward flagging the target as unreachable.
+    pub fn is_transport(&self) -> bool {
+        matches!(self, Error::Connection(_) | Error::Timeout)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Timeout => write!(f, "deadline has elapsed"),
+            Error::RateLimited(wait) => write!(f, "rate limited for {:?}", wait),
+            Error::Connection(s)
+            | Error::Protocol(s)
+            | Error::ProtocolMismatch(s)
+            | Error::Auth(s)
+            | Error::Other(s) => write!(f, "{}", s),
+            Error::AccountLocked(s) => write!(f, "account locked out: {}", s),
+            Error::Proxy(s) => write!(f, "proxy error: {}", s),
+            Error::ProxyAuth => write!(f, "proxy error: proxy authentication required"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl Serialize for Error {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl From<String> for Error {
+    fn from(s: String) -> Self {
+        Error::Other(s)
+    }
+}
+
+impl From<&str> for Error {
+    fn from(s: &str) -> Self {
+        Error::Other(s.to_owned())
+    }
+}
+
+impl From<tokio::time::error::Elapsed> for Error {
+    fn from(_: tokio::time::error::Elapsed) -> Self {
+        Error::Timeout
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        match e.kind() {
+            std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock => Error::Timeout,
+            std::io::ErrorKind::ConnectionRefused
+            | std::io::ErrorKind::ConnectionReset
+            | std::io::ErrorKind::ConnectionAborted
+            | std::io::ErrorKind::NotConnected
+            | std::io::ErrorKind::AddrNotAvailable
+            | std::io::ErrorKind::BrokenPipe
+            | std::io::ErrorKind::UnexpectedEof => Error::Connection(e.to_string()),
+            std::io::ErrorKind::InvalidData => Error::Protocol(e.to_string()),
+            _ => Error::Other(e.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::Error;
+
+    #[test]
+    fn display_matches_message() {
+        assert_eq!(
+            Error::from("no plugin selected").to_string(),
+            "no plugin selected"
+        );
+        assert_eq!(
+            Error::Connection("connection refused".to_owned()).to_string(),
+            "connection refused"
+        );
+        assert_eq!(Error::Timeout.to_string(), "deadline has elapsed");
+        assert_eq!(
+            Error::ProxyAuth.to_string(),
+            "proxy error: proxy authentication required"
+        );
+    }
+
+    #[test]
+    fn io_errors_are_classified() {
+        assert!(matches!(
+            Error::from(std::io::Error::from(std::io::ErrorKind::ConnectionRefused)),
+            Error::Connection(_)
+        ));
+        assert!(matches!(
+            Error::from(std::io::Error::from(std::io::ErrorKind::TimedOut)),
+            Error::Timeout
+        ));
+        assert!(matches!(
+            Error::from(std::io::Error::from(std::io::ErrorKind::InvalidData)),
+            Error::Protocol(_)
+        ));
+        assert!(matches!(
+            Error::from(std::io::Error::from(std::io::ErrorKind::PermissionDenied)),
+            Error::Other(_)
+        ));
+    }
+
+    #[test]
+    fn only_transport_errors_are_transport() {
+        assert!(Error::Timeout.is_transport());
+        assert!(Error::Connection("connection reset".to_owned()).is_transport());
+        assert!(!Error::Other("unexpected status code".to_owned()).is_transport());
+        assert!(!Error::Protocol("malformed reply".to_owned()).is_transport());
+        assert!(!Error::Auth("server requires TLS".to_owned()).is_transport());
+        assert!(!Error::Proxy("bad gateway".to_owned()).is_transport());
+        assert!(!Error::ProxyAuth.is_transport());
+    }
+
+    #[test]
+    fn protocol_mismatch_guesses_service() {
+        let err = Error::protocol_mismatch("redis", b"HTTP/1.1 400 Bad Request\r\n");
+        assert_eq!(err.to_string(), "not a redis service, looks like HTTP");
+        assert!(!err.is_transport());
+
+        assert_eq!(
+            Error::protocol_mismatch("stomp", b"hello").to_string(),
+            "not a stomp service, unexpected data \"hello\""
+        );
+    }
+
+    #[test]
+    fn can_parse_retry_after() {
+        assert_eq!(
+            Error::rate_limited(Some("30")),
+            Error::RateLimited(Duration::from_secs(30))
+        );
+        assert_eq!(
+            Error::rate_limited(None),
+            Error::RateLimited(super::DEFAULT_RATE_LIMIT_WAIT)
+        );
+        assert_eq!(
+            Error::rate_limited(Some("soon")),
+            Error::RateLimited(super::DEFAULT_RATE_LIMIT_WAIT)
+        );
+        assert_eq!(
+            Error::rate_limited(Some("Wed, 21 Oct 2015 07:28:00 GMT")),
+            Error::RateLimited(Duration::ZERO)
+        );
+    }
+
+    #[tokio::test]
+    async fn elapsed_is_timeout() {
+        let res = tokio::time::timeout(
+            std::time::Duration::from_millis(1),
+            tokio::time::sleep(std::time::Duration::from_secs(1)),
+        )
+        .await;
+        assert_eq!(Error::from(res.unwrap_err()), Error::Timeout);
+    }
+}
@@ -1,14 +1,22 @@
+use std::collections::hash_map::DefaultHasher;
 use std::fs::OpenOptions;
+use std::hash::{Hash, Hasher};
 use std::io::prelude::*;
+use std::sync::Mutex;
 use std::{fmt, path::Path};
 
 use ansi_term::Colour;
 use chrono::{DateTime, Local};
 use clap::ValueEnum;
 use indexmap::IndexMap;
+use lazy_regex::{lazy_regex, Lazy};
+use regex::{Captures, Regex};
 use serde::{Deserialize, Serialize};
 
 use crate::session::Error;
+use crate::utils;
+
+static TEMPLATE_KEY_PARSER: Lazy<Regex> = lazy_regex!(r"\{([\w.-]+)\}");
 
 #[derive(ValueEnum, Serialize, Deserialize, Debug, Default, Clone)]
 pub(crate) enum OutputFormat {
@@ -16,6 +24,7 @@ pub(crate) enum OutputFormat {
     Text,
     CSV,
     JSONL,
+    Hydra,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -28,6 +37,7 @@ pub(crate) struct Loot {
 }
 
 impl Loot {
+    // besides the credentials, plugins can record anything they learn about the target
     pub fn new<I: IntoIterator<Item = (String, String)>>(
         plugin: &str,
         target: &str,
@@ -51,6 +61,10 @@ impl Loot {
         &self.target
     }
 
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.data.keys().map(|key| key.as_str())
+    }
+
     pub fn is_partial(&self) -> bool {
         self.partial
     }
@@ -60,12 +74,60 @@ impl Loot {
         self
     }
 
+    pub fn with_data(mut self, key: &str, value: String) -> Self {
+        self.data.insert(key.to_owned(), value);
+        self
+    }
+
+    // stable hash of what identifies this loot, the time it was found at is not included
+    pub fn dedup_key(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+
+        self.plugin.hash(&mut hasher);
+        self.target.hash(&mut hasher);
+        self.partial.hash(&mut hasher);
+
+        match (self.data.get("username"), self.data.get("password")) {
+            // no credentials, use all the data
+            (None, None) => {
+                for (key, value) in &self.data {
+                    key.hash(&mut hasher);
+                    value.hash(&mut hasher);
+                }
+            }
+            (username, password) => {
+                username.hash(&mut hasher);
+                password.hash(&mut hasher);
+            }
+        }
+
+        hasher.finish()
+    }
+
+    // replace every {key} of the template with the loot plugin, target, found_at or data values
+    pub fn format_template(&self, template: &str) -> String {
+        TEMPLATE_KEY_PARSER
+            .replace_all(template, |caps: &Captures| {
+                let key = &caps[1];
+                match key {
+                    "plugin" => self.plugin.to_owned(),
+                    "target" => self.target.to_owned(),
+                    "found_at" => self.found_at_string(),
+                    _ => self.data.get(key).cloned().unwrap_or_else(|| {
+                        log::warn!("loot template key '{}' not found", key);
+                        String::new()
+                    }),
+                }
+            })
+            .into_owned()
+    }
+
     fn found_at_string(&self) -> String {
         self.found_at.format("%Y-%m-%d %H:%M:%S").to_string()
     }
 
     fn to_json(&self) -> Result<String, Error> {
-        serde_json::to_string(self).map_err(|e| e.to_string())
+        serde_json::to_string(self).map_err(|e| e.to_string().into())
     }
 
     fn to_text(&self) -> Result<String, Error> {
@@ -107,43 +169,126 @@ impl Loot {
         wtr.write_record([&self.found_at_string(), &self.plugin, &self.target, &data])
             .map_err(|e| e.to_string())?;
 
-        String::from_utf8(wtr.into_inner().unwrap()).map_err(|e| e.to_string())
+        String::from_utf8(wtr.into_inner().unwrap()).map_err(|e| e.to_string().into())
     }
 
-    pub fn append_to_file(&self, path: &str, format: &OutputFormat) -> Result<(), Error> {
-        let data = match format {
-            OutputFormat::JSONL => self.to_json()?,
-            OutputFormat::Text => self.to_text()?,
-            OutputFormat::CSV => self.to_csv(path)?,
+    fn to_hydra(&self) -> Result<String, Error> {
+        // hydra always reports the port, if the target doesn't have one try to guess from the scheme
+        let default_port = if self.target.starts_with("https://") {
+            443
+        } else if self.target.starts_with("http://") {
+            80
+        } else {
+            0
         };
+        let (host, port) = utils::parse_target(&self.target, default_port)?;
 
-        let mut file = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(path)
-            .map_err(|e| e.to_string())?;
+        let login = ["username", "user", "login"]
+            .iter()
+            .find_map(|k| self.data.get(*k));
+        let password = ["password", "key", "pass"]
+            .iter()
+            .find_map(|k| self.data.get(*k));
+
+        let mut line = format!("[{}][{}] host: {}", port, &self.plugin, host);
+        if let Some(login) = login {
+            line.push_str(&format!("   login: {}", login));
+        }
+        if let Some(password) = password {
+            line.push_str(&format!("   password: {}", password));
+        }
+
+        Ok(line)
+    }
+
+    pub async fn append_to_file(
+        &self,
+        path: &str,
+        format: &OutputFormat,
+        fsync: bool,
+    ) -> Result<(), Error> {
+        let loot = self.clone();
+        let path = path.to_owned();
+        let format = format.clone();
 
-        writeln!(file, "{}", data.trim()).map_err(|e| e.to_string())
+        tokio::task::spawn_blocking(move || {
+            // the CSV header depends on the file existing already, keep the check and the write
+            // atomic
+            let _guard = OUTPUT_LOCK.lock().unwrap();
+
+            let data = match format {
+                OutputFormat::JSONL => loot.to_json()?,
+                OutputFormat::Text => loot.to_text()?,
+                OutputFormat::CSV => loot.to_csv(&path)?,
+                OutputFormat::Hydra => loot.to_hydra()?,
+            };
+
+            write_line(&path, &data, fsync)
+        })
+        .await
+        .map_err(|e| e.to_string())?
     }
 }
 
-impl fmt::Display for Loot {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+// serializes the writes to the output file from every worker, it's only taken by blocking tasks
+// so that the async workers never wait on the disk
+static OUTPUT_LOCK: Mutex<()> = Mutex::new(());
+
+fn write_line(path: &str, data: &str, fsync: bool) -> Result<(), Error> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| e.to_string())?;
+
+    // a single write per line so that nothing can end up in between its parts
+    file.write_all(format!("{}\n", data.trim()).as_bytes())?;
+    if fsync {
+        file.sync_data()?;
+    }
+
+    Ok(())
+}
+
+// append a line to the output file, waiting for any other write in progress
+pub(crate) async fn append_line(path: &str, data: &str, fsync: bool) -> Result<(), Error> {
+    let path = path.to_owned();
+    let data = data.to_owned();
+
+    tokio::task::spawn_blocking(move || {
+        let _guard = OUTPUT_LOCK.lock().unwrap();
+        write_line(&path, &data, fsync)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+impl Loot {
+    // same as the Display implementation, for when the target is shown elsewhere
+    pub fn to_string_without_target(&self) -> String {
+        format!(
+            "[{}] ({}) {}",
+            self.found_at_string(),
+            &self.plugin,
+            self.data_string()
+        )
+    }
+
+    fn data_string(&self) -> String {
         let mut str = String::new();
         for (key, value) in &self.data {
             if !value.is_empty() {
                 str.push_str(&format!("{}={} ", key, Colour::Green.bold().paint(value)));
             }
         }
+        str.trim_end().to_owned()
+    }
+}
 
+impl fmt::Display for Loot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         if self.target.is_empty() {
-            write!(
-                f,
-                "[{}] ({}) {}",
-                self.found_at_string(),
-                &self.plugin,
-                str.trim_end()
-            )
+            write!(f, "{}", self.to_string_without_target())
         } else {
             write!(
                 f,
@@ -151,8 +296,102 @@ impl fmt::Display for Loot {
                 self.found_at_string(),
                 &self.plugin,
                 &self.target,
-                str.trim_end()
+                self.data_string()
             )
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Loot;
+
+    #[test]
+    fn can_format_template() {
+        let loot = Loot::new(
+            "ssh",
+            "192.168.1.10:22",
+            [
+                ("username".to_owned(), "root".to_owned()),
+                ("password".to_owned(), "toor".to_owned()),
+            ],
+        );
+        assert_eq!(
+            loot.format_template("{plugin}:{target}:{username}:{password}"),
+            "ssh:192.168.1.10:22:root:toor"
+        );
+        // unknown keys are empty
+        assert_eq!(loot.format_template("{username}/{domain}"), "root/");
+    }
+
+    #[test]
+    fn serializers_keep_extra_data() {
+        let loot = Loot::new(
+            "redis",
+            "10.0.0.1:6379",
+            [
+                ("username".to_owned(), "default".to_owned()),
+                ("password".to_owned(), "foobar".to_owned()),
+                ("version".to_owned(), "7.2.4".to_owned()),
+                ("role".to_owned(), "master".to_owned()),
+            ],
+        );
+
+        assert!(loot
+            .to_text()
+            .unwrap()
+            .ends_with("username=default\tpassword=foobar\tversion=7.2.4\trole=master"));
+        assert!(loot.to_csv("/nonexistent/loot.csv").unwrap().ends_with(
+            ",redis,10.0.0.1:6379,username=default;password=foobar;version=7.2.4;role=master\n"
+        ));
+
+        let json: serde_json::Value = serde_json::from_str(&loot.to_json().unwrap()).unwrap();
+        assert_eq!(json["data"]["version"], "7.2.4");
+        assert_eq!(json["data"]["role"], "master");
+    }
+
+    #[test]
+    fn can_serialize_to_hydra() {
+        let loot = Loot::new(
+            "ssh",
+            "192.168.1.10:22",
+            [
+                ("username".to_owned(), "root".to_owned()),
+                ("password".to_owned(), "toor".to_owned()),
+            ],
+        );
+        assert_eq!(
+            loot.to_hydra().unwrap(),
+            "[22][ssh] host: 192.168.1.10   login: root   password: toor"
+        );
+    }
+
+    #[test]
+    fn can_serialize_to_hydra_without_login() {
+        let loot = Loot::new(
+            "vnc",
+            "10.0.0.1:5900",
+            [("password".to_owned(), "secret".to_owned())],
+        );
+        assert_eq!(
+            loot.to_hydra().unwrap(),
+            "[5900][vnc] host: 10.0.0.1   password: secret"
+        );
+    }
+
+    #[test]
+    fn can_serialize_to_hydra_with_url_target() {
+        let loot = Loot::new(
+            "http",
+            "https://www.example.com/login",
+            [
+                ("username".to_owned(), "admin".to_owned()),
+                ("password".to_owned(), "admin".to_owned()),
+            ],
+        );
+        assert_eq!(
+            loot.to_hydra().unwrap(),
+            "[443][http] host: www.example.com   login: admin   password: admin"
+        );
+    }
+}
@@ -1,29 +1,35 @@
+use std::collections::BTreeMap;
 use std::fs;
+use std::io::IsTerminal;
 use std::path::Path;
 use std::sync::atomic::{AtomicUsize, Ordering};
 
+use clap::ValueEnum;
+use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 
-use crate::creds::{Combinator, Expression};
+use crate::creds::{validation, Combinator, Expression, Provenance};
+use crate::events::Events;
 use crate::Options;
 
+mod error;
 pub(crate) mod loot;
 mod runtime;
 
 use runtime::*;
 
-use crate::utils::{parse_multiple_targets, parse_target};
+use crate::utils::{net, parse_target, parse_targets};
 pub(crate) use crate::Credentials;
+pub(crate) use error::Error;
 pub(crate) use loot::Loot;
 
 use std::sync::{Arc, Mutex};
 use std::time;
 
-pub(crate) type Error = String;
-
 async fn periodic_saver(session: Arc<Session>) {
     let one_sec = time::Duration::from_millis(1000);
     let mut last_done: usize = 0;
+    let mut last_work: usize = 0;
     let persistent = session.options.session.is_some();
 
     while !session.is_stop() {
@@ -36,9 +42,14 @@ async fn periodic_saver(session: Arc<Session>) {
 
         session.set_speed(speed);
 
+        if let Some((_, work)) = session.get_work() {
+            session.set_work_speed(work.saturating_sub(last_work));
+            last_work = work;
+        }
+
         if persistent {
             if let Err(e) = session.save() {
-                log::error!("could not save session: {:?}", e);
+                log::error!("could not save session: {}", e);
             }
         }
     }
@@ -46,11 +57,31 @@ async fn periodic_saver(session: Arc<Session>) {
     if persistent {
         // update and save to the last state before exiting
         if let Err(e) = session.save() {
-            log::error!("could not save session: {:?}", e);
+            log::error!("could not save session: {}", e);
         }
     }
 }
 
+// what to do when a plugin reports that an account is locked out
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) enum LockoutPolicy {
+    // stop trying passwords for that username
+    #[default]
+    Skip,
+    // stop the whole run before locking out more accounts
+    Abort,
+    // keep going as if it was a failed attempt
+    Continue,
+}
+
+// counters of a single plugin, attempts and errors are per set of credentials
+#[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq)]
+pub(crate) struct PluginStats {
+    pub attempts: usize,
+    pub successes: usize,
+    pub errors: usize,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub(crate) struct Session {
     pub options: Options,
@@ -59,21 +90,44 @@ pub(crate) struct Session {
     pub done: AtomicUsize,
     pub errors: AtomicUsize,
     pub results: Mutex<Vec<Loot>>,
+    #[serde(default)]
+    pub plugins: Mutex<BTreeMap<String, PluginStats>>,
+    // only updated when saving, the runtime holds the live state
+    #[serde(default)]
+    flagged: Mutex<Flagged>,
+    // credentials put aside and not attempted yet, only updated when saving
+    #[serde(default)]
+    deferred: Mutex<Vec<(Credentials, Provenance)>>,
 
     #[serde(skip_serializing, skip_deserializing)]
     runtime: Runtime,
 }
 
 impl Session {
-    fn from_options(options: Options) -> Result<Arc<Self>, Error> {
-        let targets = if let Some(target) = options.target.as_ref() {
-            parse_multiple_targets(target)?
+    pub(crate) fn from_options(mut options: Options) -> Result<Arc<Self>, Error> {
+        // the seed is saved with the session so that restoring it samples the same combinations
+        if options.sample.is_some() && options.seed.is_none() {
+            options.seed = Some(rand::random());
+        }
+
+        let targets = if let Some(path) = options.validate.as_ref() {
+            // targets are part of the credentials
+            validation::load(path)?
+                .into_iter()
+                .map(|creds| creds.target)
+                .unique()
+                .collect()
         } else {
-            return Err("no --target/-T argument provided".to_owned());
+            parse_targets(
+                options.target.as_ref(),
+                options.targets.as_ref(),
+                options.ports.as_ref(),
+                &options.exclude,
+            )?
         };
 
         if targets.is_empty() {
-            return Err("empty list of target(s) provided".to_owned());
+            return Err("empty list of target(s) provided".into());
         }
 
         // perform pre-emptive target validation
@@ -81,11 +135,17 @@ impl Session {
             parse_target(target, 0)?;
         }
 
-        let runtime = Runtime::new(options.concurrency);
+        let runtime = Runtime::new(
+            options.queue_size.unwrap_or(options.concurrency()),
+            net::Context::from_options(&options),
+        );
         let total = AtomicUsize::new(0);
         let done = AtomicUsize::new(0);
         let errors = AtomicUsize::new(0);
         let results = Mutex::new(vec![]);
+        let plugins = Mutex::new(BTreeMap::new());
+        let flagged = Mutex::new(Flagged::default());
+        let deferred = Mutex::new(vec![]);
 
         Ok(Arc::new(Self {
             options,
@@ -94,6 +154,9 @@ impl Session {
             done,
             errors,
             results,
+            plugins,
+            flagged,
+            deferred,
             runtime,
         }))
     }
@@ -105,7 +168,37 @@ impl Session {
             let file = fs::File::open(path).map_err(|e| e.to_string())?;
             let mut session: Session = serde_json::from_reader(file).map_err(|e| e.to_string())?;
 
-            session.runtime = Runtime::new(session.options.concurrency);
+            session.runtime = Runtime::new(
+                session
+                    .options
+                    .queue_size
+                    .unwrap_or(session.options.concurrency()),
+                net::Context::from_options(&session.options),
+            );
+            // make sure restored loot is not reported again
+            for loot in session.results.lock().unwrap().iter() {
+                session.runtime.add_loot_key(loot.dedup_key());
+            }
+            // don't probe dead targets or locked out accounts again
+            {
+                let flagged = session.flagged.lock().unwrap();
+                if !flagged.unreachables.is_empty() || !flagged.locked.is_empty() {
+                    log::info!(
+                        "restored {} unreachable target(s) and {} locked out username(s)",
+                        flagged.unreachables.len(),
+                        flagged.locked.len()
+                    );
+                }
+                session.runtime.set_flagged(&flagged);
+            }
+            // the credentials put aside are attempted first
+            {
+                let deferred = session.deferred.lock().unwrap();
+                if !deferred.is_empty() {
+                    log::info!("restored {} deferred credential(s)", deferred.len());
+                }
+                session.runtime.set_deferred(&deferred);
+            }
 
             Ok(Arc::new(session))
         } else {
@@ -131,7 +224,13 @@ impl Session {
             } else {
                 "".to_owned()
             },
-            session.options.target.as_ref().unwrap()
+            if let Some(target) = session.options.target.as_ref() {
+                target.to_owned()
+            } else if let Some(path) = session.options.validate.as_ref() {
+                format!("@{}", path)
+            } else {
+                format!("@{}", session.options.targets.as_ref().unwrap())
+            }
         );
 
         // set ctrl-c handler
@@ -155,6 +254,67 @@ impl Session {
         self.runtime.set_stop()
     }
 
+    pub async fn wait_stop(&self) {
+        self.runtime.wait_stop().await
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.runtime.is_paused()
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.runtime.set_paused(paused)
+    }
+
+    pub async fn wait_resume(&self) {
+        self.runtime.wait_resume().await
+    }
+
+    // register a function to be called with the result of every attempt, before successes are
+    // verified and recorded as loot; it runs on the worker itself so it must return quickly
+    #[allow(dead_code)] // only meant for code embedding the session
+    pub fn set_on_attempt<F>(&self, callback: F)
+    where
+        F: Fn(&Credentials, &Result<Option<Vec<Loot>>, Error>) + Send + Sync + 'static,
+    {
+        self.runtime.set_on_attempt(callback)
+    }
+
+    pub fn notify_attempt(&self, creds: &Credentials, result: &Result<Option<Vec<Loot>>, Error>) {
+        if let Some(callback) = self.runtime.get_on_attempt() {
+            callback.call(creds, result);
+        }
+    }
+
+    // connection settings the plugins run with, see net::scope
+    pub fn get_net_context(&self) -> Arc<net::Context> {
+        self.runtime.get_net_context()
+    }
+
+    pub fn events(&self) -> &Events {
+        self.runtime.events()
+    }
+
+    pub fn set_concurrency(&self, concurrency: usize) {
+        self.runtime.set_concurrency(concurrency)
+    }
+
+    pub fn get_concurrency(&self) -> usize {
+        self.runtime.get_concurrency()
+    }
+
+    pub fn inc_in_flight(&self) {
+        self.runtime.inc_in_flight()
+    }
+
+    pub fn dec_in_flight(&self) {
+        self.runtime.dec_in_flight()
+    }
+
+    pub fn get_in_flight(&self) -> usize {
+        self.runtime.get_in_flight()
+    }
+
     pub fn set_speed(&self, rps: usize) {
         self.runtime.set_speed(rps);
     }
@@ -163,14 +323,93 @@ impl Session {
         self.runtime.get_speed()
     }
 
-    pub async fn send_credentials(&self, creds: Credentials) -> Result<(), Error> {
-        self.runtime.send_credentials(creds).await
+    pub fn is_unreachable(&self, key: &str) -> bool {
+        self.runtime.is_unreachable(key)
+    }
+
+    pub fn add_unreachable(&self, key: &str) {
+        self.runtime.add_unreachable(key)
+    }
+
+    pub fn get_unreachables(&self) -> usize {
+        self.runtime.get_unreachables()
+    }
+
+    pub fn is_locked(&self, username: &str) -> bool {
+        self.runtime.is_locked(username)
+    }
+
+    pub fn add_locked(&self, username: &str) -> bool {
+        self.runtime.add_locked(username)
+    }
+
+    pub fn add_reported(&self, key: &str) -> bool {
+        self.runtime.add_reported(key)
     }
 
-    pub async fn recv_credentials(&self) -> Result<Credentials, Error> {
+    pub fn get_target_errors(&self, target: &str) -> usize {
+        self.runtime.get_target_errors(target)
+    }
+
+    pub fn inc_target_errors(&self, target: &str) {
+        self.runtime.inc_target_errors(target)
+    }
+
+    pub fn reset_target_errors(&self, target: &str) {
+        self.runtime.reset_target_errors(target)
+    }
+
+    pub fn get_target_cooldown(&self, target: &str) -> Option<time::Duration> {
+        self.runtime.get_target_cooldown(target)
+    }
+
+    pub fn set_target_cooldown(&self, target: &str, wait: time::Duration) {
+        self.runtime.set_target_cooldown(target, wait)
+    }
+
+    pub fn reserve_target_slot(&self, target: &str, delay: time::Duration) -> time::Duration {
+        self.runtime.reserve_target_slot(target, delay)
+    }
+
+    pub async fn send_credentials(
+        &self,
+        creds: Credentials,
+        provenance: Provenance,
+    ) -> Result<(), Error> {
+        self.runtime.send_credentials(creds, provenance).await
+    }
+
+    pub fn close_credentials(&self) {
+        self.runtime.close_credentials()
+    }
+
+    pub async fn recv_credentials(&self) -> Result<(Credentials, Provenance), Error> {
         self.runtime.recv_credentials().await
     }
 
+    pub fn try_recv_credentials(&self) -> Option<(Credentials, Provenance)> {
+        self.runtime.try_recv_credentials()
+    }
+
+    pub fn defer_credentials(
+        &self,
+        creds: (Credentials, Provenance),
+    ) -> Option<(Credentials, Provenance)> {
+        self.runtime.defer_credentials(creds)
+    }
+
+    pub fn take_deferred(&self, force: bool) -> Option<(Credentials, Provenance)> {
+        self.runtime.take_deferred(force)
+    }
+
+    pub fn next_deferred_wait(&self) -> Option<time::Duration> {
+        self.runtime.next_deferred_wait()
+    }
+
+    pub fn put_back_credentials(&self, creds: (Credentials, Provenance)) {
+        self.runtime.put_back_credentials(creds)
+    }
+
     pub fn is_done(&self) -> bool {
         self.get_done() >= self.get_total()
     }
@@ -187,6 +426,70 @@ impl Session {
         self.errors.load(Ordering::Relaxed)
     }
 
+    // findings are printed grouped by target at the end of the run
+    pub fn is_grouping_by_target(&self) -> bool {
+        self.options.group_by_target && !self.options.quiet && std::io::stdout().is_terminal()
+    }
+
+    pub fn add_plugin_stats(&self, plugin: &str, attempts: usize, successes: usize, errors: usize) {
+        let mut plugins = self.plugins.lock().unwrap();
+        let stats = plugins.entry(plugin.to_owned()).or_default();
+        stats.attempts += attempts;
+        stats.successes += successes;
+        stats.errors += errors;
+    }
+
+    pub fn get_plugin_stats(&self) -> BTreeMap<String, PluginStats> {
+        self.plugins.lock().unwrap().clone()
+    }
+
+    pub fn record_timing(&self, plugin: &str, elapsed: time::Duration) {
+        if self.options.timing_stats {
+            self.runtime.record_timing(plugin, elapsed)
+        }
+    }
+
+    pub fn get_timings(&self) -> BTreeMap<String, crate::utils::Histogram> {
+        self.runtime.get_timings()
+    }
+
+    pub fn set_plugin_weights<I: IntoIterator<Item = (String, usize)>>(&self, weights: I) {
+        self.runtime.set_weights(weights.into_iter().collect());
+    }
+
+    // total and done units of work, where each attempt counts as the weight of its plugin. None
+    // if all plugins weigh the same, as the progress would be the same as counting combinations.
+    pub fn get_work(&self) -> Option<(usize, usize)> {
+        let weights = self.runtime.get_weights();
+        if weights.values().all_equal() {
+            return None;
+        }
+
+        let total = self.get_total();
+        let total = if total == usize::MAX {
+            total
+        } else {
+            total.saturating_mul(weights.values().sum())
+        };
+        let done = self
+            .plugins
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, stats)| stats.attempts * weights.get(name).copied().unwrap_or(1))
+            .sum();
+
+        Some((total, done))
+    }
+
+    pub fn set_work_speed(&self, speed: usize) {
+        self.runtime.set_work_speed(speed)
+    }
+
+    pub fn get_work_speed(&self) -> usize {
+        self.runtime.get_work_speed()
+    }
+
     pub fn inc_done(&self) {
         self.done.fetch_add(1, Ordering::Relaxed);
     }
@@ -203,21 +506,34 @@ impl Session {
         self.total.load(Ordering::Relaxed)
     }
 
+    // true if the total saturated because the combinations don't fit a usize
+    pub fn is_total_overflow(&self) -> bool {
+        self.runtime.is_overflow()
+    }
+
     pub fn combinations(
         &self,
         override_payload: Option<Expression>,
         single: bool,
     ) -> Result<Combinator, Error> {
+        // the deferred credentials come before the ones done after them
         let combinator = Combinator::create(
             &self.targets,
             self.options.clone(),
-            self.get_done(),
+            self.get_done() + self.runtime.num_deferred(),
             single,
             override_payload,
         )?;
 
         self.set_total(combinator.search_space_size());
 
+        // when sampling the total is unknown rather than too large
+        let overflow = combinator.search_space_size() == usize::MAX && !combinator.is_sampling();
+        self.runtime.set_overflow(overflow);
+        if overflow {
+            log::warn!("the number of combinations is too large to be counted, progress and eta won't be available");
+        }
+
         if single {
             log::info!("using -> {}\n", combinator.username_expression());
         } else {
@@ -231,40 +547,378 @@ impl Session {
     pub async fn add_loot(&self, loot: Loot) -> Result<(), Error> {
         // append to loot vector
         if let Ok(mut results) = self.results.lock() {
-            if !results.contains(&loot) {
-                results.push(loot.clone());
-
-                // report credentials to screen
-                log::info!("{}", &loot);
+            // the same credentials might be confirmed more than once by retries or
+            // overlapping targets
+            if !self.runtime.add_loot_key(loot.dedup_key()) {
+                return Ok(());
+            }
+            results.push(loot.clone());
+        } else {
+            return Err("could not lock session results".into());
+        }
 
-                // check if we have to output to file
-                if let Some(path) = &self.options.output {
-                    if let Err(e) = loot.append_to_file(path, &self.options.output_format) {
-                        log::error!("could not write to {}: {:?}", &path, e);
-                    }
-                }
+        self.events().emit("loot", &loot);
 
-                // if we only need one match, stop
-                if !loot.is_partial() && self.options.single_match {
-                    self.set_stop();
-                }
+        // report credentials to screen, unless they're going to be reported at the end
+        if self.is_grouping_by_target() {
+            log::debug!("{}", &loot);
+        } else if let Some(template) = &self.options.loot_template {
+            log::info!("{}", loot.format_template(template));
+        } else {
+            log::info!("{}", &loot);
+        }
 
-                // save session if needed
-                return self.save();
+        // check if we have to output to file
+        if let Some(path) = &self.options.output {
+            if let Err(e) = loot
+                .append_to_file(path, &self.options.output_format, self.options.output_fsync)
+                .await
+            {
+                log::error!("could not write to {}: {}", &path, e);
             }
-        } else {
-            return Err("could not lock session results".to_owned());
         }
 
-        Ok(())
+        // if we only need one match, stop
+        if !loot.is_partial() && self.options.single_match {
+            self.set_stop();
+        }
+
+        // save session if needed
+        self.save()
     }
 
     pub fn save(&self) -> Result<(), Error> {
         if let Some(path) = self.options.session.as_ref() {
             log::debug!("saving session to {}", path);
+            *self.flagged.lock().unwrap() = self.runtime.get_flagged();
+            *self.deferred.lock().unwrap() = self.runtime.get_deferred();
             let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
-            return fs::write(path, json).map_err(|e| e.to_string());
+            return fs::write(path, json).map_err(Error::from);
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Loot, PluginStats, Provenance, Session};
+
+    #[test]
+    fn target_cooldowns_are_bounded() {
+        let mut opts = crate::Options::default();
+        opts.target = Some("127.0.0.1:80".to_owned());
+
+        let session = Session::from_options(opts).unwrap();
+        session.set_target_cooldown("127.0.0.1:80", std::time::Duration::MAX);
+
+        let wait = session.get_target_cooldown("127.0.0.1:80").unwrap();
+        assert!(wait <= std::time::Duration::from_secs(3600));
+    }
+
+    #[tokio::test]
+    async fn loot_is_deduplicated() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let output = tmpdir.path().join("loot.txt");
+
+        let mut opts = crate::Options::default();
+        opts.target = Some("127.0.0.1:22".to_owned());
+        opts.output = Some(output.to_str().unwrap().to_owned());
+
+        let session = Session::from_options(opts).unwrap();
+        let make_loot = || {
+            Loot::new(
+                "ssh",
+                "127.0.0.1:22",
+                [
+                    ("username".to_owned(), "admin".to_owned()),
+                    ("password".to_owned(), "hunter2".to_owned()),
+                ],
+            )
+        };
+
+        session.add_loot(make_loot()).await.unwrap();
+        session.add_loot(make_loot()).await.unwrap();
+
+        assert_eq!(session.results.lock().unwrap().len(), 1);
+        assert_eq!(std::fs::read_to_string(&output).unwrap().lines().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn can_pause_and_resume() {
+        let mut opts = crate::Options::default();
+        opts.target = Some("127.0.0.1:22".to_owned());
+        let session = Session::from_options(opts).unwrap();
+
+        session.set_paused(true);
+        let waiting = tokio::spawn({
+            let session = session.clone();
+            async move { session.wait_resume().await }
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert!(!waiting.is_finished());
+
+        session.set_paused(false);
+        tokio::time::timeout(std::time::Duration::from_secs(1), waiting)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    #[test]
+    fn total_overflow_is_detected() {
+        let mut opts = crate::Options::default();
+        opts.target = Some("127.0.0.1:22".to_owned());
+        opts.username = Some("admin".to_owned());
+        // 94^12 passwords, more than a u64 can hold
+        opts.password = Some("#12-12".to_owned());
+
+        let session = Session::from_options(opts.clone()).unwrap();
+        session.combinations(None, false).unwrap();
+        assert_eq!(session.get_total(), usize::MAX);
+        assert!(session.is_total_overflow());
+
+        // sampling makes the total unknown, not too large
+        opts.sample = Some(1.0);
+        let session = Session::from_options(opts).unwrap();
+        session.combinations(None, false).unwrap();
+        assert!(!session.is_total_overflow());
+    }
+
+    #[test]
+    fn plugin_stats_are_accumulated() {
+        let mut opts = crate::Options::default();
+        opts.target = Some("127.0.0.1:22".to_owned());
+
+        let session = Session::from_options(opts).unwrap();
+        session.add_plugin_stats("ssh", 10, 1, 0);
+        session.add_plugin_stats("ssh", 5, 0, 5);
+        session.add_plugin_stats("ftp", 3, 0, 0);
+
+        let stats = session.get_plugin_stats();
+        assert_eq!(
+            stats["ssh"],
+            PluginStats {
+                attempts: 15,
+                successes: 1,
+                errors: 5
+            }
+        );
+        assert_eq!(stats["ftp"].attempts, 3);
+    }
+
+    #[test]
+    fn progress_is_weighted_by_plugin() {
+        let mut opts = crate::Options::default();
+        opts.target = Some("127.0.0.1:22".to_owned());
+
+        let session = Session::from_options(opts).unwrap();
+        session.set_total(100);
+        session.add_plugin_stats("redis", 10, 0, 0);
+        session.add_plugin_stats("ssh", 5, 0, 0);
+
+        session.set_plugin_weights([("redis".to_owned(), 1), ("ssh".to_owned(), 1)]);
+        assert_eq!(session.get_work(), None);
+
+        session.set_plugin_weights([("redis".to_owned(), 1), ("ssh".to_owned(), 4)]);
+        assert_eq!(session.get_work(), Some((500, 30)));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+    async fn concurrent_loot_is_valid_jsonl() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let output = tmpdir.path().join("loot.jsonl");
+
+        let mut opts = crate::Options::default();
+        opts.target = Some("127.0.0.1:22".to_owned());
+        opts.output = Some(output.to_str().unwrap().to_owned());
+        opts.output_format = super::loot::OutputFormat::JSONL;
+
+        let session = Session::from_options(opts).unwrap();
+        let mut tasks = vec![];
+        for task in 0..32 {
+            let session = session.clone();
+            tasks.push(tokio::spawn(async move {
+                for i in 0..32 {
+                    let loot = Loot::new(
+                        "ssh",
+                        "127.0.0.1:22",
+                        [
+                            ("username".to_owned(), format!("user{}", task)),
+                            ("password".to_owned(), format!("{}", i).repeat(512)),
+                        ],
+                    );
+                    session.add_loot(loot).await.unwrap();
+                }
+            }));
+        }
+
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        let data = std::fs::read_to_string(&output).unwrap();
+        assert_eq!(data.lines().count(), 32 * 32);
+        for line in data.lines() {
+            serde_json::from_str::<serde_json::Value>(line).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn single_match_ignores_partial_loot() {
+        use clap::Parser;
+
+        let opts = crate::Options::try_parse_from([
+            "legba",
+            "kerberos",
+            "--target",
+            "127.0.0.1:88",
+            "--first-match-exit",
+        ])
+        .unwrap();
+        assert!(opts.single_match);
+
+        let session = Session::from_options(opts).unwrap();
+        session
+            .add_loot(
+                Loot::new(
+                    "kerberos",
+                    "127.0.0.1:88",
+                    [("username".to_owned(), "admin".to_owned())],
+                )
+                .set_partial(),
+            )
+            .await
+            .unwrap();
+        assert!(!session.is_stop());
+
+        session
+            .add_loot(Loot::new(
+                "kerberos",
+                "127.0.0.1:88",
+                [
+                    ("username".to_owned(), "admin".to_owned()),
+                    ("password".to_owned(), "admin".to_owned()),
+                ],
+            ))
+            .await
+            .unwrap();
+        assert!(session.is_stop());
+    }
+
+    #[tokio::test]
+    async fn stop_unblocks_full_queue() {
+        let mut opts = crate::Options::default();
+        opts.target = Some("127.0.0.1".to_owned());
+        opts.queue_size = Some(1);
+
+        let session = Session::from_options(opts).unwrap();
+        let creds = crate::Credentials {
+            target: "127.0.0.1".to_owned(),
+            username: "admin".to_owned(),
+            password: "admin".to_owned(),
+        };
+
+        session
+            .send_credentials(creds.clone(), Provenance::default())
+            .await
+            .unwrap();
+
+        // the queue is full, this would block forever without anyone consuming it
+        let producer = {
+            let session = session.clone();
+            tokio::spawn(
+                async move { session.send_credentials(creds, Provenance::default()).await },
+            )
+        };
+
+        session.set_stop();
+
+        let res = tokio::time::timeout(std::time::Duration::from_secs(5), producer)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn target_delay_spaces_attempts() {
+        let session = Session::from_options({
+            let mut opts = crate::Options::default();
+            opts.target = Some("127.0.0.1".to_owned());
+            opts
+        })
+        .unwrap();
+        let delay = std::time::Duration::from_secs(10);
+
+        assert!(session.reserve_target_slot("127.0.0.1", delay).is_zero());
+        // the next two attempts are queued one after the other
+        let second = session.reserve_target_slot("127.0.0.1", delay);
+        let third = session.reserve_target_slot("127.0.0.1", delay);
+        assert!(second > delay / 2 && second <= delay);
+        assert!(third > delay + delay / 2 && third <= delay * 2);
+        // other targets are not affected
+        assert!(session.reserve_target_slot("127.0.0.2", delay).is_zero());
+    }
+
+    #[test]
+    fn flagged_state_survives_resume() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let path = tmpdir.path().join("session.json");
+
+        let mut opts = crate::Options::default();
+        opts.target = Some("127.0.0.1:22".to_owned());
+        opts.session = Some(path.to_str().unwrap().to_owned());
+
+        let session = Session::from_options(opts.clone()).unwrap();
+        session.add_unreachable("ssh://127.0.0.1:22");
+        assert!(session.add_locked("admin"));
+        session.save().unwrap();
+        let flagged = session.runtime.get_flagged();
+
+        let restored = Session::from_disk(path.to_str().unwrap(), opts).unwrap();
+        assert!(restored.is_unreachable("ssh://127.0.0.1:22"));
+        assert!(restored.is_locked("admin"));
+        assert!(!restored.add_locked("admin"));
+        // the original timestamps are kept
+        assert_eq!(restored.runtime.get_flagged(), flagged);
+    }
+
+    #[test]
+    fn deferred_credentials_survive_resume() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let path = tmpdir.path().join("session.json");
+
+        let mut opts = crate::Options::default();
+        opts.target = Some("127.0.0.1:22".to_owned());
+        opts.username = Some("admin".to_owned());
+        opts.password = Some("[1-5]".to_owned());
+        opts.session = Some(path.to_str().unwrap().to_owned());
+
+        let session = Session::from_options(opts.clone()).unwrap();
+        let creds = crate::creds::Credentials {
+            target: "127.0.0.1:22".to_owned(),
+            username: "admin".to_owned(),
+            password: "1".to_owned(),
+        };
+        let provenance = Provenance {
+            password_line: Some(1),
+            ..Default::default()
+        };
+        session.set_target_cooldown("127.0.0.1:22", std::time::Duration::from_secs(60));
+        assert!(session
+            .defer_credentials((creds.clone(), provenance.clone()))
+            .is_none());
+        // the following ones are done meanwhile, then the run is stopped
+        session.inc_done();
+        session.inc_done();
+        session.set_stop();
+        session.save().unwrap();
+
+        let restored = Session::from_disk(path.to_str().unwrap(), opts).unwrap();
+        assert_eq!(restored.get_done(), 2);
+        // the combinations resume after the deferred credentials, which are attempted first
+        assert_eq!(restored.combinations(None, false).unwrap().dispatched(), 3);
+        assert_eq!(restored.take_deferred(false), Some((creds, provenance)));
+        assert_eq!(restored.take_deferred(true), None);
+    }
+}
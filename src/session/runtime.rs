@@ -1,30 +1,120 @@
+use std::collections::{BTreeMap, VecDeque};
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use super::Error;
-use crate::Credentials;
+use ahash::{HashMap, HashSet};
+use serde::{Deserialize, Serialize};
+
+use super::{Error, Loot};
+use crate::creds::{Credentials, Provenance};
+use crate::events::Events;
+use crate::utils::{net, Histogram};
+
+// longest a target asking us to slow down is waited for
+const MAX_TARGET_COOLDOWN: Duration = Duration::from_secs(3600);
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+// unreachable targets and locked out usernames, saved with the session so that a resumed run
+// doesn't try them again
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+pub(crate) struct Flagged {
+    pub unreachables: BTreeMap<String, u64>,
+    pub locked: BTreeMap<String, u64>,
+}
+
+type AttemptFn = dyn Fn(&Credentials, &Result<Option<Vec<Loot>>, Error>) + Send + Sync;
+
+// user provided function receiving the outcome of every attempt
+#[derive(Clone)]
+pub(crate) struct AttemptCallback(Arc<AttemptFn>);
+
+impl AttemptCallback {
+    pub fn call(&self, creds: &Credentials, result: &Result<Option<Vec<Loot>>, Error>) {
+        (self.0)(creds, result)
+    }
+}
+
+impl std::fmt::Debug for AttemptCallback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("AttemptCallback")
+    }
+}
 
 #[derive(Debug)]
 pub(crate) struct Runtime {
     stop: AtomicBool,
-    creds_tx: async_channel::Sender<Credentials>,
-    creds_rx: async_channel::Receiver<Credentials>,
+    stopped: tokio::sync::Notify,
+    paused: AtomicBool,
+    resumed: tokio::sync::Notify,
+    creds_tx: async_channel::Sender<(Credentials, Provenance)>,
+    creds_rx: async_channel::Receiver<(Credentials, Provenance)>,
+    // credentials of targets cooling down, put aside while the other targets are attempted
+    deferred: Mutex<VecDeque<(Credentials, Provenance)>>,
+    deferred_limit: usize,
     speed: AtomicUsize,
+    work_speed: AtomicUsize,
+    weights: RwLock<HashMap<String, usize>>,
+    overflow: AtomicBool,
+    concurrency: AtomicUsize,
+    in_flight: AtomicUsize,
+    // values are the unix time they have been flagged at
+    unreachables: RwLock<HashMap<String, u64>>,
+    locked: RwLock<HashMap<String, u64>>,
+    target_errors: Mutex<HashMap<String, usize>>,
+    loot_keys: Mutex<HashSet<u64>>,
+    target_cooldowns: Mutex<HashMap<String, Instant>>,
+    target_slots: Mutex<HashMap<String, Instant>>,
+    reported: Mutex<HashSet<String>>,
+    // attempt latency per plugin, only collected with --timing-stats
+    timings: Mutex<HashMap<String, Histogram>>,
+    on_attempt: RwLock<Option<AttemptCallback>>,
+    net: Arc<net::Context>,
+    events: Events,
 }
 
 impl Default for Runtime {
     fn default() -> Self {
-        Self::new(1)
+        Self::new(1, net::Context::default())
     }
 }
 
 impl Runtime {
-    pub(crate) fn new(concurrency: usize) -> Self {
-        let (creds_tx, creds_rx) = async_channel::bounded(concurrency);
+    pub(crate) fn new(queue_size: usize, net: net::Context) -> Self {
+        // a zero sized queue is reported by the options validation, don't panic before that
+        let (creds_tx, creds_rx) = async_channel::bounded(queue_size.max(1));
         Self {
             stop: AtomicBool::new(false),
+            stopped: tokio::sync::Notify::new(),
+            paused: AtomicBool::new(false),
+            resumed: tokio::sync::Notify::new(),
             speed: AtomicUsize::new(0),
+            work_speed: AtomicUsize::new(0),
+            weights: RwLock::new(HashMap::default()),
+            overflow: AtomicBool::new(false),
+            concurrency: AtomicUsize::new(0),
+            in_flight: AtomicUsize::new(0),
+            unreachables: RwLock::new(HashMap::default()),
+            locked: RwLock::new(HashMap::default()),
+            target_errors: Mutex::new(HashMap::default()),
+            loot_keys: Mutex::new(HashSet::default()),
+            target_cooldowns: Mutex::new(HashMap::default()),
+            target_slots: Mutex::new(HashMap::default()),
+            reported: Mutex::new(HashSet::default()),
+            timings: Mutex::new(HashMap::default()),
+            on_attempt: RwLock::new(None),
+            net: Arc::new(net),
+            events: Events::default(),
             creds_tx,
             creds_rx,
+            deferred: Mutex::new(VecDeque::new()),
+            deferred_limit: queue_size.max(1),
         }
     }
 
@@ -34,6 +124,60 @@ impl Runtime {
 
     pub fn set_stop(&self) {
         self.stop.store(true, Ordering::SeqCst);
+        // wake up the producer if it's blocked on a full queue
+        self.creds_tx.close();
+        self.stopped.notify_waiters();
+    }
+
+    // resolves once the stop flag is set
+    pub async fn wait_stop(&self) {
+        // the future must exist before checking the flag in order not to miss the notification
+        let stopped = self.stopped.notified();
+        if !self.is_stop() {
+            stopped.await;
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::SeqCst);
+        if !paused {
+            self.resumed.notify_waiters();
+        }
+    }
+
+    // resolves once not paused anymore or stopped
+    pub async fn wait_resume(&self) {
+        let resumed = self.resumed.notified();
+        if self.is_paused() && !self.is_stop() {
+            tokio::select! {
+                _ = resumed => {}
+                _ = self.wait_stop() => {}
+            }
+        }
+    }
+
+    pub fn set_on_attempt<F>(&self, callback: F)
+    where
+        F: Fn(&Credentials, &Result<Option<Vec<Loot>>, Error>) + Send + Sync + 'static,
+    {
+        *self.on_attempt.write().unwrap() = Some(AttemptCallback(Arc::new(callback)));
+    }
+
+    // cloned so that the lock is not held while the callback runs
+    pub fn get_on_attempt(&self) -> Option<AttemptCallback> {
+        self.on_attempt.read().unwrap().clone()
+    }
+
+    pub fn get_net_context(&self) -> Arc<net::Context> {
+        self.net.clone()
+    }
+
+    pub fn events(&self) -> &Events {
+        &self.events
     }
 
     pub fn set_speed(&self, rps: usize) {
@@ -44,11 +188,278 @@ impl Runtime {
         self.speed.load(Ordering::Relaxed)
     }
 
-    pub async fn send_credentials(&self, creds: Credentials) -> Result<(), Error> {
-        self.creds_tx.send(creds).await.map_err(|e| e.to_string())
+    pub fn set_work_speed(&self, speed: usize) {
+        self.work_speed.store(speed, Ordering::Relaxed);
+    }
+
+    pub fn get_work_speed(&self) -> usize {
+        self.work_speed.load(Ordering::Relaxed)
+    }
+
+    pub fn set_weights(&self, weights: HashMap<String, usize>) {
+        *self.weights.write().unwrap() = weights;
+    }
+
+    pub fn get_weights(&self) -> HashMap<String, usize> {
+        self.weights.read().unwrap().clone()
+    }
+
+    pub fn set_overflow(&self, overflow: bool) {
+        self.overflow.store(overflow, Ordering::Relaxed);
+    }
+
+    pub fn is_overflow(&self) -> bool {
+        self.overflow.load(Ordering::Relaxed)
+    }
+
+    pub fn set_concurrency(&self, concurrency: usize) {
+        self.concurrency.store(concurrency, Ordering::Relaxed);
+    }
+
+    pub fn get_concurrency(&self) -> usize {
+        self.concurrency.load(Ordering::Relaxed)
+    }
+
+    pub fn inc_in_flight(&self) {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn dec_in_flight(&self) {
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    pub fn get_in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    pub fn is_unreachable(&self, key: &str) -> bool {
+        self.unreachables.read().unwrap().contains_key(key)
+    }
+
+    pub fn add_unreachable(&self, key: &str) {
+        self.unreachables
+            .write()
+            .unwrap()
+            .entry(key.to_owned())
+            .or_insert_with(now);
+    }
+
+    pub fn get_unreachables(&self) -> usize {
+        self.unreachables.read().unwrap().len()
+    }
+
+    pub fn is_locked(&self, username: &str) -> bool {
+        self.locked.read().unwrap().contains_key(username)
+    }
+
+    // returns false if the username was already locked
+    pub fn add_locked(&self, username: &str) -> bool {
+        let mut locked = self.locked.write().unwrap();
+        if locked.contains_key(username) {
+            return false;
+        }
+        locked.insert(username.to_owned(), now());
+        true
+    }
+
+    pub fn get_flagged(&self) -> Flagged {
+        Flagged {
+            unreachables: self
+                .unreachables
+                .read()
+                .unwrap()
+                .iter()
+                .map(|(key, at)| (key.to_owned(), *at))
+                .collect(),
+            locked: self
+                .locked
+                .read()
+                .unwrap()
+                .iter()
+                .map(|(username, at)| (username.to_owned(), *at))
+                .collect(),
+        }
+    }
+
+    // restore the state of a previous session, keeping the original timestamps
+    pub fn set_flagged(&self, flagged: &Flagged) {
+        let mut unreachables = self.unreachables.write().unwrap();
+        for (key, at) in &flagged.unreachables {
+            unreachables.insert(key.to_owned(), *at);
+        }
+
+        let mut locked = self.locked.write().unwrap();
+        for (username, at) in &flagged.locked {
+            locked.insert(username.to_owned(), *at);
+        }
+    }
+
+    // returns false if the error was already reported
+    pub fn add_reported(&self, key: &str) -> bool {
+        self.reported.lock().unwrap().insert(key.to_owned())
+    }
+
+    pub fn record_timing(&self, plugin: &str, elapsed: Duration) {
+        self.timings
+            .lock()
+            .unwrap()
+            .entry(plugin.to_owned())
+            .or_default()
+            .record(elapsed);
+    }
+
+    pub fn get_timings(&self) -> BTreeMap<String, Histogram> {
+        self.timings
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(plugin, histogram)| (plugin.to_owned(), histogram.clone()))
+            .collect()
+    }
+
+    pub fn get_target_errors(&self, target: &str) -> usize {
+        *self.target_errors.lock().unwrap().get(target).unwrap_or(&0)
+    }
+
+    pub fn inc_target_errors(&self, target: &str) {
+        *self
+            .target_errors
+            .lock()
+            .unwrap()
+            .entry(target.to_owned())
+            .or_insert(0) += 1;
+    }
+
+    pub fn reset_target_errors(&self, target: &str) {
+        self.target_errors.lock().unwrap().remove(target);
+    }
+
+    // returns how long we still have to wait before sending requests to this target
+    pub fn get_target_cooldown(&self, target: &str) -> Option<Duration> {
+        let mut cooldowns = self.target_cooldowns.lock().unwrap();
+        let until = *cooldowns.get(target)?;
+        let now = Instant::now();
+        if until > now {
+            Some(until - now)
+        } else {
+            cooldowns.remove(target);
+            None
+        }
+    }
+
+    pub fn set_target_cooldown(&self, target: &str, wait: Duration) {
+        // a bogus Retry-After must neither stall the target forever nor overflow the clock
+        let Some(until) = Instant::now().checked_add(wait.min(MAX_TARGET_COOLDOWN)) else {
+            return;
+        };
+        let mut cooldowns = self.target_cooldowns.lock().unwrap();
+        // never shorten an existing cooldown
+        if cooldowns.get(target).is_none_or(|current| *current < until) {
+            cooldowns.insert(target.to_owned(), until);
+        }
+    }
+
+    // reserve the next attempt to this target at least delay after the previous one, returns how
+    // long to wait before performing it
+    pub fn reserve_target_slot(&self, target: &str, delay: Duration) -> Duration {
+        let now = Instant::now();
+        let mut slots = self.target_slots.lock().unwrap();
+        let slot = match slots.get(target) {
+            Some(last) if *last + delay > now => *last + delay,
+            _ => now,
+        };
+        slots.insert(target.to_owned(), slot);
+
+        slot - now
+    }
+
+    // returns false if this loot has already been registered
+    pub fn add_loot_key(&self, key: u64) -> bool {
+        self.loot_keys.lock().unwrap().insert(key)
+    }
+
+    pub async fn send_credentials(
+        &self,
+        creds: Credentials,
+        provenance: Provenance,
+    ) -> Result<(), Error> {
+        self.creds_tx
+            .send((creds, provenance))
+            .await
+            .map_err(|e| e.to_string().into())
+    }
+
+    // no more credentials are going to be sent, the queued ones can still be received
+    pub fn close_credentials(&self) {
+        self.creds_tx.close();
+    }
+
+    pub async fn recv_credentials(&self) -> Result<(Credentials, Provenance), Error> {
+        self.creds_rx.recv().await.map_err(|e| e.to_string().into())
+    }
+
+    pub fn try_recv_credentials(&self) -> Option<(Credentials, Provenance)> {
+        self.creds_rx.try_recv().ok()
+    }
+
+    // puts the credentials aside if their target is cooling down and there's room for them,
+    // otherwise they're given back
+    pub fn defer_credentials(
+        &self,
+        creds: (Credentials, Provenance),
+    ) -> Option<(Credentials, Provenance)> {
+        let mut deferred = self.deferred.lock().unwrap();
+        if deferred.len() < self.deferred_limit
+            && self.get_target_cooldown(&creds.0.target).is_some()
+        {
+            deferred.push_back(creds);
+            None
+        } else {
+            Some(creds)
+        }
+    }
+
+    // the oldest deferred credentials whose target is done cooling down, or just the oldest ones
+    // if forced
+    pub fn take_deferred(&self, force: bool) -> Option<(Credentials, Provenance)> {
+        let mut deferred = self.deferred.lock().unwrap();
+        let ready = deferred
+            .iter()
+            .position(|(creds, _)| self.get_target_cooldown(&creds.target).is_none());
+        match ready {
+            Some(idx) => deferred.remove(idx),
+            None if force => deferred.pop_front(),
+            None => None,
+        }
+    }
+
+    // credentials taken but not attempted because of a stop, saved with the deferred ones
+    pub fn put_back_credentials(&self, creds: (Credentials, Provenance)) {
+        self.deferred.lock().unwrap().push_front(creds);
+    }
+
+    pub fn get_deferred(&self) -> Vec<(Credentials, Provenance)> {
+        self.deferred.lock().unwrap().iter().cloned().collect()
+    }
+
+    pub fn set_deferred(&self, deferred: &[(Credentials, Provenance)]) {
+        self.deferred
+            .lock()
+            .unwrap()
+            .extend(deferred.iter().cloned());
+    }
+
+    pub fn num_deferred(&self) -> usize {
+        self.deferred.lock().unwrap().len()
     }
 
-    pub async fn recv_credentials(&self) -> Result<Credentials, Error> {
-        self.creds_rx.recv().await.map_err(|e| e.to_string())
+    // how long until the first of the deferred credentials can be attempted, if any
+    pub fn next_deferred_wait(&self) -> Option<Duration> {
+        self.deferred
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(creds, _)| self.get_target_cooldown(&creds.target).unwrap_or_default())
+            .min()
     }
 }
@@ -0,0 +1,128 @@
+use std::net::IpAddr;
+use std::sync::Mutex;
+
+use ahash::HashMap;
+use x509_parser::prelude::{FromDer, GeneralName, X509Certificate};
+
+use crate::session::Error;
+
+// certificate summaries by address, servers present the same certificate to every connection so
+// we only parse the first one
+#[derive(Debug, Default)]
+pub(crate) struct Certificates(Mutex<HashMap<String, Vec<(String, String)>>>);
+
+impl Certificates {
+    // store the summary of the certificate presented by the server at this address
+    pub fn capture(&self, address: &str, der: &[u8]) {
+        let mut certificates = self.0.lock().unwrap();
+        if certificates.contains_key(address) {
+            return;
+        }
+
+        match summary(der) {
+            Ok(summary) => {
+                certificates.insert(address.to_owned(), summary);
+            }
+            Err(e) => log::debug!("[{}] {}", address, e),
+        }
+    }
+
+    pub fn get(&self, address: &str) -> Option<Vec<(String, String)>> {
+        self.0.lock().unwrap().get(address).cloned()
+    }
+}
+
+// serial, subject, issuer, validity and alternative names of a DER encoded certificate, with the
+// same keys used by the port scanner banners
+pub(crate) fn summary(der: &[u8]) -> Result<Vec<(String, String)>, Error> {
+    let (_, cert) = X509Certificate::from_der(der)
+        .map_err(|e| Error::Protocol(format!("could not parse certificate: {}", e)))?;
+
+    let subject = cert.subject().to_string();
+    let issuer = cert.issuer().to_string();
+    let self_signed = subject == issuer;
+    let validity = cert.validity();
+
+    let mut data = vec![
+        ("certificate.serial".to_owned(), cert.raw_serial_as_string()),
+        ("certificate.subject".to_owned(), subject),
+        ("certificate.issuer".to_owned(), issuer),
+        (
+            "certificate.valid_from".to_owned(),
+            validity.not_before.to_string(),
+        ),
+        (
+            "certificate.valid_to".to_owned(),
+            validity.not_after.to_string(),
+        ),
+        (
+            "certificate.self_signed".to_owned(),
+            self_signed.to_string(),
+        ),
+    ];
+
+    if let Ok(Some(alt_names)) = cert.subject_alternative_name() {
+        let names: Vec<String> = alt_names
+            .value
+            .general_names
+            .iter()
+            .map(|n| match n {
+                GeneralName::DNSName(s) => s.to_string(),
+                GeneralName::IPAddress(bytes) => match bytes.len() {
+                    4 => IpAddr::from(<[u8; 4]>::try_from(*bytes).unwrap()).to_string(),
+                    16 => IpAddr::from(<[u8; 16]>::try_from(*bytes).unwrap()).to_string(),
+                    _ => n.to_string(),
+                },
+                _ => n.to_string(),
+            })
+            .collect();
+        data.push(("certificate.names".to_owned(), names.join(", ")));
+    }
+
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::summary;
+
+    const SELF_SIGNED: &str = "-----BEGIN CERTIFICATE-----
+MIIBzjCCAXWgAwIBAgIUX7H7oHptk6QykL0riLvu/LvHrAkwCgYIKoZIzj0EAwIw
+JTETMBEGA1UEAwwKbGVnYmEudGVzdDEOMAwGA1UECgwFTGVnYmEwIBcNMjYxMDE1
+MDgzMzE4WhgPMjEyNjA5MjEwODMzMThaMCUxEzARBgNVBAMMCmxlZ2JhLnRlc3Qx
+DjAMBgNVBAoMBUxlZ2JhMFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAESMowMuaA
+UY5Fp+SpO1eU7OEB07v3VEVvA+Z9P9+sNIUVRdacczRMMHb7euyGSg20CtShXwDk
+hyMaJEWUKP1q56OBgDB+MB0GA1UdDgQWBBS4HvjKEca1VysF9StDjCpercGSAjAf
+BgNVHSMEGDAWgBS4HvjKEca1VysF9StDjCpercGSAjAPBgNVHRMBAf8EBTADAQH/
+MCsGA1UdEQQkMCKCCmxlZ2JhLnRlc3SCDnd3dy5sZWdiYS50ZXN0hwR/AAABMAoG
+CCqGSM49BAMCA0cAMEQCIBuBaOq6jpSwtWmRokA5d0G73Z8BgfWBZUGZb7d7W1B1
+AiAupuyZx5aEzr11V+JI+ybhSuAzEah2IW7VBE/m6qNfPA==
+-----END CERTIFICATE-----
+";
+
+    #[test]
+    fn can_summarize_certificate() {
+        let (_, pem) = x509_parser::pem::parse_x509_pem(SELF_SIGNED.as_bytes()).unwrap();
+        let data = summary(&pem.contents).unwrap();
+        let get = |key: &str| {
+            data.iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.as_str())
+                .unwrap()
+        };
+
+        assert_eq!(get("certificate.subject"), "CN=legba.test, O=Legba");
+        assert_eq!(get("certificate.issuer"), "CN=legba.test, O=Legba");
+        assert_eq!(
+            get("certificate.names"),
+            "legba.test, www.legba.test, 127.0.0.1"
+        );
+        assert!(get("certificate.valid_to").contains("2126"));
+        assert_eq!(get("certificate.self_signed"), "true");
+    }
+
+    #[test]
+    fn invalid_certificate_is_an_error() {
+        assert!(summary(b"not a certificate").is_err());
+    }
+}
@@ -0,0 +1,82 @@
+use std::time::Duration;
+
+// parse a human friendly duration like 90, 30s, 15m, 2h, 1d or a combination as 1h30m, numbers
+// without a unit are seconds
+pub(crate) fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Err("empty duration".to_owned());
+    } else if let Ok(secs) = value.parse::<u64>() {
+        return Ok(Duration::from_secs(secs));
+    }
+
+    let mut total_ms: u64 = 0;
+    let mut rest = value;
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        if digits == 0 {
+            return Err(format!("invalid duration '{}'", value));
+        }
+
+        let amount: u64 = rest[..digits]
+            .parse()
+            .map_err(|e| format!("invalid duration '{}': {}", value, e))?;
+        rest = &rest[digits..];
+
+        let unit_len = rest
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let unit = match &rest[..unit_len] {
+            "ms" => 1,
+            "s" => 1000,
+            "m" => 60 * 1000,
+            "h" => 60 * 60 * 1000,
+            "d" => 24 * 60 * 60 * 1000,
+            unit => {
+                return Err(format!(
+                    "invalid unit '{}' in duration '{}', use ms, s, m, h or d",
+                    unit, value
+                ))
+            }
+        };
+        rest = &rest[unit_len..];
+
+        total_ms = amount
+            .checked_mul(unit)
+            .and_then(|ms| total_ms.checked_add(ms))
+            .ok_or_else(|| format!("duration '{}' is too long", value))?;
+    }
+
+    Ok(Duration::from_millis(total_ms))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::parse_duration;
+
+    #[test]
+    fn can_parse_durations() {
+        assert_eq!(parse_duration("90").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("30s").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_duration("30m").unwrap(), Duration::from_secs(30 * 60));
+        assert_eq!(parse_duration("2h").unwrap(), Duration::from_secs(2 * 3600));
+        assert_eq!(parse_duration("1d").unwrap(), Duration::from_secs(86400));
+        assert_eq!(parse_duration("500ms").unwrap(), Duration::from_millis(500));
+        assert_eq!(
+            parse_duration("1h30m").unwrap(),
+            Duration::from_secs(3600 + 30 * 60)
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_durations() {
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("m").is_err());
+        assert!(parse_duration("10y").is_err());
+        assert!(parse_duration("1.5h").is_err());
+    }
+}
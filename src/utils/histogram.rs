@@ -0,0 +1,112 @@
+use std::time::Duration;
+
+// 128 buckets per power of two, recorded values are within 1% of the real ones
+const SUB_BUCKET_BITS: u32 = 7;
+const SUB_BUCKETS: usize = 1 << SUB_BUCKET_BITS;
+// far beyond any timeout, keeps the bucket bounds from overflowing
+const MAX_VALUE: u64 = 1 << 62;
+
+// log-linear histogram of durations in microseconds, its size only depends on the range of the
+// recorded values and not on how many of them have been recorded
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Histogram {
+    counts: Vec<u64>,
+    samples: u64,
+    max: u64,
+}
+
+fn bucket_index(value: u64) -> usize {
+    if value < SUB_BUCKETS as u64 {
+        value as usize
+    } else {
+        let exp = 63 - value.leading_zeros() - SUB_BUCKET_BITS;
+        ((exp as usize) << SUB_BUCKET_BITS) + (value >> exp) as usize
+    }
+}
+
+// lowest value falling in the bucket
+fn bucket_value(index: usize) -> u64 {
+    if index < 2 * SUB_BUCKETS {
+        index as u64
+    } else {
+        let exp = (index >> SUB_BUCKET_BITS) - 1;
+        ((index - (exp << SUB_BUCKET_BITS)) as u64) << exp
+    }
+}
+
+impl Histogram {
+    pub fn record(&mut self, duration: Duration) {
+        let value = duration.as_micros().min(MAX_VALUE as u128) as u64;
+        let index = bucket_index(value);
+        if index >= self.counts.len() {
+            self.counts.resize(index + 1, 0);
+        }
+        self.counts[index] += 1;
+        self.samples += 1;
+        self.max = self.max.max(value);
+    }
+
+    pub fn samples(&self) -> u64 {
+        self.samples
+    }
+
+    pub fn max(&self) -> Duration {
+        Duration::from_micros(self.max)
+    }
+
+    // value below which the given fraction of the samples falls, like 0.99 for the p99, reported
+    // as the highest value of its bucket
+    pub fn percentile(&self, fraction: f64) -> Duration {
+        let rank = ((fraction * self.samples as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (index, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Duration::from_micros((bucket_value(index + 1) - 1).min(self.max));
+            }
+        }
+        self.max()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{bucket_index, bucket_value, Histogram};
+
+    #[test]
+    fn buckets_are_contiguous() {
+        for value in [0, 1, 127, 128, 255, 256, 344, 1_000_000, 1 << 62] {
+            let index = bucket_index(value);
+            assert!(bucket_value(index) <= value);
+            assert!(bucket_value(index + 1) > value);
+            // 1% precision
+            assert!((value - bucket_value(index)) as f64 <= value as f64 / 100.0 + 1.0);
+        }
+    }
+
+    #[test]
+    fn can_compute_percentiles() {
+        let mut histogram = Histogram::default();
+        for ms in 1..=100 {
+            histogram.record(Duration::from_millis(ms));
+        }
+
+        assert_eq!(histogram.samples(), 100);
+        assert_eq!(histogram.max(), Duration::from_millis(100));
+
+        let p50 = histogram.percentile(0.5).as_micros() as f64;
+        let p99 = histogram.percentile(0.99).as_micros() as f64;
+        assert!((p50 - 50_000.0).abs() <= 500.0);
+        assert!((p99 - 99_000.0).abs() <= 990.0);
+        assert_eq!(histogram.percentile(1.0), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn empty_histogram_is_zero() {
+        let histogram = Histogram::default();
+        assert_eq!(histogram.percentile(0.5), Duration::ZERO);
+        assert_eq!(histogram.max(), Duration::ZERO);
+    }
+}
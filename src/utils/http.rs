@@ -0,0 +1,15 @@
+use reqwest::{header::RETRY_AFTER, Response, StatusCode};
+
+use crate::session::Error;
+
+// a 429 means that we have to slow down, not that the credentials are wrong
+pub(crate) fn check_rate_limit(response: &Response) -> Result<(), Error> {
+    if response.status() == StatusCode::TOO_MANY_REQUESTS {
+        let retry_after = response
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|v| v.to_str().ok());
+        return Err(Error::rate_limited(retry_after));
+    }
+    Ok(())
+}
@@ -1,4 +1,19 @@
+pub(crate) mod cert;
+mod duration;
+mod histogram;
+#[cfg(any(
+    feature = "http",
+    feature = "wordpress",
+    feature = "couchdb",
+    feature = "etcd",
+    feature = "git"
+))]
+pub(crate) mod http;
 pub(crate) mod net;
+mod schedule;
 mod target;
 
+pub(crate) use duration::*;
+pub(crate) use histogram::*;
+pub(crate) use schedule::*;
 pub(crate) use target::*;
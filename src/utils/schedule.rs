@@ -0,0 +1,152 @@
+use chrono::{FixedOffset, Local, NaiveTime, Utc};
+
+// daily time windows the attempts are allowed in, like 22:00-06:00,12:00-13:00
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Schedule {
+    windows: Vec<(NaiveTime, NaiveTime)>,
+    // local time if not set
+    offset: Option<FixedOffset>,
+}
+
+impl Schedule {
+    pub fn parse(windows: &str, timezone: Option<&str>) -> Result<Self, String> {
+        let mut parsed = vec![];
+        let time = |value: &str| {
+            NaiveTime::parse_from_str(value.trim(), "%H:%M")
+                .map_err(|_| format!("invalid time '{}', expected HH:MM", value.trim()))
+        };
+
+        for window in windows
+            .split(',')
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+        {
+            let Some((start, end)) = window.split_once('-') else {
+                return Err(format!(
+                    "invalid schedule window '{}', expected HH:MM-HH:MM",
+                    window
+                ));
+            };
+            let (start, end) = (time(start)?, time(end)?);
+            if start == end {
+                return Err(format!("schedule window '{}' is empty", window));
+            }
+            parsed.push((start, end));
+        }
+
+        if parsed.is_empty() {
+            return Err("empty schedule".to_owned());
+        }
+
+        Ok(Self {
+            windows: parsed,
+            offset: match timezone {
+                None => None,
+                Some(timezone) => parse_timezone(timezone)?,
+            },
+        })
+    }
+
+    pub fn is_open(&self) -> bool {
+        let now = match self.offset {
+            Some(offset) => Utc::now().with_timezone(&offset).time(),
+            None => Local::now().time(),
+        };
+        self.is_open_at(now)
+    }
+
+    // windows ending before they start go past midnight
+    fn is_open_at(&self, time: NaiveTime) -> bool {
+        self.windows.iter().any(|(start, end)| {
+            if start < end {
+                *start <= time && time < *end
+            } else {
+                time >= *start || time < *end
+            }
+        })
+    }
+}
+
+// local, UTC or an offset like +02:00, -0530 or +3
+fn parse_timezone(timezone: &str) -> Result<Option<FixedOffset>, String> {
+    let invalid = || {
+        format!(
+            "invalid timezone '{}', use local, UTC or an offset like +02:00",
+            timezone
+        )
+    };
+
+    match timezone.trim().to_lowercase().as_str() {
+        "local" => return Ok(None),
+        "utc" | "z" => return Ok(FixedOffset::east_opt(0)),
+        _ => {}
+    }
+
+    let timezone = timezone.trim();
+    let (sign, offset) = match timezone.split_at_checked(1) {
+        Some(("+", offset)) => (1, offset),
+        Some(("-", offset)) => (-1, offset),
+        _ => return Err(invalid()),
+    };
+    let (hours, minutes) = match offset.split_once(':') {
+        Some((hours, minutes)) => (hours, minutes),
+        None if offset.len() == 4 => offset.split_at(2),
+        None => (offset, "0"),
+    };
+    let hours: i32 = hours.parse().map_err(|_| invalid())?;
+    let minutes: i32 = minutes.parse().map_err(|_| invalid())?;
+    if hours > 23 || minutes > 59 {
+        return Err(invalid());
+    }
+
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+        .map(Some)
+        .ok_or_else(invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{FixedOffset, NaiveTime};
+
+    use super::{parse_timezone, Schedule};
+
+    fn at(time: &str) -> NaiveTime {
+        NaiveTime::parse_from_str(time, "%H:%M").unwrap()
+    }
+
+    #[test]
+    fn can_parse_windows() {
+        let schedule = Schedule::parse("22:00-06:00, 12:00-13:30", None).unwrap();
+        assert!(schedule.is_open_at(at("23:15")));
+        assert!(schedule.is_open_at(at("00:00")));
+        assert!(schedule.is_open_at(at("05:59")));
+        assert!(!schedule.is_open_at(at("06:00")));
+        assert!(schedule.is_open_at(at("12:00")));
+        assert!(schedule.is_open_at(at("13:29")));
+        assert!(!schedule.is_open_at(at("13:30")));
+        assert!(!schedule.is_open_at(at("21:59")));
+    }
+
+    #[test]
+    fn rejects_invalid_windows() {
+        assert!(Schedule::parse("", None).is_err());
+        assert!(Schedule::parse("22:00", None).is_err());
+        assert!(Schedule::parse("22:00-25:00", None).is_err());
+        assert!(Schedule::parse("10:00-10:00", None).is_err());
+        assert!(Schedule::parse("22:00-06:00", Some("Mars/Olympus")).is_err());
+    }
+
+    #[test]
+    fn can_parse_timezones() {
+        assert_eq!(parse_timezone("local"), Ok(None));
+        assert_eq!(parse_timezone("UTC"), Ok(FixedOffset::east_opt(0)));
+        assert_eq!(parse_timezone("+02:00"), Ok(FixedOffset::east_opt(7200)));
+        assert_eq!(
+            parse_timezone("-0530"),
+            Ok(FixedOffset::west_opt(5 * 3600 + 30 * 60))
+        );
+        assert_eq!(parse_timezone("+3"), Ok(FixedOffset::east_opt(3 * 3600)));
+        assert!(parse_timezone("+24:00").is_err());
+        assert!(parse_timezone("02:00").is_err());
+    }
+}
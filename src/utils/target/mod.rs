@@ -0,0 +1,19 @@
+mod multi;
+mod single;
+// only the plugins declaring SRV records need the resolver
+#[cfg(any(feature = "kerberos", feature = "ldap", feature = "xmpp"))]
+mod srv;
+
+pub(crate) use multi::*;
+pub(crate) use single::*;
+#[cfg(any(feature = "kerberos", feature = "ldap", feature = "xmpp"))]
+pub(crate) use srv::*;
+
+// none of the plugins built in declares SRV records, --srv is rejected before getting here
+#[cfg(not(any(feature = "kerberos", feature = "ldap", feature = "xmpp")))]
+pub(crate) async fn resolve_srv(
+    _domains: &[String],
+    service: &str,
+) -> Result<Vec<String>, crate::session::Error> {
+    Err(format!("can't resolve the {} SRV records in this build", service).into())
+}
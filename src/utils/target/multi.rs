@@ -0,0 +1,542 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+};
+
+use ahash::HashSet;
+use itertools::Itertools;
+
+use crate::session::Error;
+
+use cidr_utils::cidr::IpCidr;
+use lazy_regex::{lazy_regex, Lazy};
+use regex::Regex;
+
+static IPV4_RANGE_PARSER: Lazy<Regex> = lazy_regex!(r"^(\d+)\.(\d+)\.(\d+)\.(\d+)-(\d+):?(\d+)?$");
+
+fn parse_multiple_targets_atom(expression: &str) -> Result<Vec<String>, Error> {
+    if let Some(path) = expression.strip_prefix('@') {
+        // load from file
+        let file = File::open(path).map_err(|e| e.to_string())?;
+        let reader = BufReader::new(file);
+
+        Ok(reader
+            .lines()
+            .map(|l| l.unwrap_or_default())
+            .filter(|s| !s.is_empty())
+            .collect())
+    } else if let Some(caps) = IPV4_RANGE_PARSER.captures(expression) {
+        // ipv4 range like 192.168.1.1-10 or 192.168.1.1-10:port
+        let a: u8 = caps.get(1).unwrap().as_str().parse().unwrap();
+        let b: u8 = caps.get(2).unwrap().as_str().parse().unwrap();
+        let c: u8 = caps.get(3).unwrap().as_str().parse().unwrap();
+        let start: u8 = caps.get(4).unwrap().as_str().parse().unwrap();
+        let stop: u8 = caps.get(5).unwrap().as_str().parse().unwrap();
+
+        if stop < start {
+            return Err(format!(
+                "invalid ip range {}, {} is greater than {}",
+                expression, start, stop
+            )
+            .into());
+        }
+
+        let port_part = if let Some(port) = caps.get(6) {
+            format!(":{}", port.as_str())
+        } else {
+            "".to_owned()
+        };
+
+        let mut range = vec![];
+        for d in start..=stop {
+            range.push(format!("{}.{}.{}.{}{}", a, b, c, d, port_part));
+        }
+
+        Ok(range)
+    } else {
+        // check for the port part
+        let (cidr_part, port_part) = if expression.contains(":[") && expression.ends_with(']') {
+            let (cidr, port) = expression.split_once(":[").unwrap();
+            (
+                cidr,
+                if cidr.contains(':') {
+                    // ipv6 cidr
+                    format!(":[{}", port)
+                } else {
+                    // ipv4 cidr
+                    format!(":{}", port.trim_end_matches(']'))
+                },
+            )
+        } else {
+            (expression, "".to_owned())
+        };
+
+        // attempt as cidr
+        if let Ok(cidr) = IpCidr::from_str(cidr_part) {
+            Ok(cidr
+                .iter()
+                .map(|ip| format!("{}{}", ip, port_part))
+                .collect())
+        } else {
+            // just return as it is
+            Ok(vec![expression.to_string()])
+        }
+    }
+}
+
+pub(crate) fn parse_multiple_targets(expression: &str) -> Result<Vec<String>, Error> {
+    let mut all = vec![];
+
+    for atom in expression
+        .split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+    {
+        all.extend(parse_multiple_targets_atom(atom)?);
+    }
+
+    Ok(all)
+}
+
+pub(crate) fn parse_targets_file(path: &str) -> Result<Vec<String>, Error> {
+    let file = File::open(path).map_err(|e| format!("could not open {}: {}", path, e))?;
+    let reader = BufReader::new(file);
+    let mut all = vec![];
+
+    for line in reader.lines() {
+        let line = line.map_err(|e| e.to_string())?;
+        let line = line.trim();
+        // skip empty lines and comments
+        if !line.is_empty() && !line.starts_with('#') {
+            all.extend(parse_multiple_targets_atom(line)?);
+        }
+    }
+
+    Ok(all)
+}
+
+// remove the excluded hosts from the expanded targets, exclusions with a port only remove that
+// host:port while the others remove the host regardless of the port
+pub(crate) fn exclude_targets(
+    targets: Vec<String>,
+    exclude: &[String],
+) -> Result<Vec<String>, Error> {
+    if exclude.is_empty() {
+        return Ok(targets);
+    }
+
+    let mut hosts = HashSet::default();
+    let mut host_ports = HashSet::default();
+    for expression in exclude {
+        for excluded in parse_multiple_targets(expression)? {
+            match super::parse_target(&excluded, 0)? {
+                (host, 0) => hosts.insert(host.to_lowercase()),
+                (host, port) => host_ports.insert((host.to_lowercase(), port)),
+            };
+        }
+    }
+
+    let mut included = vec![];
+    for target in targets {
+        let (host, port) = super::parse_target(&target, 0)?;
+        let host = host.to_lowercase();
+        if !hosts.contains(&host) && !host_ports.contains(&(host, port)) {
+            included.push(target);
+        }
+    }
+
+    Ok(included)
+}
+
+// parse a comma separated list of ports and port ranges like 6379,6380 or 8000-8010
+pub(crate) fn parse_ports(expression: &str) -> Result<Vec<u16>, Error> {
+    let mut ports = vec![];
+    let parse = |port: &str| {
+        port.trim()
+            .parse::<u16>()
+            .ok()
+            .filter(|port| *port > 0)
+            .ok_or(format!("invalid port '{}'", port.trim()))
+    };
+
+    for atom in expression
+        .split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+    {
+        if let Some((start, stop)) = atom.split_once('-') {
+            let (start, stop) = (parse(start)?, parse(stop)?);
+            if stop < start {
+                return Err(format!(
+                    "invalid port range {}, {} is greater than {}",
+                    atom, start, stop
+                )
+                .into());
+            }
+            ports.extend(start..=stop);
+        } else {
+            ports.push(parse(atom)?);
+        }
+    }
+
+    if ports.is_empty() {
+        return Err("empty list of ports provided".into());
+    }
+
+    Ok(ports.into_iter().unique().collect())
+}
+
+// one target per port for every target that doesn't already have one
+pub(crate) fn with_ports(targets: Vec<String>, ports: &[u16]) -> Result<Vec<String>, Error> {
+    let mut all = vec![];
+    for target in targets {
+        if super::parse_target(&target, 0)?.1 != 0 {
+            all.push(target);
+            continue;
+        }
+
+        // the port goes after the host of urls
+        let (scheme, rest) = match target.split_once("://") {
+            Some((scheme, rest)) => (format!("{}://", scheme), rest),
+            None => (String::new(), target.as_str()),
+        };
+        let (host, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+        let host = if host.contains(':') && !host.starts_with('[') {
+            // ipv6
+            format!("[{}]", host)
+        } else {
+            host.to_owned()
+        };
+
+        for port in ports {
+            all.push(format!("{}{}:{}{}", scheme, host, port, path));
+        }
+    }
+
+    Ok(all)
+}
+
+// collect targets from --target and --targets on the --ports, minus the --exclude ones
+pub(crate) fn parse_targets(
+    target: Option<&String>,
+    targets_file: Option<&String>,
+    ports: Option<&String>,
+    exclude: &[String],
+) -> Result<Vec<String>, Error> {
+    if target.is_none() && targets_file.is_none() {
+        return Err("no --target/-T or --targets argument provided".into());
+    }
+
+    let mut all = vec![];
+
+    if let Some(target) = target {
+        all.extend(parse_multiple_targets(target)?);
+    }
+
+    if let Some(path) = targets_file {
+        all.extend(parse_targets_file(path)?);
+    }
+
+    if let Some(ports) = ports {
+        all = with_ports(all, &parse_ports(ports)?)?;
+    }
+
+    exclude_targets(all, exclude)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::Write;
+
+    use super::{exclude_targets, parse_multiple_targets, parse_ports, parse_targets, with_ports};
+
+    #[test]
+    fn can_parse_single() {
+        let expected = vec!["127.0.0.1:22".to_owned()];
+        let res = parse_multiple_targets("127.0.0.1:22").unwrap();
+        assert_eq!(res, expected);
+
+        let expected = vec!["http://www.something.it:8000".to_owned()];
+        let res = parse_multiple_targets("http://www.something.it:8000").unwrap();
+        assert_eq!(res, expected);
+
+        let expected = vec!["host:1234".to_owned()];
+        let res = parse_multiple_targets(",,host:1234,,,").unwrap();
+        assert_eq!(res, expected);
+    }
+
+    #[test]
+    fn can_parse_from_file() {
+        let num_items = 5;
+        let tmpdir = tempfile::tempdir().unwrap();
+        let tmppath = tmpdir.path().join("targets.txt");
+        let mut tmptargets = File::create(&tmppath).unwrap();
+        let mut expected = vec![];
+
+        for i in 0..num_items {
+            writeln!(tmptargets, "127.0.0.1:{}", i).unwrap();
+            expected.push(format!("127.0.0.1:{}", i));
+        }
+        tmptargets.flush().unwrap();
+        drop(tmptargets);
+
+        let res = parse_multiple_targets(&format!("@{}", tmppath.to_str().unwrap())).unwrap();
+        assert_eq!(res, expected);
+    }
+
+    #[test]
+    fn can_parse_targets_file() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let tmppath = tmpdir.path().join("targets.txt");
+        let mut tmptargets = File::create(&tmppath).unwrap();
+
+        writeln!(tmptargets, "# some comment").unwrap();
+        writeln!(tmptargets, "127.0.0.1:22").unwrap();
+        writeln!(tmptargets).unwrap();
+        writeln!(tmptargets, "  192.168.1.1-2  ").unwrap();
+        writeln!(tmptargets, "10.0.0.0/31:[8080]").unwrap();
+        tmptargets.flush().unwrap();
+        drop(tmptargets);
+
+        let res = parse_targets(
+            Some("www.google.com".to_owned()).as_ref(),
+            Some(tmppath.to_str().unwrap().to_owned()).as_ref(),
+            None,
+            &[],
+        );
+        assert_eq!(
+            res,
+            Ok(vec![
+                "www.google.com".to_owned(),
+                "127.0.0.1:22".to_owned(),
+                "192.168.1.1".to_owned(),
+                "192.168.1.2".to_owned(),
+                "10.0.0.0:8080".to_owned(),
+                "10.0.0.1:8080".to_owned(),
+            ])
+        );
+    }
+
+    #[test]
+    fn returns_error_for_no_targets() {
+        assert!(parse_targets(None, None, None, &[]).is_err());
+    }
+
+    #[test]
+    fn can_exclude_host_from_cidr() {
+        let res = parse_targets(
+            Some("192.168.1.0/24".to_owned()).as_ref(),
+            None,
+            None,
+            &["192.168.1.1".to_owned()],
+        )
+        .unwrap();
+        assert_eq!(res.len(), 255);
+        assert!(!res.contains(&"192.168.1.1".to_owned()));
+    }
+
+    #[test]
+    fn can_exclude_ranges_and_ports() {
+        let targets =
+            parse_multiple_targets("10.0.0.0/30:[22], 10.0.0.0/30:[80], Example.com").unwrap();
+        let res = exclude_targets(
+            targets,
+            &[
+                "10.0.0.0/31".to_owned(),
+                "10.0.0.3:80".to_owned(),
+                "example.com".to_owned(),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            res,
+            vec![
+                "10.0.0.2:22".to_owned(),
+                "10.0.0.3:22".to_owned(),
+                "10.0.0.2:80".to_owned(),
+            ]
+        );
+    }
+
+    #[test]
+    fn can_parse_ports() {
+        assert_eq!(parse_ports("6379, 6380"), Ok(vec![6379, 6380]));
+        assert_eq!(
+            parse_ports("8000-8002,8001,22"),
+            Ok(vec![8000, 8001, 8002, 22])
+        );
+        assert!(parse_ports("8002-8000").is_err());
+        assert!(parse_ports("0").is_err());
+        assert!(parse_ports("65536").is_err());
+        assert!(parse_ports(",").is_err());
+    }
+
+    #[test]
+    fn can_expand_targets_on_ports() {
+        let targets = vec![
+            "10.0.0.1".to_owned(),
+            "10.0.0.2:22".to_owned(),
+            "::1".to_owned(),
+            "https://example.com/login".to_owned(),
+        ];
+        assert_eq!(
+            with_ports(targets, &[6379, 6380]),
+            Ok(vec![
+                "10.0.0.1:6379".to_owned(),
+                "10.0.0.1:6380".to_owned(),
+                "10.0.0.2:22".to_owned(),
+                "[::1]:6379".to_owned(),
+                "[::1]:6380".to_owned(),
+                "https://example.com:6379/login".to_owned(),
+                "https://example.com:6380/login".to_owned(),
+            ])
+        );
+    }
+
+    #[test]
+    fn ports_compose_with_cidr_and_exclusions() {
+        let res = parse_targets(
+            Some("192.168.1.0/30".to_owned()).as_ref(),
+            None,
+            Some("6379-6380".to_owned()).as_ref(),
+            &["192.168.1.1".to_owned(), "192.168.1.2:6380".to_owned()],
+        )
+        .unwrap();
+        assert_eq!(
+            res,
+            vec![
+                "192.168.1.0:6379".to_owned(),
+                "192.168.1.0:6380".to_owned(),
+                "192.168.1.2:6379".to_owned(),
+                "192.168.1.3:6379".to_owned(),
+                "192.168.1.3:6380".to_owned(),
+            ]
+        );
+    }
+
+    #[test]
+    fn returns_error_for_wrong_filename() {
+        let res = parse_multiple_targets("@i-do-not-exist.lol");
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn can_parse_comma_separated() {
+        let expected = Ok(vec![
+            "127.0.0.1:22".to_owned(),
+            "www.google.com".to_owned(),
+            "cnn.com".to_owned(),
+            "8.8.8.8:4444".to_owned(),
+        ]);
+        let res = parse_multiple_targets("127.0.0.1:22, www.google.com, cnn.com,, 8.8.8.8:4444");
+        assert_eq!(res, expected);
+    }
+
+    #[test]
+    fn can_parse_ip_range_without_port() {
+        let expected = Ok(vec![
+            "192.168.1.1".to_owned(),
+            "192.168.1.2".to_owned(),
+            "192.168.1.3".to_owned(),
+            "192.168.1.4".to_owned(),
+            "192.168.1.5".to_owned(),
+        ]);
+        let res = parse_multiple_targets("192.168.1.1-5");
+        assert_eq!(res, expected);
+    }
+
+    #[test]
+    fn can_parse_ip_range_with_port() {
+        let expected = Ok(vec![
+            "192.168.1.1:1234".to_owned(),
+            "192.168.1.2:1234".to_owned(),
+            "192.168.1.3:1234".to_owned(),
+            "192.168.1.4:1234".to_owned(),
+            "192.168.1.5:1234".to_owned(),
+        ]);
+        let res = parse_multiple_targets("192.168.1.1-5:1234");
+        assert_eq!(res, expected);
+    }
+
+    #[test]
+    fn can_parse_ipv4_cidr_without_port() {
+        let expected = Ok(vec![
+            "192.168.1.0".to_owned(),
+            "192.168.1.1".to_owned(),
+            "192.168.1.2".to_owned(),
+            "192.168.1.3".to_owned(),
+        ]);
+        let res = parse_multiple_targets("192.168.1.0/30");
+        assert_eq!(res, expected);
+    }
+
+    #[test]
+    fn can_parse_ipv4_cidr_with_port() {
+        let expected = Ok(vec![
+            "192.168.1.0:1234".to_owned(),
+            "192.168.1.1:1234".to_owned(),
+            "192.168.1.2:1234".to_owned(),
+            "192.168.1.3:1234".to_owned(),
+        ]);
+        let res = parse_multiple_targets("192.168.1.0/30:[1234]");
+        assert_eq!(res, expected);
+    }
+
+    #[test]
+    fn can_parse_ipv6_cidr_without_port() {
+        let expected = Ok(vec![
+            "2001:4f8:3:ba:2e0:81ff:fe22:d1f0".to_owned(),
+            "2001:4f8:3:ba:2e0:81ff:fe22:d1f1".to_owned(),
+            "2001:4f8:3:ba:2e0:81ff:fe22:d1f2".to_owned(),
+            "2001:4f8:3:ba:2e0:81ff:fe22:d1f3".to_owned(),
+        ]);
+        let res = parse_multiple_targets("2001:4f8:3:ba:2e0:81ff:fe22:d1f1/126");
+        assert_eq!(res, expected);
+    }
+
+    #[test]
+    fn can_parse_ipv6_cidr_with_port() {
+        let expected = Ok(vec![
+            "2001:4f8:3:ba:2e0:81ff:fe22:d1f0:[1234]".to_owned(),
+            "2001:4f8:3:ba:2e0:81ff:fe22:d1f1:[1234]".to_owned(),
+            "2001:4f8:3:ba:2e0:81ff:fe22:d1f2:[1234]".to_owned(),
+            "2001:4f8:3:ba:2e0:81ff:fe22:d1f3:[1234]".to_owned(),
+        ]);
+        let res = parse_multiple_targets("2001:4f8:3:ba:2e0:81ff:fe22:d1f1/126:[1234]");
+        assert_eq!(res, expected);
+    }
+
+    #[test]
+    fn can_parse_combined() {
+        let num_items = 5;
+        let tmpdir = tempfile::tempdir().unwrap();
+        let tmppath = tmpdir.path().join("targets.txt");
+        let mut tmptargets = File::create(&tmppath).unwrap();
+        let expected = vec![
+            "192.168.1.1",
+            "127.0.0.1:0",
+            "127.0.0.1:1",
+            "127.0.0.1:2",
+            "127.0.0.1:3",
+            "127.0.0.1:4",
+            "8.8.8.8",
+            "8.8.8.9",
+            "8.8.8.10",
+            "8.8.8.11",
+        ];
+
+        for i in 0..num_items {
+            writeln!(tmptargets, "127.0.0.1:{}", i).unwrap();
+        }
+        tmptargets.flush().unwrap();
+        drop(tmptargets);
+
+        let res = parse_multiple_targets(&format!(
+            "192.168.1.1, @{}, 8.8.8.8/30",
+            tmppath.to_str().unwrap()
+        ))
+        .unwrap();
+        assert_eq!(res, expected);
+    }
+}
@@ -0,0 +1,97 @@
+use trust_dns_resolver::TokioAsyncResolver;
+
+use crate::session::Error;
+
+#[derive(Debug, Clone, PartialEq)]
+struct Server {
+    priority: u16,
+    weight: u16,
+    host: String,
+    port: u16,
+}
+
+// lowest priority first and, within the same priority, heaviest first; a target of "." means
+// that the service is not available for the domain
+fn order_servers(mut servers: Vec<Server>) -> Vec<String> {
+    servers.retain(|server| !server.host.is_empty() && server.host != ".");
+    servers.sort_by(|a, b| {
+        a.priority
+            .cmp(&b.priority)
+            .then_with(|| b.weight.cmp(&a.weight))
+    });
+
+    servers
+        .into_iter()
+        .map(|server| format!("{}:{}", server.host.trim_end_matches('.'), server.port))
+        .collect()
+}
+
+// resolve the <service>.<domain> SRV records of every domain into host:port targets
+pub(crate) async fn resolve_srv(domains: &[String], service: &str) -> Result<Vec<String>, Error> {
+    let resolver = TokioAsyncResolver::tokio_from_system_conf().map_err(|e| e.to_string())?;
+    let mut all = vec![];
+
+    for domain in domains {
+        let (domain, _) = super::parse_target(domain, 0)?;
+        let name = format!("{}.{}.", service, domain.trim_end_matches('.'));
+
+        match resolver.srv_lookup(name.as_str()).await {
+            Ok(response) => {
+                let servers = order_servers(
+                    response
+                        .iter()
+                        .map(|srv| Server {
+                            priority: srv.priority(),
+                            weight: srv.weight(),
+                            host: srv.target().to_utf8(),
+                            port: srv.port(),
+                        })
+                        .collect(),
+                );
+
+                log::info!("{} -> {}", &name, servers.join(", "));
+                all.extend(servers);
+            }
+            Err(e) => log::warn!("could not resolve {}: {}", &name, e),
+        }
+    }
+
+    if all.is_empty() {
+        return Err(format!("no {} SRV records found", service).into());
+    }
+
+    Ok(all)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{order_servers, Server};
+
+    fn server(priority: u16, weight: u16, host: &str) -> Server {
+        Server {
+            priority,
+            weight,
+            host: host.to_owned(),
+            port: 389,
+        }
+    }
+
+    #[test]
+    fn orders_by_priority_and_weight() {
+        let servers = vec![
+            server(20, 0, "backup.example.com."),
+            server(10, 10, "light.example.com."),
+            server(10, 60, "heavy.example.com."),
+            server(0, 0, "."),
+        ];
+
+        assert_eq!(
+            order_servers(servers),
+            vec![
+                "heavy.example.com:389".to_owned(),
+                "light.example.com:389".to_owned(),
+                "backup.example.com:389".to_owned(),
+            ]
+        );
+    }
+}
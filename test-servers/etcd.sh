@@ -0,0 +1,7 @@
+docker run -p 2379:2379 \
+    -e ALLOW_NONE_AUTHENTICATION=no \
+    -e ETCD_ROOT_PASSWORD=test12345 \
+    bitnami/etcd
+
+# then test with:
+# legba etcd --target localhost:2379 --username root --password test12345